@@ -2,11 +2,16 @@
 //!
 //! Handles all communication with the NoChat backend server.
 
-use reqwest::{Client, Response};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::{Client, Response, StatusCode};
 use serde::{de::DeserializeOwned, Serialize};
+use std::io::Write;
 
+use crate::crypto::{CredentialResponse, OprfRequest, OprfResponse, RegistrationRecord};
 use crate::error::{AppError, AppResult};
-use crate::models::{AuthResponse, Conversation, Message, UserInfo};
+use crate::models::{AuthResponse, Conversation, Device, KeyPayload, Message, PushKeys, UserInfo};
+use crate::state::SharedState;
 
 /// Wrapper for user response from /api/users/me
 #[derive(Debug, serde::Deserialize)]
@@ -14,24 +19,63 @@ struct UserResponse {
     user: UserInfo,
 }
 
+/// Response body from `/api/auth/refresh`
+#[derive(Debug, serde::Deserialize)]
+struct RefreshResponse {
+    token: String,
+    refresh_token: Option<String>,
+}
+
+/// POST bodies larger than this are gzip-compressed before sending; smaller
+/// bodies aren't worth the compression overhead.
+const COMPRESSION_THRESHOLD_BYTES: usize = 1024;
+
 /// API client for NoChat backend
 #[derive(Debug, Clone)]
 pub struct ApiClient {
     client: Client,
     base_url: String,
+    /// Shared app state, used to look up the current refresh token and persist
+    /// a refreshed session after a transparent token refresh. `None` for
+    /// unauthenticated callers (e.g. signin/signup) that have no session yet.
+    session: Option<SharedState>,
+    /// Whether gzip request/response compression is enabled. Exposed so it
+    /// can be turned off for debugging (e.g. inspecting raw traffic).
+    compression_enabled: bool,
 }
 
 impl ApiClient {
-    /// Create a new API client
+    /// Create a new API client with no session attached, with compression enabled
+    ///
+    /// A client created this way cannot refresh an expired access token; use
+    /// [`ApiClient::with_session`] for any call made on behalf of a logged-in user.
     pub fn new(base_url: &str) -> Self {
+        Self::with_compression(base_url, true)
+    }
+
+    /// Create a new API client with compression explicitly enabled or disabled
+    pub fn with_compression(base_url: &str, enabled: bool) -> Self {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(30))
+            // Transparently decompresses gzip responses and sends
+            // `Accept-Encoding: gzip` on every request.
+            .gzip(enabled)
             .build()
             .expect("Failed to create HTTP client");
 
         Self {
             client,
             base_url: base_url.to_string(),
+            session: None,
+            compression_enabled: enabled,
+        }
+    }
+
+    /// Create a new API client that can transparently refresh an expired access token
+    pub fn with_session(base_url: &str, session: SharedState) -> Self {
+        Self {
+            session: Some(session),
+            ..Self::new(base_url)
         }
     }
 
@@ -40,35 +84,160 @@ impl ApiClient {
         format!("{}{}", self.base_url, endpoint)
     }
 
-    /// Make authenticated GET request
-    async fn get<T: DeserializeOwned>(&self, endpoint: &str, token: Option<&str>) -> AppResult<T> {
+    /// Send a GET request without interpreting the response
+    async fn send_get(&self, endpoint: &str, token: Option<&str>) -> AppResult<Response> {
         let mut request = self.client.get(self.url(endpoint));
 
         if let Some(token) = token {
             request = request.header("Authorization", format!("Bearer {}", token));
         }
 
-        let response = request.send().await?;
+        Ok(request.send().await?)
+    }
+
+    /// Send a POST request without interpreting the response
+    ///
+    /// Bodies larger than [`COMPRESSION_THRESHOLD_BYTES`] are gzip-compressed
+    /// with a `Content-Encoding: gzip` header when compression is enabled -
+    /// this matters most for batched message sync, where a history page can
+    /// run to hundreds of KB of JSON.
+    async fn send_post<B: Serialize>(
+        &self,
+        endpoint: &str,
+        body: &B,
+        token: Option<&str>,
+    ) -> AppResult<Response> {
+        let json_bytes = serde_json::to_vec(body)?;
+
+        let mut request = self
+            .client
+            .post(self.url(endpoint))
+            .header("Content-Type", "application/json");
+
+        request = if self.compression_enabled && json_bytes.len() > COMPRESSION_THRESHOLD_BYTES {
+            request
+                .header("Content-Encoding", "gzip")
+                .body(gzip_compress(&json_bytes)?)
+        } else {
+            request.body(json_bytes)
+        };
+
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        Ok(request.send().await?)
+    }
+
+    /// Make authenticated GET request
+    ///
+    /// On a 401, attempts a single transparent token refresh (if a session is
+    /// attached) and replays the request once before giving up.
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str, token: Option<&str>) -> AppResult<T> {
+        let response = self.send_get(endpoint, token).await?;
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let Some(refreshed_token) = self.try_refresh().await {
+                let retried = self.send_get(endpoint, Some(&refreshed_token)).await?;
+                return self.handle_response(retried).await;
+            }
+            return Err(AppError::SessionExpired);
+        }
+
         self.handle_response(response).await
     }
 
     /// Make authenticated POST request
+    ///
+    /// On a 401, attempts a single transparent token refresh (if a session is
+    /// attached) and replays the request once before giving up.
     async fn post<T: DeserializeOwned, B: Serialize>(
         &self,
         endpoint: &str,
         body: &B,
         token: Option<&str>,
     ) -> AppResult<T> {
-        let mut request = self.client.post(self.url(endpoint)).json(body);
+        let response = self.send_post(endpoint, body, token).await?;
 
-        if let Some(token) = token {
-            request = request.header("Authorization", format!("Bearer {}", token));
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let Some(refreshed_token) = self.try_refresh().await {
+                let retried = self.send_post(endpoint, body, Some(&refreshed_token)).await?;
+                return self.handle_response(retried).await;
+            }
+            return Err(AppError::SessionExpired);
         }
 
-        let response = request.send().await?;
         self.handle_response(response).await
     }
 
+    /// Attempt to refresh the access token using the stored refresh token
+    ///
+    /// On success, persists the new session via `db::save_session` and updates
+    /// the in-memory `UserSession`, returning the new access token. Returns
+    /// `None` if no session is attached, no refresh token is available, or the
+    /// refresh request itself fails - callers should treat that as a hard
+    /// `SessionExpired`.
+    async fn try_refresh(&self) -> Option<String> {
+        let session = self.session.as_ref()?;
+
+        let (user_id, refresh_token, db) = {
+            let state = session.read().await;
+            let current = state.session.as_ref()?;
+            (
+                current.user_id.clone(),
+                current.refresh_token.clone()?,
+                state.db.clone(),
+            )
+        };
+
+        #[derive(Serialize)]
+        struct RefreshRequest<'a> {
+            refresh_token: &'a str,
+        }
+
+        let response = self
+            .client
+            .post(self.url("/api/auth/refresh"))
+            .json(&RefreshRequest {
+                refresh_token: &refresh_token,
+            })
+            .send()
+            .await
+            .ok()?;
+
+        if !response.status().is_success() {
+            return None;
+        }
+
+        let refreshed: RefreshResponse = response.json().await.ok()?;
+
+        let state = session.read().await;
+        if let Err(e) = crate::db::save_session(
+            &db,
+            &user_id,
+            &refreshed.token,
+            refreshed.refresh_token.as_deref(),
+            None,
+            state.store_cipher.as_ref(),
+        )
+        .await
+        {
+            tracing::error!("Failed to persist refreshed session: {}", e);
+        }
+        drop(state);
+
+        let mut state = session.write().await;
+        if let Some(current) = state.session.as_mut() {
+            current.token = refreshed.token.clone();
+            if refreshed.refresh_token.is_some() {
+                current.refresh_token = refreshed.refresh_token.clone();
+            }
+        }
+
+        tracing::info!("Refreshed access token for user: {}", user_id);
+        Some(refreshed.token)
+    }
+
     /// Handle response and parse JSON
     async fn handle_response<T: DeserializeOwned>(&self, response: Response) -> AppResult<T> {
         let status = response.status();
@@ -79,6 +248,11 @@ impl ApiClient {
 
         if !status.is_success() {
             let error_text = response.text().await.unwrap_or_default();
+
+            if let Ok(body) = serde_json::from_str::<crate::error::ApiErrorBody>(&error_text) {
+                return Err(AppError::Api(body.into_error()));
+            }
+
             return Err(AppError::Auth(format!(
                 "API error ({}): {}",
                 status, error_text
@@ -90,59 +264,175 @@ impl ApiClient {
     }
 
     // ========================================================================
-    // Auth Endpoints
+    // OPAQUE Auth Endpoints
     // ========================================================================
-
-    /// Sign in with email and password
-    pub async fn signin(&self, email: &str, password: &str) -> AppResult<AuthResponse> {
+    //
+    // The wire shapes here are the [`crate::crypto::opaque`] types directly -
+    // see that module for what each step actually proves and why the raw
+    // password never appears in any of these requests.
+
+    /// Step 1 of OPAQUE registration: send the blinded password and get back
+    /// the server's OPRF evaluation to unblind locally.
+    pub async fn opaque_register_start(
+        &self,
+        email: &str,
+        username: &str,
+        request: &OprfRequest,
+    ) -> AppResult<OprfResponse> {
         #[derive(Serialize)]
-        struct SigninRequest<'a> {
+        struct OpaqueRegisterStartRequest<'a> {
             email: &'a str,
-            password: &'a str,
+            username: &'a str,
+            #[serde(flatten)]
+            request: &'a OprfRequest,
         }
 
-        self.post("/api/auth/signin", &SigninRequest { email, password }, None)
-            .await
+        self.post(
+            "/api/auth/opaque/register/start",
+            &OpaqueRegisterStartRequest { email, username, request },
+            None,
+        )
+        .await
     }
 
-    /// Sign up with email and password
-    pub async fn signup(
+    /// Step 2 of OPAQUE registration: send the sealed envelope for the server
+    /// to store. `invite_code` is required for invite-gated deployments; the
+    /// server rejects this with `ApiError::InviteOnly` if it's missing or
+    /// invalid.
+    pub async fn opaque_register_finish(
         &self,
         email: &str,
         username: &str,
-        password: &str,
+        invite_code: Option<&str>,
+        record: &RegistrationRecord,
     ) -> AppResult<AuthResponse> {
         #[derive(Serialize)]
-        struct SignupRequest<'a> {
+        struct OpaqueRegisterFinishRequest<'a> {
             email: &'a str,
             username: &'a str,
-            password: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            invite_code: Option<&'a str>,
+            #[serde(flatten)]
+            record: &'a RegistrationRecord,
         }
 
         self.post(
-            "/api/auth/signup",
-            &SignupRequest {
-                email,
-                username,
-                password,
-            },
+            "/api/auth/opaque/register/finish",
+            &OpaqueRegisterFinishRequest { email, username, invite_code, record },
             None,
         )
         .await
     }
 
-    /// Exchange OAuth code for token
+    /// Step 1 of OPAQUE login: send the blinded password and our ephemeral
+    /// Triple-DH public key, and get back the stored envelope, the server's
+    /// OPRF evaluation, and the server's long-term and ephemeral public keys.
+    pub async fn opaque_login_start(
+        &self,
+        email: &str,
+        client_ephemeral_public: &[u8],
+        request: &OprfRequest,
+    ) -> AppResult<CredentialResponse> {
+        #[derive(Serialize)]
+        struct OpaqueLoginStartRequest<'a> {
+            email: &'a str,
+            client_ephemeral_public: &'a [u8],
+            #[serde(flatten)]
+            request: &'a OprfRequest,
+        }
+
+        self.post(
+            "/api/auth/opaque/login/start",
+            &OpaqueLoginStartRequest { email, client_ephemeral_public, request },
+            None,
+        )
+        .await
+    }
+
+    /// Step 2 of OPAQUE login: present the client authentication tag the
+    /// server computed alongside ours in [`Self::opaque_login_start`]. The
+    /// server only issues a session once this tag checks out, which is
+    /// unforgeable without having derived the same randomized password.
+    pub async fn opaque_login_finish(&self, email: &str, client_mac: &[u8; 32]) -> AppResult<AuthResponse> {
+        #[derive(Serialize)]
+        struct OpaqueLoginFinishRequest<'a> {
+            email: &'a str,
+            client_mac: &'a [u8; 32],
+        }
+
+        self.post(
+            "/api/auth/opaque/login/finish",
+            &OpaqueLoginFinishRequest { email, client_mac },
+            None,
+        )
+        .await
+    }
+
+    /// Confirm a pending signup with the code sent to the user's email
+    pub async fn verify_email(&self, email: &str, code: &str) -> AppResult<AuthResponse> {
+        #[derive(Serialize)]
+        struct VerifyEmailRequest<'a> {
+            email: &'a str,
+            code: &'a str,
+        }
+
+        self.post(
+            "/api/auth/verify-email",
+            &VerifyEmailRequest { email, code },
+            None,
+        )
+        .await
+    }
+
+    /// Ask the server to re-send the pending signup's verification email
+    pub async fn resend_verification(&self, email: &str) -> AppResult<()> {
+        #[derive(Serialize)]
+        struct ResendVerificationRequest<'a> {
+            email: &'a str,
+        }
+
+        self.post(
+            "/api/auth/resend-verification",
+            &ResendVerificationRequest { email },
+            None,
+        )
+        .await
+    }
+
+    /// Redeem an invite code, completing a pending signup on invite-only
+    /// deployments
+    pub async fn redeem_invite(&self, email: &str, invite_code: &str) -> AppResult<AuthResponse> {
+        #[derive(Serialize)]
+        struct RedeemInviteRequest<'a> {
+            email: &'a str,
+            invite_code: &'a str,
+        }
+
+        self.post(
+            "/api/auth/invite/redeem",
+            &RedeemInviteRequest { email, invite_code },
+            None,
+        )
+        .await
+    }
+
+    /// Exchange an OAuth authorization code for a token
+    ///
+    /// `code_verifier` must match the `code_challenge` sent to `/api/auth/oauth/{provider}`
+    /// so the server can confirm this client initiated the authorization request (PKCE).
     pub async fn oauth_callback(
         &self,
         provider: &str,
         code: &str,
         state: &str,
+        code_verifier: &str,
     ) -> AppResult<AuthResponse> {
         #[derive(Serialize)]
         struct OAuthRequest<'a> {
             provider: &'a str,
             code: &'a str,
             state: &'a str,
+            code_verifier: &'a str,
         }
 
         self.post(
@@ -151,6 +441,7 @@ impl ApiClient {
                 provider,
                 code,
                 state,
+                code_verifier,
             },
             None,
         )
@@ -230,15 +521,23 @@ impl ApiClient {
     }
 
     /// Send a message to a conversation
+    ///
+    /// `message_type` is stored and echoed back on the `Message` as-is (see
+    /// `models::Message::message_type`) - e.g. `"text"` or
+    /// [`crate::models::SEALED_SENDER_MESSAGE_TYPE`] for a `content` that's
+    /// actually a sealed-sender envelope rather than a Double Ratchet
+    /// ciphertext directly.
     pub async fn send_message(
         &self,
         conversation_id: &str,
         content: &str,
+        message_type: &str,
         token: &str,
     ) -> AppResult<Message> {
         #[derive(Serialize)]
         struct SendMessageRequest<'a> {
             content: &'a str,
+            message_type: &'a str,
             encrypted: bool,
         }
 
@@ -246,10 +545,141 @@ impl ApiClient {
             &format!("/api/conversations/{}/messages", conversation_id),
             &SendMessageRequest {
                 content,
+                message_type,
                 encrypted: true,
             },
             Some(token),
         )
         .await
     }
+
+    /// Send a batch of read receipts for `conversation_id` in one request -
+    /// coalesced by `crate::receipts`'s debounce flusher so marking several
+    /// messages read in quick succession costs one API call, not one per
+    /// message.
+    pub async fn send_read_receipts(
+        &self,
+        conversation_id: &str,
+        message_ids: &[String],
+        token: &str,
+    ) -> AppResult<()> {
+        #[derive(Serialize)]
+        struct SendReceiptsRequest<'a> {
+            message_ids: &'a [String],
+        }
+
+        self.post(
+            &format!("/api/conversations/{}/receipts", conversation_id),
+            &SendReceiptsRequest { message_ids },
+            Some(token),
+        )
+        .await
+    }
+
+    // ========================================================================
+    // Device Endpoints
+    // ========================================================================
+
+    /// Register a new device for the current user
+    pub async fn register_device(
+        &self,
+        device_type: &str,
+        name: Option<&str>,
+        token: &str,
+    ) -> AppResult<Device> {
+        #[derive(Serialize)]
+        struct RegisterDeviceRequest<'a> {
+            device_type: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            name: Option<&'a str>,
+        }
+
+        self.post(
+            "/api/devices",
+            &RegisterDeviceRequest { device_type, name },
+            Some(token),
+        )
+        .await
+    }
+
+    /// List devices registered for the current user
+    pub async fn list_devices(&self, token: &str) -> AppResult<Vec<Device>> {
+        self.get("/api/devices", Some(token)).await
+    }
+
+    /// Revoke a device, ending its sessions
+    pub async fn revoke_device(&self, device_id: &str, token: &str) -> AppResult<()> {
+        #[derive(Serialize)]
+        struct Empty {}
+
+        self.post(
+            &format!("/api/devices/{}/revoke", device_id),
+            &Empty {},
+            Some(token),
+        )
+        .await
+    }
+
+    /// Upload a key bundle (identity key + one-time prekeys) for a device
+    pub async fn upload_prekeys(
+        &self,
+        device_id: &str,
+        payload: &KeyPayload,
+        token: &str,
+    ) -> AppResult<()> {
+        self.post(
+            &format!("/api/devices/{}/keys", device_id),
+            payload,
+            Some(token),
+        )
+        .await
+    }
+
+    // ========================================================================
+    // Push Notification Endpoints
+    // ========================================================================
+
+    /// Register this device's OS-level push/notification channel with the
+    /// server so it can deliver messages while the app isn't polling
+    pub async fn register_push_token(
+        &self,
+        endpoint: &str,
+        keys: &PushKeys,
+        token: &str,
+    ) -> AppResult<()> {
+        #[derive(Serialize)]
+        struct RegisterPushRequest<'a> {
+            endpoint: &'a str,
+            keys: &'a PushKeys,
+        }
+
+        self.post(
+            "/api/push/register",
+            &RegisterPushRequest { endpoint, keys },
+            Some(token),
+        )
+        .await
+    }
+
+    /// Unregister this device's push subscription from the server
+    pub async fn unregister_push_token(&self, endpoint: &str, token: &str) -> AppResult<()> {
+        #[derive(Serialize)]
+        struct UnregisterPushRequest<'a> {
+            endpoint: &'a str,
+        }
+
+        self.post(
+            "/api/push/unregister",
+            &UnregisterPushRequest { endpoint },
+            Some(token),
+        )
+        .await
+    }
+}
+
+/// Gzip-compress a byte buffer for use as a request body
+fn gzip_compress(data: &[u8]) -> AppResult<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
 }
@@ -4,12 +4,183 @@ use tauri::State;
 use tauri_plugin_opener::OpenerExt;
 
 use crate::api::ApiClient;
+use crate::crypto::{opaque, Curve25519KeyPair, CryptoService};
 use crate::db;
-use crate::error::AppError;
-use crate::models::{AuthResponse, OAuthProvider, OAuthUrlResponse, UserInfo};
+use crate::error::{ApiError, AppError, AppResult};
+use crate::models::{
+    AuthResponse, KeyEntry, KeyPayload, OAuthProvider, OAuthUrlResponse, PushKeys, UserInfo,
+};
 use crate::state::{OAuthState, SharedState, UserSession};
 
+/// Register this device (if not already registered) and publish its current
+/// one-time prekey bundle. Called after a successful login/OAuth exchange so
+/// other users can start an encrypted session with this device.
+///
+/// Best-effort: failures are logged but never surface to the caller, since a
+/// user should still be able to use the app offline or if device
+/// registration is temporarily unavailable.
+async fn register_device_and_prekeys(state: &SharedState, token: &str) {
+    let (api_url, db) = {
+        let app_state = state.read().await;
+        (app_state.api_url.clone(), app_state.db.clone())
+    };
+
+    let crypto = match CryptoService::initialize(db).await {
+        Ok(crypto) => crypto,
+        Err(e) => {
+            tracing::warn!("Failed to initialize crypto service for device registration: {}", e);
+            return;
+        }
+    };
+
+    let api_client = ApiClient::with_session(&api_url, state.clone());
+
+    let device = match api_client
+        .register_device("desktop", hostname().as_deref(), token)
+        .await
+    {
+        Ok(device) => device,
+        Err(e) => {
+            tracing::warn!("Failed to register device: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = publish_prekeys(&api_client, &crypto, &device.device_id, token).await {
+        tracing::warn!("Failed to publish prekey bundle: {}", e);
+    }
+}
+
+/// Publish the current one-time prekey bundle for a device, replenishing
+/// keys first if the server reports none are available.
+async fn publish_prekeys(
+    api_client: &ApiClient,
+    crypto: &CryptoService,
+    device_id: &str,
+    token: &str,
+) -> Result<(), AppError> {
+    let payload = build_key_payload(crypto).await;
+
+    match api_client.upload_prekeys(device_id, &payload, token).await {
+        Ok(()) => {
+            crypto.mark_keys_as_published().await.map_err(|e| {
+                AppError::Internal(format!("Failed to mark keys as published: {}", e))
+            })?;
+            Ok(())
+        }
+        Err(AppError::Api(ApiError::NoPrekeysAvailable)) => {
+            crypto
+                .generate_one_time_keys(100)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to generate prekeys: {}", e)))?;
+            let payload = build_key_payload(crypto).await;
+            api_client.upload_prekeys(device_id, &payload, token).await?;
+            crypto.mark_keys_as_published().await.map_err(|e| {
+                AppError::Internal(format!("Failed to mark keys as published: {}", e))
+            })?;
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn build_key_payload(crypto: &CryptoService) -> KeyPayload {
+    use base64::Engine;
+
+    let identity_key = base64::engine::general_purpose::STANDARD.encode(crypto.identity_key().await);
+    let one_time_prekeys = crypto
+        .get_one_time_keys()
+        .await
+        .into_iter()
+        .map(|(key_id, key)| KeyEntry {
+            key_id,
+            public_key: base64::engine::general_purpose::STANDARD.encode(key),
+        })
+        .collect();
+
+    KeyPayload {
+        identity_key,
+        one_time_prekeys,
+    }
+}
+
+/// Best-effort local hostname to use as a human-readable device name
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+}
+
+/// Generate a PKCE code verifier: 32 CSPRNG bytes, base64url-encoded without
+/// padding, giving 43 characters drawn entirely from the unreserved alphabet
+/// required by RFC 7636 (43-128 chars).
+fn generate_pkce_verifier() -> String {
+    use base64::Engine;
+    use rand::RngCore;
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Derive the PKCE code challenge from a verifier: `base64url_nopad(sha256(verifier))`
+fn pkce_challenge(code_verifier: &str) -> String {
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+
+    let hash = Sha256::digest(code_verifier.as_bytes());
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash)
+}
+
+/// Run the OPAQUE login protocol against `api_client`: blind `password`
+/// locally, let the server evaluate the OPRF and hand back the stored
+/// envelope, unseal the envelope and run Triple-DH to derive a client
+/// authentication tag, then present that tag for the server to check before
+/// it issues a session. The raw password never leaves this function.
+async fn opaque_login(api_client: &ApiClient, email: &str, password: &str) -> AppResult<AuthResponse> {
+    let (blind, request) = opaque::blind_password(password.as_bytes());
+    let client_ephemeral = Curve25519KeyPair::generate();
+
+    let credential_response = api_client
+        .opaque_login_start(email, &client_ephemeral.public_key_bytes(), &request)
+        .await?;
+
+    let (login_result, _client_identity) =
+        opaque::client_finish_login(&blind, &credential_response, &client_ephemeral)
+            .map_err(|e| AppError::Auth(format!("OPAQUE login failed: {}", e)))?;
+
+    api_client.opaque_login_finish(email, &login_result.client_mac).await
+}
+
+/// Run the OPAQUE registration protocol against `api_client`: blind
+/// `password` locally, unblind the server's OPRF evaluation to derive the
+/// envelope key, seal a fresh long-term identity key pair into an envelope,
+/// and send the envelope for the server to store. The raw password never
+/// leaves this function.
+async fn opaque_signup(
+    api_client: &ApiClient,
+    email: &str,
+    username: &str,
+    password: &str,
+    invite_code: Option<&str>,
+) -> AppResult<AuthResponse> {
+    let (blind, request) = opaque::blind_password(password.as_bytes());
+    let oprf_response = api_client.opaque_register_start(email, username, &request).await?;
+
+    let client_identity = Curve25519KeyPair::generate();
+    let (record, _export_key) = opaque::finalize_registration(&blind, &oprf_response, &client_identity)
+        .map_err(|e| AppError::Auth(format!("OPAQUE registration failed: {}", e)))?;
+
+    api_client
+        .opaque_register_finish(email, username, invite_code, &record)
+        .await
+}
+
 /// Sign in with email and password
+///
+/// Runs the OPAQUE protocol ([`opaque_login`]) rather than posting the
+/// password directly - the server authenticates us without ever seeing it or
+/// storing anything password-equivalent. See [`crate::crypto::opaque`].
 #[tauri::command]
 pub async fn login(
     state: State<'_, SharedState>,
@@ -20,7 +191,7 @@ pub async fn login(
     let api_client = ApiClient::new(&app_state.api_url);
     drop(app_state);
 
-    match api_client.signin(&email, &password).await {
+    match opaque_login(&api_client, &email, &password).await {
         Ok(response) => {
             if response.success {
                 if let (Some(user), Some(token)) = (&response.user, &response.token) {
@@ -33,6 +204,7 @@ pub async fn login(
                         token,
                         response.refresh_token.as_deref(),
                         None,
+                        app_state.store_cipher.as_ref(),
                     )
                     .await
                     {
@@ -48,6 +220,7 @@ pub async fn login(
                         user.display_name.as_deref(),
                         user.avatar_url.as_deref(),
                         user.is_anonymous,
+                        app_state.store_cipher.as_ref(),
                     )
                     .await
                     {
@@ -64,6 +237,9 @@ pub async fn login(
                         expires_at: None,
                     });
 
+                    drop(app_state);
+                    register_device_and_prekeys(state.inner(), token).await;
+
                     tracing::info!("User logged in: {}", user.id);
                 }
             }
@@ -74,7 +250,9 @@ pub async fn login(
             user: None,
             token: None,
             refresh_token: None,
+            error_code: e.api_code().map(str::to_string),
             error: Some(e.to_string()),
+            pending_verification: false,
         }),
     }
 }
@@ -82,6 +260,8 @@ pub async fn login(
 /// Sign out current user
 #[tauri::command]
 pub async fn logout(state: State<'_, SharedState>) -> Result<(), String> {
+    unregister_push_subscription(&state).await;
+
     let mut app_state = state.write().await;
 
     // Clear sessions from database
@@ -96,6 +276,76 @@ pub async fn logout(state: State<'_, SharedState>) -> Result<(), String> {
     Ok(())
 }
 
+/// Register this device's OS-level push/notification channel with the
+/// backend, so it can deliver messages while the main window isn't polling
+#[tauri::command]
+pub async fn register_device_push(
+    state: State<'_, SharedState>,
+    endpoint: String,
+    p256dh: String,
+    auth: String,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+    let token = app_state
+        .token()
+        .map(str::to_string)
+        .ok_or_else(|| AppError::NotAuthenticated.to_string())?;
+    let api_client = ApiClient::with_session(&app_state.api_url, state.inner().clone());
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    let keys = PushKeys { p256dh, auth };
+    api_client
+        .register_push_token(&endpoint, &keys, &token)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Err(e) = db::save_push_subscription(&db, &endpoint).await {
+        tracing::error!("Failed to persist push subscription: {}", e);
+    }
+
+    tracing::info!("Registered push subscription for this device");
+    Ok(())
+}
+
+/// Unregister this device's push subscription from the backend
+#[tauri::command]
+pub async fn unregister_push(state: State<'_, SharedState>) -> Result<(), String> {
+    unregister_push_subscription(&state).await;
+    Ok(())
+}
+
+/// Best-effort push unsubscribe, shared by `unregister_push` and `logout`.
+/// Requires an authenticated session; a no-op if there's no active
+/// subscription to clear.
+async fn unregister_push_subscription(state: &SharedState) {
+    let app_state = state.read().await;
+    let token = app_state.token().map(str::to_string);
+    let api_url = app_state.api_url.clone();
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    let Some(token) = token else { return };
+
+    let endpoint = match db::get_push_subscription(&db).await {
+        Ok(Some(endpoint)) => endpoint,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::error!("Failed to read push subscription: {}", e);
+            return;
+        }
+    };
+
+    let api_client = ApiClient::new(&api_url);
+    if let Err(e) = api_client.unregister_push_token(&endpoint, &token).await {
+        tracing::warn!("Failed to unregister push subscription: {}", e);
+    }
+
+    if let Err(e) = db::clear_push_subscription(&db).await {
+        tracing::error!("Failed to clear push subscription: {}", e);
+    }
+}
+
 /// Get current authenticated user
 #[tauri::command]
 pub async fn get_current_user(state: State<'_, SharedState>) -> Result<Option<UserInfo>, String> {
@@ -103,7 +353,7 @@ pub async fn get_current_user(state: State<'_, SharedState>) -> Result<Option<Us
 
     if let Some(session) = &app_state.session {
         // Fetch fresh user data from API
-        let api_client = ApiClient::new(&app_state.api_url);
+        let api_client = ApiClient::with_session(&app_state.api_url, state.inner().clone());
         match api_client.get_current_user(&session.token).await {
             Ok(user) => Ok(Some(user)),
             Err(AppError::SessionExpired) => {
@@ -149,12 +399,18 @@ pub async fn start_oauth(
     // Generate random state for CSRF protection
     let oauth_state = uuid::Uuid::new_v4().to_string();
 
+    // PKCE: bind this authorization request to a high-entropy verifier so an
+    // intercepted authorization code can't be redeemed by anyone else.
+    let code_verifier = generate_pkce_verifier();
+    let code_challenge = pkce_challenge(&code_verifier);
+
     // Build OAuth URL - use desktop=true to tell server to redirect to nochat:// scheme
     let app_state = state.read().await;
     let auth_url = format!(
-        "{}/api/auth/oauth/{}?desktop=true",
+        "{}/api/auth/oauth/{}?desktop=true&code_challenge={}&code_challenge_method=S256",
         app_state.api_url,
         oauth_provider,
+        code_challenge,
     );
     drop(app_state);
 
@@ -164,6 +420,7 @@ pub async fn start_oauth(
         state: oauth_state.clone(),
         provider: provider.clone(),
         created_at: chrono::Utc::now(),
+        code_verifier,
     });
     drop(app_state);
 
@@ -181,75 +438,289 @@ pub async fn start_oauth(
     })
 }
 
-/// Handle OAuth callback with token from deep link
-/// The backend has already exchanged the code for a token and redirected to nochat://auth/callback?token=...
+/// Handle OAuth callback with an authorization code from the deep link
+///
+/// Exchanges the code for a token ourselves (rather than trusting a
+/// pre-exchanged token from the redirect), binding the exchange to the PKCE
+/// `code_verifier` generated in `start_oauth` so an intercepted `nochat://`
+/// redirect can't be replayed by another process on the machine.
 #[tauri::command]
 pub async fn handle_oauth_callback(
     state: State<'_, SharedState>,
-    token: String,
+    code: String,
+    oauth_state: String,
 ) -> Result<AuthResponse, String> {
-    let app_state = state.read().await;
+    let mut app_state = state.write().await;
+    let pending = app_state.validate_oauth_state(&oauth_state);
     let api_url = app_state.api_url.clone();
     let db = app_state.db.clone();
     drop(app_state);
 
-    // Validate token by fetching user info
-    let api_client = ApiClient::new(&api_url);
-    match api_client.get_current_user(&token).await {
-        Ok(user) => {
-            let mut app_state = state.write().await;
+    let pending = match pending {
+        Some(pending) => pending,
+        None => {
+            tracing::warn!("OAuth callback with unknown or expired state");
+            return Ok(AuthResponse {
+                success: false,
+                user: None,
+                token: None,
+                refresh_token: None,
+                error_code: Some("invalid_oauth_state".to_string()),
+                error: Some(AppError::InvalidOAuthState.to_string()),
+                pending_verification: false,
+            });
+        }
+    };
 
-            // Save session to database
-            if let Err(e) = db::save_session(&db, &user.id, &token, None, None).await {
-                tracing::error!("Failed to save session: {}", e);
+    let api_client = ApiClient::new(&api_url);
+    match api_client
+        .oauth_callback(&pending.provider, &code, &oauth_state, &pending.code_verifier)
+        .await
+    {
+        Ok(response) => {
+            if response.success {
+                if let (Some(user), Some(token)) = (&response.user, &response.token) {
+                    establish_session(&state, &db, user, token, response.refresh_token.as_deref())
+                        .await;
+                    register_device_and_prekeys(state.inner(), token).await;
+                    tracing::info!("User logged in via OAuth: {}", user.id);
+                }
             }
+            Ok(response)
+        }
+        Err(e) => {
+            tracing::error!("OAuth callback failed: {}", e);
+            Ok(AuthResponse {
+                success: false,
+                user: None,
+                token: None,
+                refresh_token: None,
+                error_code: e.api_code().map(str::to_string),
+                error: Some(e.to_string()),
+                pending_verification: false,
+            })
+        }
+    }
+}
 
-            // Save user to cache
-            if let Err(e) = db::upsert_user(
-                &db,
-                &user.id,
-                user.email.as_deref(),
-                user.username.as_deref(),
-                user.display_name.as_deref(),
-                user.avatar_url.as_deref(),
-                user.is_anonymous,
-            )
-            .await
-            {
-                tracing::error!("Failed to cache user: {}", e);
-            }
+/// Sign up with email, username and password
+///
+/// Runs the OPAQUE protocol ([`opaque_signup`]) rather than posting the
+/// password directly - see [`crate::crypto::opaque`]. On invite-gated or
+/// email-verified deployments the server defers issuing a session -
+/// `response.pending_verification` is `true` and `token` is `None` in that
+/// case, and the pending signup's email is persisted so `restore_session`
+/// can pick the flow back up after an app restart.
+#[tauri::command]
+pub async fn signup(
+    state: State<'_, SharedState>,
+    email: String,
+    username: String,
+    password: String,
+    invite_code: Option<String>,
+) -> Result<AuthResponse, String> {
+    let app_state = state.read().await;
+    let api_client = ApiClient::new(&app_state.api_url);
+    let db = app_state.db.clone();
+    drop(app_state);
 
-            // Set session in memory
-            app_state.set_session(UserSession {
-                user_id: user.id.clone(),
-                token: token.clone(),
-                refresh_token: None,
-                email: user.email.clone(),
-                username: user.username.clone(),
-                expires_at: None,
-            });
+    match opaque_signup(&api_client, &email, &username, &password, invite_code.as_deref()).await {
+        Ok(response) => {
+            if response.success {
+                if response.pending_verification {
+                    if let Err(e) = db::save_pending_verification(&db, &email).await {
+                        tracing::error!("Failed to persist pending verification: {}", e);
+                    }
+                    tracing::info!("Signup pending verification for: {}", email);
+                } else if let (Some(user), Some(token)) = (&response.user, &response.token) {
+                    establish_session(&state, &db, user, token, response.refresh_token.as_deref())
+                        .await;
+                    register_device_and_prekeys(state.inner(), token).await;
+                    tracing::info!("User signed up: {}", user.id);
+                }
+            }
+            Ok(response)
+        }
+        Err(e) => Ok(AuthResponse {
+            success: false,
+            user: None,
+            token: None,
+            refresh_token: None,
+            error_code: e.api_code().map(str::to_string),
+            error: Some(e.to_string()),
+            pending_verification: false,
+        }),
+    }
+}
 
-            tracing::info!("User logged in via OAuth: {}", user.id);
+/// Confirm a pending signup with the verification code sent by email
+#[tauri::command]
+pub async fn verify_email(
+    state: State<'_, SharedState>,
+    code: String,
+) -> Result<AuthResponse, String> {
+    let app_state = state.read().await;
+    let api_client = ApiClient::new(&app_state.api_url);
+    let db = app_state.db.clone();
+    drop(app_state);
 
-            Ok(AuthResponse {
-                success: true,
-                user: Some(user),
-                token: Some(token),
+    let email = match db::get_pending_verification(&db).await {
+        Ok(Some(email)) => email,
+        Ok(None) => {
+            return Ok(AuthResponse {
+                success: false,
+                user: None,
+                token: None,
                 refresh_token: None,
-                error: None,
+                error_code: None,
+                error: Some("No pending signup to verify".to_string()),
+                pending_verification: false,
             })
         }
-        Err(e) => {
-            tracing::error!("OAuth callback failed: {}", e);
-            Ok(AuthResponse {
+        Err(e) => return Err(e.to_string()),
+    };
+
+    match api_client.verify_email(&email, &code).await {
+        Ok(response) => {
+            if response.success && !response.pending_verification {
+                if let (Some(user), Some(token)) = (&response.user, &response.token) {
+                    establish_session(&state, &db, user, token, response.refresh_token.as_deref())
+                        .await;
+                    if let Err(e) = db::clear_pending_verification(&db).await {
+                        tracing::error!("Failed to clear pending verification: {}", e);
+                    }
+                    register_device_and_prekeys(state.inner(), token).await;
+                    tracing::info!("Email verified for user: {}", user.id);
+                }
+            }
+            Ok(response)
+        }
+        Err(e) => Ok(AuthResponse {
+            success: false,
+            user: None,
+            token: None,
+            refresh_token: None,
+            error_code: e.api_code().map(str::to_string),
+            error: Some(e.to_string()),
+            pending_verification: true,
+        }),
+    }
+}
+
+/// Re-send the verification email for a pending signup
+#[tauri::command]
+pub async fn resend_verification(state: State<'_, SharedState>) -> Result<(), String> {
+    let app_state = state.read().await;
+    let api_client = ApiClient::new(&app_state.api_url);
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    let email = db::get_pending_verification(&db)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "No pending signup to resend verification for".to_string())?;
+
+    api_client
+        .resend_verification(&email)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Redeem an invite code, completing a pending signup on invite-only
+/// deployments
+#[tauri::command]
+pub async fn submit_invite(
+    state: State<'_, SharedState>,
+    invite_code: String,
+) -> Result<AuthResponse, String> {
+    let app_state = state.read().await;
+    let api_client = ApiClient::new(&app_state.api_url);
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    let email = match db::get_pending_verification(&db).await {
+        Ok(Some(email)) => email,
+        Ok(None) => {
+            return Ok(AuthResponse {
                 success: false,
                 user: None,
                 token: None,
                 refresh_token: None,
-                error: Some(e.to_string()),
+                error_code: None,
+                error: Some("No pending signup to redeem an invite for".to_string()),
+                pending_verification: false,
             })
         }
+        Err(e) => return Err(e.to_string()),
+    };
+
+    match api_client.redeem_invite(&email, &invite_code).await {
+        Ok(response) => {
+            if response.success && !response.pending_verification {
+                if let (Some(user), Some(token)) = (&response.user, &response.token) {
+                    establish_session(&state, &db, user, token, response.refresh_token.as_deref())
+                        .await;
+                    if let Err(e) = db::clear_pending_verification(&db).await {
+                        tracing::error!("Failed to clear pending verification: {}", e);
+                    }
+                    register_device_and_prekeys(state.inner(), token).await;
+                    tracing::info!("Invite redeemed for user: {}", user.id);
+                }
+            }
+            Ok(response)
+        }
+        Err(e) => Ok(AuthResponse {
+            success: false,
+            user: None,
+            token: None,
+            refresh_token: None,
+            error_code: e.api_code().map(str::to_string),
+            error: Some(e.to_string()),
+            pending_verification: true,
+        }),
+    }
+}
+
+/// Save a session to the database and in-memory state
+async fn establish_session(
+    state: &SharedState,
+    db: &sqlx::SqlitePool,
+    user: &UserInfo,
+    token: &str,
+    refresh_token: Option<&str>,
+) {
+    let app_state = state.read().await;
+    let cipher = app_state.store_cipher.as_ref();
+
+    if let Err(e) = db::save_session(db, &user.id, token, refresh_token, None, cipher).await {
+        tracing::error!("Failed to save session: {}", e);
     }
+
+    if let Err(e) = db::upsert_user(
+        db,
+        &user.id,
+        user.email.as_deref(),
+        user.username.as_deref(),
+        user.display_name.as_deref(),
+        user.avatar_url.as_deref(),
+        user.is_anonymous,
+        cipher,
+    )
+    .await
+    {
+        tracing::error!("Failed to cache user: {}", e);
+    }
+    drop(app_state);
+
+    let mut app_state = state.write().await;
+    app_state.set_session(UserSession {
+        user_id: user.id.clone(),
+        token: token.to_string(),
+        refresh_token: refresh_token.map(|s| s.to_string()),
+        email: user.email.clone(),
+        username: user.username.clone(),
+        expires_at: None,
+    });
 }
 
 /// Restore session from database on app startup
@@ -258,21 +729,43 @@ pub async fn restore_session(state: State<'_, SharedState>) -> Result<Option<Use
     let app_state = state.read().await;
     let db = app_state.db.clone();
     let api_url = app_state.api_url.clone();
+    let active_session = db::get_active_session(&app_state.db, app_state.store_cipher.as_ref()).await;
     drop(app_state);
 
     // Check for existing session in database
-    match db::get_active_session(&db).await {
+    match active_session {
         Ok(Some((_session_id, user_id, token, refresh_token))) => {
+            // Restore the session in memory first so the refresh subsystem has a
+            // refresh token to work with if the access token has already expired.
+            {
+                let mut app_state = state.write().await;
+                app_state.set_session(UserSession {
+                    user_id: user_id.clone(),
+                    token: token.clone(),
+                    refresh_token: refresh_token.clone(),
+                    email: None,
+                    username: None,
+                    expires_at: None,
+                });
+            }
+
             // Try to validate the token
-            let api_client = ApiClient::new(&api_url);
+            let api_client = ApiClient::with_session(&api_url, state.inner().clone());
             match api_client.get_current_user(&token).await {
                 Ok(user) => {
-                    // Session is valid, restore it
+                    // Session is valid - fill in the user details. Re-read the
+                    // token/refresh_token rather than the captured locals, since
+                    // the call above may have transparently refreshed them.
                     let mut app_state = state.write().await;
+                    let (current_token, current_refresh) = app_state
+                        .session
+                        .as_ref()
+                        .map(|s| (s.token.clone(), s.refresh_token.clone()))
+                        .unwrap_or((token, refresh_token));
                     app_state.set_session(UserSession {
                         user_id: user.id.clone(),
-                        token,
-                        refresh_token,
+                        token: current_token,
+                        refresh_token: current_refresh,
                         email: user.email.clone(),
                         username: user.username.clone(),
                         expires_at: None,
@@ -318,6 +811,23 @@ pub async fn restore_session(state: State<'_, SharedState>) -> Result<Option<Use
     }
 }
 
+/// Resume a half-finished signup on app startup
+///
+/// Returns the email awaiting verification/invite redemption, if any, so the
+/// frontend can route back into the pending-verification screen instead of
+/// the login screen. Checked alongside `restore_session` since a pending
+/// signup has no session to restore.
+#[tauri::command]
+pub async fn get_pending_verification_email(
+    state: State<'_, SharedState>,
+) -> Result<Option<String>, String> {
+    let app_state = state.read().await;
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    db::get_pending_verification(&db).await.map_err(|e| e.to_string())
+}
+
 /// Get pending OAuth deep links that arrived before the frontend was ready.
 /// This handles the race condition where deep links arrive before React mounts.
 #[tauri::command]
@@ -0,0 +1,193 @@
+//! Key re-request ("gossip") command handlers
+//!
+//! Exposes [`crate::crypto::key_requests`] over IPC: `request_missing_key`
+//! lets the frontend enqueue a request when `decrypt_message` (or group
+//! decryption) fails for lack of a known session or sender key, and
+//! `incoming_key_request` answers a peer's request by re-sharing the
+//! session we hold, sealed under our existing pairwise channel with them
+//! exactly like any other message. The frontend owns actually transmitting
+//! the request/response between devices, the same way `export_linked_device`
+//! hands back an envelope for the frontend to deliver.
+
+use base64::Engine;
+use tauri::State;
+
+use crate::crypto::sessions::{generate_pickle_key, SessionStore};
+use crate::crypto::{CryptoService, InboundGroupSession, KeyRequest, KeyRequestStore};
+use crate::state::SharedState;
+
+/// Enqueue a request for the key material needed to decrypt
+/// `session_id`/`sender_key` in `conversation_id`, called once decryption
+/// has failed for lack of it. De-duplicated against any already-outstanding
+/// request for the same key from `requesting_device_id` (see
+/// [`crate::crypto::KeyRequestStore::save_key_request`]); the frontend is
+/// responsible for transmitting the returned request to the user's other
+/// devices (or the original sender) and calling [`mark_key_request_sent`]
+/// once it has.
+#[tauri::command]
+pub async fn request_missing_key(
+    state: State<'_, SharedState>,
+    conversation_id: String,
+    session_id: String,
+    sender_key: String,
+    requesting_device_id: String,
+) -> Result<KeyRequest, String> {
+    let app_state = state.read().await;
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    let request = KeyRequestStore::new(db)
+        .save_key_request(&conversation_id, &session_id, &sender_key, &requesting_device_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!(
+        "Requested missing key for session {} in conversation {}",
+        session_id, conversation_id
+    );
+    Ok(request)
+}
+
+/// Mark a previously enqueued request as sent, once the frontend has
+/// actually transmitted it to its targets.
+#[tauri::command]
+pub async fn mark_key_request_sent(
+    state: State<'_, SharedState>,
+    request_id: String,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    KeyRequestStore::new(db)
+        .mark_request_sent(&request_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Give up on a request that no longer needs an answer.
+#[tauri::command]
+pub async fn cancel_key_request(
+    state: State<'_, SharedState>,
+    request_id: String,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    KeyRequestStore::new(db)
+        .mark_request_cancelled(&request_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List the key requests we've made, for the frontend to show pending
+/// recovery state or retry delivery.
+#[tauri::command]
+pub async fn get_outgoing_key_requests(state: State<'_, SharedState>) -> Result<Vec<KeyRequest>, String> {
+    let app_state = state.read().await;
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    KeyRequestStore::new(db)
+        .get_outgoing_requests()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Answer an incoming key request from `requester_peer_id`: if we hold the
+/// inbound group session they're missing, re-export its pickled state
+/// sealed under our existing pairwise session with them (so only they can
+/// read it) for them to unpickle and import. Returns `None` if we don't
+/// have that session either.
+#[tauri::command]
+pub async fn incoming_key_request(
+    state: State<'_, SharedState>,
+    requester_peer_id: String,
+    conversation_id: String,
+    session_id: String,
+    sender_key: String,
+) -> Result<Option<String>, String> {
+    let app_state = state.read().await;
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    // Group sessions are stored under their own pickle key, the same way
+    // `CryptoService::initialize` derives one for pairwise sessions and the
+    // account.
+    let session_store = SessionStore::new(db.clone(), generate_pickle_key());
+    let session = session_store
+        .load_inbound_group_session(&conversation_id, &session_id, &sender_key)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let session = match session {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+
+    let pickled = session.pickle().map_err(|e| e.to_string())?;
+
+    let crypto = CryptoService::initialize(db).await.map_err(|e| e.to_string())?;
+    let sealed = crypto
+        .encrypt(&requester_peer_id, pickled.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!(
+        "Answered key request from {} for session {} in conversation {}",
+        requester_peer_id, session_id, conversation_id
+    );
+    Ok(Some(base64::engine::general_purpose::STANDARD.encode(&sealed)))
+}
+
+/// Import key material received in answer to one of our own
+/// [`request_missing_key`] requests: unseal `sealed_session` (as produced by
+/// [`incoming_key_request`]) over our pairwise session with `responder_peer_id`,
+/// store the recovered session, and mark any outstanding requests for this
+/// key fulfilled so it isn't asked for again.
+#[tauri::command]
+pub async fn import_requested_key(
+    state: State<'_, SharedState>,
+    responder_peer_id: String,
+    conversation_id: String,
+    sender_key: String,
+    sealed_session: String,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    let sealed_bytes = base64::engine::general_purpose::STANDARD
+        .decode(&sealed_session)
+        .map_err(|e| e.to_string())?;
+
+    let crypto = CryptoService::initialize(db.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    let pickled = crypto
+        .decrypt(&responder_peer_id, None, &sealed_bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+    let pickled = String::from_utf8(pickled).map_err(|e| e.to_string())?;
+
+    let session = InboundGroupSession::unpickle(&pickled).map_err(|e| e.to_string())?;
+    let session_id = session.session_id();
+
+    let session_store = SessionStore::new(db.clone(), generate_pickle_key());
+    session_store
+        .save_inbound_group_session(&conversation_id, &sender_key, &session)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    KeyRequestStore::new(db)
+        .cancel_requests_for_key(&conversation_id, &session_id, &sender_key)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!(
+        "Imported requested key for session {} in conversation {}",
+        session_id, conversation_id
+    );
+    Ok(())
+}
@@ -1,11 +1,13 @@
 //! Messaging command handlers
 
-use tauri::State;
+use tauri::{Emitter, State};
 
 use crate::api::ApiClient;
+use crate::crypto;
+use crate::crypto::CryptoError;
 use crate::db;
 use crate::error::AppError;
-use crate::models::{Conversation, Message};
+use crate::models::{Conversation, Message, UnreadCount, VerificationStatus};
 use crate::state::SharedState;
 
 /// Get user's conversations
@@ -24,7 +26,7 @@ pub async fn get_conversations(
     let session = app_state.session.as_ref().ok_or("Not authenticated")?;
 
     // Try to fetch from API first
-    let api_client = ApiClient::new(&app_state.api_url);
+    let api_client = ApiClient::with_session(&app_state.api_url, state.inner().clone());
     match api_client.get_conversations(&session.token).await {
         Ok(conversations) => {
             // Cache conversations locally
@@ -63,7 +65,7 @@ pub async fn get_messages(
     let session = app_state.session.as_ref().ok_or("Not authenticated")?;
 
     // Try to fetch from API first
-    let api_client = ApiClient::new(&app_state.api_url);
+    let api_client = ApiClient::with_session(&app_state.api_url, state.inner().clone());
     match api_client
         .get_messages(&conversation_id, limit, offset, &session.token)
         .await
@@ -71,7 +73,7 @@ pub async fn get_messages(
         Ok(messages) => {
             // Cache messages locally
             for msg in &messages {
-                if let Err(e) = db::save_message(&app_state.db, msg).await {
+                if let Err(e) = db::save_message(&app_state.db, msg, None).await {
                     tracing::warn!("Failed to cache message: {}", e);
                 }
             }
@@ -81,7 +83,7 @@ pub async fn get_messages(
         Err(e) => {
             // Fall back to cached data
             tracing::warn!("Failed to fetch messages from API: {}", e);
-            db::get_messages(&app_state.db, &conversation_id, limit, offset)
+            db::get_messages(&app_state.db, &conversation_id, limit, offset, None)
                 .await
                 .map_err(|e| e.to_string())
         }
@@ -89,48 +91,76 @@ pub async fn get_messages(
 }
 
 /// Send a message to a conversation
+///
+/// If the API call fails with anything other than `AppError::SessionExpired`
+/// (e.g. the network is down), the message is queued in the offline outbox
+/// instead of being lost - see `crate::outbox`. The caller still gets a
+/// `Message` back immediately, an optimistic one that'll be reconciled with
+/// the server's real message id once the background drainer succeeds.
+///
+/// `message_type` defaults to `"text"` when not given. Pass
+/// [`crate::models::SEALED_SENDER_MESSAGE_TYPE`] for `content` produced by
+/// `seal_message`, so the recipient knows to open it via
+/// `open_sealed_message` instead of `decrypt_message`.
 #[tauri::command]
 pub async fn send_message(
     state: State<'_, SharedState>,
     conversation_id: String,
     content: String,
+    message_type: Option<String>,
 ) -> Result<Message, String> {
     let app_state = state.read().await;
 
     // Check authentication
     let session = app_state.session.as_ref().ok_or("Not authenticated")?;
+    let message_type = message_type.as_deref().unwrap_or("text");
 
     // Send via API
-    let api_client = ApiClient::new(&app_state.api_url);
-    let message = api_client
-        .send_message(&conversation_id, &content, &session.token)
+    let api_client = ApiClient::with_session(&app_state.api_url, state.inner().clone());
+    match api_client
+        .send_message(&conversation_id, &content, message_type, &session.token)
         .await
-        .map_err(|e| e.to_string())?;
+    {
+        Ok(message) => {
+            // Cache message locally
+            if let Err(e) = db::save_message(&app_state.db, &message, None).await {
+                tracing::warn!("Failed to cache sent message: {}", e);
+            }
 
-    // Cache message locally
-    if let Err(e) = db::save_message(&app_state.db, &message).await {
-        tracing::warn!("Failed to cache sent message: {}", e);
+            tracing::info!("Message sent to conversation: {}", conversation_id);
+            Ok(message)
+        }
+        Err(AppError::SessionExpired) => Err("Session expired".to_string()),
+        Err(e) => {
+            tracing::warn!("Failed to send message, queuing in outbox: {}", e);
+            crate::outbox::queue_for_retry(&app_state.db, &conversation_id, &session.user_id, &content)
+                .await
+                .map_err(|e| e.to_string())
+        }
     }
-
-    tracing::info!("Message sent to conversation: {}", conversation_id);
-    Ok(message)
 }
 
-/// Mark a message as read
+/// Mark a single message as read.
+///
+/// Records the read locally and queues a receipt for `crate::receipts`'s
+/// background flusher to batch-send to the server (see
+/// `db::receipts::mark_read`) - a no-op if the message was already read. For
+/// the common "user opened the thread" case, prefer `mark_conversation_read`
+/// over calling this once per message.
 #[tauri::command]
 pub async fn mark_as_read(
     state: State<'_, SharedState>,
-    _message_id: String,
+    conversation_id: String,
+    message_id: String,
 ) -> Result<(), String> {
     let app_state = state.read().await;
 
     // Check authentication
     let _session = app_state.session.as_ref().ok_or("Not authenticated")?;
 
-    // TODO: Implement mark as read API call
-    // For now, this is a stub
-
-    Ok(())
+    db::receipts::mark_read(&app_state.db, &conversation_id, &message_id)
+        .await
+        .map_err(|e| e.to_string())
 }
 
 /// Create a new conversation
@@ -145,7 +175,7 @@ pub async fn create_conversation(
     let session = app_state.session.as_ref().ok_or("Not authenticated")?;
 
     // Create via API
-    let api_client = ApiClient::new(&app_state.api_url);
+    let api_client = ApiClient::with_session(&app_state.api_url, state.inner().clone());
     let conversation = api_client
         .create_conversation(&participant_ids, &session.token)
         .await
@@ -160,7 +190,13 @@ pub async fn create_conversation(
     Ok(conversation)
 }
 
-/// Search for users
+/// Search for users.
+///
+/// Merges the API's results with locally cached contacts matching `query`
+/// (see `db::search_cached_users`), so offline or slow-network users still
+/// see instant suggestions from people they've already talked to -
+/// de-duplicated by user id, cached contacts first since they're already on
+/// screen by the time the API responds.
 #[tauri::command]
 pub async fn search_users(
     state: State<'_, SharedState>,
@@ -171,31 +207,40 @@ pub async fn search_users(
     // Check authentication
     let session = app_state.session.as_ref().ok_or("Not authenticated")?;
 
-    // Search via API
-    let api_client = ApiClient::new(&app_state.api_url);
-    api_client
+    let cached = db::search_cached_users(&app_state.db, &query, 20, app_state.store_cipher.as_ref())
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Failed to search cached users: {}", e);
+            Vec::new()
+        });
+
+    let api_client = ApiClient::with_session(&app_state.api_url, state.inner().clone());
+    let remote = api_client
         .search_users(&query, &session.token)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+
+    let mut seen: std::collections::HashSet<String> = cached.iter().map(|u| u.id.clone()).collect();
+    let mut results = cached;
+    results.extend(remote.into_iter().filter(|u| seen.insert(u.id.clone())));
+
+    Ok(results)
 }
 
 // ============================================================================
 // Crypto commands (Signal Protocol)
 // ============================================================================
 
-use crate::crypto::CryptoService;
-
-/// Initialize the crypto service
+/// Confirm the crypto service is ready.
+///
+/// `CryptoService` itself now lives on `SharedState`, initialized once at
+/// app startup (see `state::AppState::new`) rather than re-initialized by
+/// every command - this is a no-op kept for frontend compatibility with
+/// code that calls it before the first crypto operation.
 #[tauri::command]
 pub async fn init_crypto(state: State<'_, SharedState>) -> Result<(), String> {
-    let app_state = state.read().await;
-    let _crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    // Store crypto service in state
-    // Note: In a full implementation, you'd add CryptoService to SharedState
-    tracing::info!("Crypto service initialized");
+    let _app_state = state.read().await;
+    tracing::info!("Crypto service ready");
     Ok(())
 }
 
@@ -203,11 +248,7 @@ pub async fn init_crypto(state: State<'_, SharedState>) -> Result<(), String> {
 #[tauri::command]
 pub async fn get_identity_key(state: State<'_, SharedState>) -> Result<String, String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let key = crypto.identity_key().await;
+    let key = app_state.crypto.identity_key().await;
     Ok(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &key))
 }
 
@@ -218,12 +259,9 @@ pub async fn get_one_time_keys(
     count: Option<usize>,
 ) -> Result<Vec<(String, String)>, String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
 
     let count = count.unwrap_or(100);
-    let keys = crypto.generate_one_time_keys(count)
+    let keys = app_state.crypto.generate_one_time_keys(count)
         .await
         .map_err(|e| e.to_string())?;
 
@@ -237,36 +275,47 @@ pub async fn get_one_time_keys(
 #[tauri::command]
 pub async fn mark_keys_published(state: State<'_, SharedState>) -> Result<(), String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    crypto.mark_keys_as_published()
+    app_state.crypto.mark_keys_as_published()
         .await
         .map_err(|e| e.to_string())
 }
 
 /// Establish an outbound session with a peer
+///
+/// Checks `identity_key` against whatever we've previously seen for
+/// `peer_id` before establishing anything - a mismatch comes back as
+/// [`CryptoError::IdentityKeyChanged`] rather than silently trusting a
+/// changed key. `signing_identity_key` and `signed_prekey` are the peer's
+/// X3DH identity (see [`crate::crypto::PreKeyManager`]) and current signed
+/// prekey - `establish_outbound_session` verifies the signed prekey's
+/// signature and refuses to proceed if it fails. That signature does not
+/// cover `identity_key` or `one_time_key`, so it does not by itself stop a
+/// server from substituting those two keys.
 #[tauri::command]
 pub async fn establish_session(
     state: State<'_, SharedState>,
     peer_id: String,
     identity_key: String,
     one_time_key: String,
-) -> Result<(), String> {
+    signing_identity_key: String,
+    signed_prekey: crypto::SignedPreKey,
+) -> Result<(), CryptoError> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
 
     let identity = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &identity_key)
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
     let otk = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &one_time_key)
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+    let signing_identity =
+        base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &signing_identity_key)
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+
+    crypto::identity::check_or_record(&app_state.db, &peer_id, &identity).await?;
 
-    crypto.establish_outbound_session(&peer_id, &identity, &otk)
+    app_state
+        .crypto
+        .establish_outbound_session(&peer_id, &identity, &otk, &signing_identity, &signed_prekey)
         .await
-        .map_err(|e| e.to_string())
 }
 
 /// Check if we have a session with a peer
@@ -276,11 +325,7 @@ pub async fn has_session(
     peer_id: String,
 ) -> Result<bool, String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(crypto.has_session(&peer_id).await)
+    Ok(app_state.crypto.has_session(&peer_id).await)
 }
 
 /// Encrypt a message for a peer (Signal Protocol)
@@ -291,11 +336,8 @@ pub async fn encrypt_message(
     plaintext: String,
 ) -> Result<String, String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
 
-    let ciphertext = crypto.encrypt(&peer_id, plaintext.as_bytes())
+    let ciphertext = app_state.crypto.encrypt(&peer_id, plaintext.as_bytes())
         .await
         .map_err(|e| e.to_string())?;
 
@@ -303,41 +345,121 @@ pub async fn encrypt_message(
 }
 
 /// Decrypt a message from a peer (Signal Protocol)
+///
+/// When `sender_identity_key` is provided, it's checked against whatever
+/// we've previously seen for `peer_id` before decrypting - a mismatch
+/// comes back as [`CryptoError::IdentityKeyChanged`] rather than silently
+/// accepting a changed key.
 #[tauri::command]
 pub async fn decrypt_message(
     state: State<'_, SharedState>,
     peer_id: String,
     ciphertext: String,
     sender_identity_key: Option<String>,
-) -> Result<String, String> {
+) -> Result<String, CryptoError> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
 
     let ciphertext_bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &ciphertext)
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
 
     let identity_key = sender_identity_key.map(|k| {
         base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &k)
-    }).transpose().map_err(|e| e.to_string())?;
+    }).transpose().map_err(|e| CryptoError::InvalidKey(e.to_string()))?;
+
+    if let Some(identity_key) = &identity_key {
+        crypto::identity::check_or_record(&app_state.db, &peer_id, identity_key).await?;
+    }
+
+    let plaintext = app_state.crypto.decrypt(&peer_id, identity_key.as_deref(), &ciphertext_bytes).await?;
+
+    String::from_utf8(plaintext).map_err(|e| CryptoError::DecryptionError(e.to_string()))
+}
 
-    let plaintext = crypto.decrypt(&peer_id, identity_key.as_deref(), &ciphertext_bytes)
+/// Seal a message for `peer_id` so the transport (and the relay server
+/// forwarding it) only ever sees an opaque envelope, never who sent it -
+/// see [`crate::crypto::CryptoService::seal_sender`]. `recipient_sealing_key`
+/// is the peer's currently published signed prekey (base64, fetched from
+/// their [`crate::crypto::PreKeyManager`] bundle the same way
+/// `establish_session`'s `signed_prekey` is). `peer_id` must already have an
+/// established session.
+///
+/// Returns the serialized envelope - pass it straight through as
+/// `send_message`'s `content` with `message_type` set to
+/// [`crate::models::SEALED_SENDER_MESSAGE_TYPE`].
+#[tauri::command]
+pub async fn seal_message(
+    state: State<'_, SharedState>,
+    peer_id: String,
+    recipient_sealing_key: String,
+    plaintext: String,
+) -> Result<String, String> {
+    let app_state = state.read().await;
+
+    let recipient_key =
+        base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &recipient_sealing_key)
+            .map_err(|e| format!("Invalid base64 recipient sealing key: {}", e))?;
+    let our_id = app_state.session.as_ref().ok_or("Not authenticated")?.user_id.clone();
+
+    let payload = app_state
+        .crypto
+        .seal_sender(&peer_id, &recipient_key, &our_id, plaintext.as_bytes())
         .await
         .map_err(|e| e.to_string())?;
 
-    String::from_utf8(plaintext).map_err(|e| e.to_string())
+    let envelope = crate::models::SealedSenderEnvelope {
+        ephemeral_public: payload.ephemeral_public,
+        ciphertext: payload.ciphertext,
+    };
+    serde_json::to_string(&envelope).map_err(|e| e.to_string())
 }
 
-/// Get identity key fingerprint for verification
+/// Open a sealed-sender envelope produced by `seal_message` (i.e. a
+/// `Message` whose `message_type` is
+/// [`crate::models::SEALED_SENDER_MESSAGE_TYPE`]).
+///
+/// Opens against the `Account` domain's signed prekey - the same key
+/// `export_linked_device`/a published prekey bundle advertises as our
+/// current signed prekey, so it's whatever a sender's `seal_message` call
+/// would have sealed to. Returns the authenticated sender id alongside the
+/// decrypted plaintext.
 #[tauri::command]
-pub async fn get_fingerprint(state: State<'_, SharedState>) -> Result<String, String> {
+pub async fn open_sealed_message(
+    state: State<'_, SharedState>,
+    envelope: String,
+) -> Result<(String, String), String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
+
+    let envelope: crate::models::SealedSenderEnvelope =
+        serde_json::from_str(&envelope).map_err(|e| format!("Invalid sealed-sender envelope: {}", e))?;
+
+    let sealing_key = app_state
+        .prekey_manager
+        .as_ref()
+        .ok_or("Prekey manager not initialized")?
+        .get_signed_prekey_pair(crypto::KeyDomain::Account)
+        .ok_or("Account domain is not registered")?;
+
+    let (sender_id, plaintext) = app_state
+        .crypto
+        .open_sealed_sender(
+            sealing_key,
+            &crypto::SealedSenderPayload {
+                ephemeral_public: envelope.ephemeral_public,
+                ciphertext: envelope.ciphertext,
+            },
+        )
         .await
         .map_err(|e| e.to_string())?;
 
-    Ok(crypto.fingerprint().await)
+    let plaintext = String::from_utf8(plaintext).map_err(|e| e.to_string())?;
+    Ok((sender_id, plaintext))
+}
+
+/// Get identity key fingerprint for verification
+#[tauri::command]
+pub async fn get_fingerprint(state: State<'_, SharedState>) -> Result<String, String> {
+    let app_state = state.read().await;
+    Ok(app_state.crypto.fingerprint().await)
 }
 
 /// Get session statistics
@@ -346,16 +468,17 @@ pub async fn get_session_stats(
     state: State<'_, SharedState>,
 ) -> Result<Vec<serde_json::Value>, String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
 
-    let stats = crypto.get_session_stats().await;
+    let stats = app_state.crypto.get_session_stats().await;
     Ok(stats.into_iter().map(|s| serde_json::json!({
         "peer_id": s.peer_id,
         "session_id": s.session_id,
         "messages_sent": s.messages_sent,
         "messages_received": s.messages_received,
+        "consecutive_failures": s.consecutive_failures,
+        "wedged": s.wedged,
+        "creation_time": s.creation_time,
+        "last_use_time": s.last_use_time,
     })).collect())
 }
 
@@ -363,11 +486,7 @@ pub async fn get_session_stats(
 #[tauri::command]
 pub async fn needs_more_keys(state: State<'_, SharedState>) -> Result<bool, String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
-        .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(crypto.needs_more_keys().await)
+    Ok(app_state.crypto.needs_more_keys().await)
 }
 
 /// Delete session with a peer
@@ -377,11 +496,229 @@ pub async fn delete_session(
     peer_id: String,
 ) -> Result<(), String> {
     let app_state = state.read().await;
-    let crypto = CryptoService::initialize(app_state.db.clone())
+    app_state.crypto.delete_session(&peer_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Mark a peer's currently-stored identity key as verified, e.g. after the
+/// user confirms matching safety numbers with them out-of-band.
+#[tauri::command]
+pub async fn mark_peer_verified(
+    state: State<'_, SharedState>,
+    peer_id: String,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+    crypto::identity::mark_verified(&app_state.db, &peer_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// A peer's trust-on-first-use verification state: whether we've seen a key
+/// for them at all, its short fingerprint, and whether the user has
+/// verified it.
+#[tauri::command]
+pub async fn get_verification_status(
+    state: State<'_, SharedState>,
+    peer_id: String,
+) -> Result<VerificationStatus, String> {
+    let app_state = state.read().await;
+    let stored = crypto::identity::get_identity(&app_state.db, &peer_id)
         .await
         .map_err(|e| e.to_string())?;
 
-    crypto.delete_session(&peer_id)
+    Ok(match stored {
+        Some(identity) => VerificationStatus {
+            peer_id,
+            fingerprint: Some(crypto::identity::short_fingerprint(&identity.identity_key)),
+            verified: identity.verified,
+        },
+        None => VerificationStatus { peer_id, fingerprint: None, verified: false },
+    })
+}
+
+/// Derive the Signal-style 60-digit safety number for `peer_id`, computed
+/// from our own identity key and whatever key we have on file for them - so
+/// both sides of the conversation land on the same number when they compare
+/// it out-of-band.
+#[tauri::command]
+pub async fn compute_safety_number(
+    state: State<'_, SharedState>,
+    peer_id: String,
+) -> Result<String, String> {
+    let app_state = state.read().await;
+    let stored = crypto::identity::get_identity(&app_state.db, &peer_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No identity key on file for peer: {}", peer_id))?;
+
+    let our_identity_key = app_state.crypto.identity_key().await;
+    Ok(crypto::compute_safety_number(&our_identity_key, &stored.identity_key))
+}
+
+/// Handle an inbound push/websocket payload: decrypt it and, if successful,
+/// show it as a native notification. Called by the frontend's push/websocket
+/// listener when a message arrives while the app isn't actively polling.
+///
+/// There's no `always_encrypted`-style flag to check here: whether a
+/// payload was actually encrypted is decided by whether it actually
+/// decrypts (see `push::notify_incoming_message`), not by a boolean the
+/// same server delivering the payload could just assert away.
+///
+/// When `message_type` is [`crate::models::SEALED_SENDER_MESSAGE_TYPE`],
+/// `ciphertext` actually holds a serialized `SealedSenderEnvelope` (see
+/// `seal_message`/`open_sealed_message`) rather than a raw Double Ratchet
+/// ciphertext, and the caller-supplied `sender_id`/`sender_display_name`
+/// are ignored in favor of the authenticated sender id recovered from
+/// inside the envelope.
+#[tauri::command]
+pub async fn handle_push_payload(
+    app_handle: tauri::AppHandle,
+    state: State<'_, SharedState>,
+    sender_id: String,
+    sender_display_name: Option<String>,
+    conversation_id: String,
+    ciphertext: String,
+    message_type: Option<String>,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+
+    if message_type.as_deref() == Some(crate::models::SEALED_SENDER_MESSAGE_TYPE) {
+        let envelope: crate::models::SealedSenderEnvelope =
+            serde_json::from_str(&ciphertext).map_err(|e| format!("Invalid sealed-sender envelope: {}", e))?;
+        let sealing_key = app_state
+            .prekey_manager
+            .as_ref()
+            .ok_or("Prekey manager not initialized")?
+            .get_signed_prekey_pair(crypto::KeyDomain::Account)
+            .ok_or("Account domain is not registered")?;
+
+        crate::push::notify_incoming_sealed_message(
+            &app_handle,
+            &app_state.crypto,
+            sealing_key,
+            &conversation_id,
+            &crypto::SealedSenderPayload {
+                ephemeral_public: envelope.ephemeral_public,
+                ciphertext: envelope.ciphertext,
+            },
+        )
+        .await;
+
+        return Ok(());
+    }
+
+    let ciphertext_bytes =
+        base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &ciphertext)
+            .map_err(|e| e.to_string())?;
+
+    crate::push::notify_incoming_message(
+        &app_handle,
+        &app_state.crypto,
+        &sender_id,
+        sender_display_name.as_deref(),
+        &conversation_id,
+        &ciphertext_bytes,
+    )
+    .await;
+
+    Ok(())
+}
+
+// ============================================================================
+// Search commands
+// ============================================================================
+
+/// Index a message's decrypted content for `search_messages`.
+///
+/// Cached messages are E2E-encrypted at rest (`messages.encrypted_content`),
+/// so there's no plaintext for this to pull from the database itself - the
+/// frontend calls this with the plaintext right after it decrypts a message
+/// for display, the same explicit, frontend-driven pattern every other
+/// crypto operation in this app follows. Safe to call again for the same
+/// `message_id` (e.g. on every app launch while re-rendering history); it
+/// replaces the prior entry rather than duplicating it.
+#[tauri::command]
+pub async fn index_message_content(
+    state: State<'_, SharedState>,
+    message_id: String,
+    conversation_id: String,
+    content: String,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+    db::search::index_message(&app_state.db, &message_id, &conversation_id, &content)
         .await
         .map_err(|e| e.to_string())
 }
+
+/// Full-text search over locally indexed (decrypted) message content - see
+/// `index_message_content` and `crate::db::search`.
+#[tauri::command]
+pub async fn search_messages(
+    state: State<'_, SharedState>,
+    query: String,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<crate::models::MessageSearchHit>, String> {
+    let app_state = state.read().await;
+    db::search::search_messages(&app_state.db, &query, limit.unwrap_or(50), offset.unwrap_or(0))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// ============================================================================
+// Receipt commands
+// ============================================================================
+
+/// Mark every unread message in `conversation_id` as read in one call - the
+/// common "user opened the thread" case, queued as a single batch rather
+/// than one `mark_as_read` per message.
+#[tauri::command]
+pub async fn mark_conversation_read(
+    state: State<'_, SharedState>,
+    conversation_id: String,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+    let session = app_state.session.as_ref().ok_or("Not authenticated")?;
+
+    db::receipts::mark_conversation_read(&app_state.db, &conversation_id, &session.user_id)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Per-conversation unread tallies computed from the local message cache.
+#[tauri::command]
+pub async fn get_unread_counts(state: State<'_, SharedState>) -> Result<Vec<UnreadCount>, String> {
+    let app_state = state.read().await;
+    let session = app_state.session.as_ref().ok_or("Not authenticated")?;
+
+    db::receipts::get_unread_counts(&app_state.db, &session.user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Apply a delivery/read-receipt event pushed from the server for a message
+/// *we* sent, and emit `receipt-updated` so the UI can redraw checkmarks
+/// without a full refetch. Called by the frontend's push/websocket listener,
+/// the same pattern as `handle_push_payload`.
+#[tauri::command]
+pub async fn handle_incoming_receipt(
+    app_handle: tauri::AppHandle,
+    state: State<'_, SharedState>,
+    message_id: String,
+    delivered_at: Option<chrono::DateTime<chrono::Utc>>,
+    read_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<(), String> {
+    let app_state = state.read().await;
+
+    db::receipts::apply_incoming_receipt(&app_state.db, &message_id, delivered_at, read_at)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Err(e) = app_handle.emit("receipt-updated", &message_id) {
+        tracing::error!("Failed to emit receipt-updated event: {}", e);
+    }
+
+    Ok(())
+}
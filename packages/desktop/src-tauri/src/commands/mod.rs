@@ -3,9 +3,13 @@
 //! All frontend-to-backend communication goes through these commands.
 
 pub mod auth;
+pub mod key_requests;
 pub mod messaging;
+pub mod provisioning;
 pub mod settings;
 
 pub use auth::*;
+pub use key_requests::*;
 pub use messaging::*;
+pub use provisioning::*;
 pub use settings::*;
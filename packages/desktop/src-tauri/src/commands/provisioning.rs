@@ -0,0 +1,178 @@
+//! Device linking command handlers
+//!
+//! Exposes [`crate::crypto::provisioning`] over IPC so the frontend can drive
+//! a QR-code device linking flow: the primary calls [`begin_device_linking`]
+//! to get a public key to render as a QR code, then [`export_linked_device`]
+//! once the secondary's scanned reply comes back; the secondary calls
+//! [`begin_device_linking`] itself (to get the ephemeral key pair it shows to
+//! the primary) and then [`import_linked_device`] once the primary's envelope
+//! arrives.
+
+use base64::Engine;
+use tauri::State;
+
+use crate::crypto::{
+    self, Curve25519KeyPair, IdentityKeyPair, KeyDomain, LinkedDeviceInfo, PreKeyManager, StoredPreKey,
+};
+use crate::db;
+use crate::state::SharedState;
+
+/// Generate an ephemeral Curve25519 key pair for a device linking attempt,
+/// stash it in `AppState` for the matching `export_linked_device`/
+/// `import_linked_device` call, and return its base64-encoded tagged public
+/// key to render/transmit as the QR payload.
+#[tauri::command]
+pub async fn begin_device_linking(state: State<'_, SharedState>) -> Result<String, String> {
+    let ephemeral = Curve25519KeyPair::generate();
+    let public_key = base64::engine::general_purpose::STANDARD.encode(ephemeral.public_key_bytes());
+
+    let mut app_state = state.write().await;
+    app_state.linking_ephemeral = Some(ephemeral);
+
+    Ok(public_key)
+}
+
+/// Primary side: seal a provisioning envelope for the secondary device whose
+/// base64-encoded ephemeral public key (`peer_public_key`) was scanned from
+/// its QR code, using the identity and signed prekey of the `Account`
+/// domain's [`crate::crypto::PreKeyManager`]. Registers the newly-linked
+/// device and returns the base64-encoded envelope to transmit back.
+#[tauri::command]
+pub async fn export_linked_device(
+    state: State<'_, SharedState>,
+    peer_public_key: String,
+) -> Result<String, String> {
+    let mut app_state = state.write().await;
+
+    let our_ephemeral = app_state
+        .linking_ephemeral
+        .take()
+        .ok_or("Device linking was not started - call begin_device_linking first")?;
+
+    let user_id = app_state
+        .user_id()
+        .ok_or("Not authenticated")?
+        .to_string();
+
+    let manager = app_state
+        .prekey_manager
+        .as_ref()
+        .ok_or("Prekey manager not initialized")?;
+    let identity = manager
+        .identity(KeyDomain::Account)
+        .ok_or("Account domain is not registered")?;
+    let signed_prekey = manager
+        .get_signed_prekey(KeyDomain::Account)
+        .ok_or("Account domain is not registered")?;
+    let signed_prekey_pair = manager
+        .get_signed_prekey_pair(KeyDomain::Account)
+        .ok_or("Account domain is not registered")?;
+
+    let peer_pub = base64::engine::general_purpose::STANDARD
+        .decode(&peer_public_key)
+        .map_err(|e| format!("Invalid base64 peer public key: {}", e))?;
+    let linked_device_id = crypto::generate_linked_device_id();
+
+    let envelope = crypto::export_provisioning_envelope(
+        &our_ephemeral,
+        &peer_pub,
+        identity,
+        signed_prekey,
+        signed_prekey_pair,
+        &user_id,
+        &linked_device_id,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    db::register_device(&db, &user_id, &linked_device_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("Linked new device {} for {}", linked_device_id, user_id);
+    Ok(base64::engine::general_purpose::STANDARD.encode(envelope))
+}
+
+/// Secondary side: recover the [`crypto::LinkedDeviceState`] sealed by the
+/// primary's [`export_linked_device`] call, using the ephemeral key pair
+/// this device generated in its own `begin_device_linking` call, and install
+/// the recovered identity into this device's `PreKeyManager` so it actually
+/// acts as the account from here on - not just registers its device id.
+///
+/// The identity *secret* key never leaves this function: it's consumed
+/// locally to build the `PreKeyManager` and the caller only ever sees the
+/// non-secret [`LinkedDeviceInfo`] returned here.
+#[tauri::command]
+pub async fn import_linked_device(
+    state: State<'_, SharedState>,
+    envelope: String,
+) -> Result<LinkedDeviceInfo, String> {
+    let mut app_state = state.write().await;
+
+    let our_ephemeral = app_state
+        .linking_ephemeral
+        .take()
+        .ok_or("Device linking was not started - call begin_device_linking first")?;
+
+    let envelope_bytes = base64::engine::general_purpose::STANDARD
+        .decode(&envelope)
+        .map_err(|e| format!("Invalid base64 envelope: {}", e))?;
+
+    let linked = crypto::import_provisioning_envelope(&envelope_bytes, &our_ephemeral)
+        .map_err(|e| e.to_string())?;
+
+    // Seed a fresh manager for its one-time prekey pool / fallback prekey
+    // (those aren't carried over the wire - each device keeps its own), then
+    // overwrite just the signed prekey with the one actually transmitted, so
+    // a PreKey message already addressed to the primary's published signed
+    // prekey can still be decrypted on this device instead of silently
+    // failing against an unrelated, freshly-generated one.
+    let identity = IdentityKeyPair::from_bytes(&linked.identity_public, &linked.identity_secret)
+        .map_err(|e| e.to_string())?;
+    let mut manager = PreKeyManager::new(identity);
+
+    let signed_prekey_pair =
+        Curve25519KeyPair::from_bytes(&linked.signed_prekey.public_key, &linked.signed_prekey_secret)
+            .map_err(|e| e.to_string())?;
+    let (_, one_time_prekeys, fallback_prekey) = manager
+        .get_stored_prekeys(KeyDomain::Account)
+        .ok_or("Prekey manager domain missing immediately after creation")?;
+    let next_prekey_id = manager.next_prekey_id(KeyDomain::Account).unwrap_or(0);
+    let fallback_prekey_created = manager.fallback_prekey_created(KeyDomain::Account).unwrap_or(0);
+    let published_key_ids = manager
+        .published_key_ids(KeyDomain::Account)
+        .cloned()
+        .unwrap_or_default();
+    let identity = IdentityKeyPair::from_bytes(&linked.identity_public, &linked.identity_secret)
+        .map_err(|e| e.to_string())?;
+
+    manager
+        .restore_domain(
+            KeyDomain::Account,
+            identity,
+            StoredPreKey::from_keypair(linked.signed_prekey.key_id, &signed_prekey_pair, true),
+            linked.signed_prekey.created_at,
+            one_time_prekeys,
+            next_prekey_id,
+            fallback_prekey,
+            fallback_prekey_created,
+            published_key_ids,
+        )
+        .map_err(|e| e.to_string())?;
+
+    app_state.prekey_manager = Some(manager);
+
+    let db = app_state.db.clone();
+    let user_id = linked.user_id;
+    let device_id = linked.device_id;
+    drop(app_state);
+
+    db::register_device(&db, &user_id, &device_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("Joined account {} as linked device {}", user_id, device_id);
+    Ok(LinkedDeviceInfo { user_id, device_id })
+}
@@ -3,6 +3,7 @@
 use tauri::State;
 
 use crate::db;
+use crate::db::store_cipher::StoreCipher;
 use crate::models::Settings;
 use crate::state::SharedState;
 
@@ -42,3 +43,26 @@ pub async fn reset_settings(state: State<'_, SharedState>) -> Result<Settings, S
     tracing::info!("Settings reset to defaults");
     Ok(default_settings)
 }
+
+/// Unlock (or, on first use, create) the encrypted store layer with
+/// `passphrase`, and migrate any session tokens/display names still sitting
+/// in plaintext from before encryption was enabled. Every call site that
+/// persists or reads those columns reads `SharedState::store_cipher` and
+/// keeps working unencrypted until this has been called once.
+#[tauri::command]
+pub async fn unlock_store(state: State<'_, SharedState>, passphrase: String) -> Result<(), String> {
+    let mut app_state = state.write().await;
+
+    let cipher = StoreCipher::unlock(&app_state.db, passphrase.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    db::migrate_legacy_plaintext(&app_state.db, &cipher)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    app_state.store_cipher = Some(cipher);
+
+    tracing::info!("Encrypted store unlocked");
+    Ok(())
+}
@@ -0,0 +1,204 @@
+//! In-memory cache in front of [`SessionStore`]
+//!
+//! Every [`SessionStore::load_session`](crate::crypto::sessions::SessionStore::load_session)/
+//! `load_account` call round-trips through SQLite and runs an authenticated
+//! decrypt plus JSON deserialize, which dominates latency for a hot peer in
+//! an active conversation. [`CachedSessionStore`] wraps a `SessionStore` with
+//! an in-memory cache of already-unpickled account/session state: reads are
+//! served from the cache when present and only fall back to the database on
+//! a miss, and every write updates or evicts the cache entry after the
+//! underlying write succeeds, so the cache never observes a state the store
+//! doesn't also have.
+//!
+//! Mirrors how mature Signal Protocol implementations keep a live session
+//! cache separate from the persistent store.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::crypto::errors::CryptoResult;
+use crate::crypto::ratchet::{OlmAccount, RatchetSession};
+use crate::crypto::sessions::{CryptoStore, SessionStore};
+
+/// Above this many distinct cached peers, the least-recently-touched peer's
+/// sessions are evicted on insert, so a long-running process that
+/// accumulates sessions with thousands of peers doesn't grow the cache
+/// unbounded. Sized generously above a typical active conversation list.
+const MAX_CACHED_PEERS: usize = 2048;
+
+struct Cache {
+    account: Option<OlmAccount>,
+    /// peer_id -> that peer's concurrent sessions, as returned by
+    /// [`SessionStore::load_sessions_for_peer`](crate::crypto::sessions::SessionStore::load_sessions_for_peer).
+    sessions: HashMap<String, Vec<RatchetSession>>,
+    /// peer_id -> the `clock` value at its last read/write, so the
+    /// least-recently-used peer can be found on eviction without a separate
+    /// linked-list-based LRU structure.
+    last_touched: HashMap<String, u64>,
+    clock: u64,
+}
+
+impl Cache {
+    fn touch(&mut self, peer_id: &str) {
+        self.clock += 1;
+        self.last_touched.insert(peer_id.to_string(), self.clock);
+    }
+
+    fn evict_if_needed(&mut self) {
+        if self.sessions.len() <= MAX_CACHED_PEERS {
+            return;
+        }
+
+        if let Some(lru_peer) = self
+            .last_touched
+            .iter()
+            .min_by_key(|(_, touched)| **touched)
+            .map(|(peer_id, _)| peer_id.clone())
+        {
+            self.sessions.remove(&lru_peer);
+            self.last_touched.remove(&lru_peer);
+        }
+    }
+}
+
+/// A [`CryptoStore`] that serves `load_account`/`load_sessions_for_peer`
+/// from an in-memory cache when possible, keeping it coherent with every
+/// write that goes through this wrapper.
+pub struct CachedSessionStore {
+    inner: SessionStore,
+    cache: Mutex<Cache>,
+}
+
+impl CachedSessionStore {
+    /// Wrap `inner` with an empty cache.
+    pub fn new(inner: SessionStore) -> Self {
+        Self {
+            inner,
+            cache: Mutex::new(Cache {
+                account: None,
+                sessions: HashMap::new(),
+                last_touched: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    /// Drop every cached entry without touching the underlying store - for
+    /// logout, where in-memory key material should be forgotten immediately
+    /// even though the (still-encrypted) rows remain on disk.
+    pub fn flush(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.account = None;
+        cache.sessions.clear();
+        cache.last_touched.clear();
+    }
+
+    /// Forget the cached sessions for a single peer, e.g. after an external
+    /// change to their sessions the cache wouldn't otherwise know about.
+    pub fn invalidate(&self, peer_id: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.sessions.remove(peer_id);
+        cache.last_touched.remove(peer_id);
+    }
+}
+
+#[async_trait]
+impl CryptoStore for CachedSessionStore {
+    async fn load_account(&self) -> CryptoResult<Option<OlmAccount>> {
+        if let Some(account) = &self.cache.lock().unwrap().account {
+            return Ok(Some(account.cheap_clone()));
+        }
+
+        let account = self.inner.load_account().await?;
+        if let Some(account) = &account {
+            self.cache.lock().unwrap().account = Some(account.cheap_clone());
+        }
+        Ok(account)
+    }
+
+    async fn save_account(&self, account: &OlmAccount) -> CryptoResult<()> {
+        self.inner.save_account(account).await?;
+        self.cache.lock().unwrap().account = Some(account.cheap_clone());
+        Ok(())
+    }
+
+    async fn load_sessions_for_peer(&self, peer_id: &str) -> CryptoResult<Vec<RatchetSession>> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(sessions) = cache.sessions.get(peer_id) {
+                let cloned = sessions.iter().map(RatchetSession::cheap_clone).collect();
+                cache.touch(peer_id);
+                return Ok(cloned);
+            }
+        }
+
+        let sessions = self.inner.load_sessions_for_peer(peer_id).await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.sessions.insert(
+            peer_id.to_string(),
+            sessions.iter().map(RatchetSession::cheap_clone).collect(),
+        );
+        cache.touch(peer_id);
+        cache.evict_if_needed();
+
+        Ok(sessions)
+    }
+
+    async fn save_session(&self, session: &RatchetSession) -> CryptoResult<()> {
+        self.inner.save_session(session).await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.sessions.entry(session.peer_id.clone()).or_default();
+        entry.retain(|cached| cached.session_id() != session.session_id());
+        entry.push(session.cheap_clone());
+        cache.touch(&session.peer_id);
+        cache.evict_if_needed();
+
+        Ok(())
+    }
+
+    async fn delete_session(&self, peer_id: &str) -> CryptoResult<()> {
+        self.inner.delete_session(peer_id).await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.sessions.remove(peer_id);
+        cache.last_touched.remove(peer_id);
+
+        Ok(())
+    }
+
+    async fn delete_session_by_id(&self, session_id: &str) -> CryptoResult<()> {
+        self.inner.delete_session_by_id(session_id).await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        for sessions in cache.sessions.values_mut() {
+            sessions.retain(|cached| cached.session_id() != session_id);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_all_sessions(&self) -> CryptoResult<()> {
+        self.inner.delete_all_sessions().await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        cache.sessions.clear();
+        cache.last_touched.clear();
+
+        Ok(())
+    }
+
+    async fn list_peers(&self) -> CryptoResult<Vec<String>> {
+        // Not served from the cache: the cache may be missing cold or
+        // evicted peers, and listing peers isn't on the hot per-message
+        // path this cache exists for.
+        self.inner.list_peers().await
+    }
+
+    async fn count_one_time_prekeys(&self) -> CryptoResult<i64> {
+        self.inner.count_one_time_prekeys().await
+    }
+}
@@ -0,0 +1,170 @@
+//! Multi-device identity: one user, many devices, one prekey pool each
+//!
+//! Modeled on Comm's device-list / `get_inbound_keys_for_user` design: a
+//! sender doesn't address a single session per recipient user, it fans out
+//! an initial X3DH handshake to every device that user has registered, so
+//! each of the recipient's devices can independently decrypt. This module
+//! owns the local side of that: a [`PreKeyManager`] per device, keyed by
+//! [`DeviceId`].
+
+use std::collections::HashMap;
+
+use crate::crypto::keys::IdentityKeyPair;
+use crate::crypto::prekeys::{KeyDomain, PreKeyConfig, PreKeyManager};
+use crate::crypto::x3dh::PreKeyBundle;
+
+/// Opaque device identifier, matching the `device_id` used by the device
+/// registration API (see [`crate::api::client::ApiClient::register_device`]).
+pub type DeviceId = String;
+
+/// Owns one [`PreKeyManager`] per device belonging to a single user.
+///
+/// Scoped to one `user_id` at a time rather than being a global multi-user
+/// directory: each device's `PreKeyManager` holds private key material, so
+/// this only ever manages devices this application account controls, not
+/// devices belonging to other users (those are looked up through fetched
+/// [`PreKeyBundle`]s instead, never through a `DeviceManager`).
+pub struct DeviceManager {
+    user_id: String,
+    devices: HashMap<DeviceId, PreKeyManager>,
+}
+
+impl DeviceManager {
+    /// Create an empty device manager for `user_id`.
+    pub fn new(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            devices: HashMap::new(),
+        }
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// Register a new device with a fresh identity and default prekey pool.
+    /// A no-op if the device is already registered.
+    pub fn add_device(&mut self, device_id: DeviceId, identity: IdentityKeyPair) {
+        self.devices
+            .entry(device_id)
+            .or_insert_with(|| PreKeyManager::new(identity));
+    }
+
+    /// Register a new device with custom prekey configuration (e.g. a
+    /// smaller initial batch for a resource-constrained device).
+    pub fn add_device_with_config(
+        &mut self,
+        device_id: DeviceId,
+        identity: IdentityKeyPair,
+        config: PreKeyConfig,
+    ) {
+        self.devices
+            .entry(device_id)
+            .or_insert_with(|| PreKeyManager::with_config(identity, config));
+    }
+
+    /// Insert an already-constructed `PreKeyManager`, e.g. one restored from
+    /// [`crate::db::load_prekeys`] on startup.
+    pub fn insert_device(&mut self, device_id: DeviceId, manager: PreKeyManager) {
+        self.devices.insert(device_id, manager);
+    }
+
+    /// Remove a device (e.g. the user revoked it from another client).
+    ///
+    /// This only drops the device's local `PreKeyManager` - it's the
+    /// caller's responsibility to also invalidate the device's row in the
+    /// `sessions`/`devices` tables (see [`crate::db::prekeys`] and the
+    /// `devices` table) and to tear down any established ratchet sessions
+    /// addressed to it via [`crate::crypto::CryptoService`].
+    pub fn remove_device(&mut self, device_id: &str) -> Option<PreKeyManager> {
+        self.devices.remove(device_id)
+    }
+
+    pub fn device(&self, device_id: &str) -> Option<&PreKeyManager> {
+        self.devices.get(device_id)
+    }
+
+    pub fn device_mut(&mut self, device_id: &str) -> Option<&mut PreKeyManager> {
+        self.devices.get_mut(device_id)
+    }
+
+    pub fn device_ids(&self) -> impl Iterator<Item = &DeviceId> {
+        self.devices.keys()
+    }
+
+    pub fn device_count(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// One [`PreKeyBundle`] per registered device, so a sender can fan out
+    /// an initial message to every device `user_id` owns instead of
+    /// addressing a single session per user.
+    ///
+    /// Always draws from the primary `Account` identity - a device's
+    /// secondary phone-number identity (if any) is addressed separately via
+    /// [`Self::device`] and `PreKeyManager::get_bundle(KeyDomain::PhoneNumber)`.
+    ///
+    /// `user_id` is checked against [`Self::user_id`] rather than used to
+    /// look anything up - a `DeviceManager` only ever holds one user's
+    /// devices - so callers get an empty result instead of silently mixing
+    /// up accounts if they pass the wrong one.
+    pub fn get_inbound_bundles_for_user(&self, user_id: &str) -> Vec<(DeviceId, PreKeyBundle)> {
+        if user_id != self.user_id {
+            return Vec::new();
+        }
+
+        self.devices
+            .iter()
+            .filter_map(|(device_id, manager)| {
+                manager.get_bundle(KeyDomain::Account).map(|bundle| (device_id.clone(), bundle))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keys::IdentityKeyPair;
+
+    #[test]
+    fn test_add_device_is_idempotent() {
+        let mut manager = DeviceManager::new("alice");
+        manager.add_device("laptop".to_string(), IdentityKeyPair::generate());
+        let first_fingerprint = manager.device("laptop").unwrap().fingerprint(KeyDomain::Account);
+
+        manager.add_device("laptop".to_string(), IdentityKeyPair::generate());
+        assert_eq!(manager.device("laptop").unwrap().fingerprint(KeyDomain::Account), first_fingerprint);
+    }
+
+    #[test]
+    fn test_get_inbound_bundles_returns_one_per_device() {
+        let mut manager = DeviceManager::new("alice");
+        manager.add_device("laptop".to_string(), IdentityKeyPair::generate());
+        manager.add_device("phone".to_string(), IdentityKeyPair::generate());
+
+        let bundles = manager.get_inbound_bundles_for_user("alice");
+        assert_eq!(bundles.len(), 2);
+        let device_ids: Vec<&str> = bundles.iter().map(|(id, _)| id.as_str()).collect();
+        assert!(device_ids.contains(&"laptop"));
+        assert!(device_ids.contains(&"phone"));
+    }
+
+    #[test]
+    fn test_get_inbound_bundles_for_wrong_user_is_empty() {
+        let mut manager = DeviceManager::new("alice");
+        manager.add_device("laptop".to_string(), IdentityKeyPair::generate());
+
+        assert!(manager.get_inbound_bundles_for_user("bob").is_empty());
+    }
+
+    #[test]
+    fn test_remove_device() {
+        let mut manager = DeviceManager::new("alice");
+        manager.add_device("laptop".to_string(), IdentityKeyPair::generate());
+
+        assert!(manager.remove_device("laptop").is_some());
+        assert!(manager.device("laptop").is_none());
+        assert!(manager.remove_device("laptop").is_none());
+    }
+}
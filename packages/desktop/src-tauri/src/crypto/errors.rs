@@ -23,6 +23,10 @@ pub enum CryptoError {
     #[error("X3DH error: {0}")]
     X3dhError(String),
 
+    /// OPAQUE augmented PAKE error (registration or login)
+    #[error("OPAQUE error: {0}")]
+    OpaqueError(String),
+
     /// Double Ratchet protocol error
     #[error("Ratchet error: {0}")]
     RatchetError(String),
@@ -51,6 +55,30 @@ pub enum CryptoError {
     #[error("No prekeys available for peer: {0}")]
     NoPrekeysAvailable(String),
 
+    /// A ciphertext was decrypted successfully but its ratchet index has
+    /// already been seen - almost certainly a replayed message rather than
+    /// a new one.
+    #[error("Replayed message detected for peer: {peer_id}")]
+    ReplayedMessage { peer_id: String },
+
+    /// A session has seen too many consecutive prekey-decrypt failures from
+    /// a sender key that no longer matches our ratchet state and should be
+    /// torn down and re-established.
+    #[error("Session wedged for peer: {peer_id}")]
+    SessionWedged { peer_id: String },
+
+    /// A peer's identity key no longer matches the one we first saw for
+    /// them (see [`crate::crypto::identity`]) - a classic MITM/key-compromise
+    /// signal. Carries both keys' short fingerprints so the caller can show
+    /// the user a "security code changed" prompt instead of silently
+    /// accepting the new key.
+    #[error("Identity key changed for peer {peer_id}: {old_fingerprint} -> {new_fingerprint}")]
+    IdentityKeyChanged {
+        peer_id: String,
+        old_fingerprint: String,
+        new_fingerprint: String,
+    },
+
     /// Database error
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
@@ -96,3 +124,28 @@ impl From<aes_gcm::Error> for CryptoError {
 
 /// Result type for cryptographic operations
 pub type CryptoResult<T> = Result<T, CryptoError>;
+
+// Implement serialization for Tauri IPC. Every variant serializes to its
+// `Display` message, same as `AppError`, except `IdentityKeyChanged` - that
+// one needs to hand the UI structured data (both fingerprints) rather than
+// a message it would have to parse back apart.
+impl serde::Serialize for CryptoError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        match self {
+            CryptoError::IdentityKeyChanged { peer_id, old_fingerprint, new_fingerprint } => {
+                let mut s = serializer.serialize_struct("IdentityKeyChanged", 4)?;
+                s.serialize_field("kind", "identity_key_changed")?;
+                s.serialize_field("peerId", peer_id)?;
+                s.serialize_field("oldFingerprint", old_fingerprint)?;
+                s.serialize_field("newFingerprint", new_fingerprint)?;
+                s.end()
+            }
+            other => serializer.serialize_str(&other.to_string()),
+        }
+    }
+}
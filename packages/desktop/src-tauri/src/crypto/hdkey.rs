@@ -0,0 +1,200 @@
+//! Hierarchical deterministic key derivation (SLIP-0010, ed25519)
+//!
+//! Lets a user back up their entire identity + prekey set as a single BIP39
+//! recovery phrase instead of exporting raw key blobs. Regenerating from the
+//! same mnemonic reproduces the same identity key, fallback prekey, signed
+//! prekey, and one-time prekeys (keyed by `key_id`) deterministically.
+//!
+//! Ed25519 (per SLIP-0010) supports only hardened child derivation, so every
+//! index this module produces is hardened internally; a caller-supplied
+//! index that already has the hardened bit set is rejected.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::keys::{Curve25519KeyPair, IdentityKeyPair};
+use crate::crypto::x3dh::convert_ed25519_to_curve25519_secret;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// Derivation path indices, chosen so regenerating from the same mnemonic
+/// always reproduces the same `key_id` -> key mapping. Each key type gets
+/// its own range so `key_id`s never collide across types.
+pub const IDENTITY_KEY_INDEX: u32 = 0;
+pub const FALLBACK_PREKEY_INDEX: u32 = 1;
+pub const SIGNED_PREKEY_BASE_INDEX: u32 = 1_000;
+pub const ONE_TIME_PREKEY_BASE_INDEX: u32 = 1_000_000;
+
+/// SLIP-0010 only derives hardened children for ed25519; every index is
+/// combined with this offset before use.
+const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+/// A node in the SLIP-0010 hierarchy: a 32-byte secret plus its chain code.
+struct HdNode {
+    secret: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+/// Derive the 64-byte BIP39 seed from a mnemonic phrase and optional
+/// passphrase (per BIP39, this is itself a PBKDF2-HMAC-SHA512 stretch of the
+/// mnemonic, handled by the `bip39` crate).
+pub fn seed_from_mnemonic(phrase: &str, passphrase: &str) -> CryptoResult<[u8; 64]> {
+    let mnemonic = bip39::Mnemonic::parse(phrase)
+        .map_err(|e| CryptoError::InvalidKey(format!("Invalid BIP39 mnemonic: {}", e)))?;
+    Ok(mnemonic.to_seed(passphrase))
+}
+
+/// Compute the SLIP-0010 master node for the ed25519 curve:
+/// `I = HMAC-SHA512(key="ed25519 seed", data=seed)`.
+fn master_node(seed: &[u8]) -> CryptoResult<HdNode> {
+    let mut mac = HmacSha512::new_from_slice(b"ed25519 seed")
+        .map_err(|e| CryptoError::KeyGenerationFailed(format!("HMAC init failed: {}", e)))?;
+    mac.update(seed);
+    split_node(&mac.finalize().into_bytes())
+}
+
+/// Derive a single hardened child node from a parent node:
+/// `I = HMAC-SHA512(key=chain_code, data=0x00 || parent_secret || ser32(index | 0x80000000))`.
+///
+/// `index` is the child number *before* hardening (e.g. `0`, `1`, ...); it is
+/// always combined with [`HARDENED_OFFSET`] before use, since ed25519
+/// supports only hardened derivation. An `index` that already has the
+/// hardened bit set is rejected, since callers of this module should never
+/// need to think in terms of already-hardened indices.
+fn derive_child(parent: &HdNode, index: u32) -> CryptoResult<HdNode> {
+    if index & HARDENED_OFFSET != 0 {
+        return Err(CryptoError::InvalidKey(
+            "ed25519 HD derivation only supports hardened indices; pass the index without the hardened bit set".to_string(),
+        ));
+    }
+    let hardened_index = index | HARDENED_OFFSET;
+
+    let mut mac = HmacSha512::new_from_slice(&parent.chain_code)
+        .map_err(|e| CryptoError::KeyGenerationFailed(format!("HMAC init failed: {}", e)))?;
+    mac.update(&[0u8]);
+    mac.update(&parent.secret);
+    mac.update(&hardened_index.to_be_bytes());
+    split_node(&mac.finalize().into_bytes())
+}
+
+/// Split a 64-byte HMAC-SHA512 output into its secret (`IL`) and chain code
+/// (`IR`) halves.
+fn split_node(i: &[u8]) -> CryptoResult<HdNode> {
+    if i.len() != 64 {
+        return Err(CryptoError::KeyGenerationFailed(
+            "Unexpected HMAC-SHA512 output length".to_string(),
+        ));
+    }
+    let mut secret = [0u8; 32];
+    let mut chain_code = [0u8; 32];
+    secret.copy_from_slice(&i[..32]);
+    chain_code.copy_from_slice(&i[32..]);
+    Ok(HdNode { secret, chain_code })
+}
+
+/// Derive the node reached by walking `path` (a sequence of non-hardened
+/// indices, each hardened internally) from the master node for `seed`.
+fn derive_path(seed: &[u8], path: &[u32]) -> CryptoResult<HdNode> {
+    let mut node = master_node(seed)?;
+    for &index in path {
+        node = derive_child(&node, index)?;
+    }
+    Ok(node)
+}
+
+/// SLIP-0010 (ed25519) only ever produces ed25519 node secrets, but prekeys
+/// need Curve25519 keys for Diffie-Hellman - reuse the same conversion X3DH
+/// already applies to identity keys rather than duplicating it.
+fn node_to_curve25519(node: &HdNode) -> CryptoResult<Curve25519KeyPair> {
+    convert_ed25519_to_curve25519_secret(&node.secret)
+}
+
+/// Derive the identity key pair from a seed (path: `[IDENTITY_KEY_INDEX]`).
+pub fn derive_identity_key_pair(seed: &[u8]) -> CryptoResult<IdentityKeyPair> {
+    let node = derive_path(seed, &[IDENTITY_KEY_INDEX])?;
+    IdentityKeyPair::from_seed_bytes(&node.secret)
+}
+
+/// Derive the fallback prekey's key pair from a seed
+/// (path: `[FALLBACK_PREKEY_INDEX]`).
+pub fn derive_fallback_prekey(seed: &[u8]) -> CryptoResult<Curve25519KeyPair> {
+    let node = derive_path(seed, &[FALLBACK_PREKEY_INDEX])?;
+    node_to_curve25519(&node)
+}
+
+/// Derive a signed prekey's key pair from a seed, keyed by `key_id` so the
+/// same `key_id` always reproduces the same key.
+pub fn derive_signed_prekey(seed: &[u8], key_id: u32) -> CryptoResult<Curve25519KeyPair> {
+    let node = derive_path(seed, &[SIGNED_PREKEY_BASE_INDEX.wrapping_add(key_id)])?;
+    node_to_curve25519(&node)
+}
+
+/// Derive a one-time prekey's key pair from a seed, keyed by `key_id`.
+pub fn derive_one_time_prekey(seed: &[u8], key_id: u32) -> CryptoResult<Curve25519KeyPair> {
+    let node = derive_path(seed, &[ONE_TIME_PREKEY_BASE_INDEX.wrapping_add(key_id)])?;
+    node_to_curve25519(&node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_seed() -> [u8; 32] {
+        [0x42; 32]
+    }
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let seed = test_seed();
+        let a = derive_identity_key_pair(&seed).unwrap();
+        let b = derive_identity_key_pair(&seed).unwrap();
+        assert_eq!(a.public_key_bytes(), b.public_key_bytes());
+    }
+
+    #[test]
+    fn test_different_seeds_produce_different_keys() {
+        let a = derive_identity_key_pair(&[0x01; 32]).unwrap();
+        let b = derive_identity_key_pair(&[0x02; 32]).unwrap();
+        assert_ne!(a.public_key_bytes(), b.public_key_bytes());
+    }
+
+    #[test]
+    fn test_identity_and_fallback_keys_differ() {
+        let seed = test_seed();
+        let identity = derive_identity_key_pair(&seed).unwrap();
+        let fallback = derive_fallback_prekey(&seed).unwrap();
+        assert_ne!(identity.public_key_bytes(), fallback.public_key_bytes());
+    }
+
+    #[test]
+    fn test_prekeys_keyed_by_id_are_deterministic_and_distinct() {
+        let seed = test_seed();
+        let otk0_a = derive_one_time_prekey(&seed, 0).unwrap();
+        let otk0_b = derive_one_time_prekey(&seed, 0).unwrap();
+        let otk1 = derive_one_time_prekey(&seed, 1).unwrap();
+
+        assert_eq!(otk0_a.public_key_bytes(), otk0_b.public_key_bytes());
+        assert_ne!(otk0_a.public_key_bytes(), otk1.public_key_bytes());
+    }
+
+    #[test]
+    fn test_signed_and_one_time_prekeys_do_not_collide() {
+        let seed = test_seed();
+        let signed = derive_signed_prekey(&seed, 0).unwrap();
+        let otk = derive_one_time_prekey(&seed, 0).unwrap();
+        assert_ne!(signed.public_key_bytes(), otk.public_key_bytes());
+    }
+
+    #[test]
+    fn test_hardened_index_is_rejected() {
+        let parent = master_node(&test_seed()).unwrap();
+        let result = derive_child(&parent, HARDENED_OFFSET);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_seed_from_mnemonic_requires_valid_mnemonic() {
+        assert!(seed_from_mnemonic("not a valid mnemonic phrase", "").is_err());
+    }
+}
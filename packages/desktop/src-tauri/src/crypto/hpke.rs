@@ -0,0 +1,172 @@
+//! HPKE-style single-shot sealing, used to build sealed-sender envelopes
+//!
+//! [`crate::crypto::service::CryptoService::seal_sender`] needs to encrypt a
+//! message to a recipient's public key alone, without first negotiating a
+//! Double Ratchet session key. This follows the shape of single-shot HPKE
+//! "Base" mode (RFC 9180): a fresh ephemeral X25519 key pair is
+//! Diffie-Hellman'd against the recipient's static public key, HKDF-SHA256
+//! turns the result into a key, and that key seals exactly one
+//! ChaCha20-Poly1305 message under a fixed (all-zero) nonce. The key
+//! schedule is this module's own (`derive_key`), not RFC 9180's labeled
+//! extract/expand, so envelopes are not interoperable with RFC 9180
+//! implementations. Since a fresh ephemeral key pair is
+//! generated per call, the derived key is never reused across calls, so a
+//! fixed nonce is safe here the same way it is in
+//! [`crate::crypto::opaque`]'s envelope sealing.
+//!
+//! This module only seals/opens a single opaque payload - it does not care
+//! what's inside. [`crate::crypto::service::CryptoService::seal_sender`] is
+//! the layer that decides what goes in the envelope (the true sender id plus
+//! the Double Ratchet ciphertext of the message body). No separate
+//! sender-authentication tag is included: the sender id is accepted only
+//! because that ciphertext decrypts under an existing session with it.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use vodozemac::Curve25519PublicKey;
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::keys::Curve25519KeyPair;
+use crate::crypto::system;
+
+const HPKE_INFO: &[u8] = b"NoChat HPKE single-shot v1";
+const SEAL_NONCE: [u8; 12] = [0u8; 12];
+
+/// A single-shot HPKE-sealed payload: the encapsulated key (our fresh
+/// ephemeral public key) plus the AEAD-sealed ciphertext.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBox {
+    /// Our ephemeral public key, tagged the same way as
+    /// [`Curve25519KeyPair::public_key_bytes`].
+    pub ephemeral_public: Vec<u8>,
+    /// The AEAD-sealed ciphertext.
+    pub ciphertext: Vec<u8>,
+}
+
+/// Seal `plaintext` to `recipient_public`, authenticating `aad` alongside
+/// it. Only the holder of the matching secret key can call [`open`] on the
+/// result.
+pub fn seal(
+    recipient_public: &Curve25519PublicKey,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> CryptoResult<SealedBox> {
+    let ephemeral = Curve25519KeyPair::generate();
+    let dh = ephemeral.diffie_hellman(recipient_public);
+    let key = derive_key(&dh, &ephemeral.public.to_bytes(), &recipient_public.to_bytes())?;
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&SEAL_NONCE),
+            Payload { msg: plaintext, aad },
+        )
+        .map_err(|_| CryptoError::EncryptionError("HPKE seal failed".to_string()))?;
+
+    Ok(SealedBox {
+        ephemeral_public: ephemeral.public_key_bytes(),
+        ciphertext,
+    })
+}
+
+/// Open a [`SealedBox`] produced by [`seal`] for `recipient_public`, using
+/// `recipient`'s matching secret key. `aad` must match what was passed to
+/// [`seal`].
+pub fn open(recipient: &Curve25519KeyPair, aad: &[u8], sealed: &SealedBox) -> CryptoResult<Vec<u8>> {
+    let (_, ephemeral_bytes) = system::untag(&sealed.ephemeral_public)?;
+    let ephemeral_arr: [u8; 32] = ephemeral_bytes
+        .try_into()
+        .map_err(|_| CryptoError::InvalidKey("Ephemeral public key must be 32 bytes".to_string()))?;
+    let ephemeral_public = Curve25519PublicKey::from_slice(&ephemeral_arr)?;
+
+    let dh = recipient.diffie_hellman(&ephemeral_public);
+    let key = derive_key(&dh, &ephemeral_arr, &recipient.public.to_bytes())?;
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    cipher
+        .decrypt(
+            Nonce::from_slice(&SEAL_NONCE),
+            Payload {
+                msg: &sealed.ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| CryptoError::DecryptionError("HPKE open failed".to_string()))
+}
+
+/// Derive the single-shot AEAD key from the DH shared secret, binding it to
+/// both public keys via a transcript hash - the same shape as
+/// [`crate::crypto::transport::Handshake::agree`]'s key derivation.
+fn derive_key(
+    dh: &[u8; 32],
+    ephemeral_public: &[u8; 32],
+    recipient_public: &[u8; 32],
+) -> CryptoResult<[u8; 32]> {
+    let mut transcript = Sha256::new();
+    transcript.update(ephemeral_public);
+    transcript.update(recipient_public);
+    let transcript_hash: [u8; 32] = transcript.finalize().into();
+
+    let hkdf = Hkdf::<Sha256>::new(Some(&transcript_hash), dh);
+    let mut key = [0u8; 32];
+    hkdf.expand(HPKE_INFO, &mut key)
+        .map_err(|e| CryptoError::KeyExchangeFailed(format!("HKDF expansion failed: {}", e)))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_round_trips() {
+        let recipient = Curve25519KeyPair::generate();
+        let plaintext = b"the true sender id lives in here";
+
+        let sealed = seal(&recipient.public, b"envelope-v1", plaintext).unwrap();
+        let opened = open(&recipient, b"envelope-v1", &sealed).unwrap();
+
+        assert_eq!(opened, plaintext);
+    }
+
+    #[test]
+    fn test_wrong_recipient_cannot_open() {
+        let recipient = Curve25519KeyPair::generate();
+        let impostor = Curve25519KeyPair::generate();
+
+        let sealed = seal(&recipient.public, b"envelope-v1", b"hello").unwrap();
+
+        assert!(open(&impostor, b"envelope-v1", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_aad_is_rejected() {
+        let recipient = Curve25519KeyPair::generate();
+        let sealed = seal(&recipient.public, b"envelope-v1", b"hello").unwrap();
+
+        assert!(open(&recipient, b"different-aad", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_is_rejected() {
+        let recipient = Curve25519KeyPair::generate();
+        let mut sealed = seal(&recipient.public, b"envelope-v1", b"hello").unwrap();
+        let last = sealed.ciphertext.len() - 1;
+        sealed.ciphertext[last] ^= 0xFF;
+
+        assert!(open(&recipient, b"envelope-v1", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_each_seal_uses_a_fresh_ephemeral_key() {
+        let recipient = Curve25519KeyPair::generate();
+        let a = seal(&recipient.public, b"envelope-v1", b"hello").unwrap();
+        let b = seal(&recipient.public, b"envelope-v1", b"hello").unwrap();
+
+        assert_ne!(a.ephemeral_public, b.ephemeral_public);
+        assert_ne!(a.ciphertext, b.ciphertext);
+    }
+}
@@ -0,0 +1,237 @@
+//! Trust-on-first-use identity verification
+//!
+//! The first time we see a peer's identity key (on [`establish_session`] or
+//! an inbound [`decrypt`] that carries `sender_identity_key`), it's recorded
+//! here alongside a `verified` flag the user hasn't yet set. Every
+//! subsequent sighting is compared against that stored key - a mismatch
+//! means the peer's key changed without an intervening, user-acknowledged
+//! re-verification, the classic signal of a MITM or a device compromise, so
+//! it's surfaced as [`CryptoError::IdentityKeyChanged`] instead of silently
+//! trusting the new key.
+//!
+//! [`establish_session`]: crate::commands::messaging::establish_session
+//! [`decrypt`]: crate::commands::messaging::decrypt_message
+//!
+//! Like `db::devices` and `db::outbox`, this table has no entry in a real
+//! migrations directory - it's created lazily via `ensure_schema`.
+
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::keys::constant_time_eq;
+
+/// A peer's first-seen identity key and whether the user has verified it
+/// out-of-band (e.g. by comparing safety numbers in person).
+#[derive(Debug, Clone)]
+pub struct StoredIdentity {
+    pub identity_key: Vec<u8>,
+    pub verified: bool,
+    pub first_seen_at: i64,
+}
+
+/// Create the `identity_keys` table if it doesn't already exist. Safe to
+/// call on every startup.
+pub async fn ensure_schema(pool: &SqlitePool) -> CryptoResult<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS identity_keys (
+            peer_id TEXT PRIMARY KEY,
+            identity_key BLOB NOT NULL,
+            verified INTEGER NOT NULL DEFAULT 0,
+            first_seen_at INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Compare `presented_identity_key` against whatever we've previously seen
+/// for `peer_id`, recording it as the trusted key if this is the first
+/// sighting. Returns [`CryptoError::IdentityKeyChanged`] if it differs from
+/// the stored key - callers (`establish_session`, `decrypt_message`) should
+/// propagate this rather than proceeding, so the UI can prompt the user to
+/// re-verify before the session is (re-)established.
+pub async fn check_or_record(
+    pool: &SqlitePool,
+    peer_id: &str,
+    presented_identity_key: &[u8],
+) -> CryptoResult<()> {
+    ensure_schema(pool).await?;
+
+    let existing = get_identity(pool, peer_id).await?;
+
+    match existing {
+        None => {
+            sqlx::query(
+                "INSERT INTO identity_keys (peer_id, identity_key, verified, first_seen_at) VALUES (?, ?, 0, ?)",
+            )
+            .bind(peer_id)
+            .bind(presented_identity_key)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(pool)
+            .await?;
+
+            Ok(())
+        }
+        Some(stored) if constant_time_eq(&stored.identity_key, presented_identity_key) => Ok(()),
+        Some(stored) => Err(CryptoError::IdentityKeyChanged {
+            peer_id: peer_id.to_string(),
+            old_fingerprint: short_fingerprint(&stored.identity_key),
+            new_fingerprint: short_fingerprint(presented_identity_key),
+        }),
+    }
+}
+
+/// Mark `peer_id`'s currently-stored identity key as verified, e.g. after
+/// the user confirms matching safety numbers with them out-of-band. A no-op
+/// if we've never seen a key for this peer.
+pub async fn mark_verified(pool: &SqlitePool, peer_id: &str) -> CryptoResult<()> {
+    ensure_schema(pool).await?;
+
+    sqlx::query("UPDATE identity_keys SET verified = 1 WHERE peer_id = ?")
+        .bind(peer_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// The identity key we've stored for `peer_id`, if we've ever seen one.
+pub async fn get_identity(pool: &SqlitePool, peer_id: &str) -> CryptoResult<Option<StoredIdentity>> {
+    ensure_schema(pool).await?;
+
+    let row = sqlx::query(
+        "SELECT identity_key, verified, first_seen_at FROM identity_keys WHERE peer_id = ?",
+    )
+    .bind(peer_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| StoredIdentity {
+        identity_key: row.get("identity_key"),
+        verified: row.get::<i64, _>("verified") != 0,
+        first_seen_at: row.get("first_seen_at"),
+    }))
+}
+
+/// Short (8-byte, hex-encoded) fingerprint of a raw identity key, used to
+/// label the before/after keys on [`CryptoError::IdentityKeyChanged`] and
+/// by `get_verification_status`. Deliberately the same truncated-SHA-256
+/// shape as
+/// [`CryptoService::fingerprint`](crate::crypto::CryptoService::fingerprint),
+/// just computed from an arbitrary key rather than only our own.
+pub fn short_fingerprint(key: &[u8]) -> String {
+    let hash = Sha256::digest(key);
+    hex::encode(&hash[..8])
+}
+
+/// A stable, mutually-computable numeric safety number for a pair of
+/// identity keys, Signal-style: each key is hashed independently into a
+/// 30-digit run (see [`numeric_fingerprint`]), and the two runs are ordered
+/// by raw key bytes rather than by
+/// "ours then theirs" - so both ends of the conversation land on the exact
+/// same 60-digit number regardless of who's asking.
+pub fn compute_safety_number(our_identity_key: &[u8], their_identity_key: &[u8]) -> String {
+    let (first, second) = if our_identity_key <= their_identity_key {
+        (our_identity_key, their_identity_key)
+    } else {
+        (their_identity_key, our_identity_key)
+    };
+
+    format!("{}{}", numeric_fingerprint(first), numeric_fingerprint(second))
+}
+
+/// 30 decimal digits derived from `key`: six 5-digit groups, one from each
+/// 5-byte chunk of SHA-256(round || key) reduced mod 100000. One 32-byte
+/// hash already yields all six groups, so only round 0 is ever hashed.
+fn numeric_fingerprint(key: &[u8]) -> String {
+    let mut digits = String::with_capacity(30);
+    let mut round: u32 = 0;
+
+    while digits.len() < 30 {
+        let mut hasher = Sha256::new();
+        hasher.update(round.to_be_bytes());
+        hasher.update(key);
+        let hash = hasher.finalize();
+
+        for chunk in hash.chunks(5) {
+            if digits.len() >= 30 {
+                break;
+            }
+            let value = chunk.iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+            digits.push_str(&format!("{:05}", value % 100_000));
+        }
+
+        round += 1;
+    }
+
+    digits.truncate(30);
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_first_sighting_is_recorded_unverified() {
+        let pool = memory_pool().await;
+        check_or_record(&pool, "alice", b"alice-key-v1").await.unwrap();
+
+        let stored = get_identity(&pool, "alice").await.unwrap().unwrap();
+        assert_eq!(stored.identity_key, b"alice-key-v1");
+        assert!(!stored.verified);
+    }
+
+    #[tokio::test]
+    async fn test_matching_key_is_accepted_silently() {
+        let pool = memory_pool().await;
+        check_or_record(&pool, "alice", b"alice-key-v1").await.unwrap();
+        check_or_record(&pool, "alice", b"alice-key-v1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_changed_key_is_rejected() {
+        let pool = memory_pool().await;
+        check_or_record(&pool, "alice", b"alice-key-v1").await.unwrap();
+
+        let err = check_or_record(&pool, "alice", b"alice-key-v2").await.unwrap_err();
+        match err {
+            CryptoError::IdentityKeyChanged { peer_id, old_fingerprint, new_fingerprint } => {
+                assert_eq!(peer_id, "alice");
+                assert_ne!(old_fingerprint, new_fingerprint);
+            }
+            other => panic!("expected IdentityKeyChanged, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mark_verified() {
+        let pool = memory_pool().await;
+        check_or_record(&pool, "alice", b"alice-key-v1").await.unwrap();
+        mark_verified(&pool, "alice").await.unwrap();
+
+        assert!(get_identity(&pool, "alice").await.unwrap().unwrap().verified);
+    }
+
+    #[test]
+    fn test_compute_safety_number_is_order_independent() {
+        let a = compute_safety_number(b"our-key", b"their-key");
+        let b = compute_safety_number(b"their-key", b"our-key");
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 60);
+        assert!(a.chars().all(|c| c.is_ascii_digit()));
+    }
+}
@@ -0,0 +1,243 @@
+//! Key re-request ("gossip") subsystem
+//!
+//! Today, a message that fails to decrypt because we never received (or
+//! have since pruned) the pairwise session or group sender key it was
+//! encrypted under is gone for good - there's no recovery path. This module
+//! tracks outgoing requests for that missing key material, analogous to
+//! Megolm key-request gossip in other Signal/Matrix-derived clients: record
+//! the request, send it to the user's other devices (or the original
+//! sender), and once a matching key arrives, mark the request fulfilled so
+//! it isn't resent.
+//!
+//! Requests for the same (conversation, session, sender key, requesting
+//! device) are coalesced: a second decryption failure for a key we've
+//! already asked for reuses the existing request rather than enqueuing a
+//! duplicate.
+
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+
+/// Lifecycle of an outgoing key request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyRequestState {
+    /// Recorded locally, not yet sent anywhere.
+    Created,
+    /// Sent to at least one target device.
+    Sent,
+    /// No longer needed (superseded, or the requester gave up).
+    Cancelled,
+    /// The missing key arrived and the request has served its purpose.
+    Fulfilled,
+}
+
+impl KeyRequestState {
+    fn as_str(self) -> &'static str {
+        match self {
+            KeyRequestState::Created => "created",
+            KeyRequestState::Sent => "sent",
+            KeyRequestState::Cancelled => "cancelled",
+            KeyRequestState::Fulfilled => "fulfilled",
+        }
+    }
+
+    fn parse(s: &str) -> CryptoResult<Self> {
+        match s {
+            "created" => Ok(Self::Created),
+            "sent" => Ok(Self::Sent),
+            "cancelled" => Ok(Self::Cancelled),
+            "fulfilled" => Ok(Self::Fulfilled),
+            other => Err(CryptoError::SerializationError(format!(
+                "unknown key request state: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A single outgoing request for missing key material.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRequest {
+    pub id: String,
+    pub conversation_id: String,
+    pub session_id: String,
+    pub sender_key: String,
+    pub requesting_device_id: String,
+    pub state: KeyRequestState,
+    pub created_at: i64,
+}
+
+/// Tracks outgoing key requests alongside [`crate::crypto::sessions::SessionStore`]'s
+/// pairwise/group session storage.
+pub struct KeyRequestStore {
+    db: SqlitePool,
+}
+
+impl KeyRequestStore {
+    /// Create a new key request store.
+    pub fn new(db: SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Record a request for the key that would let us decrypt
+    /// `session_id`/`sender_key` in `conversation_id`, coalescing with any
+    /// already-outstanding (`Created`/`Sent`) request for the same key and
+    /// requesting device rather than enqueuing a duplicate.
+    pub async fn save_key_request(
+        &self,
+        conversation_id: &str,
+        session_id: &str,
+        sender_key: &str,
+        requesting_device_id: &str,
+    ) -> CryptoResult<KeyRequest> {
+        if let Some(existing) = self
+            .find_active_request(conversation_id, session_id, sender_key, requesting_device_id)
+            .await?
+        {
+            return Ok(existing);
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let created_at = chrono::Utc::now().timestamp();
+
+        sqlx::query(
+            r#"
+            INSERT INTO key_requests
+                (id, conversation_id, session_id, sender_key, requesting_device_id, state, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#
+        )
+        .bind(&id)
+        .bind(conversation_id)
+        .bind(session_id)
+        .bind(sender_key)
+        .bind(requesting_device_id)
+        .bind(KeyRequestState::Created.as_str())
+        .bind(created_at)
+        .execute(&self.db)
+        .await?;
+
+        Ok(KeyRequest {
+            id,
+            conversation_id: conversation_id.to_string(),
+            session_id: session_id.to_string(),
+            sender_key: sender_key.to_string(),
+            requesting_device_id: requesting_device_id.to_string(),
+            state: KeyRequestState::Created,
+            created_at,
+        })
+    }
+
+    /// An outstanding (`Created`/`Sent`) request already covering this key
+    /// and requesting device, if one exists.
+    async fn find_active_request(
+        &self,
+        conversation_id: &str,
+        session_id: &str,
+        sender_key: &str,
+        requesting_device_id: &str,
+    ) -> CryptoResult<Option<KeyRequest>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, conversation_id, session_id, sender_key, requesting_device_id, state, created_at
+            FROM key_requests
+            WHERE conversation_id = $1 AND session_id = $2 AND sender_key = $3
+                AND requesting_device_id = $4 AND state IN ('created', 'sent')
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#
+        )
+        .bind(conversation_id)
+        .bind(session_id)
+        .bind(sender_key)
+        .bind(requesting_device_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        row.map(row_to_key_request).transpose()
+    }
+
+    /// Mark a request as sent to at least one target device.
+    pub async fn mark_request_sent(&self, request_id: &str) -> CryptoResult<()> {
+        self.set_state(request_id, KeyRequestState::Sent).await
+    }
+
+    /// Mark a request as no longer needed.
+    pub async fn mark_request_cancelled(&self, request_id: &str) -> CryptoResult<()> {
+        self.set_state(request_id, KeyRequestState::Cancelled).await
+    }
+
+    /// Mark a request as fulfilled - the missing key arrived.
+    pub async fn mark_request_fulfilled(&self, request_id: &str) -> CryptoResult<()> {
+        self.set_state(request_id, KeyRequestState::Fulfilled).await
+    }
+
+    /// Fulfil every still-outstanding (`Created`/`Sent`) request for the same
+    /// (conversation, session, sender key), regardless of which device made
+    /// it. Call this once the key material actually backfills - e.g. right
+    /// after [`crate::crypto::sessions::SessionStore::save_inbound_group_session`]
+    /// succeeds for that key - so a key that arrived through one path
+    /// doesn't leave sibling requests (from other local devices) dangling.
+    pub async fn cancel_requests_for_key(
+        &self,
+        conversation_id: &str,
+        session_id: &str,
+        sender_key: &str,
+    ) -> CryptoResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE key_requests SET state = $1
+            WHERE conversation_id = $2 AND session_id = $3 AND sender_key = $4
+                AND state IN ('created', 'sent')
+            "#
+        )
+        .bind(KeyRequestState::Fulfilled.as_str())
+        .bind(conversation_id)
+        .bind(session_id)
+        .bind(sender_key)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_state(&self, request_id: &str, state: KeyRequestState) -> CryptoResult<()> {
+        sqlx::query(r#"UPDATE key_requests SET state = $1 WHERE id = $2"#)
+            .bind(state.as_str())
+            .bind(request_id)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// All outgoing requests we've made, newest first.
+    pub async fn get_outgoing_requests(&self) -> CryptoResult<Vec<KeyRequest>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, conversation_id, session_id, sender_key, requesting_device_id, state, created_at
+            FROM key_requests
+            ORDER BY created_at DESC
+            "#
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter().map(row_to_key_request).collect()
+    }
+}
+
+fn row_to_key_request(row: SqliteRow) -> CryptoResult<KeyRequest> {
+    let state: String = row.get("state");
+    Ok(KeyRequest {
+        id: row.get("id"),
+        conversation_id: row.get("conversation_id"),
+        session_id: row.get("session_id"),
+        sender_key: row.get("sender_key"),
+        requesting_device_id: row.get("requesting_device_id"),
+        state: KeyRequestState::parse(&state)?,
+        created_at: row.get("created_at"),
+    })
+}
@@ -8,8 +8,23 @@
 
 use serde::{Deserialize, Serialize};
 use vodozemac::{Curve25519PublicKey, Curve25519SecretKey, Ed25519PublicKey, Ed25519SecretKey};
+use zeroize::Zeroizing;
 
 use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::system::{self, CryptoSystemId};
+
+/// Constant-time byte comparison
+///
+/// Used anywhere key material is compared (fingerprints, public keys) so
+/// verification can't leak timing information about how many leading bytes
+/// matched. Returns `false` immediately on a length mismatch since the
+/// length of key material is not secret.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
 
 /// Long-term identity key pair (Ed25519)
 ///
@@ -17,6 +32,11 @@ use crate::crypto::errors::{CryptoError, CryptoResult};
 /// - Signing prekeys to prove ownership
 /// - Long-term identity verification
 /// - Key fingerprint generation for verification
+///
+/// Deliberately does not derive `PartialEq`/`Eq`/`Hash`/`Ord`: a derived
+/// comparison on secret material would short-circuit on the first differing
+/// byte and leak timing information. Compare fingerprints via
+/// [`IdentityKeyPair::verify_fingerprint`] instead.
 pub struct IdentityKeyPair {
     /// Public key (safe to share)
     pub public: Ed25519PublicKey,
@@ -32,8 +52,12 @@ impl IdentityKeyPair {
         Self { public, secret }
     }
 
-    /// Restore from existing key bytes
+    /// Restore from existing key bytes, each prefixed with a
+    /// [`CryptoSystemId`] tag (see [`IdentityKeyPair::public_key_bytes`]).
     pub fn from_bytes(public_bytes: &[u8], secret_bytes: &[u8]) -> CryptoResult<Self> {
+        let (_, public_bytes) = system::untag(public_bytes)?;
+        let (_, secret_bytes) = system::untag(secret_bytes)?;
+
         let public_arr: [u8; 32] = public_bytes.try_into()
             .map_err(|_| CryptoError::InvalidKey("Public key must be 32 bytes".to_string()))?;
         let secret_arr: [u8; 32] = secret_bytes.try_into()
@@ -44,19 +68,41 @@ impl IdentityKeyPair {
         Ok(Self { public, secret })
     }
 
+    /// Derive an identity key pair from a raw (untagged) 32-byte seed (e.g. a
+    /// SLIP-0010 derived node secret in `crypto::hdkey`), rather than
+    /// generating fresh randomness.
+    pub fn from_seed_bytes(seed: &[u8]) -> CryptoResult<Self> {
+        let seed_arr: [u8; 32] = seed.try_into()
+            .map_err(|_| CryptoError::InvalidKey("Seed must be 32 bytes".to_string()))?;
+        let secret = Ed25519SecretKey::from_slice(&seed_arr);
+        let public = secret.public_key();
+        Ok(Self { public, secret })
+    }
+
     /// Sign a message with this identity key
+    ///
+    /// The returned signature is prefixed with a [`CryptoSystemId`] tag, same
+    /// as [`public_key_bytes`](Self::public_key_bytes), so a verifier knows
+    /// which suite produced it.
     pub fn sign(&self, message: &[u8]) -> Vec<u8> {
-        self.secret.sign(message).to_bytes().to_vec()
+        let signature = self.secret.sign(message).to_bytes().to_vec();
+        system::tag(CryptoSystemId::V0, &signature)
     }
 
-    /// Get the public key bytes
+    /// Get the public key bytes, prefixed with a one-byte [`CryptoSystemId`]
+    /// tag so a future `from_bytes` can tell which suite produced them
+    /// instead of misinterpreting bytes from a different one.
     pub fn public_key_bytes(&self) -> Vec<u8> {
-        self.public.as_bytes().to_vec()
+        system::tag(CryptoSystemId::V0, self.public.as_bytes())
     }
 
-    /// Get the secret key bytes (for secure storage)
-    pub fn secret_key_bytes(&self) -> Vec<u8> {
-        self.secret.to_bytes().to_vec()
+    /// Get the secret key bytes (for secure storage), tagged the same way as
+    /// [`public_key_bytes`](Self::public_key_bytes).
+    ///
+    /// Returned wrapped in [`Zeroizing`] so the copy is scrubbed as soon as
+    /// the caller drops it, rather than lingering in memory indefinitely.
+    pub fn secret_key_bytes(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(system::tag(CryptoSystemId::V0, &self.secret.to_bytes()))
     }
 
     /// Compute a fingerprint for key verification
@@ -65,6 +111,12 @@ impl IdentityKeyPair {
         let hash = Sha256::digest(self.public.as_bytes());
         hex::encode(&hash[..8])
     }
+
+    /// Check a fingerprint against this key's own fingerprint in constant
+    /// time, e.g. when a user manually verifies a peer's safety number.
+    pub fn verify_fingerprint(&self, expected: &str) -> bool {
+        constant_time_eq(self.fingerprint().as_bytes(), expected.as_bytes())
+    }
 }
 
 /// Curve25519 key pair for Diffie-Hellman key exchange
@@ -74,6 +126,10 @@ impl IdentityKeyPair {
 /// - Ratchet keys in Double Ratchet
 /// - Signed prekeys
 /// - One-time prekeys
+/// - Sealed-sender HPKE envelope sealing/opening ([`crate::crypto::hpke`])
+///
+/// Deliberately does not derive `PartialEq`/`Eq`/`Hash`/`Ord` - see
+/// [`IdentityKeyPair`]'s docs for why.
 pub struct Curve25519KeyPair {
     /// Public key (safe to share)
     pub public: Curve25519PublicKey,
@@ -89,8 +145,12 @@ impl Curve25519KeyPair {
         Self { public, secret }
     }
 
-    /// Restore from existing key bytes
+    /// Restore from existing key bytes, each prefixed with a
+    /// [`CryptoSystemId`] tag (see [`Curve25519KeyPair::public_key_bytes`]).
     pub fn from_bytes(public_bytes: &[u8], secret_bytes: &[u8]) -> CryptoResult<Self> {
+        let (_, public_bytes) = system::untag(public_bytes)?;
+        let (_, secret_bytes) = system::untag(secret_bytes)?;
+
         let public_arr: [u8; 32] = public_bytes.try_into()
             .map_err(|_| CryptoError::InvalidKey("Public key must be 32 bytes".to_string()))?;
         let secret_arr: [u8; 32] = secret_bytes.try_into()
@@ -106,14 +166,19 @@ impl Curve25519KeyPair {
         self.secret.diffie_hellman(their_public).to_bytes()
     }
 
-    /// Get the public key bytes
+    /// Get the public key bytes, prefixed with a one-byte [`CryptoSystemId`]
+    /// tag so a future `from_bytes` can tell which suite produced them
+    /// instead of misinterpreting bytes from a different one.
     pub fn public_key_bytes(&self) -> Vec<u8> {
-        self.public.to_bytes().to_vec()
+        system::tag(CryptoSystemId::V0, &self.public.to_bytes())
     }
 
     /// Get the secret key bytes (for secure storage)
-    pub fn secret_key_bytes(&self) -> Vec<u8> {
-        self.secret.to_bytes().to_vec()
+    ///
+    /// Returned wrapped in [`Zeroizing`] so the copy is scrubbed as soon as
+    /// the caller drops it, rather than lingering in memory indefinitely.
+    pub fn secret_key_bytes(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(system::tag(CryptoSystemId::V0, &self.secret.to_bytes()))
     }
 }
 
@@ -149,7 +214,8 @@ impl SignedPreKey {
 
     /// Verify the signature with the identity public key
     pub fn verify(&self, identity_public: &Ed25519PublicKey) -> CryptoResult<()> {
-        let signature = vodozemac::Ed25519Signature::from_slice(&self.signature).map_err(|e| {
+        let (_, signature) = system::untag(&self.signature)?;
+        let signature = vodozemac::Ed25519Signature::from_slice(signature).map_err(|e| {
             CryptoError::SignatureError(format!("Invalid signature format: {:?}", e))
         })?;
 
@@ -168,7 +234,118 @@ impl SignedPreKey {
 
     /// Get the Curve25519 public key
     pub fn get_public_key(&self) -> CryptoResult<Curve25519PublicKey> {
-        let arr: [u8; 32] = self.public_key.as_slice().try_into()
+        let (_, public_key) = system::untag(&self.public_key)?;
+        let arr: [u8; 32] = public_key.try_into()
+            .map_err(|_| CryptoError::InvalidKey("Key must be 32 bytes".to_string()))?;
+        Curve25519PublicKey::from_slice(&arr).map_err(Into::into)
+    }
+
+    /// Verify many prekeys signed by the same identity key at once, using
+    /// the ed25519 batch-verification equation instead of checking each
+    /// signature in turn - substantially cheaper than a `verify()` loop when
+    /// syncing a server bundle with dozens of prekeys.
+    ///
+    /// Returns the index of the first prekey whose signature doesn't verify
+    /// on failure, rather than just an aggregate error, by falling back to
+    /// individual checks (the batch equation itself can only tell you *that*
+    /// something in the batch is wrong, not *which* signature).
+    pub fn verify_batch(prekeys: &[&SignedPreKey], identity_public: &Ed25519PublicKey) -> Result<(), usize> {
+        use ed25519_dalek::{Signature, VerifyingKey};
+
+        if prekeys.is_empty() {
+            return Ok(());
+        }
+
+        let verifying_key = VerifyingKey::from_bytes(identity_public.as_bytes()).map_err(|_| 0usize)?;
+
+        let mut signatures = Vec::with_capacity(prekeys.len());
+        for (i, prekey) in prekeys.iter().enumerate() {
+            let (_, sig_bytes) = system::untag(&prekey.signature).map_err(|_| i)?;
+            let sig_arr: [u8; 64] = sig_bytes.try_into().map_err(|_| i)?;
+            signatures.push(Signature::from_bytes(&sig_arr));
+        }
+
+        let messages: Vec<&[u8]> = prekeys.iter().map(|p| p.public_key.as_slice()).collect();
+        let verifying_keys: Vec<VerifyingKey> = std::iter::repeat(verifying_key).take(prekeys.len()).collect();
+
+        if ed25519_dalek::verify_batch(&messages, &signatures, &verifying_keys).is_ok() {
+            return Ok(());
+        }
+
+        // Something in the batch failed; find out which one.
+        for (i, prekey) in prekeys.iter().enumerate() {
+            if prekey.verify(identity_public).is_err() {
+                return Err(i);
+            }
+        }
+        // Unreachable in practice: the aggregate check failed but every
+        // individual signature verified. Blame the first entry rather than
+        // panic, since that's still a safer default than claiming success.
+        Err(0)
+    }
+}
+
+/// Fallback prekey (aka "last-resort" prekey)
+///
+/// Structurally identical to a [`SignedPreKey`] - a signed Curve25519 key -
+/// but it is never consumed. It exists so that X3DH always has a
+/// forward-secret-ish key to fall back on when a peer's one-time prekey
+/// pool has run dry, instead of the handshake failing outright. It is
+/// rotated on a schedule rather than removed after use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FallbackPreKey {
+    /// Unique identifier for this prekey
+    pub key_id: u32,
+    /// The Curve25519 public key
+    pub public_key: Vec<u8>,
+    /// Ed25519 signature of the public key
+    pub signature: Vec<u8>,
+    /// Unix timestamp when this prekey was created
+    pub created_at: i64,
+}
+
+impl FallbackPreKey {
+    /// Create a new fallback prekey
+    pub fn new(key_id: u32, key_pair: &Curve25519KeyPair, identity: &IdentityKeyPair) -> Self {
+        let public_key = key_pair.public_key_bytes();
+        let signature = identity.sign(&public_key);
+
+        Self {
+            key_id,
+            public_key,
+            signature,
+            created_at: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    /// Verify the signature with the identity public key
+    pub fn verify(&self, identity_public: &Ed25519PublicKey) -> CryptoResult<()> {
+        let (_, signature) = system::untag(&self.signature)?;
+        let signature = vodozemac::Ed25519Signature::from_slice(signature).map_err(|e| {
+            CryptoError::SignatureError(format!("Invalid signature format: {:?}", e))
+        })?;
+
+        identity_public
+            .verify(&self.public_key, &signature)
+            .map_err(|e| CryptoError::SignatureError(format!("Signature verification failed: {}", e)))
+    }
+
+    /// Check if this prekey is due for rotation (older than max_age_days)
+    ///
+    /// Unlike [`SignedPreKey::is_expired`], an overdue fallback key is still
+    /// usable - rotation is a hygiene measure, not a hard cutoff - so callers
+    /// should rotate proactively rather than reject the key outright.
+    pub fn is_due_for_rotation(&self, max_age_days: i64) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        let age_seconds = now - self.created_at;
+        let max_age_seconds = max_age_days * 24 * 60 * 60;
+        age_seconds > max_age_seconds
+    }
+
+    /// Get the Curve25519 public key
+    pub fn get_public_key(&self) -> CryptoResult<Curve25519PublicKey> {
+        let (_, public_key) = system::untag(&self.public_key)?;
+        let arr: [u8; 32] = public_key.try_into()
             .map_err(|_| CryptoError::InvalidKey("Key must be 32 bytes".to_string()))?;
         Curve25519PublicKey::from_slice(&arr).map_err(Into::into)
     }
@@ -197,20 +374,24 @@ impl OneTimePreKey {
 
     /// Get the Curve25519 public key
     pub fn get_public_key(&self) -> CryptoResult<Curve25519PublicKey> {
-        let arr: [u8; 32] = self.public_key.as_slice().try_into()
+        let (_, public_key) = system::untag(&self.public_key)?;
+        let arr: [u8; 32] = public_key.try_into()
             .map_err(|_| CryptoError::InvalidKey("Key must be 32 bytes".to_string()))?;
         Curve25519PublicKey::from_slice(&arr).map_err(Into::into)
     }
 }
 
 /// Stored prekey with secret key (for local storage only)
+///
+/// Deliberately does not derive `PartialEq`/`Eq`/`Hash`/`Ord` - see
+/// [`IdentityKeyPair`]'s docs for why.
 pub struct StoredPreKey {
     /// Unique identifier
     pub key_id: u32,
     /// Public key bytes
     pub public_key: Vec<u8>,
-    /// Secret key bytes (encrypted before storage)
-    pub secret_key: Vec<u8>,
+    /// Secret key bytes (encrypted before storage), scrubbed on drop
+    pub secret_key: Zeroizing<Vec<u8>>,
     /// Whether this is a signed prekey (vs one-time)
     pub is_signed: bool,
 }
@@ -239,8 +420,9 @@ mod tests {
     #[test]
     fn test_identity_key_generation() {
         let key = IdentityKeyPair::generate();
-        assert_eq!(key.public_key_bytes().len(), 32);
-        assert_eq!(key.secret_key_bytes().len(), 32); // Ed25519 secret key seed is 32 bytes
+        // +1 for the CryptoSystemId tag byte prefixed onto serialized key material
+        assert_eq!(key.public_key_bytes().len(), 33);
+        assert_eq!(key.secret_key_bytes().len(), 33); // Ed25519 secret key seed is 32 bytes
     }
 
     #[test]
@@ -248,18 +430,19 @@ mod tests {
         let key = IdentityKeyPair::generate();
         let message = b"test message";
         let signature = key.sign(message);
-        assert_eq!(signature.len(), 64); // Ed25519 signature is 64 bytes
+        assert_eq!(signature.len(), 65); // tag byte + 64-byte Ed25519 signature
 
         // Verify signature
-        let sig = vodozemac::Ed25519Signature::from_slice(&signature).unwrap();
+        let (_, signature) = system::untag(&signature).unwrap();
+        let sig = vodozemac::Ed25519Signature::from_slice(signature).unwrap();
         assert!(key.public.verify(message, &sig).is_ok());
     }
 
     #[test]
     fn test_curve25519_key_generation() {
         let key = Curve25519KeyPair::generate();
-        assert_eq!(key.public_key_bytes().len(), 32);
-        assert_eq!(key.secret_key_bytes().len(), 32);
+        assert_eq!(key.public_key_bytes().len(), 33);
+        assert_eq!(key.secret_key_bytes().len(), 33);
     }
 
     #[test]
@@ -283,6 +466,35 @@ mod tests {
         assert!(signed_prekey.verify(&identity.public).is_ok());
     }
 
+    #[test]
+    fn test_signed_prekey_verify_batch_all_valid() {
+        let identity = IdentityKeyPair::generate();
+        let prekeys: Vec<SignedPreKey> = (0..5)
+            .map(|i| SignedPreKey::new(i, &Curve25519KeyPair::generate(), &identity))
+            .collect();
+        let refs: Vec<&SignedPreKey> = prekeys.iter().collect();
+
+        assert!(SignedPreKey::verify_batch(&refs, &identity.public).is_ok());
+    }
+
+    #[test]
+    fn test_signed_prekey_verify_batch_reports_bad_index() {
+        let identity = IdentityKeyPair::generate();
+        let mut prekeys: Vec<SignedPreKey> = (0..5)
+            .map(|i| SignedPreKey::new(i, &Curve25519KeyPair::generate(), &identity))
+            .collect();
+        prekeys[3].signature[1] ^= 0xFF;
+        let refs: Vec<&SignedPreKey> = prekeys.iter().collect();
+
+        assert_eq!(SignedPreKey::verify_batch(&refs, &identity.public), Err(3));
+    }
+
+    #[test]
+    fn test_signed_prekey_verify_batch_empty_is_ok() {
+        let identity = IdentityKeyPair::generate();
+        assert!(SignedPreKey::verify_batch(&[], &identity.public).is_ok());
+    }
+
     #[test]
     fn test_signed_prekey_expiry() {
         let identity = IdentityKeyPair::generate();
@@ -303,4 +515,44 @@ mod tests {
         let fingerprint = key.fingerprint();
         assert_eq!(fingerprint.len(), 16); // 8 bytes as hex = 16 chars
     }
+
+    #[test]
+    fn test_verify_fingerprint() {
+        let key = IdentityKeyPair::generate();
+        let fingerprint = key.fingerprint();
+
+        assert!(key.verify_fingerprint(&fingerprint));
+        assert!(!key.verify_fingerprint("0000000000000000"));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn test_fallback_prekey() {
+        let identity = IdentityKeyPair::generate();
+        let prekey_pair = Curve25519KeyPair::generate();
+        let fallback_prekey = FallbackPreKey::new(1, &prekey_pair, &identity);
+
+        assert_eq!(fallback_prekey.key_id, 1);
+        assert!(fallback_prekey.verify(&identity.public).is_ok());
+    }
+
+    #[test]
+    fn test_fallback_prekey_rotation_due() {
+        let identity = IdentityKeyPair::generate();
+        let prekey_pair = Curve25519KeyPair::generate();
+        let mut fallback_prekey = FallbackPreKey::new(1, &prekey_pair, &identity);
+
+        // Fresh key should not need rotation
+        assert!(!fallback_prekey.is_due_for_rotation(30));
+
+        // Set created_at to 31 days ago
+        fallback_prekey.created_at = chrono::Utc::now().timestamp() - (31 * 24 * 60 * 60);
+        assert!(fallback_prekey.is_due_for_rotation(30));
+    }
 }
@@ -0,0 +1,237 @@
+//! Megolm group sessions for scalable multi-party room encryption
+//!
+//! [`RatchetSession`](crate::crypto::RatchetSession) only covers pairwise
+//! Olm, so an N-person room would otherwise need every sender re-encrypting
+//! each message once per recipient (O(N^2) ciphertexts). Megolm instead
+//! gives each sender a single forward-ratcheting symmetric chain: they
+//! encrypt once per message with an [`OutboundGroupSession`], and share its
+//! current ratchet state ([`OutboundGroupSession::export_session_key`]) with
+//! room members once - typically wrapped in an existing 1:1 Olm message so
+//! only they can read it. Each recipient imports that session key into an
+//! [`InboundGroupSession`] and can then decrypt every subsequent message in
+//! the chain without any further key exchange.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use vodozemac::megolm::{
+    GroupSession, GroupSessionPickle, InboundGroupSession as VodozemacInboundGroupSession,
+    InboundGroupSessionPickle, MegolmMessage, SessionConfig, SessionKey,
+};
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+
+/// An outbound Megolm session: encrypts messages for a room by ratcheting a
+/// single symmetric chain forward, rather than re-encrypting per recipient.
+pub struct OutboundGroupSession {
+    inner: GroupSession,
+}
+
+impl OutboundGroupSession {
+    /// Start a new outbound group session.
+    pub fn new() -> Self {
+        Self {
+            inner: GroupSession::new(SessionConfig::version_1()),
+        }
+    }
+
+    /// Encrypt a message, advancing the ratchet by one step.
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> MegolmMessage {
+        self.inner.encrypt(plaintext)
+    }
+
+    /// How many messages this chain has encrypted so far.
+    pub fn message_index(&self) -> u32 {
+        self.inner.message_index()
+    }
+
+    /// Export the current ratchet state, to be shared with room members -
+    /// typically over an existing 1:1 Olm channel - so they can decrypt this
+    /// chain's future messages via [`InboundGroupSession::import`].
+    pub fn export_session_key(&self) -> SessionKey {
+        self.inner.session_key()
+    }
+
+    /// Unique identifier for this session, shared with the room alongside
+    /// the exported session key.
+    pub fn session_id(&self) -> String {
+        self.inner.session_id()
+    }
+
+    /// Serialize the session for storage.
+    pub fn pickle(&self) -> CryptoResult<String> {
+        serde_json::to_string(&self.inner.pickle())
+            .map_err(|e| CryptoError::SerializationError(e.to_string()))
+    }
+
+    /// Restore a session from storage.
+    pub fn unpickle(pickled: &str) -> CryptoResult<Self> {
+        let pickle: GroupSessionPickle = serde_json::from_str(pickled)
+            .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+        Ok(Self {
+            inner: GroupSession::from(pickle),
+        })
+    }
+}
+
+impl Default for OutboundGroupSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An inbound Megolm session: imported once from a sender's
+/// [`OutboundGroupSession::export_session_key`], then used to decrypt every
+/// subsequent message in that chain.
+pub struct InboundGroupSession {
+    inner: VodozemacInboundGroupSession,
+    /// Message indices already decrypted - a forward-ratcheting chain will
+    /// happily decrypt a replayed ciphertext at an index it has already
+    /// advanced past, so duplicates are rejected here instead.
+    seen_indices: HashSet<u32>,
+}
+
+impl InboundGroupSession {
+    /// Import a session key shared by the sender's [`OutboundGroupSession`].
+    pub fn import(session_key: &SessionKey) -> Self {
+        Self {
+            inner: VodozemacInboundGroupSession::new(session_key, SessionConfig::version_1()),
+            seen_indices: HashSet::new(),
+        }
+    }
+
+    /// Decrypt a message, returning the plaintext and its message index.
+    ///
+    /// Rejects a message whose index has already been decrypted by this
+    /// session, rather than silently decrypting a replayed ciphertext.
+    pub fn decrypt(&mut self, message: &MegolmMessage) -> CryptoResult<(Vec<u8>, u32)> {
+        let decrypted = self
+            .inner
+            .decrypt(message)
+            .map_err(|e| CryptoError::DecryptionError(e.to_string()))?;
+
+        if !self.seen_indices.insert(decrypted.message_index) {
+            return Err(CryptoError::DecryptionError(format!(
+                "message index {} was already decrypted (possible replay)",
+                decrypted.message_index
+            )));
+        }
+
+        Ok((decrypted.plaintext, decrypted.message_index))
+    }
+
+    /// Unique identifier for this session.
+    pub fn session_id(&self) -> String {
+        self.inner.session_id()
+    }
+
+    /// The earliest message index this session can decrypt. A session
+    /// imported from a key shared mid-conversation (e.g. a new room member)
+    /// starts above zero, which is why
+    /// [`SessionStore::save_inbound_group_session`](crate::crypto::sessions::SessionStore::save_inbound_group_session)
+    /// refuses to replace an already-stored session with one whose
+    /// `first_known_index` is higher - that would throw away the ability to
+    /// decrypt earlier history.
+    pub fn first_known_index(&self) -> u32 {
+        self.inner.first_known_index()
+    }
+
+    /// Serialize the session (including which indices have been seen) for
+    /// storage.
+    pub fn pickle(&self) -> CryptoResult<String> {
+        let state = PickledInboundGroupSession {
+            session: self.inner.pickle(),
+            seen_indices: self.seen_indices.iter().copied().collect(),
+        };
+        serde_json::to_string(&state).map_err(|e| CryptoError::SerializationError(e.to_string()))
+    }
+
+    /// Restore a session from storage.
+    pub fn unpickle(pickled: &str) -> CryptoResult<Self> {
+        let state: PickledInboundGroupSession = serde_json::from_str(pickled)
+            .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+        Ok(Self {
+            inner: VodozemacInboundGroupSession::from(state.session),
+            seen_indices: state.seen_indices.into_iter().collect(),
+        })
+    }
+}
+
+/// Serializable inbound session state
+#[derive(Serialize, Deserialize)]
+struct PickledInboundGroupSession {
+    session: InboundGroupSessionPickle,
+    seen_indices: Vec<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_session_round_trip() {
+        let mut outbound = OutboundGroupSession::new();
+        let session_key = outbound.export_session_key();
+        let mut inbound = InboundGroupSession::import(&session_key);
+
+        let message = outbound.encrypt(b"hello room");
+        let (plaintext, index) = inbound.decrypt(&message).unwrap();
+
+        assert_eq!(plaintext, b"hello room");
+        assert_eq!(index, 0);
+    }
+
+    #[test]
+    fn test_message_index_advances() {
+        let mut outbound = OutboundGroupSession::new();
+        let mut inbound = InboundGroupSession::import(&outbound.export_session_key());
+
+        let first = outbound.encrypt(b"one");
+        let second = outbound.encrypt(b"two");
+
+        let (_, first_index) = inbound.decrypt(&first).unwrap();
+        let (_, second_index) = inbound.decrypt(&second).unwrap();
+
+        assert_eq!(first_index, 0);
+        assert_eq!(second_index, 1);
+    }
+
+    #[test]
+    fn test_duplicate_index_is_rejected() {
+        let mut outbound = OutboundGroupSession::new();
+        let mut inbound = InboundGroupSession::import(&outbound.export_session_key());
+
+        let message = outbound.encrypt(b"hello");
+        assert!(inbound.decrypt(&message).is_ok());
+        assert!(inbound.decrypt(&message).is_err());
+    }
+
+    #[test]
+    fn test_outbound_pickle_unpickle_round_trip() {
+        let mut outbound = OutboundGroupSession::new();
+        outbound.encrypt(b"before pickling");
+
+        let pickled = outbound.pickle().unwrap();
+        let mut restored = OutboundGroupSession::unpickle(&pickled).unwrap();
+
+        assert_eq!(restored.message_index(), outbound.message_index());
+        let message = restored.encrypt(b"after restoring");
+        assert_eq!(restored.message_index(), outbound.message_index() + 1);
+        let _ = message;
+    }
+
+    #[test]
+    fn test_inbound_pickle_unpickle_preserves_seen_indices() {
+        let mut outbound = OutboundGroupSession::new();
+        let mut inbound = InboundGroupSession::import(&outbound.export_session_key());
+
+        let message = outbound.encrypt(b"hello");
+        inbound.decrypt(&message).unwrap();
+
+        let pickled = inbound.pickle().unwrap();
+        let mut restored = InboundGroupSession::unpickle(&pickled).unwrap();
+
+        // The restored session must still reject the already-seen index.
+        assert!(restored.decrypt(&message).is_err());
+    }
+}
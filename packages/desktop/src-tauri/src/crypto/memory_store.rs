@@ -0,0 +1,163 @@
+//! In-memory [`CryptoStore`] implementation
+//!
+//! Backs [`crate::crypto::service::CryptoService`] with a plain in-process
+//! map instead of SQLite, so the crypto layer can be exercised in unit tests
+//! without standing up a database. Sessions and the account are still run
+//! through [`pickle`]/[`unpickle`](RatchetSession::unpickle) under a random
+//! in-memory-only pickle key, exactly as [`SessionStore`] does - this keeps
+//! both implementations exercising the same (de)serialization path and
+//! avoids needing [`OlmAccount`]/[`RatchetSession`] to implement `Clone`.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::crypto::errors::CryptoResult;
+use crate::crypto::ratchet::{OlmAccount, PickleKey};
+use crate::crypto::sessions::{generate_pickle_key, CryptoStore};
+use crate::crypto::RatchetSession;
+
+#[derive(Default)]
+struct State {
+    account: Option<String>,
+    /// `session_id -> (peer_id, pickled session)`
+    sessions: HashMap<String, (String, String)>,
+}
+
+/// A [`CryptoStore`] that keeps everything in memory for the lifetime of the
+/// process - nothing is persisted across restarts. Intended for tests and
+/// for callers that want a crypto-only sandbox with no database dependency.
+pub struct InMemoryCryptoStore {
+    pickle_key: PickleKey,
+    state: RwLock<State>,
+}
+
+impl InMemoryCryptoStore {
+    /// Create an empty store with a freshly generated pickle key.
+    pub fn new() -> Self {
+        Self {
+            pickle_key: generate_pickle_key(),
+            state: RwLock::new(State::default()),
+        }
+    }
+}
+
+impl Default for InMemoryCryptoStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl CryptoStore for InMemoryCryptoStore {
+    async fn load_account(&self) -> CryptoResult<Option<OlmAccount>> {
+        let state = self.state.read().await;
+        match &state.account {
+            Some(pickled) => Ok(Some(OlmAccount::from_pickle(pickled, &self.pickle_key)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save_account(&self, account: &OlmAccount) -> CryptoResult<()> {
+        let pickled = account.pickle(&self.pickle_key)?;
+        self.state.write().await.account = Some(pickled);
+        Ok(())
+    }
+
+    async fn load_sessions_for_peer(&self, peer_id: &str) -> CryptoResult<Vec<RatchetSession>> {
+        let state = self.state.read().await;
+        state
+            .sessions
+            .values()
+            .filter(|(peer, _)| peer == peer_id)
+            .map(|(_, pickled)| RatchetSession::unpickle(pickled, &self.pickle_key))
+            .collect()
+    }
+
+    async fn save_session(&self, session: &RatchetSession) -> CryptoResult<()> {
+        let pickled = session.pickle(&self.pickle_key)?;
+        self.state
+            .write()
+            .await
+            .sessions
+            .insert(session.session_id(), (session.peer_id.clone(), pickled));
+        Ok(())
+    }
+
+    async fn delete_session(&self, peer_id: &str) -> CryptoResult<()> {
+        self.state
+            .write()
+            .await
+            .sessions
+            .retain(|_, (peer, _)| peer != peer_id);
+        Ok(())
+    }
+
+    async fn delete_session_by_id(&self, session_id: &str) -> CryptoResult<()> {
+        self.state.write().await.sessions.remove(session_id);
+        Ok(())
+    }
+
+    async fn delete_all_sessions(&self) -> CryptoResult<()> {
+        self.state.write().await.sessions.clear();
+        Ok(())
+    }
+
+    async fn list_peers(&self) -> CryptoResult<Vec<String>> {
+        let state = self.state.read().await;
+        let mut peers: Vec<String> = state
+            .sessions
+            .values()
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        peers.sort();
+        peers.dedup();
+        Ok(peers)
+    }
+
+    async fn count_one_time_prekeys(&self) -> CryptoResult<i64> {
+        // The in-memory store only covers the account/session surface used
+        // by `CryptoService` - one-time prekey bookkeeping lives in
+        // `crate::crypto::prekeys` and isn't part of this trait's contract,
+        // so there's nothing to count here.
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_account_round_trips() {
+        let store = InMemoryCryptoStore::new();
+        assert!(store.load_account().await.unwrap().is_none());
+
+        let account = OlmAccount::new();
+        store.save_account(&account).await.unwrap();
+
+        let loaded = store.load_account().await.unwrap().unwrap();
+        assert_eq!(loaded.identity_key().to_bytes(), account.identity_key().to_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_round_trip_and_delete() {
+        let store = InMemoryCryptoStore::new();
+
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(1);
+        let bob_otk = bob.one_time_keys().into_iter().next().unwrap().1;
+        let session = alice.create_outbound_session(bob.identity_key(), bob_otk).unwrap();
+        let session_id = session.session_id();
+
+        store.save_session(&session).await.unwrap();
+        assert_eq!(store.list_peers().await.unwrap(), vec![session.peer_id.clone()]);
+        assert_eq!(store.load_sessions_for_peer(&session.peer_id).await.unwrap().len(), 1);
+
+        store.delete_session_by_id(&session_id).await.unwrap();
+        assert!(store.load_sessions_for_peer(&session.peer_id).await.unwrap().is_empty());
+        assert!(store.list_peers().await.unwrap().is_empty());
+    }
+}
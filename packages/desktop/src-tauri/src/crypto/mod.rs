@@ -6,10 +6,28 @@
 //! ## Components
 //!
 //! - **keys**: Key generation and storage (identity, signed prekeys, one-time prekeys)
+//! - **devices**: Multi-device identity - one `PreKeyManager` per device, bundle fan-out
 //! - **x3dh**: Extended Triple Diffie-Hellman for asynchronous key agreement
 //! - **ratchet**: Double Ratchet for per-message forward secrecy
-//! - **prekeys**: Prekey bundle management and replenishment
-//! - **sessions**: Session storage and retrieval (SQLite-backed)
+//! - **prekeys**: Prekey bundle management and replenishment, namespaced per `KeyDomain` (account / phone number)
+//! - **identity**: Trust-on-first-use identity verification and key-change detection
+//! - **hdkey**: SLIP-0010/BIP39 deterministic key derivation from a recovery phrase
+//! - **hpke**: Single-shot HPKE sealing used to build sealed-sender envelopes
+//!   that hide the true sender from the relay server
+//! - **cache**: In-memory `CryptoStore` cache in front of `SessionStore`
+//! - **megolm**: Megolm group sessions for scalable multi-party room encryption
+//! - **opaque**: OPAQUE augmented PAKE password login - the server never
+//!   sees a password or anything password-equivalent
+//! - **key_requests**: Outgoing key re-request ("gossip") tracking for
+//!   messages that fail to decrypt
+//! - **pickle**: Authenticated encryption for account/session pickles at rest
+//! - **export**: Passphrase-protected account/session export for device migration
+//! - **provisioning**: QR-based device linking (presage/libsignal-style `LinkDevice`)
+//! - **system**: Pluggable, versioned cryptosystem abstraction for algorithm agility
+//! - **transport**: Authenticated-encryption transport handshake (`SecretConnection`-style)
+//! - **sessions**: Session storage and retrieval (SQLite-backed), and the
+//!   [`CryptoStore`] trait that abstracts it
+//! - **memory_store**: In-memory `CryptoStore` for tests and DB-free use
 //! - **service**: High-level CryptoService facade
 //!
 //! ## Usage
@@ -31,20 +49,57 @@
 //! let plaintext = service.decrypt(peer_id, &ciphertext).await?;
 //! ```
 
+pub mod cache;
+pub mod devices;
 pub mod errors;
+pub mod export;
+pub mod hdkey;
+pub mod hpke;
+pub mod identity;
+pub mod key_requests;
 pub mod keys;
+pub mod megolm;
+pub mod memory_store;
+pub mod opaque;
+pub mod pickle;
 pub mod prekeys;
+pub mod provisioning;
 pub mod ratchet;
 pub mod service;
 pub mod sessions;
+pub mod system;
+pub mod transport;
 pub mod x3dh;
 
 // Re-export commonly used types
+pub use cache::CachedSessionStore;
+pub use devices::{DeviceId, DeviceManager};
 pub use errors::{CryptoError, CryptoResult};
-pub use keys::{Curve25519KeyPair, IdentityKeyPair, OneTimePreKey, SignedPreKey};
-pub use prekeys::PreKeyManager;
+pub use export::{export_keys, import_keys};
+pub use hdkey::{
+    derive_fallback_prekey, derive_identity_key_pair, derive_one_time_prekey, derive_signed_prekey,
+    seed_from_mnemonic,
+};
+pub use hpke::SealedBox;
+pub use identity::{compute_safety_number, StoredIdentity};
+pub use key_requests::{KeyRequest, KeyRequestState, KeyRequestStore};
+pub use keys::{
+    constant_time_eq, Curve25519KeyPair, FallbackPreKey, IdentityKeyPair, OneTimePreKey, SignedPreKey, StoredPreKey,
+};
+pub use megolm::{InboundGroupSession, OutboundGroupSession};
+pub use memory_store::InMemoryCryptoStore;
+pub use opaque::{
+    CredentialResponse, OpaqueLoginResult, OprfKeyPair, OprfRequest, OprfResponse, RegistrationRecord,
+};
+pub use prekeys::{KeyDomain, PreKeyManager, PreKeyStatus, UnpublishedPreKeys};
+pub use provisioning::{
+    export_provisioning_envelope, generate_linked_device_id, import_provisioning_envelope, LinkedDeviceInfo,
+    LinkedDeviceState,
+};
 pub use x3dh::PreKeyBundle;
-pub use ratchet::RatchetSession;
-pub use service::CryptoService;
-pub use sessions::SessionStore;
+pub use ratchet::{PeerSessions, RatchetSession};
+pub use service::{CryptoService, SealedSenderPayload};
+pub use sessions::{CryptoStore, SessionStore};
+pub use system::{CryptoSystem, CryptoSystemId};
+pub use transport::{Handshake, HandshakeSecrets, SecretConnection, MAX_FRAME_LEN};
 pub use x3dh::{x3dh_initiate, x3dh_respond, X3dhResult};
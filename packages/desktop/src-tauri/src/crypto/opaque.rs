@@ -0,0 +1,469 @@
+//! OPAQUE augmented PAKE for password login
+//!
+//! The existing token-based login hands raw credentials to the server, so
+//! the server (or a breach of it) learns the password directly. This module
+//! implements an OPAQUE-style flow so the password never crosses the wire
+//! and the server never stores anything password-equivalent:
+//!
+//! ## Registration
+//!
+//! 1. The client blinds its password through an OPRF ([`blind_password`]):
+//!    hash the password onto the Ristretto group, then multiply by a random
+//!    blinding scalar before sending it to the server.
+//! 2. The server evaluates the OPRF with its per-user key ([`evaluate`])
+//!    and returns the result - it never sees the password or the blinding
+//!    factor, so it learns nothing from this exchange.
+//! 3. The client unblinds the response to recover a "randomized password"
+//!    that only it and the server (who holds the OPRF key) can reconstruct,
+//!    derives an envelope key from it, and seals its long-term Curve25519
+//!    identity key pair into an [`Envelope`] ([`finalize_registration`]).
+//!    The server stores only the envelope, the OPRF key, and the client's
+//!    public identity key.
+//!
+//! ## Login
+//!
+//! Client and server rerun the same OPRF exchange so the client can rederive
+//! the randomized password and unseal its envelope. Both sides then run a
+//! Triple-DH key exchange ([`client_finish_login`]/[`server_finish_login`])
+//! between the client's and server's long-term and ephemeral keys - the same
+//! DH-combining shape as `x3dh`'s `kdf_x3dh`, just with its own domain
+//! separation label - to derive a shared session key plus mutual
+//! authentication tags. The server only issues its existing session token
+//! once it has checked the client's tag, which is unforgeable without
+//! knowing the password.
+
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::keys::{constant_time_eq, Curve25519KeyPair};
+use crate::crypto::pickle::{decrypt_pickle, encrypt_pickle};
+use crate::crypto::system;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Domain-separation label for hashing a password onto the Ristretto group.
+const OPRF_HASH_TO_GROUP_INFO: &[u8] = b"NoChat OPAQUE hash-to-group v1";
+/// HKDF info label for the envelope-sealing key, derived from the
+/// randomized password.
+const ENVELOPE_KEY_INFO: &[u8] = b"NoChat OPAQUE envelope key v1";
+/// HKDF info label for the export key, derived alongside the envelope key
+/// but only ever held by the client - useful for encrypting other
+/// client-side secrets under the same password without involving the server.
+const EXPORT_KEY_INFO: &[u8] = b"NoChat OPAQUE export key v1";
+/// HKDF info label for the Triple-DH session key.
+const SESSION_KEY_INFO: &[u8] = b"NoChat OPAQUE session key v1";
+/// HKDF info label for the client's authentication MAC key.
+const CLIENT_MAC_KEY_INFO: &[u8] = b"NoChat OPAQUE client mac v1";
+/// HKDF info label for the server's authentication MAC key.
+const SERVER_MAC_KEY_INFO: &[u8] = b"NoChat OPAQUE server mac v1";
+
+/// The server's long-term, per-user OPRF key. Analogous to a password hash,
+/// but useless to an attacker who doesn't also recover the client's
+/// blinding factor - the OPRF evaluation alone reveals nothing about the
+/// password.
+pub struct OprfKeyPair {
+    scalar: Scalar,
+}
+
+impl OprfKeyPair {
+    /// Generate a fresh OPRF key, done once per user at registration time.
+    pub fn generate() -> Self {
+        Self {
+            scalar: Scalar::random(&mut rand::thread_rng()),
+        }
+    }
+
+    /// Serialize for storage, prefixed with a [`crate::crypto::system::CryptoSystemId`]
+    /// tag like every other secret this crate persists.
+    pub fn secret_key_bytes(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(system::tag(system::CryptoSystemId::V0, self.scalar.as_bytes()))
+    }
+
+    /// Restore a previously generated OPRF key from [`secret_key_bytes`](Self::secret_key_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> CryptoResult<Self> {
+        let (_, bytes) = system::untag(bytes)?;
+        let arr: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("OPRF key must be 32 bytes".to_string()))?;
+        let scalar = Option::<Scalar>::from(Scalar::from_canonical_bytes(arr))
+            .ok_or_else(|| CryptoError::InvalidKey("OPRF key is not a canonical scalar".to_string()))?;
+        Ok(Self { scalar })
+    }
+}
+
+/// A password blinded through the OPRF, ready to send to the server. Kept
+/// client-side only - never serialized or sent anywhere.
+pub struct Blind(Scalar);
+
+/// Sent from client to server to request an OPRF evaluation, during either
+/// registration or login - the wire shape is identical, only what the
+/// caller does with the result differs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OprfRequest {
+    pub blinded_element: [u8; 32],
+}
+
+/// The server's OPRF evaluation, returned in response to an [`OprfRequest`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OprfResponse {
+    pub evaluated_element: [u8; 32],
+}
+
+/// Hash `password` onto the Ristretto group via wide-reduction over a
+/// SHA-512 digest, then blind it with a fresh random scalar. Neither the
+/// server nor a passive observer learns anything about `password` from the
+/// resulting [`OprfRequest`] - it's indistinguishable from a uniformly
+/// random group element.
+pub fn blind_password(password: &[u8]) -> (Blind, OprfRequest) {
+    let hashed = hash_password_to_group(password);
+    let r = Scalar::random(&mut rand::thread_rng());
+    let blinded = hashed * r;
+    (Blind(r), OprfRequest { blinded_element: blinded.compress().to_bytes() })
+}
+
+fn hash_password_to_group(password: &[u8]) -> RistrettoPoint {
+    let mut hasher = Sha512::new();
+    hasher.update(OPRF_HASH_TO_GROUP_INFO);
+    hasher.update(password);
+    let digest = hasher.finalize();
+
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(&digest);
+    RistrettoPoint::from_uniform_bytes(&wide)
+}
+
+/// Server-side OPRF evaluation: multiply the blinded element by the user's
+/// OPRF key. The server never decompresses anything that reveals the
+/// password - `request.blinded_element` is already indistinguishable from
+/// random.
+pub fn evaluate(oprf_key: &OprfKeyPair, request: &OprfRequest) -> CryptoResult<OprfResponse> {
+    let point = decompress(&request.blinded_element)?;
+    let evaluated = point * oprf_key.scalar;
+    Ok(OprfResponse { evaluated_element: evaluated.compress().to_bytes() })
+}
+
+/// Client-side: undo the blinding factor applied in [`blind_password`],
+/// recovering the randomized password - a 32-byte value only the client and
+/// the holder of the matching [`OprfKeyPair`] can reconstruct.
+fn unblind(blind: &Blind, response: &OprfResponse) -> CryptoResult<[u8; 32]> {
+    let point = decompress(&response.evaluated_element)?;
+    let unblinded = point * blind.0.invert();
+    Ok(unblinded.compress().to_bytes())
+}
+
+fn decompress(bytes: &[u8; 32]) -> CryptoResult<RistrettoPoint> {
+    CompressedRistretto(*bytes)
+        .decompress()
+        .ok_or_else(|| CryptoError::OpaqueError("OPRF element is not a valid Ristretto point".to_string()))
+}
+
+/// Derive the envelope-sealing key and the client-only export key from a
+/// randomized password, via HKDF-SHA256 with distinct info labels so
+/// neither can be derived from the other.
+fn derive_envelope_keys(randomized_password: &[u8; 32]) -> CryptoResult<(Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>)> {
+    let hkdf = Hkdf::<Sha256>::new(None, randomized_password);
+
+    let mut envelope_key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(ENVELOPE_KEY_INFO, &mut *envelope_key)
+        .map_err(|e| CryptoError::OpaqueError(format!("HKDF expansion failed: {}", e)))?;
+
+    let mut export_key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(EXPORT_KEY_INFO, &mut *export_key)
+        .map_err(|e| CryptoError::OpaqueError(format!("HKDF expansion failed: {}", e)))?;
+
+    Ok((envelope_key, export_key))
+}
+
+/// The client's long-term identity, sealed under a key derived from the
+/// randomized password. Stored server-side; opaque to the server since it
+/// never learns the envelope key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    sealed: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EnvelopeContents {
+    identity_public: Vec<u8>,
+    identity_secret: Vec<u8>,
+}
+
+/// Everything the server stores for a user after registration: the
+/// envelope, and the client's public identity key (needed for the
+/// Triple-DH login step - the server never sees the matching secret).
+///
+/// Derives `Serialize`/`Deserialize` (unlike the client-only [`Blind`]) since
+/// this is exactly what `commands::auth` sends the server as the finishing
+/// message of registration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationRecord {
+    pub envelope: Envelope,
+    pub client_identity_public: Vec<u8>,
+}
+
+/// Finalize registration: unblind the server's [`OprfResponse`], derive the
+/// envelope key, and seal `client_identity` into an [`Envelope`] for the
+/// server to store. Returns the export key alongside, for the client to use
+/// if it wants to encrypt other local secrets under this password.
+pub fn finalize_registration(
+    blind: &Blind,
+    response: &OprfResponse,
+    client_identity: &Curve25519KeyPair,
+) -> CryptoResult<(RegistrationRecord, Zeroizing<[u8; 32]>)> {
+    let randomized_password = unblind(blind, response)?;
+    let (envelope_key, export_key) = derive_envelope_keys(&randomized_password)?;
+
+    let contents = EnvelopeContents {
+        identity_public: client_identity.public_key_bytes(),
+        identity_secret: client_identity.secret_key_bytes().to_vec(),
+    };
+    let plaintext = serde_json::to_vec(&contents)
+        .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+    let sealed = encrypt_pickle(&plaintext, &*envelope_key)?;
+
+    Ok((
+        RegistrationRecord {
+            envelope: Envelope { sealed },
+            client_identity_public: client_identity.public_key_bytes(),
+        },
+        export_key,
+    ))
+}
+
+/// Sent from server to client in response to a login attempt: the OPRF
+/// evaluation needed to unseal the envelope, the envelope itself, and the
+/// server's long-term and fresh ephemeral Curve25519 public keys for the
+/// Triple-DH step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialResponse {
+    pub evaluated_element: [u8; 32],
+    pub envelope: Envelope,
+    pub server_identity_public: Vec<u8>,
+    pub server_ephemeral_public: Vec<u8>,
+}
+
+/// Server-side login step: evaluate the OPRF exactly as in registration and
+/// attach the stored envelope plus a fresh ephemeral key for this attempt.
+pub fn server_respond_login(
+    oprf_key: &OprfKeyPair,
+    request: &OprfRequest,
+    record: &RegistrationRecord,
+    server_identity: &Curve25519KeyPair,
+    server_ephemeral: &Curve25519KeyPair,
+) -> CryptoResult<CredentialResponse> {
+    let oprf_response = evaluate(oprf_key, request)?;
+    Ok(CredentialResponse {
+        evaluated_element: oprf_response.evaluated_element,
+        envelope: record.envelope.clone(),
+        server_identity_public: server_identity.public_key_bytes(),
+        server_ephemeral_public: server_ephemeral.public_key_bytes(),
+    })
+}
+
+/// Shared outcome of a completed OPAQUE login: a session key plus mutual
+/// authentication tags, both derived via HKDF-SHA256 over the concatenated
+/// Triple-DH outputs - the server gates issuing its session token on
+/// checking `client_mac` before returning `server_mac`.
+#[derive(Debug)]
+pub struct OpaqueLoginResult {
+    pub session_key: Zeroizing<[u8; 32]>,
+    pub client_mac: [u8; 32],
+    pub server_mac: [u8; 32],
+}
+
+/// Client-side login finish: unblind the response, unseal the envelope to
+/// recover the long-term identity key pair, then run Triple-DH against the
+/// server's long-term and ephemeral public keys to derive the session key
+/// and both authentication tags.
+pub fn client_finish_login(
+    blind: &Blind,
+    response: &CredentialResponse,
+    client_ephemeral: &Curve25519KeyPair,
+) -> CryptoResult<(OpaqueLoginResult, Curve25519KeyPair)> {
+    let randomized_password = unblind(blind, &OprfResponse { evaluated_element: response.evaluated_element })?;
+    let (envelope_key, _export_key) = derive_envelope_keys(&randomized_password)?;
+
+    let plaintext = decrypt_pickle(&response.envelope.sealed, &*envelope_key)
+        .map_err(|_| CryptoError::OpaqueError("incorrect password".to_string()))?;
+    let contents: EnvelopeContents = serde_json::from_slice(&plaintext)
+        .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+    let client_identity =
+        Curve25519KeyPair::from_bytes(&contents.identity_public, &contents.identity_secret)?;
+
+    let server_identity = parse_public_key(&response.server_identity_public)?;
+    let server_ephemeral = parse_public_key(&response.server_ephemeral_public)?;
+
+    let dh1 = client_identity.diffie_hellman(&server_identity);
+    let dh2 = client_identity.diffie_hellman(&server_ephemeral);
+    let dh3 = client_ephemeral.diffie_hellman(&server_ephemeral);
+
+    let result = derive_login_result(&dh1, &dh2, &dh3)?;
+    Ok((result, client_identity))
+}
+
+/// Server-side login finish: the mirror image of [`client_finish_login`],
+/// run once the client has already sent its ephemeral public key alongside
+/// the original [`OprfRequest`]. The caller should reject the login unless
+/// the client's authentication tag matches [`OpaqueLoginResult::client_mac`]
+/// before issuing a session token.
+pub fn server_finish_login(
+    server_identity: &Curve25519KeyPair,
+    server_ephemeral: &Curve25519KeyPair,
+    client_identity_public: &[u8],
+    client_ephemeral_public: &[u8],
+) -> CryptoResult<OpaqueLoginResult> {
+    let client_identity = parse_public_key(client_identity_public)?;
+    let client_ephemeral = parse_public_key(client_ephemeral_public)?;
+
+    let dh1 = server_identity.diffie_hellman(&client_identity);
+    let dh2 = server_ephemeral.diffie_hellman(&client_identity);
+    let dh3 = server_ephemeral.diffie_hellman(&client_ephemeral);
+
+    derive_login_result(&dh1, &dh2, &dh3)
+}
+
+fn parse_public_key(bytes: &[u8]) -> CryptoResult<vodozemac::Curve25519PublicKey> {
+    let (_, bytes) = system::untag(bytes)?;
+    let arr: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| CryptoError::InvalidKey("Public key must be 32 bytes".to_string()))?;
+    vodozemac::Curve25519PublicKey::from_slice(&arr).map_err(Into::into)
+}
+
+/// Combine the three Triple-DH outputs into a session key and a pair of
+/// mutual authentication tags - the same "concatenate DH outputs under
+/// HKDF-SHA256" shape `x3dh::kdf_x3dh` uses, just with its own domain
+/// separation so the two protocols' derived secrets can never collide.
+fn derive_login_result(dh1: &[u8; 32], dh2: &[u8; 32], dh3: &[u8; 32]) -> CryptoResult<OpaqueLoginResult> {
+    let mut input = Zeroizing::new(Vec::with_capacity(96));
+    input.extend_from_slice(dh1);
+    input.extend_from_slice(dh2);
+    input.extend_from_slice(dh3);
+
+    let hkdf = Hkdf::<Sha256>::new(None, &input);
+
+    let mut session_key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(SESSION_KEY_INFO, &mut *session_key)
+        .map_err(|e| CryptoError::OpaqueError(format!("HKDF expansion failed: {}", e)))?;
+
+    let mut client_mac_key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(CLIENT_MAC_KEY_INFO, &mut *client_mac_key)
+        .map_err(|e| CryptoError::OpaqueError(format!("HKDF expansion failed: {}", e)))?;
+    let mut server_mac_key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(SERVER_MAC_KEY_INFO, &mut *server_mac_key)
+        .map_err(|e| CryptoError::OpaqueError(format!("HKDF expansion failed: {}", e)))?;
+
+    let client_mac = mac_tag(&client_mac_key, &session_key)?;
+    let server_mac = mac_tag(&server_mac_key, &session_key)?;
+
+    Ok(OpaqueLoginResult { session_key, client_mac, server_mac })
+}
+
+fn mac_tag(key: &[u8], message: &[u8]) -> CryptoResult<[u8; 32]> {
+    let mut mac = HmacSha256::new_from_slice(key)
+        .map_err(|e| CryptoError::OpaqueError(format!("HMAC init failed: {}", e)))?;
+    mac.update(message);
+    Ok(mac.finalize().into_bytes().into())
+}
+
+/// Verify a MAC produced by [`derive_login_result`] in constant time.
+pub fn verify_mac(key: &[u8], message: &[u8], expected: &[u8; 32]) -> CryptoResult<bool> {
+    let actual = mac_tag(key, message)?;
+    Ok(constant_time_eq(&actual, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration_round_trip(password: &[u8]) -> (OprfKeyPair, RegistrationRecord, Curve25519KeyPair) {
+        let oprf_key = OprfKeyPair::generate();
+        let client_identity = Curve25519KeyPair::generate();
+
+        let (blind, request) = blind_password(password);
+        let response = evaluate(&oprf_key, &request).unwrap();
+        let (record, _export_key) = finalize_registration(&blind, &response, &client_identity).unwrap();
+
+        (oprf_key, record, client_identity)
+    }
+
+    #[test]
+    fn test_registration_then_login_recovers_identity_and_matching_session() {
+        let password = b"correct horse battery staple";
+        let (oprf_key, record, client_identity) = registration_round_trip(password);
+
+        let server_identity = Curve25519KeyPair::generate();
+        let server_ephemeral = Curve25519KeyPair::generate();
+        let client_ephemeral = Curve25519KeyPair::generate();
+
+        let (blind, request) = blind_password(password);
+        let credential_response =
+            server_respond_login(&oprf_key, &request, &record, &server_identity, &server_ephemeral).unwrap();
+
+        let (client_result, recovered_identity) =
+            client_finish_login(&blind, &credential_response, &client_ephemeral).unwrap();
+
+        assert_eq!(recovered_identity.public.to_bytes(), client_identity.public.to_bytes());
+
+        let server_result = server_finish_login(
+            &server_identity,
+            &server_ephemeral,
+            &recovered_identity.public_key_bytes(),
+            &client_ephemeral.public_key_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(client_result.session_key, server_result.session_key);
+        assert_eq!(client_result.client_mac, server_result.client_mac);
+        assert_eq!(client_result.server_mac, server_result.server_mac);
+    }
+
+    #[test]
+    fn test_login_with_wrong_password_fails_to_unseal_envelope() {
+        let (oprf_key, record, _client_identity) = registration_round_trip(b"correct horse battery staple");
+
+        let server_identity = Curve25519KeyPair::generate();
+        let server_ephemeral = Curve25519KeyPair::generate();
+        let client_ephemeral = Curve25519KeyPair::generate();
+
+        let (blind, request) = blind_password(b"wrong password");
+        let credential_response =
+            server_respond_login(&oprf_key, &request, &record, &server_identity, &server_ephemeral).unwrap();
+
+        assert!(client_finish_login(&blind, &credential_response, &client_ephemeral).is_err());
+    }
+
+    #[test]
+    fn test_oprf_evaluation_reveals_nothing_without_the_blind() {
+        // The same password blinded twice produces unlinkable requests -
+        // an observer (or the server) can't tell they're the same password.
+        let (_blind_a, request_a) = blind_password(b"correct horse battery staple");
+        let (_blind_b, request_b) = blind_password(b"correct horse battery staple");
+        assert_ne!(request_a.blinded_element, request_b.blinded_element);
+    }
+
+    #[test]
+    fn test_oprf_key_round_trips_through_bytes() {
+        let oprf_key = OprfKeyPair::generate();
+        let restored = OprfKeyPair::from_bytes(&oprf_key.secret_key_bytes()).unwrap();
+
+        let (_blind, request) = blind_password(b"hunter2");
+        let a = evaluate(&oprf_key, &request).unwrap();
+        let b = evaluate(&restored, &request).unwrap();
+        assert_eq!(a.evaluated_element, b.evaluated_element);
+    }
+
+    #[test]
+    fn test_mac_verification_rejects_tampered_message() {
+        let key = [0x42u8; 32];
+        let tag = mac_tag(&key, b"session transcript").unwrap();
+        assert!(verify_mac(&key, b"session transcript", &tag).unwrap());
+        assert!(!verify_mac(&key, b"tampered transcript", &tag).unwrap());
+    }
+}
@@ -0,0 +1,186 @@
+//! Encrypted pickle format for account/session storage
+//!
+//! [`OlmAccount`](crate::crypto::ratchet::OlmAccount) and
+//! [`RatchetSession`](crate::crypto::RatchetSession) serialize their
+//! internal ratchet state to JSON for storage under a `PickleKey`, but that
+//! key was previously bound and ignored, leaving live ratchet keys on disk
+//! as plaintext JSON. This module implements an authenticated-encryption
+//! wrapper matching the Matrix key-export format: PBKDF2-HMAC-SHA512
+//! stretches the `PickleKey` over a random salt into a 64-byte keystream,
+//! split into a 32-byte AES-256-CTR key and a 32-byte HMAC-SHA256 key; the
+//! plaintext is encrypted under a random IV and authenticated with an
+//! HMAC-SHA256 tag over `version || salt || iv || rounds || ciphertext`, so
+//! tampering or decrypting with the wrong key is rejected before any
+//! plaintext is returned.
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use ctr::Ctr64BE;
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2_hmac;
+use rand::RngCore;
+use sha2::{Sha256, Sha512};
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::keys::constant_time_eq;
+
+type Aes256Ctr = Ctr64BE<aes::Aes256>;
+type HmacSha256 = Hmac<Sha256>;
+
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const IV_LEN: usize = 16;
+const MAC_LEN: usize = 32;
+const HEADER_LEN: usize = 1 + SALT_LEN + IV_LEN + 4;
+
+/// PBKDF2 round count used unless the caller asks for a different one via
+/// [`encrypt_pickle_with_rounds`]. Chosen to keep unpickling (done once per
+/// session load, not per message) comfortably interactive.
+pub const DEFAULT_PBKDF2_ROUNDS: u32 = 200_000;
+
+/// Encrypt `plaintext` (typically a JSON-serialized pickle) under
+/// `pickle_key`, using [`DEFAULT_PBKDF2_ROUNDS`].
+pub fn encrypt_pickle(plaintext: &[u8], pickle_key: &[u8]) -> CryptoResult<Vec<u8>> {
+    encrypt_pickle_with_rounds(plaintext, pickle_key, DEFAULT_PBKDF2_ROUNDS)
+}
+
+/// Encrypt `plaintext` under `pickle_key` with an explicit PBKDF2 round
+/// count, recorded alongside the ciphertext so [`decrypt_pickle`] can use
+/// the same count without it needing to be passed back in separately.
+pub fn encrypt_pickle_with_rounds(
+    plaintext: &[u8],
+    pickle_key: &[u8],
+    rounds: u32,
+) -> CryptoResult<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut iv = [0u8; IV_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut iv);
+
+    let (aes_key, mac_key) = derive_keys(pickle_key, &salt, rounds);
+
+    let mut ciphertext = plaintext.to_vec();
+    let mut cipher = Aes256Ctr::new((&aes_key).into(), (&iv).into());
+    cipher.apply_keystream(&mut ciphertext);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + ciphertext.len() + MAC_LEN);
+    out.push(FORMAT_VERSION);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&iv);
+    out.extend_from_slice(&rounds.to_be_bytes());
+    out.extend_from_slice(&ciphertext);
+
+    let mut mac = HmacSha256::new_from_slice(&mac_key)
+        .map_err(|e| CryptoError::EncryptionError(format!("HMAC init failed: {}", e)))?;
+    mac.update(&out);
+    out.extend_from_slice(&mac.finalize().into_bytes());
+
+    Ok(out)
+}
+
+/// Verify and decrypt an encrypted pickle produced by [`encrypt_pickle`] (or
+/// [`encrypt_pickle_with_rounds`]).
+///
+/// Recomputes the HMAC-SHA256 tag and compares it in constant time before
+/// attempting to decrypt anything, so a tampered ciphertext or a wrong
+/// `pickle_key` is rejected rather than producing garbage plaintext.
+pub fn decrypt_pickle(encrypted: &[u8], pickle_key: &[u8]) -> CryptoResult<Vec<u8>> {
+    if encrypted.len() < HEADER_LEN + MAC_LEN {
+        return Err(CryptoError::DecryptionError(
+            "encrypted pickle is truncated".to_string(),
+        ));
+    }
+
+    let (body, tag) = encrypted.split_at(encrypted.len() - MAC_LEN);
+    let (header, ciphertext) = body.split_at(HEADER_LEN);
+
+    let version = header[0];
+    if version != FORMAT_VERSION {
+        return Err(CryptoError::DecryptionError(format!(
+            "unsupported pickle format version: {}",
+            version
+        )));
+    }
+    let salt = &header[1..1 + SALT_LEN];
+    let iv = &header[1 + SALT_LEN..1 + SALT_LEN + IV_LEN];
+    let rounds = u32::from_be_bytes(header[1 + SALT_LEN + IV_LEN..HEADER_LEN].try_into().unwrap());
+
+    let (aes_key, mac_key) = derive_keys(pickle_key, salt, rounds);
+
+    let mut mac = HmacSha256::new_from_slice(&mac_key)
+        .map_err(|e| CryptoError::DecryptionError(format!("HMAC init failed: {}", e)))?;
+    mac.update(body);
+    let expected_tag = mac.finalize().into_bytes();
+
+    if !constant_time_eq(&expected_tag, tag) {
+        return Err(CryptoError::DecryptionError(
+            "pickle authentication failed".to_string(),
+        ));
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    let mut cipher = Aes256Ctr::new(aes_key.as_slice().into(), iv.into());
+    cipher.apply_keystream(&mut plaintext);
+
+    Ok(plaintext)
+}
+
+/// Stretch `pickle_key` over `salt` with PBKDF2-HMAC-SHA512 into a 64-byte
+/// keystream, split into an AES-256-CTR key and an HMAC-SHA256 key.
+fn derive_keys(pickle_key: &[u8], salt: &[u8], rounds: u32) -> ([u8; 32], [u8; 32]) {
+    let mut okm = [0u8; 64];
+    pbkdf2_hmac::<Sha512>(pickle_key, salt, rounds, &mut okm);
+
+    let mut aes_key = [0u8; 32];
+    let mut mac_key = [0u8; 32];
+    aes_key.copy_from_slice(&okm[..32]);
+    mac_key.copy_from_slice(&okm[32..]);
+    (aes_key, mac_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let pickle_key = [0x11u8; 32];
+        let plaintext = b"{\"some\":\"pickle json\"}";
+
+        let encrypted = encrypt_pickle(plaintext, &pickle_key).unwrap();
+        let decrypted = decrypt_pickle(&encrypted, &pickle_key).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_wrong_key_is_rejected() {
+        let plaintext = b"secret session state";
+        let encrypted = encrypt_pickle(plaintext, &[0x11u8; 32]).unwrap();
+
+        assert!(decrypt_pickle(&encrypted, &[0x22u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_is_rejected() {
+        let pickle_key = [0x11u8; 32];
+        let mut encrypted = encrypt_pickle(b"secret session state", &pickle_key).unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 0xFF;
+
+        assert!(decrypt_pickle(&encrypted, &pickle_key).is_err());
+    }
+
+    #[test]
+    fn test_truncated_input_is_rejected() {
+        assert!(decrypt_pickle(&[0u8; 4], &[0x11u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_each_encryption_uses_a_fresh_salt_and_iv() {
+        let pickle_key = [0x11u8; 32];
+        let a = encrypt_pickle(b"same plaintext", &pickle_key).unwrap();
+        let b = encrypt_pickle(b"same plaintext", &pickle_key).unwrap();
+
+        assert_ne!(a, b);
+    }
+}
@@ -5,14 +5,44 @@
 //!
 //! - **Signed Prekeys**: Rotated periodically (~7 days), signed by identity key
 //! - **One-Time Prekeys**: Single-use keys for forward secrecy, replenished as needed
+//! - **Fallback Prekey**: A reusable last-resort signed prekey, substituted into
+//!   bundles when the one-time pool runs dry so X3DH never fails outright
+//!
+//! It also tracks which locally-generated prekeys have been published to the
+//! server (see [`PreKeyManager::get_unpublished_prekeys`] and
+//! [`PreKeyManager::mark_keys_as_published`]), so callers only upload the delta.
+//!
+//! ## Key domains
+//!
+//! Following libsignal-service's ACI/PNI split (see `distribute_pni_keys`), a
+//! single [`PreKeyManager`] can hold more than one independent identity
+//! keyspace, tagged by [`KeyDomain`]: the primary account identity, and
+//! optionally a secondary identity tied to a phone number. Each domain has
+//! its own [`IdentityKeyPair`], signed prekey, rotation clock, and one-time
+//! prekey pool, so publishing or rotating one domain's bundle never
+//! cross-contaminates the other's key IDs or consumes from the wrong pool.
+
+use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
 use crate::crypto::errors::CryptoResult;
-use crate::crypto::keys::{Curve25519KeyPair, IdentityKeyPair, OneTimePreKey, SignedPreKey, StoredPreKey};
+use crate::crypto::keys::{
+    Curve25519KeyPair, FallbackPreKey, IdentityKeyPair, OneTimePreKey, SignedPreKey, StoredPreKey,
+};
 use crate::crypto::x3dh::PreKeyBundle;
 
+/// Which identity keyspace a prekey operation applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyDomain {
+    /// The primary, long-lived account identity.
+    Account,
+    /// A secondary identity tied to a phone number (libsignal's PNI).
+    PhoneNumber,
+}
+
 /// Configuration for prekey management
+#[derive(Clone)]
 pub struct PreKeyConfig {
     /// Number of one-time prekeys to generate initially
     pub initial_batch_size: usize,
@@ -35,35 +65,29 @@ impl Default for PreKeyConfig {
     }
 }
 
-/// Manages prekey generation and lifecycle
-pub struct PreKeyManager {
-    /// Our identity key pair (for signing prekeys)
+/// All per-domain prekey state: identity, signed/fallback prekeys, one-time
+/// pool, rotation clock, and publish tracking. Independent of every other
+/// domain in the same [`PreKeyManager`].
+struct DomainState {
     identity: IdentityKeyPair,
-    /// Current signed prekey (key pair stored locally)
     signed_prekey: Curve25519KeyPair,
-    /// ID of the current signed prekey
     signed_prekey_id: u32,
-    /// When the signed prekey was created
     signed_prekey_created: i64,
-    /// Pool of unused one-time prekeys (stored locally)
     one_time_prekeys: Vec<(u32, Curve25519KeyPair)>,
-    /// Next ID to use for new prekeys
     next_prekey_id: u32,
-    /// Configuration
-    config: PreKeyConfig,
+    fallback_prekey: Curve25519KeyPair,
+    fallback_prekey_id: u32,
+    fallback_prekey_created: i64,
+    published_key_ids: HashSet<u32>,
 }
 
-impl PreKeyManager {
-    /// Create a new prekey manager with fresh keys
-    pub fn new(identity: IdentityKeyPair) -> Self {
-        Self::with_config(identity, PreKeyConfig::default())
-    }
-
-    /// Create a new prekey manager with custom configuration
-    pub fn with_config(identity: IdentityKeyPair, config: PreKeyConfig) -> Self {
+impl DomainState {
+    fn fresh(identity: IdentityKeyPair, config: &PreKeyConfig) -> Self {
         let signed_prekey = Curve25519KeyPair::generate();
-        let one_time_prekeys = Self::generate_prekey_batch(0, config.initial_batch_size);
-        let next_prekey_id = config.initial_batch_size as u32;
+        let one_time_prekeys = generate_prekey_batch(0, config.initial_batch_size);
+        let fallback_prekey = Curve25519KeyPair::generate();
+        let fallback_prekey_id = config.initial_batch_size as u32;
+        let next_prekey_id = fallback_prekey_id + 1;
 
         Self {
             identity,
@@ -72,171 +96,424 @@ impl PreKeyManager {
             signed_prekey_created: chrono::Utc::now().timestamp(),
             one_time_prekeys,
             next_prekey_id,
-            config,
+            fallback_prekey,
+            fallback_prekey_id,
+            fallback_prekey_created: chrono::Utc::now().timestamp(),
+            published_key_ids: HashSet::new(),
         }
     }
+}
 
-    /// Restore from persisted state
-    pub fn restore(
+/// Generate a batch of one-time prekeys
+fn generate_prekey_batch(start_id: u32, count: usize) -> Vec<(u32, Curve25519KeyPair)> {
+    (0..count)
+        .map(|i| (start_id + i as u32, Curve25519KeyPair::generate()))
+        .collect()
+}
+
+/// Manages prekey generation and lifecycle across one or more [`KeyDomain`]s
+pub struct PreKeyManager {
+    domains: std::collections::HashMap<KeyDomain, DomainState>,
+    config: PreKeyConfig,
+}
+
+impl PreKeyManager {
+    /// Create a new prekey manager with a fresh `Account` identity.
+    pub fn new(identity: IdentityKeyPair) -> Self {
+        Self::with_config(identity, PreKeyConfig::default())
+    }
+
+    /// Create a new prekey manager with a fresh `Account` identity and
+    /// custom configuration.
+    pub fn with_config(identity: IdentityKeyPair, config: PreKeyConfig) -> Self {
+        let mut domains = std::collections::HashMap::new();
+        domains.insert(KeyDomain::Account, DomainState::fresh(identity, &config));
+        Self { domains, config }
+    }
+
+    /// Add an independent identity keyspace - e.g. the secondary
+    /// phone-number identity - with its own fresh signed/fallback/one-time
+    /// prekeys. A no-op if `domain` is already populated.
+    pub fn add_domain(&mut self, domain: KeyDomain, identity: IdentityKeyPair) {
+        let config = self.config.clone();
+        self.domains
+            .entry(domain)
+            .or_insert_with(|| DomainState::fresh(identity, &config));
+    }
+
+    /// Whether `domain` has been populated (via [`Self::new`],
+    /// [`Self::add_domain`], or [`Self::restore_domain`]).
+    pub fn has_domain(&self, domain: KeyDomain) -> bool {
+        self.domains.contains_key(&domain)
+    }
+
+    /// Restore a single domain's state from persisted storage, overwriting
+    /// whatever was previously registered for it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn restore_domain(
+        &mut self,
+        domain: KeyDomain,
         identity: IdentityKeyPair,
         signed_prekey: StoredPreKey,
         signed_prekey_created: i64,
         one_time_prekeys: Vec<StoredPreKey>,
         next_prekey_id: u32,
-        config: PreKeyConfig,
-    ) -> CryptoResult<Self> {
+        fallback_prekey: StoredPreKey,
+        fallback_prekey_created: i64,
+        published_key_ids: HashSet<u32>,
+    ) -> CryptoResult<()> {
         let signed_kp = signed_prekey.to_keypair()?;
         let otks: CryptoResult<Vec<_>> = one_time_prekeys
             .into_iter()
             .map(|sp| Ok((sp.key_id, sp.to_keypair()?)))
             .collect();
+        let fallback_kp = fallback_prekey.to_keypair()?;
+
+        self.domains.insert(
+            domain,
+            DomainState {
+                identity,
+                signed_prekey: signed_kp,
+                signed_prekey_id: signed_prekey.key_id,
+                signed_prekey_created,
+                one_time_prekeys: otks?,
+                next_prekey_id,
+                fallback_prekey: fallback_kp,
+                fallback_prekey_id: fallback_prekey.key_id,
+                fallback_prekey_created,
+                published_key_ids,
+            },
+        );
+
+        Ok(())
+    }
 
-        Ok(Self {
+    /// Restore a manager from persisted state for the `Account` domain -
+    /// the common case, since most accounts never populate a secondary
+    /// identity. Use [`Self::add_domain`]/[`Self::restore_domain`]
+    /// afterwards to bring in a `PhoneNumber` identity if one exists.
+    #[allow(clippy::too_many_arguments)]
+    pub fn restore(
+        identity: IdentityKeyPair,
+        signed_prekey: StoredPreKey,
+        signed_prekey_created: i64,
+        one_time_prekeys: Vec<StoredPreKey>,
+        next_prekey_id: u32,
+        fallback_prekey: StoredPreKey,
+        fallback_prekey_created: i64,
+        published_key_ids: HashSet<u32>,
+        config: PreKeyConfig,
+    ) -> CryptoResult<Self> {
+        let mut manager = Self {
+            domains: std::collections::HashMap::new(),
+            config,
+        };
+        manager.restore_domain(
+            KeyDomain::Account,
             identity,
-            signed_prekey: signed_kp,
-            signed_prekey_id: signed_prekey.key_id,
+            signed_prekey,
             signed_prekey_created,
-            one_time_prekeys: otks?,
+            one_time_prekeys,
             next_prekey_id,
-            config,
-        })
+            fallback_prekey,
+            fallback_prekey_created,
+            published_key_ids,
+        )?;
+        Ok(manager)
     }
 
-    /// Generate a batch of one-time prekeys
-    fn generate_prekey_batch(start_id: u32, count: usize) -> Vec<(u32, Curve25519KeyPair)> {
-        (0..count)
-            .map(|i| (start_id + i as u32, Curve25519KeyPair::generate()))
-            .collect()
+    fn domain(&self, domain: KeyDomain) -> Option<&DomainState> {
+        self.domains.get(&domain)
     }
 
-    /// Get the signed prekey for uploading to the server
-    pub fn get_signed_prekey(&self) -> SignedPreKey {
-        SignedPreKey::new(self.signed_prekey_id, &self.signed_prekey, &self.identity)
+    fn domain_mut(&mut self, domain: KeyDomain) -> Option<&mut DomainState> {
+        self.domains.get_mut(&domain)
     }
 
-    /// Get all one-time prekeys for uploading to the server
-    pub fn get_one_time_prekeys(&self) -> Vec<OneTimePreKey> {
-        self.one_time_prekeys
-            .iter()
-            .map(|(id, kp)| OneTimePreKey::new(*id, kp))
-            .collect()
+    /// Get the signed prekey for `domain`, for uploading to the server.
+    pub fn get_signed_prekey(&self, domain: KeyDomain) -> Option<SignedPreKey> {
+        let state = self.domain(domain)?;
+        Some(SignedPreKey::new(state.signed_prekey_id, &state.signed_prekey, &state.identity))
     }
 
-    /// Get our prekey bundle (for responding to bundle requests)
-    pub fn get_bundle(&self) -> PreKeyBundle {
-        let otk = self.one_time_prekeys.first().map(|(id, kp)| OneTimePreKey::new(*id, kp));
+    /// Get all of `domain`'s one-time prekeys, for uploading to the server.
+    pub fn get_one_time_prekeys(&self, domain: KeyDomain) -> Vec<OneTimePreKey> {
+        self.domain(domain)
+            .map(|state| {
+                state
+                    .one_time_prekeys
+                    .iter()
+                    .map(|(id, kp)| OneTimePreKey::new(*id, kp))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-        PreKeyBundle {
-            identity_key: self.identity.public_key_bytes(),
-            signed_prekey: self.get_signed_prekey(),
+    /// Get `domain`'s fallback (last-resort) prekey, for uploading to the server.
+    pub fn get_fallback_prekey(&self, domain: KeyDomain) -> Option<FallbackPreKey> {
+        let state = self.domain(domain)?;
+        Some(FallbackPreKey::new(state.fallback_prekey_id, &state.fallback_prekey, &state.identity))
+    }
+
+    /// Get `domain`'s prekey bundle (for responding to bundle requests)
+    ///
+    /// Always carries the fallback prekey alongside whatever's left of the
+    /// one-time pool, so a handshake still gets a DH4 contribution once the
+    /// one-time pool is exhausted - `x3dh_initiate` prefers the one-time
+    /// prekey when both are present and only falls back to the reusable key
+    /// otherwise.
+    pub fn get_bundle(&self, domain: KeyDomain) -> Option<PreKeyBundle> {
+        let state = self.domain(domain)?;
+
+        let otk = state
+            .one_time_prekeys
+            .first()
+            .map(|(id, kp)| OneTimePreKey::new(*id, kp));
+
+        Some(PreKeyBundle {
+            identity_key: state.identity.public_key_bytes(),
+            signed_prekey: self.get_signed_prekey(domain)?,
             one_time_prekey: otk,
-        }
+            fallback_key: self.get_fallback_prekey(domain),
+        })
     }
 
-    /// Consume a one-time prekey (when a session is established)
+    /// Consume a one-time prekey from `domain` (when a session is established)
     ///
     /// Returns the consumed key pair for use in session establishment.
-    pub fn consume_prekey(&mut self, key_id: u32) -> Option<Curve25519KeyPair> {
-        let idx = self.one_time_prekeys.iter().position(|(id, _)| *id == key_id)?;
-        Some(self.one_time_prekeys.remove(idx).1)
+    pub fn consume_prekey(&mut self, domain: KeyDomain, key_id: u32) -> Option<Curve25519KeyPair> {
+        let state = self.domain_mut(domain)?;
+        let idx = state.one_time_prekeys.iter().position(|(id, _)| *id == key_id)?;
+        Some(state.one_time_prekeys.remove(idx).1)
     }
 
-    /// Check if we need to replenish one-time prekeys
-    pub fn needs_replenishment(&self) -> bool {
-        self.one_time_prekeys.len() < self.config.min_prekey_count
+    /// Resolve the key pair behind a bundle's `one_time_prekey_id` from an
+    /// incoming X3DH handshake for `domain`.
+    ///
+    /// Checks the one-time pool first (consuming the match, same as
+    /// [`consume_prekey`](Self::consume_prekey)); if the id instead refers to
+    /// the fallback prekey, returns a clone of it without consuming it since
+    /// the fallback key is reusable across handshakes.
+    pub fn consume_or_fallback_prekey(&mut self, domain: KeyDomain, key_id: u32) -> Option<Curve25519KeyPair> {
+        if let Some(kp) = self.consume_prekey(domain, key_id) {
+            return Some(kp);
+        }
+        let state = self.domain(domain)?;
+        if key_id == state.fallback_prekey_id {
+            return Curve25519KeyPair::from_bytes(
+                &state.fallback_prekey.public_key_bytes(),
+                &state.fallback_prekey.secret_key_bytes(),
+            )
+            .ok();
+        }
+        None
+    }
+
+    /// Check if `domain` needs replenishment of one-time prekeys
+    pub fn needs_replenishment(&self, domain: KeyDomain) -> bool {
+        self.domain(domain)
+            .map(|state| state.one_time_prekeys.len() < self.config.min_prekey_count)
+            .unwrap_or(false)
     }
 
-    /// Generate more one-time prekeys
+    /// Generate more one-time prekeys for `domain`
     ///
-    /// Returns the new prekeys for uploading to the server.
-    pub fn replenish(&mut self) -> Vec<OneTimePreKey> {
-        let new_keys = Self::generate_prekey_batch(
-            self.next_prekey_id,
-            self.config.replenishment_batch_size,
-        );
+    /// Returns the new prekeys for uploading to the server, or an empty
+    /// vector if `domain` hasn't been registered.
+    pub fn replenish(&mut self, domain: KeyDomain) -> Vec<OneTimePreKey> {
+        let batch_size = self.config.replenishment_batch_size;
+        let Some(state) = self.domain_mut(domain) else {
+            return Vec::new();
+        };
 
+        let new_keys = generate_prekey_batch(state.next_prekey_id, batch_size);
         let result: Vec<OneTimePreKey> = new_keys
             .iter()
             .map(|(id, kp)| OneTimePreKey::new(*id, kp))
             .collect();
 
-        self.next_prekey_id += self.config.replenishment_batch_size as u32;
-        self.one_time_prekeys.extend(new_keys);
+        state.next_prekey_id += batch_size as u32;
+        state.one_time_prekeys.extend(new_keys);
 
         result
     }
 
-    /// Check if the signed prekey needs rotation
-    pub fn needs_signed_prekey_rotation(&self) -> bool {
+    /// Check if `domain`'s signed prekey needs rotation
+    pub fn needs_signed_prekey_rotation(&self, domain: KeyDomain) -> bool {
+        let Some(state) = self.domain(domain) else {
+            return false;
+        };
         let now = chrono::Utc::now().timestamp();
-        let age_seconds = now - self.signed_prekey_created;
+        let age_seconds = now - state.signed_prekey_created;
         let max_age_seconds = self.config.signed_prekey_max_age_days * 24 * 60 * 60;
         age_seconds > max_age_seconds
     }
 
-    /// Rotate the signed prekey
+    /// Rotate `domain`'s signed prekey
     ///
-    /// Returns the new signed prekey for uploading to the server.
-    pub fn rotate_signed_prekey(&mut self) -> SignedPreKey {
-        self.signed_prekey = Curve25519KeyPair::generate();
-        self.signed_prekey_id += 1;
-        self.signed_prekey_created = chrono::Utc::now().timestamp();
-        self.get_signed_prekey()
+    /// Returns the new signed prekey for uploading to the server, or `None`
+    /// if `domain` hasn't been registered.
+    pub fn rotate_signed_prekey(&mut self, domain: KeyDomain) -> Option<SignedPreKey> {
+        {
+            let state = self.domain_mut(domain)?;
+            state.signed_prekey = Curve25519KeyPair::generate();
+            state.signed_prekey_id += 1;
+            state.signed_prekey_created = chrono::Utc::now().timestamp();
+        }
+        self.get_signed_prekey(domain)
     }
 
-    /// Get the count of available one-time prekeys
-    pub fn prekey_count(&self) -> usize {
-        self.one_time_prekeys.len()
+    /// Check if `domain`'s fallback prekey needs rotation
+    pub fn needs_fallback_prekey_rotation(&self, domain: KeyDomain) -> bool {
+        match self.get_fallback_prekey(domain) {
+            Some(fallback) => fallback.is_due_for_rotation(self.config.signed_prekey_max_age_days),
+            None => false,
+        }
+    }
+
+    /// Rotate `domain`'s fallback prekey
+    ///
+    /// Returns the new fallback prekey for uploading to the server. The old
+    /// fallback id is left marked as published (it's simply no longer
+    /// advertised to new peers); sessions already using it keep working.
+    pub fn rotate_fallback_prekey(&mut self, domain: KeyDomain) -> Option<FallbackPreKey> {
+        {
+            let state = self.domain_mut(domain)?;
+            state.fallback_prekey = Curve25519KeyPair::generate();
+            state.fallback_prekey_id += 1;
+            state.fallback_prekey_created = chrono::Utc::now().timestamp();
+        }
+        self.get_fallback_prekey(domain)
+    }
+
+    /// Get the count of available one-time prekeys in `domain`
+    pub fn prekey_count(&self, domain: KeyDomain) -> usize {
+        self.domain(domain).map(|state| state.one_time_prekeys.len()).unwrap_or(0)
+    }
+
+    /// Get the prekeys for `domain` that have been generated locally but not
+    /// yet uploaded to the server.
+    pub fn get_unpublished_prekeys(&self, domain: KeyDomain) -> UnpublishedPreKeys {
+        let Some(state) = self.domain(domain) else {
+            return UnpublishedPreKeys {
+                signed_prekey: None,
+                fallback_prekey: None,
+                one_time_prekeys: Vec::new(),
+            };
+        };
+
+        let signed_prekey = (!state.published_key_ids.contains(&state.signed_prekey_id))
+            .then(|| self.get_signed_prekey(domain))
+            .flatten();
+
+        let fallback_prekey = (!state.published_key_ids.contains(&state.fallback_prekey_id))
+            .then(|| self.get_fallback_prekey(domain))
+            .flatten();
+
+        let one_time_prekeys = state
+            .one_time_prekeys
+            .iter()
+            .filter(|(id, _)| !state.published_key_ids.contains(id))
+            .map(|(id, kp)| OneTimePreKey::new(*id, kp))
+            .collect();
+
+        UnpublishedPreKeys {
+            signed_prekey,
+            fallback_prekey,
+            one_time_prekeys,
+        }
     }
 
-    /// Get the identity key fingerprint for verification
-    pub fn fingerprint(&self) -> String {
-        self.identity.fingerprint()
+    /// Record that the given key ids (from `domain`) have been successfully
+    /// uploaded to the server, so they're excluded from the next
+    /// [`get_unpublished_prekeys`](Self::get_unpublished_prekeys) call.
+    pub fn mark_keys_as_published(&mut self, domain: KeyDomain, key_ids: &[u32]) {
+        if let Some(state) = self.domain_mut(domain) {
+            state.published_key_ids.extend(key_ids.iter().copied());
+        }
     }
 
-    /// Get stored prekeys for persistence
-    pub fn get_stored_prekeys(&self) -> (StoredPreKey, Vec<StoredPreKey>) {
-        let signed = StoredPreKey::from_keypair(self.signed_prekey_id, &self.signed_prekey, true);
+    /// Get `domain`'s identity key fingerprint for verification
+    pub fn fingerprint(&self, domain: KeyDomain) -> Option<String> {
+        Some(self.domain(domain)?.identity.fingerprint())
+    }
 
-        let otks: Vec<StoredPreKey> = self
+    /// Get `domain`'s stored prekeys for persistence
+    pub fn get_stored_prekeys(&self, domain: KeyDomain) -> Option<(StoredPreKey, Vec<StoredPreKey>, StoredPreKey)> {
+        let state = self.domain(domain)?;
+
+        let signed = StoredPreKey::from_keypair(state.signed_prekey_id, &state.signed_prekey, true);
+
+        let otks: Vec<StoredPreKey> = state
             .one_time_prekeys
             .iter()
             .map(|(id, kp)| StoredPreKey::from_keypair(*id, kp, false))
             .collect();
 
-        (signed, otks)
+        let fallback = StoredPreKey::from_keypair(state.fallback_prekey_id, &state.fallback_prekey, true);
+
+        Some((signed, otks, fallback))
+    }
+
+    /// Get `domain`'s signed prekey creation timestamp
+    pub fn signed_prekey_created(&self, domain: KeyDomain) -> Option<i64> {
+        Some(self.domain(domain)?.signed_prekey_created)
+    }
+
+    /// Get `domain`'s fallback prekey creation timestamp
+    pub fn fallback_prekey_created(&self, domain: KeyDomain) -> Option<i64> {
+        Some(self.domain(domain)?.fallback_prekey_created)
     }
 
-    /// Get the signed prekey creation timestamp
-    pub fn signed_prekey_created(&self) -> i64 {
-        self.signed_prekey_created
+    /// Get the ids of `domain`'s prekeys already uploaded to the server, for persistence
+    pub fn published_key_ids(&self, domain: KeyDomain) -> Option<&HashSet<u32>> {
+        Some(&self.domain(domain)?.published_key_ids)
     }
 
-    /// Get the next prekey ID
-    pub fn next_prekey_id(&self) -> u32 {
-        self.next_prekey_id
+    /// Get `domain`'s next prekey ID
+    pub fn next_prekey_id(&self, domain: KeyDomain) -> Option<u32> {
+        Some(self.domain(domain)?.next_prekey_id)
     }
 
-    /// Get the signed prekey for session establishment (as responder)
-    pub fn get_signed_prekey_pair(&self) -> &Curve25519KeyPair {
-        &self.signed_prekey
+    /// Get `domain`'s signed prekey for session establishment (as responder)
+    pub fn get_signed_prekey_pair(&self, domain: KeyDomain) -> Option<&Curve25519KeyPair> {
+        Some(&self.domain(domain)?.signed_prekey)
     }
 
-    /// Get the identity key pair
-    pub fn identity(&self) -> &IdentityKeyPair {
-        &self.identity
+    /// Get `domain`'s identity key pair
+    pub fn identity(&self, domain: KeyDomain) -> Option<&IdentityKeyPair> {
+        Some(&self.domain(domain)?.identity)
     }
 }
 
-/// Status of prekey availability
+/// Locally-generated prekeys that have not yet been uploaded to the server
+///
+/// Returned by [`PreKeyManager::get_unpublished_prekeys`]; hand the contents
+/// to the device key upload endpoint and then call
+/// [`PreKeyManager::mark_keys_as_published`] with the uploaded ids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnpublishedPreKeys {
+    /// Signed prekey, if it hasn't been uploaded yet
+    pub signed_prekey: Option<SignedPreKey>,
+    /// Fallback prekey, if it hasn't been uploaded yet
+    pub fallback_prekey: Option<FallbackPreKey>,
+    /// One-time prekeys that haven't been uploaded yet
+    pub one_time_prekeys: Vec<OneTimePreKey>,
+}
+
+/// Status of prekey availability for a single [`KeyDomain`]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PreKeyStatus {
+    /// Which domain this status describes
+    pub domain: KeyDomain,
     /// Number of one-time prekeys available
     pub one_time_prekey_count: usize,
     /// Whether replenishment is needed
     pub needs_replenishment: bool,
     /// Whether signed prekey rotation is needed
     pub needs_rotation: bool,
+    /// Whether fallback prekey rotation is needed
+    pub needs_fallback_rotation: bool,
     /// Age of signed prekey in seconds
     pub signed_prekey_age_seconds: i64,
     /// Identity key fingerprint
@@ -244,17 +521,26 @@ pub struct PreKeyStatus {
 }
 
 impl PreKeyManager {
-    /// Get the current status of prekeys
-    pub fn status(&self) -> PreKeyStatus {
+    /// Get the current status of `domain`'s prekeys, or `None` if `domain`
+    /// hasn't been registered.
+    pub fn status(&self, domain: KeyDomain) -> Option<PreKeyStatus> {
+        let state = self.domain(domain)?;
         let now = chrono::Utc::now().timestamp();
 
-        PreKeyStatus {
-            one_time_prekey_count: self.one_time_prekeys.len(),
-            needs_replenishment: self.needs_replenishment(),
-            needs_rotation: self.needs_signed_prekey_rotation(),
-            signed_prekey_age_seconds: now - self.signed_prekey_created,
-            fingerprint: self.fingerprint(),
-        }
+        Some(PreKeyStatus {
+            domain,
+            one_time_prekey_count: state.one_time_prekeys.len(),
+            needs_replenishment: self.needs_replenishment(domain),
+            needs_rotation: self.needs_signed_prekey_rotation(domain),
+            needs_fallback_rotation: self.needs_fallback_prekey_rotation(domain),
+            signed_prekey_age_seconds: now - state.signed_prekey_created,
+            fingerprint: state.identity.fingerprint(),
+        })
+    }
+
+    /// Status for every currently-registered domain.
+    pub fn statuses(&self) -> Vec<PreKeyStatus> {
+        self.domains.keys().filter_map(|domain| self.status(*domain)).collect()
     }
 }
 
@@ -264,14 +550,16 @@ impl PreKeyManager {
 mod tests {
     use super::*;
 
+    const ACCOUNT: KeyDomain = KeyDomain::Account;
+
     #[test]
     fn test_prekey_manager_creation() {
         let identity = IdentityKeyPair::generate();
         let manager = PreKeyManager::new(identity);
 
         // Should have initial batch of prekeys
-        assert_eq!(manager.prekey_count(), 100);
-        assert!(!manager.needs_replenishment());
+        assert_eq!(manager.prekey_count(ACCOUNT), 100);
+        assert!(!manager.needs_replenishment(ACCOUNT));
     }
 
     #[test]
@@ -279,16 +567,16 @@ mod tests {
         let identity = IdentityKeyPair::generate();
         let mut manager = PreKeyManager::new(identity);
 
-        let prekeys = manager.get_one_time_prekeys();
+        let prekeys = manager.get_one_time_prekeys(ACCOUNT);
         let first_id = prekeys[0].key_id;
 
         // Consume the first prekey
-        let consumed = manager.consume_prekey(first_id);
+        let consumed = manager.consume_prekey(ACCOUNT, first_id);
         assert!(consumed.is_some());
-        assert_eq!(manager.prekey_count(), 99);
+        assert_eq!(manager.prekey_count(ACCOUNT), 99);
 
         // Can't consume the same prekey twice
-        let consumed_again = manager.consume_prekey(first_id);
+        let consumed_again = manager.consume_prekey(ACCOUNT, first_id);
         assert!(consumed_again.is_none());
     }
 
@@ -304,18 +592,18 @@ mod tests {
         let mut manager = PreKeyManager::with_config(identity, config);
 
         // Consume prekeys until we need replenishment
-        while !manager.needs_replenishment() {
-            let prekeys = manager.get_one_time_prekeys();
-            manager.consume_prekey(prekeys[0].key_id);
+        while !manager.needs_replenishment(ACCOUNT) {
+            let prekeys = manager.get_one_time_prekeys(ACCOUNT);
+            manager.consume_prekey(ACCOUNT, prekeys[0].key_id);
         }
 
         // Should need replenishment now
-        assert!(manager.needs_replenishment());
+        assert!(manager.needs_replenishment(ACCOUNT));
 
         // Replenish
-        let new_prekeys = manager.replenish();
+        let new_prekeys = manager.replenish(ACCOUNT);
         assert_eq!(new_prekeys.len(), 50);
-        assert!(!manager.needs_replenishment());
+        assert!(!manager.needs_replenishment(ACCOUNT));
     }
 
     #[test]
@@ -323,11 +611,11 @@ mod tests {
         let identity = IdentityKeyPair::generate();
         let mut manager = PreKeyManager::new(identity);
 
-        let original = manager.get_signed_prekey();
+        let original = manager.get_signed_prekey(ACCOUNT).unwrap();
         let original_id = original.key_id;
 
         // Rotate
-        let new_prekey = manager.rotate_signed_prekey();
+        let new_prekey = manager.rotate_signed_prekey(ACCOUNT).unwrap();
         assert_eq!(new_prekey.key_id, original_id + 1);
 
         // Public key should be different
@@ -339,13 +627,14 @@ mod tests {
         let identity = IdentityKeyPair::generate();
         let manager = PreKeyManager::new(identity);
 
-        let bundle = manager.get_bundle();
+        let bundle = manager.get_bundle(ACCOUNT).unwrap();
 
         // Bundle should have all required fields
         assert!(!bundle.identity_key.is_empty());
         assert!(!bundle.signed_prekey.public_key.is_empty());
         assert!(!bundle.signed_prekey.signature.is_empty());
         assert!(bundle.one_time_prekey.is_some());
+        assert!(bundle.fallback_key.is_some());
 
         // Bundle should be valid
         assert!(bundle.verify().is_ok());
@@ -356,10 +645,120 @@ mod tests {
         let identity = IdentityKeyPair::generate();
         let manager = PreKeyManager::new(identity);
 
-        let status = manager.status();
+        let status = manager.status(ACCOUNT).unwrap();
         assert_eq!(status.one_time_prekey_count, 100);
         assert!(!status.needs_replenishment);
         assert!(!status.needs_rotation);
+        assert!(!status.needs_fallback_rotation);
         assert!(!status.fingerprint.is_empty());
     }
+
+    #[test]
+    fn test_bundle_falls_back_when_one_time_pool_empty() {
+        let identity = IdentityKeyPair::generate();
+        let config = PreKeyConfig {
+            initial_batch_size: 1,
+            replenishment_batch_size: 1,
+            min_prekey_count: 0,
+            signed_prekey_max_age_days: 7,
+        };
+        let mut manager = PreKeyManager::with_config(identity, config);
+
+        // Drain the one-time pool
+        let prekeys = manager.get_one_time_prekeys(ACCOUNT);
+        manager.consume_prekey(ACCOUNT, prekeys[0].key_id);
+        assert_eq!(manager.prekey_count(ACCOUNT), 0);
+
+        // Bundle should still carry a usable key, sourced from the fallback
+        let bundle = manager.get_bundle(ACCOUNT).unwrap();
+        let fallback = manager.get_fallback_prekey(ACCOUNT).unwrap();
+        assert!(bundle.one_time_prekey.is_none());
+        let bundle_fallback = bundle.fallback_key.expect("bundle should carry the fallback prekey");
+        assert_eq!(bundle_fallback.key_id, fallback.key_id);
+        assert_eq!(bundle_fallback.public_key, fallback.public_key);
+        assert!(bundle.verify().is_ok());
+
+        // The fallback key is reusable: consuming it again still resolves
+        assert!(manager.consume_or_fallback_prekey(ACCOUNT, fallback.key_id).is_some());
+        assert!(manager.consume_or_fallback_prekey(ACCOUNT, fallback.key_id).is_some());
+    }
+
+    #[test]
+    fn test_fallback_prekey_rotation() {
+        let identity = IdentityKeyPair::generate();
+        let mut manager = PreKeyManager::new(identity);
+
+        let original = manager.get_fallback_prekey(ACCOUNT).unwrap();
+        let new_fallback = manager.rotate_fallback_prekey(ACCOUNT).unwrap();
+
+        assert_eq!(new_fallback.key_id, original.key_id + 1);
+        assert_ne!(original.public_key, new_fallback.public_key);
+    }
+
+    #[test]
+    fn test_unpublished_prekey_tracking() {
+        let identity = IdentityKeyPair::generate();
+        let config = PreKeyConfig {
+            initial_batch_size: 2,
+            replenishment_batch_size: 2,
+            min_prekey_count: 0,
+            signed_prekey_max_age_days: 7,
+        };
+        let mut manager = PreKeyManager::with_config(identity, config);
+
+        // Everything is unpublished before the first upload
+        let unpublished = manager.get_unpublished_prekeys(ACCOUNT);
+        assert!(unpublished.signed_prekey.is_some());
+        assert!(unpublished.fallback_prekey.is_some());
+        assert_eq!(unpublished.one_time_prekeys.len(), 2);
+
+        let mut uploaded_ids: Vec<u32> = unpublished.one_time_prekeys.iter().map(|k| k.key_id).collect();
+        uploaded_ids.push(unpublished.signed_prekey.unwrap().key_id);
+        uploaded_ids.push(unpublished.fallback_prekey.unwrap().key_id);
+        manager.mark_keys_as_published(ACCOUNT, &uploaded_ids);
+
+        // Nothing left to upload
+        let unpublished = manager.get_unpublished_prekeys(ACCOUNT);
+        assert!(unpublished.signed_prekey.is_none());
+        assert!(unpublished.fallback_prekey.is_none());
+        assert!(unpublished.one_time_prekeys.is_empty());
+
+        // Replenishing surfaces only the new keys
+        let new_keys = manager.replenish(ACCOUNT);
+        let unpublished = manager.get_unpublished_prekeys(ACCOUNT);
+        assert_eq!(unpublished.one_time_prekeys.len(), new_keys.len());
+    }
+
+    #[test]
+    fn test_phone_number_domain_is_independent_of_account_domain() {
+        let account_identity = IdentityKeyPair::generate();
+        let mut manager = PreKeyManager::new(account_identity);
+        manager.add_domain(KeyDomain::PhoneNumber, IdentityKeyPair::generate());
+
+        // Consuming from the account pool doesn't touch the PNI pool
+        let account_prekeys = manager.get_one_time_prekeys(ACCOUNT);
+        manager.consume_prekey(ACCOUNT, account_prekeys[0].key_id);
+        assert_eq!(manager.prekey_count(ACCOUNT), 99);
+        assert_eq!(manager.prekey_count(KeyDomain::PhoneNumber), 100);
+
+        // Each domain has its own identity key (key ids, by contrast, are
+        // numbered independently: both domains start from zero)
+        let account_bundle = manager.get_bundle(ACCOUNT).unwrap();
+        let pni_bundle = manager.get_bundle(KeyDomain::PhoneNumber).unwrap();
+        assert_ne!(account_bundle.identity_key, pni_bundle.identity_key);
+
+        // Rotating the PNI signed prekey doesn't bump the account's
+        let account_signed_before = manager.get_signed_prekey(ACCOUNT).unwrap();
+        manager.rotate_signed_prekey(KeyDomain::PhoneNumber).unwrap();
+        assert_eq!(manager.get_signed_prekey(ACCOUNT).unwrap().key_id, account_signed_before.key_id);
+    }
+
+    #[test]
+    fn test_operations_on_unregistered_domain_return_none_instead_of_panicking() {
+        let manager = PreKeyManager::new(IdentityKeyPair::generate());
+
+        assert!(manager.get_bundle(KeyDomain::PhoneNumber).is_none());
+        assert!(manager.status(KeyDomain::PhoneNumber).is_none());
+        assert_eq!(manager.prekey_count(KeyDomain::PhoneNumber), 0);
+    }
 }
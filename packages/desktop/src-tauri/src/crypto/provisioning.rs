@@ -0,0 +1,316 @@
+//! QR-based device linking (presage/libsignal-style `LinkDevice` provisioning)
+//!
+//! Lets an already-registered ("primary") device hand its identity to a
+//! brand-new ("secondary") device without the secondary re-registering an
+//! account from scratch:
+//!
+//! 1. The primary generates an ephemeral Curve25519 key pair and renders its
+//!    tagged public key as a QR payload for the secondary to scan.
+//! 2. The secondary generates its own ephemeral key pair and replies with its
+//!    public key (over whatever side channel carried the QR code - that
+//!    transport is out of scope for this module).
+//! 3. The primary calls [`export_provisioning_envelope`], which derives a
+//!    symmetric key via ECDH-then-HKDF-SHA256 over the two ephemeral
+//!    publics - the same construction as
+//!    [`crypto::transport::Handshake::agree`](crate::crypto::transport::Handshake::agree),
+//!    minus the `SecretConnection` framing since a provisioning envelope is
+//!    sent exactly once - and seals a [`LinkedDeviceState`] (its identity
+//!    private key, current signed prekey, user id, and a freshly-generated
+//!    linked `device_id`) under it with XChaCha20-Poly1305.
+//! 4. The secondary calls [`import_provisioning_envelope`] with the bytes and
+//!    its own ephemeral key pair to recover the [`LinkedDeviceState`] and
+//!    become a fully-provisioned second instance of the same account. The
+//!    primary's ephemeral public key travels in the envelope itself (it's
+//!    the only extra value the secondary doesn't already hold), so the
+//!    caller doesn't need to thread it through separately.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use vodozemac::Curve25519PublicKey;
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::keys::{Curve25519KeyPair, IdentityKeyPair, SignedPreKey};
+use crate::crypto::system;
+
+/// Version of the provisioning envelope format. Bumped whenever the
+/// envelope's contents change in a way that isn't backward compatible.
+const PROVISIONING_FORMAT_VERSION: u8 = 1;
+
+/// Length in bytes of a tagged Curve25519 public key (see `crypto::system::tag`).
+const TAGGED_PUBLIC_KEY_LEN: usize = 33;
+
+/// Length in bytes of an XChaCha20-Poly1305 nonce.
+const NONCE_LEN: usize = 24;
+
+/// Everything a newly-linked secondary device needs to act as the account:
+/// the shared identity key pair, the current signed prekey (public half plus
+/// its own secret, so the secondary can actually install it rather than
+/// just know about it), and the user/device ids to register itself under.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkedDeviceState {
+    pub user_id: String,
+    pub device_id: String,
+    /// Tagged identity public key (see [`IdentityKeyPair::public_key_bytes`]).
+    pub identity_public: Vec<u8>,
+    /// Tagged identity secret key (see [`IdentityKeyPair::secret_key_bytes`]).
+    pub identity_secret: Vec<u8>,
+    pub signed_prekey: SignedPreKey,
+    /// Tagged secret key for `signed_prekey` (see
+    /// [`Curve25519KeyPair::secret_key_bytes`]) - without this the secondary
+    /// can verify the signed prekey's signature but can't actually install
+    /// it, since [`SignedPreKey`] only ever carries the public half.
+    pub signed_prekey_secret: Vec<u8>,
+}
+
+/// Non-secret metadata about a newly linked device, returned to the
+/// frontend once `commands::import_linked_device` has installed the
+/// recovered identity into the local `PreKeyManager` - unlike
+/// [`LinkedDeviceState`], this never carries the identity secret key across
+/// the IPC boundary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkedDeviceInfo {
+    pub user_id: String,
+    pub device_id: String,
+}
+
+/// The envelope's plaintext, sealed by [`export_provisioning_envelope`].
+#[derive(Serialize, Deserialize)]
+struct ProvisioningPayload {
+    version: u8,
+    user_id: String,
+    device_id: String,
+    identity_public: Vec<u8>,
+    identity_secret: Vec<u8>,
+    signed_prekey: SignedPreKey,
+    signed_prekey_secret: Vec<u8>,
+}
+
+/// Generate a fresh linked `device_id` for a secondary device joining via
+/// provisioning.
+pub fn generate_linked_device_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("linked-{}", hex::encode(bytes))
+}
+
+/// Derive the shared XChaCha20-Poly1305 key for a provisioning exchange from
+/// both sides' ephemeral Curve25519 key pairs - the same ECDH-then-HKDF
+/// construction as [`crate::crypto::transport::Handshake::agree`], without
+/// the directional split since a provisioning envelope only ever flows
+/// primary-to-secondary.
+fn derive_provisioning_key(our_ephemeral: &Curve25519KeyPair, their_ephemeral_public: &[u8]) -> CryptoResult<[u8; 32]> {
+    let (_, their_bytes) = system::untag(their_ephemeral_public)?;
+    let their_arr: [u8; 32] = their_bytes
+        .try_into()
+        .map_err(|_| CryptoError::InvalidKey("Ephemeral public key must be 32 bytes".to_string()))?;
+    let their_public = Curve25519PublicKey::from_slice(&their_arr)?;
+
+    let shared_secret = our_ephemeral.diffie_hellman(&their_public);
+
+    let hkdf = Hkdf::<Sha256>::new(None, &shared_secret);
+    let mut key = [0u8; 32];
+    hkdf.expand(b"NoChat Device Provisioning v1", &mut key)
+        .map_err(|e| CryptoError::KeyExchangeFailed(format!("HKDF expansion failed: {}", e)))?;
+
+    Ok(key)
+}
+
+/// Run on the primary device once the secondary's scanned ephemeral public
+/// key (`peer_pub`) has arrived: seal a [`LinkedDeviceState`] for
+/// `identity`/`signed_prekey`/`user_id`/`linked_device_id`, so the secondary
+/// can recover it via [`import_provisioning_envelope`].
+///
+/// `linked_device_id` is generated by the caller (see
+/// [`generate_linked_device_id`]) rather than internally, so it's available
+/// to register in the `devices` table before the envelope is handed off.
+///
+/// `our_ephemeral` is the key pair the primary generated and rendered as a
+/// QR code in step 1; its public half is prepended to the output
+/// (untouched by encryption) so the secondary doesn't need it supplied
+/// separately.
+///
+/// `signed_prekey_pair` is the actual Curve25519 key pair backing
+/// `signed_prekey`, so the secondary can install the same signed prekey
+/// rather than just learn its public half - without it, any PreKey message
+/// already addressed to this signed prekey id would fail to decrypt on the
+/// secondary after linking.
+#[allow(clippy::too_many_arguments)]
+pub fn export_provisioning_envelope(
+    our_ephemeral: &Curve25519KeyPair,
+    peer_pub: &[u8],
+    identity: &IdentityKeyPair,
+    signed_prekey: SignedPreKey,
+    signed_prekey_pair: &Curve25519KeyPair,
+    user_id: &str,
+    linked_device_id: &str,
+) -> CryptoResult<Vec<u8>> {
+    let key = derive_provisioning_key(our_ephemeral, peer_pub)?;
+
+    let payload = ProvisioningPayload {
+        version: PROVISIONING_FORMAT_VERSION,
+        user_id: user_id.to_string(),
+        device_id: linked_device_id.to_string(),
+        identity_public: identity.public_key_bytes(),
+        identity_secret: identity.secret_key_bytes().to_vec(),
+        signed_prekey,
+        signed_prekey_secret: signed_prekey_pair.secret_key_bytes().to_vec(),
+    };
+    let plaintext = serde_json::to_vec(&payload).map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    let nonce = XNonce::from_slice(&nonce_bytes);
+
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key));
+    let sealed = cipher
+        .encrypt(nonce, plaintext.as_slice())
+        .map_err(|_| CryptoError::EncryptionError("provisioning envelope seal failed".to_string()))?;
+
+    let our_public = our_ephemeral.public_key_bytes();
+    let mut out = Vec::with_capacity(our_public.len() + NONCE_LEN + sealed.len());
+    out.extend_from_slice(&our_public);
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Run on the secondary device: recover the [`LinkedDeviceState`] sealed by
+/// [`export_provisioning_envelope`], using the ephemeral key pair
+/// (`our_ephemeral`) the secondary generated and showed to the primary in
+/// step 2. The primary's ephemeral public key is read back out of `bytes`
+/// itself rather than needing to be passed separately.
+pub fn import_provisioning_envelope(bytes: &[u8], our_ephemeral: &Curve25519KeyPair) -> CryptoResult<LinkedDeviceState> {
+    if bytes.len() < TAGGED_PUBLIC_KEY_LEN + NONCE_LEN {
+        return Err(CryptoError::DecryptionError("provisioning envelope too short".to_string()));
+    }
+    let (their_ephemeral_public, rest) = bytes.split_at(TAGGED_PUBLIC_KEY_LEN);
+    let (nonce_bytes, sealed) = rest.split_at(NONCE_LEN);
+
+    let key = derive_provisioning_key(our_ephemeral, their_ephemeral_public)?;
+
+    let cipher = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key));
+    let nonce = XNonce::from_slice(nonce_bytes);
+    let plaintext = cipher
+        .decrypt(nonce, sealed)
+        .map_err(|_| CryptoError::DecryptionError("provisioning envelope open failed".to_string()))?;
+
+    let payload: ProvisioningPayload =
+        serde_json::from_slice(&plaintext).map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+
+    if payload.version != PROVISIONING_FORMAT_VERSION {
+        return Err(CryptoError::SerializationError(format!(
+            "Unsupported provisioning envelope version: {}",
+            payload.version
+        )));
+    }
+
+    Ok(LinkedDeviceState {
+        user_id: payload.user_id,
+        device_id: payload.device_id,
+        identity_public: payload.identity_public,
+        identity_secret: payload.identity_secret,
+        signed_prekey: payload.signed_prekey,
+        signed_prekey_secret: payload.signed_prekey_secret,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_signed_prekey(identity: &IdentityKeyPair) -> (SignedPreKey, Curve25519KeyPair) {
+        let prekey = Curve25519KeyPair::generate();
+        (SignedPreKey::new(0, &prekey, identity), prekey)
+    }
+
+    #[test]
+    fn test_provisioning_round_trip() {
+        let primary_ephemeral = Curve25519KeyPair::generate();
+        let secondary_ephemeral = Curve25519KeyPair::generate();
+
+        let identity = IdentityKeyPair::generate();
+        let (signed_prekey, signed_prekey_pair) = sample_signed_prekey(&identity);
+
+        let envelope = export_provisioning_envelope(
+            &primary_ephemeral,
+            &secondary_ephemeral.public_key_bytes(),
+            &identity,
+            signed_prekey.clone(),
+            &signed_prekey_pair,
+            "alice",
+            "linked-test-device",
+        )
+        .unwrap();
+
+        let linked = import_provisioning_envelope(&envelope, &secondary_ephemeral).unwrap();
+
+        assert_eq!(linked.user_id, "alice");
+        assert_eq!(linked.identity_public, identity.public_key_bytes());
+        assert_eq!(linked.signed_prekey.key_id, signed_prekey.key_id);
+        assert!(linked.device_id.starts_with("linked-"));
+
+        // The recovered identity secret actually round-trips to a working key.
+        let restored = IdentityKeyPair::from_bytes(&linked.identity_public, &linked.identity_secret).unwrap();
+        assert_eq!(restored.fingerprint(), identity.fingerprint());
+
+        // The recovered signed prekey secret round-trips to the same key pair
+        // the primary actually has installed, not just the public half.
+        let restored_prekey =
+            Curve25519KeyPair::from_bytes(&linked.signed_prekey.public_key, &linked.signed_prekey_secret).unwrap();
+        assert_eq!(restored_prekey.public_key_bytes(), signed_prekey_pair.public_key_bytes());
+    }
+
+    #[test]
+    fn test_generated_linked_device_ids_are_unique() {
+        assert_ne!(generate_linked_device_id(), generate_linked_device_id());
+    }
+
+    #[test]
+    fn test_import_rejects_tampered_envelope() {
+        let primary_ephemeral = Curve25519KeyPair::generate();
+        let secondary_ephemeral = Curve25519KeyPair::generate();
+        let identity = IdentityKeyPair::generate();
+        let (signed_prekey, signed_prekey_pair) = sample_signed_prekey(&identity);
+
+        let mut envelope = export_provisioning_envelope(
+            &primary_ephemeral,
+            &secondary_ephemeral.public_key_bytes(),
+            &identity,
+            signed_prekey,
+            &signed_prekey_pair,
+            "alice",
+            "linked-test-device",
+        )
+        .unwrap();
+        let last = envelope.len() - 1;
+        envelope[last] ^= 0xFF;
+
+        assert!(import_provisioning_envelope(&envelope, &secondary_ephemeral).is_err());
+    }
+
+    #[test]
+    fn test_import_rejects_wrong_ephemeral_key() {
+        let primary_ephemeral = Curve25519KeyPair::generate();
+        let secondary_ephemeral = Curve25519KeyPair::generate();
+        let wrong_ephemeral = Curve25519KeyPair::generate();
+        let identity = IdentityKeyPair::generate();
+        let (signed_prekey, signed_prekey_pair) = sample_signed_prekey(&identity);
+
+        let envelope = export_provisioning_envelope(
+            &primary_ephemeral,
+            &secondary_ephemeral.public_key_bytes(),
+            &identity,
+            signed_prekey,
+            &signed_prekey_pair,
+            "alice",
+            "linked-test-device",
+        )
+        .unwrap();
+
+        assert!(import_provisioning_envelope(&envelope, &wrong_ephemeral).is_err());
+    }
+}
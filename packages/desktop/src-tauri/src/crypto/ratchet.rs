@@ -14,13 +14,18 @@
 //! Each message uses a unique key, and old keys are immediately deleted,
 //! providing forward secrecy.
 
+use std::collections::HashSet;
+
+use base64::Engine;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use vodozemac::olm::{
     Account, AccountPickle, OlmMessage, Session, SessionConfig, SessionPickle,
 };
 use vodozemac::{Curve25519PublicKey, KeyId};
 
 use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::pickle::{decrypt_pickle, encrypt_pickle};
 
 /// Pickle key for encrypting session state before storage
 /// This should be derived from user's device key in production
@@ -41,22 +46,50 @@ impl OlmAccount {
     }
 
     /// Restore from pickled (encrypted) state
-    pub fn from_pickle(pickled: &str, _pickle_key: &PickleKey) -> CryptoResult<Self> {
-        let pickle: AccountPickle = serde_json::from_str(pickled)
+    pub fn from_pickle(pickled: &str, pickle_key: &PickleKey) -> CryptoResult<Self> {
+        let encrypted = base64::engine::general_purpose::STANDARD
+            .decode(pickled)
+            .map_err(|e| CryptoError::SerializationError(format!("Invalid base64: {}", e)))?;
+        let json = decrypt_pickle(&encrypted, pickle_key)?;
+        Self::from_raw_pickle_json(&json)
+    }
+
+    /// Serialize the account's raw (unencrypted) pickle state as JSON, for
+    /// callers that apply their own encryption envelope instead of
+    /// [`pickle`](Self::pickle) - e.g. [`crate::crypto::export`].
+    pub(crate) fn raw_pickle_json(&self) -> CryptoResult<Vec<u8>> {
+        serde_json::to_vec(&self.inner.pickle())
+            .map_err(|e| CryptoError::SerializationError(e.to_string()))
+    }
+
+    /// Restore an account from the raw (unencrypted) pickle JSON produced by
+    /// [`raw_pickle_json`](Self::raw_pickle_json).
+    pub(crate) fn from_raw_pickle_json(json: &[u8]) -> CryptoResult<Self> {
+        let pickle: AccountPickle = serde_json::from_slice(json)
             .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
 
-        // Try libolm format first, then native format
-        let inner = Account::from_libolm_pickle(pickled, &[])
-            .unwrap_or_else(|_| Account::from(pickle));
+        Ok(Self {
+            inner: Account::from(pickle),
+        })
+    }
 
-        Ok(Self { inner })
+    /// An independent copy of this account, for callers (e.g.
+    /// [`crate::crypto::cache::CachedSessionStore`]) that need to hand out
+    /// their own copy of a cached account without re-reading and decrypting
+    /// it from storage. vodozemac's `Account` doesn't derive `Clone`, so this
+    /// goes through its own (unencrypted, in-memory) pickle representation
+    /// instead.
+    pub(crate) fn cheap_clone(&self) -> Self {
+        Self {
+            inner: Account::from(self.inner.pickle()),
+        }
     }
 
     /// Pickle (encrypt) the account for storage
-    pub fn pickle(&self, _pickle_key: &PickleKey) -> CryptoResult<String> {
-        let pickle = self.inner.pickle();
-        serde_json::to_string(&pickle)
-            .map_err(|e| CryptoError::SerializationError(e.to_string()))
+    pub fn pickle(&self, pickle_key: &PickleKey) -> CryptoResult<String> {
+        let json = self.raw_pickle_json()?;
+        let encrypted = encrypt_pickle(&json, pickle_key)?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(encrypted))
     }
 
     /// Get the identity public key (Curve25519)
@@ -87,6 +120,32 @@ impl OlmAccount {
         self.inner.mark_keys_as_published();
     }
 
+    /// Generate a new fallback key, used to create an inbound session when a
+    /// peer's one-time keys have all been consumed before they could
+    /// re-upload fresh ones.
+    ///
+    /// Unlike one-time keys, a fallback key is reused across sessions until
+    /// it is replaced, so this returns `None` (generating nothing) if the
+    /// current fallback key hasn't been consumed yet - discarding an unused
+    /// fallback key here would needlessly invalidate any in-flight prekey
+    /// message that still references it.
+    pub fn generate_fallback_key(&mut self) -> Option<(KeyId, Curve25519PublicKey)> {
+        self.inner.generate_fallback_key()?;
+        self.fallback_key()
+    }
+
+    /// Get the current (unpublished or published) fallback key, if one has
+    /// been generated.
+    pub fn fallback_key(&self) -> Option<(KeyId, Curve25519PublicKey)> {
+        self.inner.fallback_key().into_iter().next()
+    }
+
+    /// Discard the current fallback key, so it will no longer be offered to
+    /// new peers once a replacement is generated and published.
+    pub fn forget_fallback_key(&mut self) {
+        self.inner.forget_fallback_key();
+    }
+
     /// Create an outbound session (when initiating a conversation)
     pub fn create_outbound_session(
         &mut self,
@@ -106,6 +165,13 @@ impl OlmAccount {
     }
 
     /// Create an inbound session (when receiving a message from a new peer)
+    ///
+    /// Tolerates a prekey message that references our fallback key rather
+    /// than a one-time key: vodozemac's `Account` keeps the fallback key
+    /// available for repeated use (it is only removed by
+    /// [`forget_fallback_key`](Self::forget_fallback_key)), so this succeeds
+    /// the same way whether the sender picked a one-time key or fell back to
+    /// our fallback key after our one-time keys ran out.
     pub fn create_inbound_session(
         &mut self,
         their_identity_key: Curve25519PublicKey,
@@ -161,35 +227,179 @@ pub struct RatchetSession {
     messages_sent: u64,
     /// Number of messages received in this session
     messages_received: u64,
+    /// Digests of ciphertexts already successfully decrypted, so a
+    /// retransmitted (or maliciously replayed) ciphertext is rejected rather
+    /// than silently accepted - vodozemac's Session doesn't expose the raw
+    /// ratchet chain index, so identity is tracked by ciphertext digest
+    /// instead.
+    seen_message_digests: HashSet<[u8; 32]>,
+    /// Consecutive decrypt failures since the last success. Once this
+    /// crosses [`WEDGE_THRESHOLD`] the session is considered wedged: the
+    /// sender's key no longer matches our ratchet state and the session
+    /// needs to be torn down and re-established rather than retried forever.
+    consecutive_failures: u32,
+    /// Set once the session has been declared wedged; subsequent decrypts
+    /// short-circuit with [`CryptoError::SessionWedged`] instead of
+    /// continuing to hammer a session that can't recover on its own.
+    wedged: bool,
+    /// Milliseconds since the Unix epoch when this session was created.
+    creation_time: u64,
+    /// Milliseconds since the Unix epoch this session last successfully
+    /// encrypted or decrypted a message. Used to order concurrent sessions
+    /// with the same peer so the most-recently-used one is preferred for
+    /// sending - see [`PeerSessions`] - and to find sessions stale enough to
+    /// [`prune`](PeerSessions::prune).
+    last_use_time: u64,
 }
 
+pub(crate) fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Number of consecutive decrypt failures after which a session is declared
+/// wedged and surfaced to the caller for reset, instead of being retried
+/// indefinitely.
+const WEDGE_THRESHOLD: u32 = 5;
+
 impl RatchetSession {
     /// Create a new session from a vodozemac Session
     pub(crate) fn new(session: Session, peer_id: String) -> Self {
+        let now = now_millis();
         Self {
             inner: session,
             peer_id,
             messages_sent: 0,
             messages_received: 0,
+            seen_message_digests: HashSet::new(),
+            consecutive_failures: 0,
+            wedged: false,
+            creation_time: now,
+            last_use_time: now,
         }
     }
 
+    /// When this session was created, in milliseconds since the Unix epoch.
+    pub fn creation_time(&self) -> u64 {
+        self.creation_time
+    }
+
+    /// When this session last successfully encrypted or decrypted a
+    /// message, in milliseconds since the Unix epoch.
+    pub fn last_use_time(&self) -> u64 {
+        self.last_use_time
+    }
+
+    /// Whether an incoming PreKey message was encrypted for this session,
+    /// i.e. whether it was sent to the identity/one-time (or fallback) key
+    /// this session was established from. Used to route an incoming PreKey
+    /// message to an existing session instead of creating a duplicate when
+    /// both sides initiate a conversation at the same time.
+    pub fn matches(
+        &self,
+        their_identity_key: Curve25519PublicKey,
+        prekey_message: &vodozemac::olm::PreKeyMessage,
+    ) -> bool {
+        self.inner
+            .session_matches(their_identity_key, prekey_message)
+    }
+
     /// Encrypt a message
     ///
     /// Returns an OlmMessage that can be sent to the peer.
     /// The first message will be a PreKey message; subsequent messages will be normal.
     pub fn encrypt(&mut self, plaintext: &[u8]) -> OlmMessage {
         self.messages_sent += 1;
+        self.last_use_time = now_millis();
         self.inner.encrypt(plaintext)
     }
 
     /// Decrypt a message
     ///
-    /// Returns the decrypted plaintext.
+    /// Returns the decrypted plaintext. Returns
+    /// [`CryptoError::ReplayedMessage`] if this exact ciphertext has already
+    /// been decrypted by this session, and [`CryptoError::SessionWedged`]
+    /// once too many consecutive decrypt attempts have failed - in both
+    /// cases the caller should treat the session as needing a reset rather
+    /// than retrying.
     pub fn decrypt(&mut self, message: &OlmMessage) -> CryptoResult<Vec<u8>> {
-        let plaintext = self.inner.decrypt(message)?;
-        self.messages_received += 1;
-        Ok(plaintext)
+        if self.wedged {
+            return Err(CryptoError::SessionWedged {
+                peer_id: self.peer_id.clone(),
+            });
+        }
+
+        let digest = Self::digest_message(message);
+        if self.seen_message_digests.contains(&digest) {
+            return Err(CryptoError::ReplayedMessage {
+                peer_id: self.peer_id.clone(),
+            });
+        }
+
+        match self.inner.decrypt(message) {
+            Ok(plaintext) => {
+                self.consecutive_failures = 0;
+                self.seen_message_digests.insert(digest);
+                self.messages_received += 1;
+                self.last_use_time = now_millis();
+                Ok(plaintext)
+            }
+            Err(e) => {
+                self.consecutive_failures += 1;
+                if self.consecutive_failures >= WEDGE_THRESHOLD {
+                    self.wedged = true;
+                    return Err(CryptoError::SessionWedged {
+                        peer_id: self.peer_id.clone(),
+                    });
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Attempt a decrypt the same way [`Self::decrypt`] does, but without
+    /// bumping `consecutive_failures`/wedging this session on failure - used
+    /// by [`PeerSessions::decrypt`] while trying multiple candidate sessions
+    /// against the same message, where a failure here usually just means
+    /// this message wasn't for this session, not that the session itself is
+    /// unhealthy. A success still updates bookkeeping normally, since that
+    /// really was the matching session.
+    pub(crate) fn try_decrypt(&mut self, message: &OlmMessage) -> CryptoResult<Vec<u8>> {
+        if self.wedged {
+            return Err(CryptoError::SessionWedged {
+                peer_id: self.peer_id.clone(),
+            });
+        }
+
+        let digest = Self::digest_message(message);
+        if self.seen_message_digests.contains(&digest) {
+            return Err(CryptoError::ReplayedMessage {
+                peer_id: self.peer_id.clone(),
+            });
+        }
+
+        match self.inner.decrypt(message) {
+            Ok(plaintext) => {
+                self.consecutive_failures = 0;
+                self.seen_message_digests.insert(digest);
+                self.messages_received += 1;
+                self.last_use_time = now_millis();
+                Ok(plaintext)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// SHA-256 digest identifying a message's ciphertext bytes, used to
+    /// detect replays.
+    fn digest_message(message: &OlmMessage) -> [u8; 32] {
+        let bytes = match message {
+            OlmMessage::PreKey(m) => m.to_bytes(),
+            OlmMessage::Normal(m) => m.to_bytes(),
+        };
+        Sha256::digest(bytes).into()
     }
 
     /// Get the session ID (for logging/debugging)
@@ -197,6 +407,27 @@ impl RatchetSession {
         self.inner.session_id()
     }
 
+    /// An independent copy of this session, for callers (e.g.
+    /// [`crate::crypto::cache::CachedSessionStore`]) that need to hand out
+    /// their own copy of a cached session without re-reading and decrypting
+    /// it from storage. vodozemac's `Session` doesn't derive `Clone`, so this
+    /// goes through its own (unencrypted, in-memory) pickle representation
+    /// instead, alongside a plain clone of this wrapper's own bookkeeping
+    /// fields.
+    pub(crate) fn cheap_clone(&self) -> Self {
+        Self {
+            inner: Session::from(self.inner.pickle()),
+            peer_id: self.peer_id.clone(),
+            messages_sent: self.messages_sent,
+            messages_received: self.messages_received,
+            seen_message_digests: self.seen_message_digests.clone(),
+            consecutive_failures: self.consecutive_failures,
+            wedged: self.wedged,
+            creation_time: self.creation_time,
+            last_use_time: self.last_use_time,
+        }
+    }
+
     /// Check if this session has received a message
     ///
     /// Useful to determine if the session is established bidirectionally.
@@ -205,33 +436,64 @@ impl RatchetSession {
     }
 
     /// Serialize the session for storage
-    pub fn pickle(&self, _pickle_key: &PickleKey) -> CryptoResult<String> {
-        let pickle = self.inner.pickle();
+    pub fn pickle(&self, pickle_key: &PickleKey) -> CryptoResult<String> {
+        let json = self.raw_pickle_json()?;
+        let encrypted = encrypt_pickle(&json, pickle_key)?;
+        Ok(base64::engine::general_purpose::STANDARD.encode(encrypted))
+    }
+
+    /// Restore a session from storage
+    pub fn unpickle(pickled: &str, pickle_key: &PickleKey) -> CryptoResult<Self> {
+        let encrypted = base64::engine::general_purpose::STANDARD
+            .decode(pickled)
+            .map_err(|e| CryptoError::SerializationError(format!("Invalid base64: {}", e)))?;
+        let json = decrypt_pickle(&encrypted, pickle_key)?;
+        Self::from_raw_pickle_json(&json)
+    }
+
+    /// Serialize this session's raw (unencrypted) pickle state - including
+    /// `peer_id`, message counters, and replay/wedge tracking - as JSON, for
+    /// callers that apply their own encryption envelope instead of
+    /// [`pickle`](Self::pickle) - e.g. [`crate::crypto::export`].
+    pub(crate) fn raw_pickle_json(&self) -> CryptoResult<Vec<u8>> {
         let state = PickledSession {
-            session: pickle,
+            session: self.inner.pickle(),
             peer_id: self.peer_id.clone(),
             messages_sent: self.messages_sent,
             messages_received: self.messages_received,
+            seen_message_digests: self.seen_message_digests.iter().copied().collect(),
+            consecutive_failures: self.consecutive_failures,
+            wedged: self.wedged,
+            creation_time: self.creation_time,
+            last_use_time: self.last_use_time,
         };
-        serde_json::to_string(&state)
-            .map_err(|e| CryptoError::SerializationError(e.to_string()))
+        serde_json::to_vec(&state).map_err(|e| CryptoError::SerializationError(e.to_string()))
     }
 
-    /// Restore a session from storage
-    pub fn unpickle(pickled: &str, _pickle_key: &PickleKey) -> CryptoResult<Self> {
-        let state: PickledSession = serde_json::from_str(pickled)
+    /// Restore a session from the raw (unencrypted) pickle JSON produced by
+    /// [`raw_pickle_json`](Self::raw_pickle_json).
+    pub(crate) fn from_raw_pickle_json(json: &[u8]) -> CryptoResult<Self> {
+        let state: PickledSession = serde_json::from_slice(json)
             .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
 
-        let inner = Session::from(state.session);
-
         Ok(Self {
-            inner,
+            inner: Session::from(state.session),
             peer_id: state.peer_id,
             messages_sent: state.messages_sent,
             messages_received: state.messages_received,
+            seen_message_digests: state.seen_message_digests.into_iter().collect(),
+            consecutive_failures: state.consecutive_failures,
+            wedged: state.wedged,
+            creation_time: state.creation_time,
+            last_use_time: state.last_use_time,
         })
     }
 
+    /// Whether this session has been declared wedged and needs to be reset.
+    pub fn is_wedged(&self) -> bool {
+        self.wedged
+    }
+
     /// Get statistics about this session
     pub fn stats(&self) -> SessionStats {
         SessionStats {
@@ -239,10 +501,144 @@ impl RatchetSession {
             session_id: self.session_id(),
             messages_sent: self.messages_sent,
             messages_received: self.messages_received,
+            consecutive_failures: self.consecutive_failures,
+            wedged: self.wedged,
+            creation_time: self.creation_time,
+            last_use_time: self.last_use_time,
         }
     }
 }
 
+/// All concurrent sessions held with a single peer, ordered by creation
+/// time.
+///
+/// Simultaneous session initiation by both sides (Alice and Bob each send a
+/// PreKey message before seeing the other's) otherwise wedges whichever
+/// session loses the race. Keeping every session we've created or received
+/// lets outbound messages always use the newest one, and lets inbound
+/// messages be tried against each candidate (newest first) - or routed to a
+/// matching existing session via [`RatchetSession::matches`] - instead of
+/// failing or creating an unbounded number of duplicates.
+#[derive(Default)]
+pub struct PeerSessions {
+    sessions: Vec<RatchetSession>,
+}
+
+impl PeerSessions {
+    /// An empty session set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a session, e.g. one just created via
+    /// [`OlmAccount::create_outbound_session`] or
+    /// [`OlmAccount::create_inbound_session`].
+    pub fn insert(&mut self, session: RatchetSession) {
+        self.sessions.push(session);
+    }
+
+    /// The most-recently-used session, used for outbound encryption. Using
+    /// last-use (rather than creation) time as the tie-break means both
+    /// peers converge on the same session once either side has sent or
+    /// received through it.
+    pub fn newest(&self) -> Option<&RatchetSession> {
+        self.sessions.iter().max_by_key(|s| s.last_use_time())
+    }
+
+    /// The most-recently-used session, mutably.
+    pub fn newest_mut(&mut self) -> Option<&mut RatchetSession> {
+        self.sessions
+            .iter_mut()
+            .max_by_key(|s| s.last_use_time())
+    }
+
+    /// An existing session whose PreKey acceptance matches `prekey_message`,
+    /// if any - used to route an incoming PreKey message to an existing
+    /// session rather than minting a duplicate one.
+    pub fn find_matching_mut(
+        &mut self,
+        their_identity_key: Curve25519PublicKey,
+        prekey_message: &vodozemac::olm::PreKeyMessage,
+    ) -> Option<&mut RatchetSession> {
+        self.sessions_newest_first_mut()
+            .into_iter()
+            .find(|s| s.matches(their_identity_key, prekey_message))
+    }
+
+    /// Try decrypting `message` against every session, newest first,
+    /// returning the first successful result. This is the "try all
+    /// candidate sessions" half of the matrix approach: a Normal message
+    /// might belong to any session we've previously established with this
+    /// peer, not just the newest one.
+    ///
+    /// With a single session, a failure unambiguously belongs to it, so it
+    /// goes through [`RatchetSession::decrypt`] and counts toward that
+    /// session's wedge threshold as usual. With more than one concurrent
+    /// session, a failed trial against a non-matching candidate doesn't mean
+    /// anything about that session's health - it just means this message was
+    /// for a different one - so candidates are tried via
+    /// [`RatchetSession::try_decrypt`] instead, which leaves
+    /// `consecutive_failures`/`wedged` untouched on failure.
+    pub fn decrypt(&mut self, message: &OlmMessage) -> CryptoResult<Vec<u8>> {
+        let mut candidates = self.sessions_newest_first_mut();
+
+        if candidates.len() == 1 {
+            return candidates.remove(0).decrypt(message);
+        }
+
+        let mut last_err = None;
+        for session in candidates {
+            match session.try_decrypt(message) {
+                Ok(plaintext) => return Ok(plaintext),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| CryptoError::SessionNotFound("no sessions".to_string())))
+    }
+
+    /// All sessions, in insertion order.
+    pub fn all(&self) -> &[RatchetSession] {
+        &self.sessions
+    }
+
+    /// All sessions, mutably, in insertion order.
+    pub fn all_mut(&mut self) -> &mut [RatchetSession] {
+        &mut self.sessions
+    }
+
+    /// Whether any session exists for this peer.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// How many concurrent sessions exist for this peer.
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    fn sessions_newest_first_mut(&mut self) -> Vec<&mut RatchetSession> {
+        let mut refs: Vec<&mut RatchetSession> = self.sessions.iter_mut().collect();
+        refs.sort_by_key(|s| std::cmp::Reverse(s.last_use_time()));
+        refs
+    }
+
+    /// Drop every session last used before `cutoff` (milliseconds since the
+    /// Unix epoch). Returns the session ids removed, so the caller can also
+    /// delete them from [`crate::crypto::sessions::SessionStore`].
+    pub fn prune(&mut self, cutoff: u64) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.sessions.retain(|s| {
+            if s.last_use_time() < cutoff {
+                removed.push(s.session_id());
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+}
+
 /// Serializable session state
 #[derive(Serialize, Deserialize)]
 struct PickledSession {
@@ -250,6 +646,16 @@ struct PickledSession {
     peer_id: String,
     messages_sent: u64,
     messages_received: u64,
+    #[serde(default)]
+    seen_message_digests: Vec<[u8; 32]>,
+    #[serde(default)]
+    consecutive_failures: u32,
+    #[serde(default)]
+    wedged: bool,
+    #[serde(default)]
+    creation_time: u64,
+    #[serde(default)]
+    last_use_time: u64,
 }
 
 /// Statistics about a session
@@ -259,6 +665,16 @@ pub struct SessionStats {
     pub session_id: String,
     pub messages_sent: u64,
     pub messages_received: u64,
+    /// Consecutive decrypt failures since the last success.
+    pub consecutive_failures: u32,
+    /// Whether this session has been declared wedged and should be reset
+    /// by the app layer rather than retried.
+    pub wedged: bool,
+    /// Milliseconds since the Unix epoch when this session was created.
+    pub creation_time: u64,
+    /// Milliseconds since the Unix epoch this session last successfully
+    /// encrypted or decrypted a message.
+    pub last_use_time: u64,
 }
 
 /// Encrypted message with metadata
@@ -354,6 +770,57 @@ mod tests {
         assert_eq!(keys.len(), 0);
     }
 
+    #[test]
+    fn test_fallback_key_generation_and_retrieval() {
+        let mut account = OlmAccount::new();
+        assert!(account.fallback_key().is_none());
+
+        let (id, key) = account.generate_fallback_key().unwrap();
+        assert_eq!(key.to_bytes().len(), 32);
+        assert_eq!(account.fallback_key(), Some((id, key)));
+    }
+
+    #[test]
+    fn test_generating_fallback_key_twice_without_use_is_a_noop() {
+        let mut account = OlmAccount::new();
+        let first = account.generate_fallback_key().unwrap();
+
+        // The previous fallback key hasn't been consumed yet, so generating
+        // again shouldn't replace it.
+        assert!(account.generate_fallback_key().is_none());
+        assert_eq!(account.fallback_key(), Some(first));
+    }
+
+    #[test]
+    fn test_forget_fallback_key() {
+        let mut account = OlmAccount::new();
+        account.generate_fallback_key();
+        assert!(account.fallback_key().is_some());
+
+        account.forget_fallback_key();
+        assert!(account.fallback_key().is_none());
+    }
+
+    #[test]
+    fn test_inbound_session_from_fallback_key() {
+        // Bob has no one-time keys left, only a fallback key.
+        let mut bob = OlmAccount::new();
+        let (_, bob_fallback) = bob.generate_fallback_key().unwrap();
+
+        let mut alice = OlmAccount::new();
+        let mut alice_session = alice
+            .create_outbound_session(bob.identity_key(), bob_fallback)
+            .unwrap();
+
+        let plaintext = b"hello via fallback key";
+        let ciphertext = alice_session.encrypt(plaintext);
+
+        let (_, decrypted) = bob
+            .create_inbound_session(alice.identity_key(), &ciphertext)
+            .unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
     #[test]
     fn test_session_creation_and_encryption() {
         // Alice creates her account
@@ -460,4 +927,255 @@ mod tests {
         assert!(matches!(olm_msg, OlmMessage::PreKey(_)));
         assert!(matches!(restored_olm, OlmMessage::PreKey(_)));
     }
+
+    #[test]
+    fn test_replayed_message_is_rejected() {
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(1);
+
+        let bob_otk = bob.one_time_keys().into_iter().next().unwrap().1;
+        let mut alice_session = alice
+            .create_outbound_session(bob.identity_key(), bob_otk)
+            .unwrap();
+
+        let message = alice_session.encrypt(b"hello");
+        let (mut bob_session, _) = bob
+            .create_inbound_session(alice.identity_key(), &message)
+            .unwrap();
+
+        // Decrypting the exact same ciphertext again must be rejected as a
+        // replay rather than re-decrypted or passed through to vodozemac.
+        let err = bob_session.decrypt(&message).unwrap_err();
+        assert!(matches!(err, CryptoError::ReplayedMessage { .. }));
+    }
+
+    #[test]
+    fn test_session_wedges_after_repeated_failures() {
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(1);
+
+        let bob_otk = bob.one_time_keys().into_iter().next().unwrap().1;
+        let mut alice_session = alice
+            .create_outbound_session(bob.identity_key(), bob_otk)
+            .unwrap();
+
+        let good_message = alice_session.encrypt(b"hello");
+        let (mut bob_session, _) = bob
+            .create_inbound_session(alice.identity_key(), &good_message)
+            .unwrap();
+
+        // A follow-up message encrypted under a session Bob never created a
+        // matching ratchet step for - decryption fails every time.
+        let mut garbage = EncryptedMessage::from_olm(&alice_session.encrypt(b"more"));
+        garbage.ciphertext.iter_mut().for_each(|b| *b ^= 0xFF);
+        let garbage_message = garbage.to_olm().unwrap();
+
+        assert!(!bob_session.is_wedged());
+        for _ in 0..WEDGE_THRESHOLD - 1 {
+            assert!(bob_session.decrypt(&garbage_message).is_err());
+            assert!(!bob_session.is_wedged());
+        }
+
+        let err = bob_session.decrypt(&garbage_message).unwrap_err();
+        assert!(matches!(err, CryptoError::SessionWedged { .. }));
+        assert!(bob_session.is_wedged());
+
+        // Once wedged, even a perfectly valid message is rejected - the
+        // session must be torn down and re-established.
+        let err = bob_session.decrypt(&good_message).unwrap_err();
+        assert!(matches!(err, CryptoError::SessionWedged { .. }));
+    }
+
+    #[test]
+    fn test_stats_reflect_wedged_state() {
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(1);
+
+        let bob_otk = bob.one_time_keys().into_iter().next().unwrap().1;
+        let mut alice_session = alice
+            .create_outbound_session(bob.identity_key(), bob_otk)
+            .unwrap();
+        let message = alice_session.encrypt(b"hello");
+        let (mut bob_session, _) = bob
+            .create_inbound_session(alice.identity_key(), &message)
+            .unwrap();
+
+        assert!(!bob_session.stats().wedged);
+
+        let mut garbage = EncryptedMessage::from_olm(&alice_session.encrypt(b"bad"));
+        garbage.ciphertext.iter_mut().for_each(|b| *b ^= 0xFF);
+        let garbage_message = garbage.to_olm().unwrap();
+
+        for _ in 0..WEDGE_THRESHOLD {
+            let _ = bob_session.decrypt(&garbage_message);
+        }
+
+        let stats = bob_session.stats();
+        assert!(stats.wedged);
+        assert_eq!(stats.consecutive_failures, WEDGE_THRESHOLD);
+    }
+
+    #[test]
+    fn test_peer_sessions_newest_is_most_recently_inserted() {
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(2);
+        let mut otks = bob.one_time_keys().into_iter();
+
+        let first = alice
+            .create_outbound_session(bob.identity_key(), otks.next().unwrap().1)
+            .unwrap();
+        let second = alice
+            .create_outbound_session(bob.identity_key(), otks.next().unwrap().1)
+            .unwrap();
+
+        let mut peer_sessions = PeerSessions::new();
+        let first_id = first.session_id();
+        peer_sessions.insert(first);
+        let second_id = second.session_id();
+        peer_sessions.insert(second);
+
+        assert_eq!(peer_sessions.newest().unwrap().session_id(), second_id);
+        assert_ne!(first_id, second_id);
+    }
+
+    #[test]
+    fn test_peer_sessions_prune_removes_sessions_older_than_cutoff() {
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(1);
+        let otk = bob.one_time_keys().into_iter().next().unwrap().1;
+
+        let session = alice.create_outbound_session(bob.identity_key(), otk).unwrap();
+        let session_id = session.session_id();
+
+        let mut peer_sessions = PeerSessions::new();
+        peer_sessions.insert(session);
+
+        // A cutoff before the session's last use shouldn't remove anything.
+        assert!(peer_sessions.prune(0).is_empty());
+        assert!(!peer_sessions.is_empty());
+
+        // A cutoff after the session's last use should remove it.
+        let removed = peer_sessions.prune(u64::MAX);
+        assert_eq!(removed, vec![session_id]);
+        assert!(peer_sessions.is_empty());
+    }
+
+    #[test]
+    fn test_peer_sessions_routes_prekey_message_to_matching_session() {
+        // Bob has an existing session with Alice, created from her first
+        // PreKey message.
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(1);
+
+        let bob_otk = bob.one_time_keys().into_iter().next().unwrap().1;
+        let mut alice_session = alice
+            .create_outbound_session(bob.identity_key(), bob_otk)
+            .unwrap();
+
+        let message = alice_session.encrypt(b"hello");
+        let (bob_session, _) = bob
+            .create_inbound_session(alice.identity_key(), &message)
+            .unwrap();
+
+        let mut peer_sessions = PeerSessions::new();
+        peer_sessions.insert(bob_session);
+
+        // A *second* PreKey message for the same already-established session
+        // (e.g. a retransmit of the first message) should be recognised as
+        // belonging to the existing session rather than needing a new one.
+        if let OlmMessage::PreKey(prekey) = &message {
+            let matched = peer_sessions.find_matching_mut(alice.identity_key(), prekey);
+            assert!(matched.is_some());
+        } else {
+            panic!("expected a PreKey message");
+        }
+    }
+
+    #[test]
+    fn test_peer_sessions_decrypt_tries_all_candidates() {
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(2);
+        let mut otks = bob.one_time_keys().into_iter();
+
+        // Two independent sessions from Alice to Bob (as if both sides raced
+        // to establish a session and Bob ended up with two).
+        let mut session_a = alice
+            .create_outbound_session(bob.identity_key(), otks.next().unwrap().1)
+            .unwrap();
+        let mut session_b = alice
+            .create_outbound_session(bob.identity_key(), otks.next().unwrap().1)
+            .unwrap();
+
+        let msg_a = session_a.encrypt(b"via session a");
+        let msg_b = session_b.encrypt(b"via session b");
+
+        let (bob_session_a, _) = bob
+            .create_inbound_session(alice.identity_key(), &msg_a)
+            .unwrap();
+        let (bob_session_b, _) = bob
+            .create_inbound_session(alice.identity_key(), &msg_b)
+            .unwrap();
+
+        let mut peer_sessions = PeerSessions::new();
+        peer_sessions.insert(bob_session_a);
+        peer_sessions.insert(bob_session_b);
+
+        // A follow-up message on session B should decrypt even when session
+        // A is tried first (the two tie on last use when created in the same
+        // millisecond, and ties keep insertion order).
+        let follow_up = session_b.encrypt(b"second message on b");
+        let plaintext = peer_sessions.decrypt(&follow_up).unwrap();
+        assert_eq!(plaintext, b"second message on b");
+    }
+
+    #[test]
+    fn test_peer_sessions_decrypt_does_not_wedge_non_matching_candidates() {
+        let mut alice = OlmAccount::new();
+        let mut bob = OlmAccount::new();
+        bob.generate_one_time_keys(2);
+        let mut otks = bob.one_time_keys().into_iter();
+
+        let mut session_a = alice
+            .create_outbound_session(bob.identity_key(), otks.next().unwrap().1)
+            .unwrap();
+        let mut session_b = alice
+            .create_outbound_session(bob.identity_key(), otks.next().unwrap().1)
+            .unwrap();
+
+        let msg_a = session_a.encrypt(b"via session a");
+        let msg_b = session_b.encrypt(b"via session b");
+
+        let (bob_session_a, _) = bob
+            .create_inbound_session(alice.identity_key(), &msg_a)
+            .unwrap();
+        let (bob_session_b, _) = bob
+            .create_inbound_session(alice.identity_key(), &msg_b)
+            .unwrap();
+
+        let mut peer_sessions = PeerSessions::new();
+        peer_sessions.insert(bob_session_a);
+        peer_sessions.insert(bob_session_b);
+
+        // Repeatedly decrypt messages meant only for session B. Whenever
+        // session A is tried first (on a last-use tie, before B's first
+        // success), its trial decrypt fails - but that must not accumulate
+        // toward session A's wedge threshold, since the message was simply
+        // never meant for it.
+        for _ in 0..WEDGE_THRESHOLD + 5 {
+            let follow_up = session_b.encrypt(b"another message on b");
+            peer_sessions.decrypt(&follow_up).unwrap();
+        }
+
+        for session in peer_sessions.all() {
+            assert!(!session.is_wedged());
+            assert_eq!(session.stats().consecutive_failures, 0);
+        }
+    }
 }
@@ -25,12 +25,15 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use sqlx::SqlitePool;
+use vodozemac::olm::OlmMessage;
 use vodozemac::Curve25519PublicKey;
 
 use crate::crypto::errors::{CryptoError, CryptoResult};
-// PreKeyBundle available via crate::crypto::x3dh if needed for session establishment
-use crate::crypto::ratchet::{EncryptedMessage, OlmAccount, PickleKey, RatchetSession};
-use crate::crypto::sessions::{derive_pickle_key, generate_pickle_key, SessionStore};
+use crate::crypto::export;
+use crate::crypto::keys::SignedPreKey;
+use crate::crypto::ratchet::{now_millis, EncryptedMessage, OlmAccount, PeerSessions, PickleKey};
+use crate::crypto::sessions::{derive_pickle_key, generate_pickle_key, CryptoStore, SessionStore};
+use crate::crypto::x3dh::PreKeyBundle;
 
 /// High-level encryption service
 ///
@@ -38,10 +41,13 @@ use crate::crypto::sessions::{derive_pickle_key, generate_pickle_key, SessionSto
 pub struct CryptoService {
     /// The Olm account (identity + one-time keys)
     account: Arc<RwLock<OlmAccount>>,
-    /// Session storage
-    store: SessionStore,
-    /// Active sessions cache (peer_id -> session)
-    sessions: Arc<RwLock<std::collections::HashMap<String, RatchetSession>>>,
+    /// Persistence backend - `dyn`-dispatched so the service can be backed by
+    /// [`SessionStore`] (SQLite), [`crate::crypto::memory_store::InMemoryCryptoStore`]
+    /// (tests, DB-free use), or any other [`CryptoStore`] implementation
+    /// without the service itself needing to be generic.
+    store: Box<dyn CryptoStore>,
+    /// Active sessions cache (peer_id -> all concurrent sessions with that peer)
+    sessions: Arc<RwLock<std::collections::HashMap<String, PeerSessions>>>,
     /// Pickle key for encrypting stored sessions
     pickle_key: PickleKey,
 }
@@ -55,8 +61,26 @@ impl CryptoService {
         // In production, this should be derived from user credentials or device secret
         let pickle_key = generate_pickle_key();
         let store = SessionStore::new(db.clone(), pickle_key);
+        Self::from_store(Box::new(store), pickle_key).await
+    }
+
+    /// Initialize with a derived pickle key
+    pub async fn initialize_with_key(db: SqlitePool, secret: &[u8], salt: &[u8]) -> CryptoResult<Self> {
+        let pickle_key = derive_pickle_key(secret, salt);
+        let store = SessionStore::new(db.clone(), pickle_key);
+        Self::from_store(Box::new(store), pickle_key).await
+    }
 
-        // Try to load existing account
+    /// Initialize from any [`CryptoStore`] backend - e.g.
+    /// [`crate::crypto::memory_store::InMemoryCryptoStore`] for tests that
+    /// want the crypto layer without a real database.
+    pub async fn initialize_with_store(store: Box<dyn CryptoStore>, pickle_key: PickleKey) -> CryptoResult<Self> {
+        Self::from_store(store, pickle_key).await
+    }
+
+    /// Shared setup for every constructor: load or create the account, then
+    /// warm the session cache from `store`.
+    async fn from_store(store: Box<dyn CryptoStore>, pickle_key: PickleKey) -> CryptoResult<Self> {
         let account = match store.load_account().await? {
             Some(account) => {
                 tracing::info!("Loaded existing crypto account");
@@ -68,6 +92,10 @@ impl CryptoService {
                 // Generate initial one-time keys
                 account.generate_one_time_keys(100);
                 account.mark_keys_as_published();
+                // Generate a fallback key so a session can still be
+                // established if our one-time keys run out before we
+                // re-upload.
+                account.generate_fallback_key();
                 // Save the new account
                 store.save_account(&account).await?;
                 account
@@ -77,40 +105,11 @@ impl CryptoService {
         // Load existing sessions into cache
         let mut sessions = std::collections::HashMap::new();
         for peer_id in store.list_peers().await? {
-            if let Some(session) = store.load_session(&peer_id).await? {
-                sessions.insert(peer_id, session);
-            }
-        }
-
-        Ok(Self {
-            account: Arc::new(RwLock::new(account)),
-            store,
-            sessions: Arc::new(RwLock::new(sessions)),
-            pickle_key,
-        })
-    }
-
-    /// Initialize with a derived pickle key
-    pub async fn initialize_with_key(db: SqlitePool, secret: &[u8], salt: &[u8]) -> CryptoResult<Self> {
-        let pickle_key = derive_pickle_key(secret, salt);
-        let store = SessionStore::new(db.clone(), pickle_key);
-
-        let account = match store.load_account().await? {
-            Some(account) => account,
-            None => {
-                let mut account = OlmAccount::new();
-                account.generate_one_time_keys(100);
-                account.mark_keys_as_published();
-                store.save_account(&account).await?;
-                account
-            }
-        };
-
-        let mut sessions = std::collections::HashMap::new();
-        for peer_id in store.list_peers().await? {
-            if let Some(session) = store.load_session(&peer_id).await? {
-                sessions.insert(peer_id, session);
+            let mut peer_sessions = PeerSessions::new();
+            for session in store.load_sessions_for_peer(&peer_id).await? {
+                peer_sessions.insert(session);
             }
+            sessions.insert(peer_id, peer_sessions);
         }
 
         Ok(Self {
@@ -168,21 +167,67 @@ impl CryptoService {
         Ok(())
     }
 
+    /// Get the current fallback key, to upload to the server alongside the
+    /// one-time keys so a peer can still establish a session with us even if
+    /// our one-time keys are exhausted before we re-upload.
+    pub async fn get_fallback_key(&self) -> Option<(String, Vec<u8>)> {
+        let account = self.account.read().await;
+        account
+            .fallback_key()
+            .map(|(id, key)| (id.to_base64(), key.to_bytes().to_vec()))
+    }
+
+    /// Generate a new fallback key and persist the account state.
+    ///
+    /// Returns `None` (and leaves the account untouched) if the current
+    /// fallback key hasn't been consumed yet.
+    pub async fn generate_fallback_key(&self) -> CryptoResult<Option<(String, Vec<u8>)>> {
+        let mut account = self.account.write().await;
+        let key = account
+            .generate_fallback_key()
+            .map(|(id, key)| (id.to_base64(), key.to_bytes().to_vec()));
+        self.store.save_account(&account).await?;
+        Ok(key)
+    }
+
     /// Check if we have a session with a peer
     pub async fn has_session(&self, peer_id: &str) -> bool {
         let sessions = self.sessions.read().await;
-        sessions.contains_key(peer_id)
+        sessions.get(peer_id).is_some_and(|s| !s.is_empty())
+    }
+
+    /// How many concurrent sessions we hold with a peer (0 if none).
+    pub async fn session_count(&self, peer_id: &str) -> usize {
+        let sessions = self.sessions.read().await;
+        sessions.get(peer_id).map(|s| s.len()).unwrap_or(0)
     }
 
     /// Establish an outbound session with a peer
     ///
-    /// Use this when initiating a conversation with someone.
+    /// Use this when initiating a conversation with someone. Before touching
+    /// the Olm account at all, verifies `their_signed_prekey`'s Ed25519
+    /// signature against `their_signing_identity_key` via
+    /// [`PreKeyBundle::verify`], and fails if it doesn't check out. That
+    /// signature covers only the signed prekey: `their_identity_key` and
+    /// `their_one_time_key`, which the Olm session is actually built from,
+    /// are not bound to it, so this check does not detect a server that
+    /// substitutes those two keys.
     pub async fn establish_outbound_session(
         &self,
         peer_id: &str,
         their_identity_key: &[u8],
         their_one_time_key: &[u8],
+        their_signing_identity_key: &[u8],
+        their_signed_prekey: &SignedPreKey,
     ) -> CryptoResult<()> {
+        let bundle = PreKeyBundle {
+            identity_key: their_signing_identity_key.to_vec(),
+            signed_prekey: their_signed_prekey.clone(),
+            one_time_prekey: None,
+            fallback_key: None,
+        };
+        bundle.verify()?;
+
         let identity = Curve25519PublicKey::from_slice(their_identity_key)?;
         let one_time = Curve25519PublicKey::from_slice(their_one_time_key)?;
 
@@ -195,9 +240,12 @@ impl CryptoService {
         // Save session
         self.store.save_session(&session).await?;
 
-        // Add to cache
+        // Add to cache, alongside any other concurrent session with this peer
         let mut sessions = self.sessions.write().await;
-        sessions.insert(peer_id.to_string(), session);
+        sessions
+            .entry(peer_id.to_string())
+            .or_insert_with(PeerSessions::new)
+            .insert(session);
 
         tracing::info!("Established outbound session with peer: {}", peer_id);
         Ok(())
@@ -225,9 +273,12 @@ impl CryptoService {
         // Save session
         self.store.save_session(&session).await?;
 
-        // Add to cache
+        // Add to cache, alongside any other concurrent session with this peer
         let mut sessions = self.sessions.write().await;
-        sessions.insert(peer_id.to_string(), session);
+        sessions
+            .entry(peer_id.to_string())
+            .or_insert_with(PeerSessions::new)
+            .insert(session);
 
         tracing::info!("Established inbound session with peer: {}", peer_id);
         Ok(plaintext)
@@ -235,14 +286,20 @@ impl CryptoService {
 
     /// Encrypt a message for a peer
     ///
-    /// The peer must have an established session.
+    /// The peer must have an established session. If several concurrent
+    /// sessions exist (e.g. both sides initiated at once), the
+    /// most-recently-used one is used (see [`PeerSessions::newest_mut`]).
     pub async fn encrypt(&self, peer_id: &str, plaintext: &[u8]) -> CryptoResult<Vec<u8>> {
         let mut sessions = self.sessions.write().await;
 
-        let session = sessions
+        let peer_sessions = sessions
             .get_mut(peer_id)
             .ok_or_else(|| CryptoError::SessionNotFound(peer_id.to_string()))?;
 
+        let session = peer_sessions
+            .newest_mut()
+            .ok_or_else(|| CryptoError::SessionNotFound(peer_id.to_string()))?;
+
         let olm_message = session.encrypt(plaintext);
         let encrypted = EncryptedMessage::from_olm(&olm_message);
 
@@ -254,29 +311,61 @@ impl CryptoService {
 
     /// Decrypt a message from a peer
     ///
-    /// If no session exists, this will attempt to create one from the message.
+    /// A PreKey message that matches one of our existing sessions with this
+    /// peer is routed there rather than starting a duplicate session. A
+    /// Normal message is tried against every candidate session (newest
+    /// first), since it may belong to any of them. If nothing matches (or no
+    /// session exists yet), this falls back to creating a new inbound
+    /// session from the message.
     pub async fn decrypt(
         &self,
         peer_id: &str,
         their_identity_key: Option<&[u8]>,
         ciphertext: &[u8],
     ) -> CryptoResult<Vec<u8>> {
-        let mut sessions = self.sessions.write().await;
-
-        // Check if we have an existing session
-        if let Some(session) = sessions.get_mut(peer_id) {
-            let encrypted = EncryptedMessage::from_bytes(ciphertext)?;
-            let olm_message = encrypted.to_olm()?;
-
-            let plaintext = session.decrypt(&olm_message)?;
+        let encrypted = EncryptedMessage::from_bytes(ciphertext)?;
+        let olm_message = encrypted.to_olm()?;
 
-            // Save session state
-            self.store.save_session(session).await?;
+        let mut sessions = self.sessions.write().await;
 
-            return Ok(plaintext);
+        if let Some(peer_sessions) = sessions.get_mut(peer_id) {
+            if !peer_sessions.is_empty() {
+                if let (OlmMessage::PreKey(prekey), Some(identity_bytes)) =
+                    (&olm_message, their_identity_key)
+                {
+                    let identity = Curve25519PublicKey::from_slice(identity_bytes)?;
+                    if let Some(session) = peer_sessions.find_matching_mut(identity, prekey) {
+                        let plaintext = session.decrypt(&olm_message)?;
+                        self.store.save_session(session).await?;
+                        return Ok(plaintext);
+                    }
+                }
+
+                match peer_sessions.decrypt(&olm_message) {
+                    Ok(plaintext) => {
+                        for session in peer_sessions.all() {
+                            self.store.save_session(session).await?;
+                        }
+                        return Ok(plaintext);
+                    }
+                    Err(err) => {
+                        // A Normal message can only belong to a session we
+                        // already have - if it doesn't decrypt against any
+                        // of them there's no message left to bootstrap a new
+                        // session from, so surface the failure (typically
+                        // `SessionWedged` once the threshold's crossed)
+                        // instead of falling through to
+                        // `establish_inbound_session`, which would reject a
+                        // Normal message with an unrelated error.
+                        if matches!(olm_message, OlmMessage::Normal(_)) {
+                            return Err(err);
+                        }
+                    }
+                }
+            }
         }
 
-        // No existing session - try to create inbound session
+        // No existing (matching) session - try to create one from this message
         drop(sessions); // Release lock before calling establish_inbound_session
 
         let identity_key = their_identity_key.ok_or_else(|| {
@@ -289,6 +378,102 @@ impl CryptoService {
         self.establish_inbound_session(peer_id, identity_key, ciphertext).await
     }
 
+    /// Seal a message for `peer_id` so the transport only ever sees an
+    /// opaque envelope, not who it's from.
+    ///
+    /// `peer_id` must already have an established session (the inner
+    /// content still goes through the normal Double Ratchet path, so
+    /// forward secrecy is unaffected); `recipient_sealing_key` is the
+    /// recipient's raw (untagged) Curve25519 identity or prekey public key
+    /// to HPKE-seal the envelope to (see [`crate::crypto::hpke`]), the same
+    /// representation [`identity_key`](Self::identity_key) returns. `our_id`
+    /// is placed inside the encrypted envelope as the true sender id - never in the envelope
+    /// itself - and is implicitly authenticated on the receiving end because
+    /// [`open_sealed_sender`](Self::open_sealed_sender) only accepts it if a
+    /// session already exists under that id and the ratchet ciphertext
+    /// decrypts under it.
+    pub async fn seal_sender(
+        &self,
+        peer_id: &str,
+        recipient_sealing_key: &[u8],
+        our_id: &str,
+        plaintext: &[u8],
+    ) -> CryptoResult<SealedSenderPayload> {
+        let ratchet_ciphertext = self.encrypt(peer_id, plaintext).await?;
+
+        let inner = SealedSenderInner {
+            sender_id: our_id.to_string(),
+            ratchet_ciphertext,
+        };
+        let inner_bytes = serde_json::to_vec(&inner)
+            .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+
+        let recipient_public = Curve25519PublicKey::from_slice(recipient_sealing_key)?;
+        let sealed = crate::crypto::hpke::seal(&recipient_public, SEALED_SENDER_AAD, &inner_bytes)?;
+
+        Ok(SealedSenderPayload {
+            ephemeral_public: sealed.ephemeral_public,
+            ciphertext: sealed.ciphertext,
+        })
+    }
+
+    /// Open a [`SealedSenderPayload`] produced by [`seal_sender`](Self::seal_sender).
+    ///
+    /// `our_sealing_key` is the secret half of whatever public key the
+    /// sender sealed to - since [`CryptoService`] only wraps a vodozemac
+    /// [`OlmAccount`], which doesn't expose a raw Curve25519 secret for
+    /// arbitrary-point Diffie-Hellman, the caller supplies it directly (e.g.
+    /// a [`crate::crypto::prekeys::PreKeyManager`]-managed signed prekey or
+    /// fallback key). Returns the authenticated sender id alongside the
+    /// decrypted plaintext.
+    pub async fn open_sealed_sender(
+        &self,
+        our_sealing_key: &crate::crypto::keys::Curve25519KeyPair,
+        envelope: &SealedSenderPayload,
+    ) -> CryptoResult<(String, Vec<u8>)> {
+        let sealed = crate::crypto::hpke::SealedBox {
+            ephemeral_public: envelope.ephemeral_public.clone(),
+            ciphertext: envelope.ciphertext.clone(),
+        };
+        let inner_bytes = crate::crypto::hpke::open(our_sealing_key, SEALED_SENDER_AAD, &sealed)?;
+        let inner: SealedSenderInner = serde_json::from_slice(&inner_bytes)
+            .map_err(|e| CryptoError::SerializationError(e.to_string()))?;
+
+        // The sender id is only authenticated by this: it's never carried
+        // outside the HPKE envelope, and the ratchet ciphertext below only
+        // decrypts if a session already exists under the claimed id - a
+        // forged id can't be paired with a ciphertext that decrypts under
+        // the real sender's ratchet state.
+        let plaintext = self
+            .decrypt(&inner.sender_id, None, &inner.ratchet_ciphertext)
+            .await?;
+
+        Ok((inner.sender_id, plaintext))
+    }
+
+    /// Drop every session across all peers that hasn't been used (encrypted
+    /// or decrypted through) in at least `max_age`, from both the in-memory
+    /// cache and [`SessionStore`]. Keeps a peer's entry entirely dropped once
+    /// its last session is pruned. Returns how many sessions were removed.
+    pub async fn prune_sessions(&self, max_age: std::time::Duration) -> CryptoResult<usize> {
+        let cutoff = now_millis().saturating_sub(max_age.as_millis() as u64);
+
+        let mut sessions = self.sessions.write().await;
+        let mut pruned_session_ids = Vec::new();
+
+        sessions.retain(|_peer_id, peer_sessions| {
+            pruned_session_ids.extend(peer_sessions.prune(cutoff));
+            !peer_sessions.is_empty()
+        });
+        drop(sessions);
+
+        for session_id in &pruned_session_ids {
+            self.store.delete_session_by_id(session_id).await?;
+        }
+
+        Ok(pruned_session_ids.len())
+    }
+
     /// Delete a session with a peer
     pub async fn delete_session(&self, peer_id: &str) -> CryptoResult<()> {
         let mut sessions = self.sessions.write().await;
@@ -297,6 +482,38 @@ impl CryptoService {
         Ok(())
     }
 
+    /// Recover from a wedged session: tear down every session we hold with
+    /// `peer_id` and establish a fresh outbound one from their identity and
+    /// one-time key, the same way [`establish_outbound_session`] would for a
+    /// brand new peer.
+    ///
+    /// Call this after [`encrypt`](Self::encrypt)/[`decrypt`](Self::decrypt)
+    /// returns [`CryptoError::SessionWedged`] - the wedged session can't
+    /// recover on its own, so the conversation must restart from a fresh
+    /// prekey exchange rather than being retried. The caller is responsible
+    /// for fetching a current one-time key for `peer_id` (e.g. from the
+    /// server) and for telling the peer a new session was forced, e.g. by
+    /// flagging the next message with
+    /// [`HybridEncryptedMessage::with_renegotiated`].
+    pub async fn renegotiate_session(
+        &self,
+        peer_id: &str,
+        their_identity_key: &[u8],
+        their_one_time_key: &[u8],
+        their_signing_identity_key: &[u8],
+        their_signed_prekey: &SignedPreKey,
+    ) -> CryptoResult<()> {
+        self.delete_session(peer_id).await?;
+        self.establish_outbound_session(
+            peer_id,
+            their_identity_key,
+            their_one_time_key,
+            their_signing_identity_key,
+            their_signed_prekey,
+        )
+        .await
+    }
+
     /// Delete all sessions (for logout)
     pub async fn delete_all_sessions(&self) -> CryptoResult<()> {
         let mut sessions = self.sessions.write().await;
@@ -305,10 +522,15 @@ impl CryptoService {
         Ok(())
     }
 
-    /// Get statistics about all sessions
+    /// Get statistics about all sessions (including concurrent sessions with
+    /// the same peer)
     pub async fn get_session_stats(&self) -> Vec<crate::crypto::ratchet::SessionStats> {
         let sessions = self.sessions.read().await;
-        sessions.values().map(|s| s.stats()).collect()
+        sessions
+            .values()
+            .flat_map(|peer_sessions| peer_sessions.all())
+            .map(|s| s.stats())
+            .collect()
     }
 
     /// Get remaining one-time key count
@@ -338,8 +560,10 @@ impl CryptoService {
         self.store.save_account(&account).await?;
 
         let sessions = self.sessions.read().await;
-        for session in sessions.values() {
-            self.store.save_session(session).await?;
+        for peer_sessions in sessions.values() {
+            for session in peer_sessions.all() {
+                self.store.save_session(session).await?;
+            }
         }
 
         Ok(())
@@ -349,6 +573,50 @@ impl CryptoService {
     pub fn pickle_key(&self) -> &PickleKey {
         &self.pickle_key
     }
+
+    /// Export the account and every session (across all peers) as a single
+    /// encrypted bundle under `passphrase`, independent of `pickle_key` -
+    /// suitable for a device migration or an offline backup that should
+    /// survive a fresh random pickle key on the next device.
+    ///
+    /// See [`export::export_keys_raw`] for the archive/encryption format.
+    pub async fn export_encrypted(&self, passphrase: &str) -> CryptoResult<Vec<u8>> {
+        let account = self.account.read().await;
+        let sessions = self.sessions.read().await;
+        let all_sessions = sessions.values().flat_map(|peer_sessions| peer_sessions.all());
+
+        export::export_keys_raw(&account, all_sessions, passphrase)
+    }
+
+    /// Restore a `CryptoService` from a bundle produced by
+    /// [`export_encrypted`], persisting the recovered account and sessions
+    /// into `db` under a freshly-generated `pickle_key`.
+    ///
+    /// Rejects `blob` if the passphrase doesn't match or the bundle has been
+    /// tampered with - see [`export::import_keys_raw`].
+    pub async fn import_encrypted(db: SqlitePool, blob: &[u8], passphrase: &str) -> CryptoResult<Self> {
+        let (account, restored_sessions) = export::import_keys_raw(blob, passphrase)?;
+
+        let pickle_key = generate_pickle_key();
+        let store: Box<dyn CryptoStore> = Box::new(SessionStore::new(db, pickle_key));
+        store.save_account(&account).await?;
+
+        let mut sessions = std::collections::HashMap::new();
+        for session in restored_sessions {
+            store.save_session(&session).await?;
+            sessions
+                .entry(session.peer_id.clone())
+                .or_insert_with(PeerSessions::new)
+                .insert(session);
+        }
+
+        Ok(Self {
+            account: Arc::new(RwLock::new(account)),
+            store,
+            sessions: Arc::new(RwLock::new(sessions)),
+            pickle_key,
+        })
+    }
 }
 
 /// Encryption mode for hybrid protocol support
@@ -369,6 +637,11 @@ pub struct HybridEncryptedMessage {
     pub ciphertext: Vec<u8>,
     /// Sender's identity key (for session establishment)
     pub sender_identity: Option<Vec<u8>>,
+    /// Set when this message is the first one sent over a session created by
+    /// [`CryptoService::renegotiate_session`], so the recipient knows their
+    /// old (wedged) session is dead and this is a deliberate fresh start
+    /// rather than, say, a dropped or out-of-order message.
+    pub renegotiated: bool,
 }
 
 impl HybridEncryptedMessage {
@@ -378,6 +651,7 @@ impl HybridEncryptedMessage {
             mode: EncryptionMode::Signal,
             ciphertext,
             sender_identity,
+            renegotiated: false,
         }
     }
 
@@ -387,9 +661,17 @@ impl HybridEncryptedMessage {
             mode: EncryptionMode::Legacy,
             ciphertext,
             sender_identity: None,
+            renegotiated: false,
         }
     }
 
+    /// Flag this message as the first one sent over a renegotiated session -
+    /// see [`renegotiated`](Self::renegotiated).
+    pub fn with_renegotiated(mut self, renegotiated: bool) -> Self {
+        self.renegotiated = renegotiated;
+        self
+    }
+
     /// Serialize for transmission
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
@@ -400,6 +682,9 @@ impl HybridEncryptedMessage {
             EncryptionMode::Signal => 1,
         });
 
+        // Renegotiation flag byte
+        bytes.push(self.renegotiated as u8);
+
         // Sender identity (if present)
         if let Some(ref identity) = self.sender_identity {
             bytes.push(identity.len() as u8);
@@ -415,7 +700,7 @@ impl HybridEncryptedMessage {
 
     /// Deserialize from transmission
     pub fn from_bytes(bytes: &[u8]) -> CryptoResult<Self> {
-        if bytes.len() < 2 {
+        if bytes.len() < 3 {
             return Err(CryptoError::DecryptionError("Message too short".to_string()));
         }
 
@@ -425,27 +710,58 @@ impl HybridEncryptedMessage {
             v => return Err(CryptoError::DecryptionError(format!("Unknown version: {}", v))),
         };
 
-        let identity_len = bytes[1] as usize;
-        if bytes.len() < 2 + identity_len {
+        let renegotiated = bytes[1] != 0;
+
+        let identity_len = bytes[2] as usize;
+        if bytes.len() < 3 + identity_len {
             return Err(CryptoError::DecryptionError("Message truncated".to_string()));
         }
 
         let sender_identity = if identity_len > 0 {
-            Some(bytes[2..2 + identity_len].to_vec())
+            Some(bytes[3..3 + identity_len].to_vec())
         } else {
             None
         };
 
-        let ciphertext = bytes[2 + identity_len..].to_vec();
+        let ciphertext = bytes[3 + identity_len..].to_vec();
 
         Ok(Self {
             mode,
             ciphertext,
             sender_identity,
+            renegotiated,
         })
     }
 }
 
+/// Domain-separation context bound into every sealed-sender HPKE envelope,
+/// so it can't be confused with any other use of [`crate::crypto::hpke::seal`].
+const SEALED_SENDER_AAD: &[u8] = b"NoChat sealed-sender v1";
+
+/// What's actually inside a [`SealedSenderPayload`]'s HPKE encryption, never
+/// visible to the transport: the true sender id plus the inner Double
+/// Ratchet ciphertext.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct SealedSenderInner {
+    sender_id: String,
+    ratchet_ciphertext: Vec<u8>,
+}
+
+/// A sealed-sender envelope produced by
+/// [`CryptoService::seal_sender`] - the wire format a relay server
+/// forwards without learning who sent it. Mirrors
+/// [`crate::crypto::hpke::SealedBox`]; kept as its own type since this one
+/// is the crypto layer's public contract for sealed-sender messages
+/// specifically, independent of `hpke`'s generic single-shot sealing.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SealedSenderPayload {
+    /// Our fresh ephemeral public key for this message (the HPKE
+    /// encapsulated key).
+    pub ephemeral_public: Vec<u8>,
+    /// The HPKE-sealed [`SealedSenderInner`].
+    pub ciphertext: Vec<u8>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -475,5 +791,129 @@ mod tests {
         assert_eq!(restored.mode, EncryptionMode::Legacy);
         assert_eq!(restored.ciphertext, vec![5, 6, 7, 8]);
         assert_eq!(restored.sender_identity, None);
+        assert!(!restored.renegotiated);
+    }
+
+    #[test]
+    fn test_renegotiated_flag_round_trips() {
+        let msg = HybridEncryptedMessage::signal(vec![1, 2, 3], None).with_renegotiated(true);
+
+        let bytes = msg.to_bytes();
+        let restored = HybridEncryptedMessage::from_bytes(&bytes).unwrap();
+
+        assert!(restored.renegotiated);
+        assert_eq!(restored.ciphertext, vec![1, 2, 3]);
+    }
+
+    async fn service_with_session() -> (CryptoService, CryptoService, Curve25519KeyPair) {
+        use crate::crypto::memory_store::InMemoryCryptoStore;
+        use crate::crypto::sessions::generate_pickle_key;
+
+        let alice = CryptoService::initialize_with_store(
+            Box::new(InMemoryCryptoStore::new()),
+            generate_pickle_key(),
+        )
+        .await
+        .unwrap();
+        let bob = CryptoService::initialize_with_store(
+            Box::new(InMemoryCryptoStore::new()),
+            generate_pickle_key(),
+        )
+        .await
+        .unwrap();
+
+        let bob_identity = bob.identity_key().await;
+        // A new account marks its initial one-time keys published, so hand
+        // Alice a freshly generated (still unpublished) one instead.
+        let (_, bob_otk) = bob.generate_one_time_keys(1).await.unwrap().into_iter().next().unwrap();
+
+        // Bob's X3DH identity/signed prekey, standing in for what a real
+        // peer would have published via `PreKeyManager` - `establish_outbound_session`
+        // verifies this before trusting `bob_identity`/`bob_otk` at all.
+        let bob_signing_identity = crate::crypto::keys::IdentityKeyPair::generate();
+        let bob_prekey_pair = crate::crypto::keys::Curve25519KeyPair::generate();
+        let bob_signed_prekey =
+            crate::crypto::keys::SignedPreKey::new(0, &bob_prekey_pair, &bob_signing_identity);
+
+        alice
+            .establish_outbound_session(
+                "bob",
+                &bob_identity,
+                &bob_otk,
+                &bob_signing_identity.public_key_bytes(),
+                &bob_signed_prekey,
+            )
+            .await
+            .unwrap();
+
+        // `open_sealed_sender` only decrypts against an existing session
+        // under the claimed sender id, so Bob first sets one up from an
+        // ordinary message from Alice.
+        let alice_identity = alice.identity_key().await;
+        let first = alice.encrypt("bob", b"hello").await.unwrap();
+        bob.decrypt("alice", Some(&alice_identity), &first).await.unwrap();
+
+        // Bob's own sealed-sender sealing key - stands in for a
+        // `PreKeyManager`-managed signed prekey, since `CryptoService` has
+        // no Curve25519 secret of its own to hand out (see
+        // `open_sealed_sender`'s doc comment).
+        let bob_sealing_key = crate::crypto::keys::Curve25519KeyPair::generate();
+
+        (alice, bob, bob_sealing_key)
+    }
+
+    #[tokio::test]
+    async fn test_seal_sender_round_trips_and_authenticates_sender_id() {
+        let (alice, bob, bob_sealing_key) = service_with_session().await;
+
+        let envelope = alice
+            .seal_sender("bob", &bob_sealing_key.public.to_bytes(), "alice", b"hi bob")
+            .await
+            .unwrap();
+
+        let (sender_id, plaintext) = bob.open_sealed_sender(&bob_sealing_key, &envelope).await.unwrap();
+
+        assert_eq!(sender_id, "alice");
+        assert_eq!(plaintext, b"hi bob");
+    }
+
+    #[tokio::test]
+    async fn test_open_sealed_sender_rejects_forged_sender_id() {
+        let (alice, bob, bob_sealing_key) = service_with_session().await;
+
+        let mut envelope = alice
+            .seal_sender("bob", &bob_sealing_key.public.to_bytes(), "alice", b"hi bob")
+            .await
+            .unwrap();
+
+        // Tamper with the sealed payload by re-sealing a forged inner
+        // payload with no matching session - the outer HPKE layer opens
+        // fine (same recipient key), but the claimed sender has no session
+        // with Bob, so the ratchet decrypt underneath must fail.
+        let forged_inner = SealedSenderInner {
+            sender_id: "mallory".to_string(),
+            ratchet_ciphertext: b"not a real ratchet message".to_vec(),
+        };
+        let forged_bytes = serde_json::to_vec(&forged_inner).unwrap();
+        let resealed = crate::crypto::hpke::seal(&bob_sealing_key.public, SEALED_SENDER_AAD, &forged_bytes)
+            .unwrap();
+        envelope.ephemeral_public = resealed.ephemeral_public;
+        envelope.ciphertext = resealed.ciphertext;
+
+        assert!(bob.open_sealed_sender(&bob_sealing_key, &envelope).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_sealed_sender_rejects_wrong_recipient_key() {
+        let (alice, bob, _bob_sealing_key) = service_with_session().await;
+        let other_key = crate::crypto::keys::Curve25519KeyPair::generate();
+
+        let envelope = alice
+            .seal_sender("bob", &other_key.public.to_bytes(), "alice", b"hi bob")
+            .await
+            .unwrap();
+
+        let wrong_key = crate::crypto::keys::Curve25519KeyPair::generate();
+        assert!(bob.open_sealed_sender(&wrong_key, &envelope).await.is_err());
     }
 }
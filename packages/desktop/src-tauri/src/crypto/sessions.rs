@@ -4,11 +4,63 @@
 //! in the local SQLite database. Sessions are encrypted with a device-specific
 //! key before storage.
 
+use async_trait::async_trait;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
 use sqlx::{Row, SqlitePool};
 
 use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::megolm::{InboundGroupSession, OutboundGroupSession};
+use crate::crypto::pickle::{decrypt_pickle, encrypt_pickle};
 use crate::crypto::ratchet::{OlmAccount, PickleKey, RatchetSession};
 
+/// Fixed known-plaintext sealed into `crypto_meta.verify_blob` under the
+/// pickle key - see [`SessionStore::verify_pickle_key`].
+const VERIFY_PLAINTEXT: &[u8] = b"NoChat verify v1";
+const VERIFY_NONCE_LEN: usize = 24;
+
+/// Persistence surface [`crate::crypto::service::CryptoService`] actually
+/// needs, abstracted away from the concrete [`SessionStore`] so the crypto
+/// layer can be unit-tested without a real database (see
+/// [`crate::crypto::memory_store::InMemoryCryptoStore`]) and, eventually,
+/// backed by something other than SQLite (a remote store, an object store).
+///
+/// Implementations are expected to be cheap to clone/share (`SessionStore`
+/// wraps a pooled connection) and safe to call concurrently from multiple
+/// tasks, matching how [`CryptoService`](crate::crypto::service::CryptoService)
+/// uses them.
+#[async_trait]
+pub trait CryptoStore: Send + Sync {
+    /// Load the Olm account, if one has been saved yet.
+    async fn load_account(&self) -> CryptoResult<Option<OlmAccount>>;
+
+    /// Save or update the Olm account.
+    async fn save_account(&self, account: &OlmAccount) -> CryptoResult<()>;
+
+    /// Load every concurrent session stored for a peer.
+    async fn load_sessions_for_peer(&self, peer_id: &str) -> CryptoResult<Vec<RatchetSession>>;
+
+    /// Save or update a single session, keyed by its `session_id`.
+    async fn save_session(&self, session: &RatchetSession) -> CryptoResult<()>;
+
+    /// Delete every session for a peer.
+    async fn delete_session(&self, peer_id: &str) -> CryptoResult<()>;
+
+    /// Delete a single session by its session id, leaving any other
+    /// concurrent sessions for the same peer intact.
+    async fn delete_session_by_id(&self, session_id: &str) -> CryptoResult<()>;
+
+    /// Delete all sessions (for logout).
+    async fn delete_all_sessions(&self) -> CryptoResult<()>;
+
+    /// Get all peer IDs with active sessions.
+    async fn list_peers(&self) -> CryptoResult<Vec<String>>;
+
+    /// Get count of remaining one-time prekeys.
+    async fn count_one_time_prekeys(&self) -> CryptoResult<i64>;
+}
+
 /// Manages storage and retrieval of cryptographic sessions
 pub struct SessionStore {
     /// Database connection pool
@@ -63,7 +115,226 @@ impl SessionStore {
         }
     }
 
+    /// Write the pickle-key verification blob, if one hasn't been written
+    /// yet. Safe to call on every unlock: a no-op once the blob exists, so
+    /// callers don't need to check [`verify_pickle_key`](Self::verify_pickle_key)
+    /// first to decide whether initialization is needed.
+    pub async fn initialize_verification(&self) -> CryptoResult<()> {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+
+        let mut nonce_bytes = [0u8; VERIFY_NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = XNonce::from_slice(&nonce_bytes);
+
+        let cipher = XChaCha20Poly1305::new((&self.pickle_key).into());
+        let verify_blob = cipher
+            .encrypt(nonce, VERIFY_PLAINTEXT)
+            .map_err(|_| CryptoError::EncryptionError("failed to seal verification blob".to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO crypto_meta (id, salt, verify_nonce, verify_blob)
+            VALUES (0, $1, $2, $3)
+            ON CONFLICT(id) DO NOTHING
+            "#
+        )
+        .bind(salt.as_slice())
+        .bind(nonce_bytes.as_slice())
+        .bind(&verify_blob)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Check whether `self.pickle_key` is the one the store was initialized
+    /// with, by decrypting the verification blob written by
+    /// [`initialize_verification`](Self::initialize_verification) and
+    /// comparing it (in constant time) against the known plaintext.
+    ///
+    /// Returns `Ok(true)` if no verification blob has been written yet - a
+    /// fresh account has nothing to contradict the key, so the caller should
+    /// treat this as "needs initialization", not "wrong passphrase", and
+    /// call `initialize_verification` once it's ready to commit to this key.
+    pub async fn verify_pickle_key(&self) -> CryptoResult<bool> {
+        let row = sqlx::query(r#"SELECT verify_nonce, verify_blob FROM crypto_meta WHERE id = 0"#)
+            .fetch_optional(&self.db)
+            .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(true),
+        };
+
+        let nonce_bytes: Vec<u8> = row.get("verify_nonce");
+        let verify_blob: Vec<u8> = row.get("verify_blob");
+
+        let cipher = XChaCha20Poly1305::new((&self.pickle_key).into());
+        let decrypted = match cipher.decrypt(XNonce::from_slice(&nonce_bytes), verify_blob.as_slice()) {
+            Ok(plaintext) => plaintext,
+            Err(_) => return Ok(false),
+        };
+
+        Ok(crate::crypto::keys::constant_time_eq(&decrypted, VERIFY_PLAINTEXT))
+    }
+
+    /// Re-encrypt every pickled/encrypted row - the Olm account, every peer
+    /// session, every Megolm session, and every stored private key half -
+    /// under `new_key`, update the verification blob to match, and swap
+    /// `self.pickle_key`, all within one SQLite transaction. A failure
+    /// partway through (a row that won't unpickle, a write error) rolls back
+    /// the whole rotation rather than leaving a mix of old- and new-keyed
+    /// rows.
+    ///
+    /// Needed for password changes, and to migrate a store created with
+    /// [`generate_pickle_key`] (e.g. right after account creation, before a
+    /// passphrase is chosen) to one [`derive_pickle_key`]-derives from it.
+    ///
+    /// Bumps `crypto_meta.pickle_key_version` as the last write inside the
+    /// transaction, so a crash between rotating rows and committing leaves
+    /// the version column out of sync with what's actually stored and is
+    /// detectable on next open, rather than silently opening with a mix of
+    /// keys.
+    pub async fn rotate_pickle_key(&mut self, new_key: PickleKey) -> CryptoResult<()> {
+        use base64::Engine;
+
+        let mut tx = self.db.begin().await?;
+
+        if let Some(row) = sqlx::query(r#"SELECT account_data FROM crypto_account WHERE id = 1"#)
+            .fetch_optional(&mut *tx)
+            .await?
+        {
+            let account_data: String = row.get("account_data");
+            let account = OlmAccount::from_pickle(&account_data, &self.pickle_key)?;
+            let repickled = account.pickle(&new_key)?;
+
+            sqlx::query(r#"UPDATE crypto_account SET account_data = $1 WHERE id = 1"#)
+                .bind(&repickled)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let session_rows = sqlx::query(r#"SELECT id, session_data FROM peer_sessions"#)
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in session_rows {
+            let id: String = row.get("id");
+            let session_data: String = row.get("session_data");
+            let session = RatchetSession::unpickle(&session_data, &self.pickle_key)?;
+            let repickled = session.pickle(&new_key)?;
+
+            sqlx::query(r#"UPDATE peer_sessions SET session_data = $1 WHERE id = $2"#)
+                .bind(&repickled)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let outbound_rows = sqlx::query(
+            r#"SELECT conversation_id, session_data FROM outbound_group_sessions"#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for row in outbound_rows {
+            let conversation_id: String = row.get("conversation_id");
+            let encrypted: Vec<u8> = row.get("session_data");
+            let pickled = decode_pickled_json(&encrypted, &self.pickle_key)?;
+            let re_encrypted = encrypt_pickle(pickled.as_bytes(), &new_key)?;
+
+            sqlx::query(
+                r#"UPDATE outbound_group_sessions SET session_data = $1 WHERE conversation_id = $2"#
+            )
+            .bind(&re_encrypted)
+            .bind(&conversation_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let inbound_rows = sqlx::query(
+            r#"SELECT conversation_id, session_id, sender_key, session_data FROM inbound_group_sessions"#
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for row in inbound_rows {
+            let conversation_id: String = row.get("conversation_id");
+            let session_id: String = row.get("session_id");
+            let sender_key: String = row.get("sender_key");
+            let encrypted: Vec<u8> = row.get("session_data");
+            let pickled = decode_pickled_json(&encrypted, &self.pickle_key)?;
+            let re_encrypted = encrypt_pickle(pickled.as_bytes(), &new_key)?;
+
+            sqlx::query(
+                r#"
+                UPDATE inbound_group_sessions SET session_data = $1
+                WHERE conversation_id = $2 AND session_id = $3 AND sender_key = $4
+                "#
+            )
+            .bind(&re_encrypted)
+            .bind(&conversation_id)
+            .bind(&session_id)
+            .bind(&sender_key)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let key_rows = sqlx::query(r#"SELECT id, private_key FROM crypto_keys"#)
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in key_rows {
+            let id: String = row.get("id");
+            let private_key_b64: String = row.get("private_key");
+            let encrypted = base64::engine::general_purpose::STANDARD
+                .decode(&private_key_b64)
+                .map_err(|e| {
+                    CryptoError::SerializationError(format!("Failed to decode private key: {}", e))
+                })?;
+            let plaintext = decrypt_pickle(&encrypted, &self.pickle_key)?;
+            let re_encrypted = encrypt_pickle(&plaintext, &new_key)?;
+            let re_encrypted_b64 = base64::engine::general_purpose::STANDARD.encode(&re_encrypted);
+
+            sqlx::query(r#"UPDATE crypto_keys SET private_key = $1 WHERE id = $2"#)
+                .bind(&re_encrypted_b64)
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // Re-seal the verification blob under the new key so future
+        // `verify_pickle_key` calls check against it, not the old key.
+        let mut nonce_bytes = [0u8; VERIFY_NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = XNonce::from_slice(&nonce_bytes);
+        let cipher = XChaCha20Poly1305::new((&new_key).into());
+        let verify_blob = cipher
+            .encrypt(nonce, VERIFY_PLAINTEXT)
+            .map_err(|_| CryptoError::EncryptionError("failed to seal verification blob".to_string()))?;
+
+        sqlx::query(
+            r#"
+            UPDATE crypto_meta SET
+                verify_nonce = $1,
+                verify_blob = $2,
+                pickle_key_version = pickle_key_version + 1
+            WHERE id = 0
+            "#
+        )
+        .bind(nonce_bytes.as_slice())
+        .bind(&verify_blob)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.pickle_key = new_key;
+
+        Ok(())
+    }
+
     /// Save or update a session with a peer
+    ///
+    /// Keyed by `session_id` rather than `peer_id`, since a peer may have
+    /// several concurrent sessions (e.g. both sides initiated at once) - see
+    /// [`load_sessions_for_peer`](Self::load_sessions_for_peer).
     pub async fn save_session(&self, session: &RatchetSession) -> CryptoResult<()> {
         let pickled = session.pickle(&self.pickle_key)?;
         let session_id = session.session_id();
@@ -72,7 +343,7 @@ impl SessionStore {
             r#"
             INSERT INTO peer_sessions (id, peer_id, session_data, updated_at)
             VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
-            ON CONFLICT(peer_id) DO UPDATE SET
+            ON CONFLICT(id) DO UPDATE SET
                 session_data = excluded.session_data,
                 updated_at = CURRENT_TIMESTAMP
             "#
@@ -86,10 +357,13 @@ impl SessionStore {
         Ok(())
     }
 
-    /// Load a session for a specific peer
+    /// Load the single most-recently-saved session for a peer.
+    ///
+    /// Prefer [`load_sessions_for_peer`](Self::load_sessions_for_peer) when a
+    /// peer may have more than one concurrent session.
     pub async fn load_session(&self, peer_id: &str) -> CryptoResult<Option<RatchetSession>> {
         let result = sqlx::query(
-            r#"SELECT session_data FROM peer_sessions WHERE peer_id = $1"#
+            r#"SELECT session_data FROM peer_sessions WHERE peer_id = $1 ORDER BY updated_at DESC LIMIT 1"#
         )
         .bind(peer_id)
         .fetch_optional(&self.db)
@@ -105,6 +379,25 @@ impl SessionStore {
         }
     }
 
+    /// Load every concurrent session stored for a peer, so the caller can
+    /// pick the newest one for sending and try all of them (newest first)
+    /// when decrypting.
+    pub async fn load_sessions_for_peer(&self, peer_id: &str) -> CryptoResult<Vec<RatchetSession>> {
+        let rows = sqlx::query(
+            r#"SELECT session_data FROM peer_sessions WHERE peer_id = $1"#
+        )
+        .bind(peer_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let session_data: String = row.get("session_data");
+                RatchetSession::unpickle(&session_data, &self.pickle_key)
+            })
+            .collect()
+    }
+
     /// Check if a session exists for a peer
     pub async fn has_session(&self, peer_id: &str) -> CryptoResult<bool> {
         let result = sqlx::query(
@@ -127,6 +420,19 @@ impl SessionStore {
         Ok(())
     }
 
+    /// Delete a single session by its session id, leaving any other
+    /// concurrent sessions for the same peer intact - used by
+    /// [`crate::crypto::service::CryptoService::prune_sessions`] to drop
+    /// stale sessions one at a time.
+    pub async fn delete_session_by_id(&self, session_id: &str) -> CryptoResult<()> {
+        sqlx::query(r#"DELETE FROM peer_sessions WHERE id = $1"#)
+            .bind(session_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
     /// Delete all sessions (for logout)
     pub async fn delete_all_sessions(&self) -> CryptoResult<()> {
         sqlx::query(r#"DELETE FROM peer_sessions"#)
@@ -138,7 +444,7 @@ impl SessionStore {
 
     /// Get all peer IDs with active sessions
     pub async fn list_peers(&self) -> CryptoResult<Vec<String>> {
-        let results = sqlx::query(r#"SELECT peer_id FROM peer_sessions"#)
+        let results = sqlx::query(r#"SELECT DISTINCT peer_id FROM peer_sessions"#)
             .fetch_all(&self.db)
             .await?;
 
@@ -305,6 +611,242 @@ impl SessionStore {
 
         Ok(())
     }
+
+    /// Save (overwrite) our outbound Megolm session for a conversation.
+    /// There's only ever one at a time per conversation, since the whole
+    /// room should be decrypting against the sender key we're currently
+    /// advancing.
+    pub async fn save_outbound_group_session(
+        &self,
+        conversation_id: &str,
+        session: &OutboundGroupSession,
+    ) -> CryptoResult<()> {
+        let encrypted = encrypt_pickle(session.pickle()?.as_bytes(), &self.pickle_key)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO outbound_group_sessions (conversation_id, session_id, session_data, updated_at)
+            VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
+            ON CONFLICT(conversation_id) DO UPDATE SET
+                session_id = excluded.session_id,
+                session_data = excluded.session_data,
+                updated_at = CURRENT_TIMESTAMP
+            "#
+        )
+        .bind(conversation_id)
+        .bind(session.session_id())
+        .bind(&encrypted)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load our outbound Megolm session for a conversation, if we've started
+    /// one.
+    pub async fn load_outbound_group_session(
+        &self,
+        conversation_id: &str,
+    ) -> CryptoResult<Option<OutboundGroupSession>> {
+        let result = sqlx::query(r#"SELECT session_data FROM outbound_group_sessions WHERE conversation_id = $1"#)
+            .bind(conversation_id)
+            .fetch_optional(&self.db)
+            .await?;
+
+        match result {
+            Some(row) => {
+                let encrypted: Vec<u8> = row.get("session_data");
+                let pickled = decode_pickled_json(&encrypted, &self.pickle_key)?;
+                Ok(Some(OutboundGroupSession::unpickle(&pickled)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Save an inbound Megolm session, keyed by the (conversation, session,
+    /// sender key) triple - a conversation can have many concurrent sender
+    /// keys, one per member currently sending.
+    ///
+    /// Refuses to replace an already-stored session with one whose
+    /// [`first_known_index`](InboundGroupSession::first_known_index) is
+    /// higher: that would mean trading away the ability to decrypt earlier
+    /// history for no benefit, since the existing session can already
+    /// decrypt everything the new one can.
+    pub async fn save_inbound_group_session(
+        &self,
+        conversation_id: &str,
+        sender_key: &str,
+        session: &InboundGroupSession,
+    ) -> CryptoResult<()> {
+        let session_id = session.session_id();
+        let first_known_index = session.first_known_index();
+
+        let existing = sqlx::query(
+            r#"
+            SELECT first_known_index FROM inbound_group_sessions
+            WHERE conversation_id = $1 AND session_id = $2 AND sender_key = $3
+            "#
+        )
+        .bind(conversation_id)
+        .bind(&session_id)
+        .bind(sender_key)
+        .fetch_optional(&self.db)
+        .await?;
+
+        if let Some(row) = existing {
+            let existing_first_known_index: i64 = row.get("first_known_index");
+            if existing_first_known_index as u32 <= first_known_index {
+                return Ok(());
+            }
+        }
+
+        let encrypted = encrypt_pickle(session.pickle()?.as_bytes(), &self.pickle_key)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO inbound_group_sessions
+                (conversation_id, session_id, sender_key, first_known_index, session_data, updated_at)
+            VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+            ON CONFLICT(conversation_id, session_id, sender_key) DO UPDATE SET
+                first_known_index = excluded.first_known_index,
+                session_data = excluded.session_data,
+                updated_at = CURRENT_TIMESTAMP
+            "#
+        )
+        .bind(conversation_id)
+        .bind(&session_id)
+        .bind(sender_key)
+        .bind(first_known_index as i64)
+        .bind(&encrypted)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Load the inbound Megolm session for a specific (conversation, session,
+    /// sender key) triple.
+    pub async fn load_inbound_group_session(
+        &self,
+        conversation_id: &str,
+        session_id: &str,
+        sender_key: &str,
+    ) -> CryptoResult<Option<InboundGroupSession>> {
+        let result = sqlx::query(
+            r#"
+            SELECT session_data FROM inbound_group_sessions
+            WHERE conversation_id = $1 AND session_id = $2 AND sender_key = $3
+            "#
+        )
+        .bind(conversation_id)
+        .bind(session_id)
+        .bind(sender_key)
+        .fetch_optional(&self.db)
+        .await?;
+
+        match result {
+            Some(row) => {
+                let encrypted: Vec<u8> = row.get("session_data");
+                let pickled = decode_pickled_json(&encrypted, &self.pickle_key)?;
+                Ok(Some(InboundGroupSession::unpickle(&pickled)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Record that `device_id` has already received our current outbound
+    /// session key for `conversation_id`/`session_id`, so a future re-share
+    /// (e.g. a new member joining, or a periodic key rotation) only goes to
+    /// devices that still need it.
+    pub async fn mark_group_session_shared(
+        &self,
+        conversation_id: &str,
+        session_id: &str,
+        device_id: &str,
+    ) -> CryptoResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO group_session_shares (conversation_id, session_id, device_id, shared_at)
+            VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
+            ON CONFLICT(conversation_id, session_id, device_id) DO NOTHING
+            "#
+        )
+        .bind(conversation_id)
+        .bind(session_id)
+        .bind(device_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Which devices have already received our current outbound session key
+    /// for `conversation_id`/`session_id` - see
+    /// [`mark_group_session_shared`](Self::mark_group_session_shared).
+    pub async fn group_session_shared_devices(
+        &self,
+        conversation_id: &str,
+        session_id: &str,
+    ) -> CryptoResult<Vec<String>> {
+        let rows = sqlx::query(
+            r#"SELECT device_id FROM group_session_shares WHERE conversation_id = $1 AND session_id = $2"#
+        )
+        .bind(conversation_id)
+        .bind(session_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| r.get("device_id")).collect())
+    }
+}
+
+/// Decrypt a pickle encrypted with [`encrypt_pickle`] and decode it back to
+/// the UTF-8 JSON string [`OutboundGroupSession::pickle`] /
+/// [`InboundGroupSession::pickle`] produce - those types don't apply their
+/// own encryption envelope the way [`OlmAccount`]/[`RatchetSession`] do, so
+/// `SessionStore` wraps them here instead.
+fn decode_pickled_json(encrypted: &[u8], pickle_key: &PickleKey) -> CryptoResult<String> {
+    let json = decrypt_pickle(encrypted, pickle_key)?;
+    String::from_utf8(json).map_err(|e| CryptoError::SerializationError(e.to_string()))
+}
+
+#[async_trait]
+impl CryptoStore for SessionStore {
+    async fn load_account(&self) -> CryptoResult<Option<OlmAccount>> {
+        SessionStore::load_account(self).await
+    }
+
+    async fn save_account(&self, account: &OlmAccount) -> CryptoResult<()> {
+        SessionStore::save_account(self, account).await
+    }
+
+    async fn load_sessions_for_peer(&self, peer_id: &str) -> CryptoResult<Vec<RatchetSession>> {
+        SessionStore::load_sessions_for_peer(self, peer_id).await
+    }
+
+    async fn save_session(&self, session: &RatchetSession) -> CryptoResult<()> {
+        SessionStore::save_session(self, session).await
+    }
+
+    async fn delete_session(&self, peer_id: &str) -> CryptoResult<()> {
+        SessionStore::delete_session(self, peer_id).await
+    }
+
+    async fn delete_session_by_id(&self, session_id: &str) -> CryptoResult<()> {
+        SessionStore::delete_session_by_id(self, session_id).await
+    }
+
+    async fn delete_all_sessions(&self) -> CryptoResult<()> {
+        SessionStore::delete_all_sessions(self).await
+    }
+
+    async fn list_peers(&self) -> CryptoResult<Vec<String>> {
+        SessionStore::list_peers(self).await
+    }
+
+    async fn count_one_time_prekeys(&self) -> CryptoResult<i64> {
+        SessionStore::count_one_time_prekeys(self).await
+    }
 }
 
 /// Derive a pickle key from the user's password or device secret
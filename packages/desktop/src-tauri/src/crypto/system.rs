@@ -0,0 +1,218 @@
+//! Pluggable, versioned cryptosystem abstraction
+//!
+//! Every key type in this module has so far hardcoded Ed25519 (signing) and
+//! Curve25519 (Diffie-Hellman) via vodozemac, which makes it impossible to
+//! roll forward to a different suite later without breaking stored data. To
+//! allow that migration, every serialized key and signature this crate
+//! produces (`public_key_bytes`, `secret_key_bytes`, `SignedPreKey::signature`,
+//! etc.) is prefixed with a one-byte [`CryptoSystemId`] tag identifying which
+//! [`CryptoSystem`] implementation produced it, so a future `from_bytes` can
+//! dispatch to the right implementation - or reject bytes produced by a
+//! suite it doesn't understand - instead of silently misinterpreting them.
+
+use vodozemac::{Curve25519PublicKey, Curve25519SecretKey, Ed25519PublicKey, Ed25519SecretKey};
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+
+/// Identifies which [`CryptoSystem`] implementation produced a piece of key
+/// material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoSystemId {
+    /// Ed25519 (signing) + Curve25519 (Diffie-Hellman) via vodozemac - the
+    /// only suite this crate currently implements.
+    V0,
+}
+
+impl CryptoSystemId {
+    /// The one-byte wire tag for this system.
+    pub fn as_byte(self) -> u8 {
+        match self {
+            CryptoSystemId::V0 => 0,
+        }
+    }
+
+    /// Look up a system by its wire tag.
+    pub fn from_byte(byte: u8) -> CryptoResult<Self> {
+        match byte {
+            0 => Ok(CryptoSystemId::V0),
+            other => Err(CryptoError::InvalidKey(format!(
+                "Unknown cryptosystem tag: {other}"
+            ))),
+        }
+    }
+}
+
+/// Prefix `payload` with `id`'s one-byte tag.
+pub fn tag(id: CryptoSystemId, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + payload.len());
+    out.push(id.as_byte());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Split tagged bytes back into the [`CryptoSystemId`] that produced them
+/// and the remaining payload.
+pub fn untag(bytes: &[u8]) -> CryptoResult<(CryptoSystemId, &[u8])> {
+    let (&tag_byte, rest) = bytes
+        .split_first()
+        .ok_or_else(|| CryptoError::InvalidKey("Key material is empty".to_string()))?;
+    Ok((CryptoSystemId::from_byte(tag_byte)?, rest))
+}
+
+/// Abstracts key generation, signing, and Diffie-Hellman over a specific
+/// cryptographic suite, so the rest of the crate can eventually negotiate
+/// between multiple registered suites instead of hardcoding vodozemac's
+/// Ed25519/Curve25519.
+///
+/// Operates on raw (untagged) byte buffers rather than this crate's concrete
+/// key types, since a future suite may not share vodozemac's representation;
+/// callers are responsible for tagging/untagging at the storage boundary via
+/// [`tag`]/[`untag`].
+pub trait CryptoSystem: Send + Sync {
+    /// Which suite this implementation is.
+    fn id(&self) -> CryptoSystemId;
+
+    /// Generate a fresh signing (identity) keypair: `(public, secret)`.
+    fn generate_signing_keypair(&self) -> (Vec<u8>, Vec<u8>);
+
+    /// Sign `message` with a secret key from [`generate_signing_keypair`](Self::generate_signing_keypair).
+    fn sign(&self, secret: &[u8], message: &[u8]) -> CryptoResult<Vec<u8>>;
+
+    /// Verify a signature produced by [`sign`](Self::sign).
+    fn verify(&self, public: &[u8], message: &[u8], signature: &[u8]) -> CryptoResult<()>;
+
+    /// Generate a fresh Diffie-Hellman keypair: `(public, secret)`.
+    fn generate_dh_keypair(&self) -> (Vec<u8>, Vec<u8>);
+
+    /// Perform Diffie-Hellman, returning a 32-byte shared secret.
+    fn diffie_hellman(&self, secret: &[u8], their_public: &[u8]) -> CryptoResult<[u8; 32]>;
+
+    /// Compute a short fingerprint for a public key, for out-of-band verification.
+    fn fingerprint(&self, public: &[u8]) -> String;
+}
+
+/// The current (and so far only) registered suite: Ed25519 for signing,
+/// Curve25519 for Diffie-Hellman, both via vodozemac.
+pub struct V0System;
+
+impl CryptoSystem for V0System {
+    fn id(&self) -> CryptoSystemId {
+        CryptoSystemId::V0
+    }
+
+    fn generate_signing_keypair(&self) -> (Vec<u8>, Vec<u8>) {
+        let secret = Ed25519SecretKey::new();
+        let public = secret.public_key();
+        (public.as_bytes().to_vec(), secret.to_bytes().to_vec())
+    }
+
+    fn sign(&self, secret: &[u8], message: &[u8]) -> CryptoResult<Vec<u8>> {
+        let secret_arr: [u8; 32] = secret
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("Secret key must be 32 bytes".to_string()))?;
+        let secret = Ed25519SecretKey::from_slice(&secret_arr);
+        Ok(secret.sign(message).to_bytes().to_vec())
+    }
+
+    fn verify(&self, public: &[u8], message: &[u8], signature: &[u8]) -> CryptoResult<()> {
+        let public_arr: [u8; 32] = public
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("Public key must be 32 bytes".to_string()))?;
+        let public = Ed25519PublicKey::from_slice(&public_arr)?;
+        let signature = vodozemac::Ed25519Signature::from_slice(signature).map_err(|e| {
+            CryptoError::SignatureError(format!("Invalid signature format: {:?}", e))
+        })?;
+        public
+            .verify(message, &signature)
+            .map_err(|e| CryptoError::SignatureError(format!("Signature verification failed: {}", e)))
+    }
+
+    fn generate_dh_keypair(&self) -> (Vec<u8>, Vec<u8>) {
+        let secret = Curve25519SecretKey::new();
+        let public = Curve25519PublicKey::from(&secret);
+        (public.to_bytes().to_vec(), secret.to_bytes().to_vec())
+    }
+
+    fn diffie_hellman(&self, secret: &[u8], their_public: &[u8]) -> CryptoResult<[u8; 32]> {
+        let secret_arr: [u8; 32] = secret
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("Secret key must be 32 bytes".to_string()))?;
+        let public_arr: [u8; 32] = their_public
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("Public key must be 32 bytes".to_string()))?;
+        let secret = Curve25519SecretKey::from_slice(&secret_arr);
+        let public = Curve25519PublicKey::from_slice(&public_arr)?;
+        Ok(secret.diffie_hellman(&public).to_bytes())
+    }
+
+    fn fingerprint(&self, public: &[u8]) -> String {
+        use sha2::{Digest, Sha256};
+        let hash = Sha256::digest(public);
+        hex::encode(&hash[..8])
+    }
+}
+
+/// Look up the registered [`CryptoSystem`] implementation for a tag.
+pub fn system_for(id: CryptoSystemId) -> Box<dyn CryptoSystem> {
+    match id {
+        CryptoSystemId::V0 => Box::new(V0System),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_untag_roundtrip() {
+        let payload = vec![1, 2, 3];
+        let tagged = tag(CryptoSystemId::V0, &payload);
+        let (id, rest) = untag(&tagged).unwrap();
+        assert_eq!(id, CryptoSystemId::V0);
+        assert_eq!(rest, payload.as_slice());
+    }
+
+    #[test]
+    fn test_untag_rejects_unknown_system() {
+        assert!(untag(&[0xFF, 1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_untag_rejects_empty() {
+        assert!(untag(&[]).is_err());
+    }
+
+    #[test]
+    fn test_v0_sign_and_verify() {
+        let system = V0System;
+        let (public, secret) = system.generate_signing_keypair();
+        let message = b"hello";
+        let signature = system.sign(&secret, message).unwrap();
+        assert!(system.verify(&public, message, &signature).is_ok());
+    }
+
+    #[test]
+    fn test_v0_verify_rejects_tampered_message() {
+        let system = V0System;
+        let (public, secret) = system.generate_signing_keypair();
+        let signature = system.sign(&secret, b"hello").unwrap();
+        assert!(system.verify(&public, b"goodbye", &signature).is_err());
+    }
+
+    #[test]
+    fn test_v0_diffie_hellman() {
+        let system = V0System;
+        let (alice_public, alice_secret) = system.generate_dh_keypair();
+        let (bob_public, bob_secret) = system.generate_dh_keypair();
+
+        let shared_alice = system.diffie_hellman(&alice_secret, &bob_public).unwrap();
+        let shared_bob = system.diffie_hellman(&bob_secret, &alice_public).unwrap();
+        assert_eq!(shared_alice, shared_bob);
+    }
+
+    #[test]
+    fn test_system_for_v0() {
+        let system = system_for(CryptoSystemId::V0);
+        assert_eq!(system.id(), CryptoSystemId::V0);
+    }
+}
@@ -0,0 +1,366 @@
+//! Authenticated-encryption transport handshake (`SecretConnection`-style)
+//!
+//! X3DH and the Double Ratchet secure messages once a session exists, but
+//! two peers without one still need a live, confidential channel to talk
+//! over in the first place (e.g. a direct P2P or relay link behind the
+//! Tauri IPC layer). This module builds one on top of the existing identity
+//! keys rather than introducing a separate PKI:
+//!
+//! 1. Each side generates an ephemeral Curve25519 key pair and exchanges
+//!    public keys via [`Handshake::ephemeral_public_bytes`].
+//! 2. [`Handshake::agree`] sorts the two ephemeral public keys
+//!    deterministically and runs HKDF-SHA256 over their Diffie-Hellman
+//!    shared secret to derive two directional ChaCha20Poly1305 keys plus a
+//!    shared challenge value.
+//! 3. Each side signs the challenge - bound to this handshake via a
+//!    transcript hash of both ephemeral publics - with
+//!    [`IdentityKeyPair::sign`] ([`HandshakeSecrets::sign`]) and sends the
+//!    signature plus its Ed25519 identity public key; the peer verifies it
+//!    with [`Ed25519PublicKey::verify`] ([`HandshakeSecrets::verify`]).
+//! 4. Once both sides have authenticated, [`HandshakeSecrets::into_connection`]
+//!    yields a [`SecretConnection`] that frames application data as a 4-byte
+//!    little-endian length prefix followed by up to [`MAX_FRAME_LEN`]
+//!    plaintext bytes, sealed under a per-direction key with a 96-bit nonce
+//!    that increments once per frame and aborts rather than wrap around.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use vodozemac::{Curve25519PublicKey, Ed25519PublicKey};
+
+use crate::crypto::errors::{CryptoError, CryptoResult};
+use crate::crypto::keys::{Curve25519KeyPair, IdentityKeyPair};
+use crate::crypto::system;
+
+/// Maximum plaintext bytes carried by a single frame.
+pub const MAX_FRAME_LEN: usize = 1024;
+
+/// Our half of the handshake: an ephemeral key pair, exchanged with the peer
+/// before either side knows the shared secret.
+pub struct Handshake {
+    ephemeral: Curve25519KeyPair,
+}
+
+impl Handshake {
+    /// Start a handshake by generating our ephemeral key pair.
+    pub fn new() -> Self {
+        Self {
+            ephemeral: Curve25519KeyPair::generate(),
+        }
+    }
+
+    /// Our ephemeral public key, tagged the same way as
+    /// [`Curve25519KeyPair::public_key_bytes`], to send to the peer.
+    pub fn ephemeral_public_bytes(&self) -> Vec<u8> {
+        self.ephemeral.public_key_bytes()
+    }
+
+    /// Complete the key-agreement half of the handshake once the peer's
+    /// ephemeral public key has arrived, deriving the directional keys and
+    /// challenge both sides now authenticate via [`HandshakeSecrets::sign`]
+    /// and [`HandshakeSecrets::verify`].
+    pub fn agree(&self, their_ephemeral_public: &[u8]) -> CryptoResult<HandshakeSecrets> {
+        let (_, their_bytes) = system::untag(their_ephemeral_public)?;
+        let their_arr: [u8; 32] = their_bytes
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("Ephemeral public key must be 32 bytes".to_string()))?;
+        let their_public = Curve25519PublicKey::from_slice(&their_arr)?;
+
+        let shared_secret = self.ephemeral.diffie_hellman(&their_public);
+
+        let our_arr = self.ephemeral.public.to_bytes();
+        let we_are_low = our_arr <= their_arr;
+        let (low, high) = if we_are_low {
+            (our_arr, their_arr)
+        } else {
+            (their_arr, our_arr)
+        };
+
+        let mut transcript = Sha256::new();
+        transcript.update(low);
+        transcript.update(high);
+        let transcript_hash: [u8; 32] = transcript.finalize().into();
+
+        let hkdf = Hkdf::<Sha256>::new(Some(&transcript_hash), &shared_secret);
+        let mut okm = [0u8; 96];
+        hkdf.expand(b"NoChat SecretConnection v1", &mut okm)
+            .map_err(|e| CryptoError::KeyExchangeFailed(format!("HKDF expansion failed: {}", e)))?;
+
+        let key_low_to_high: [u8; 32] = okm[0..32].try_into().unwrap();
+        let key_high_to_low: [u8; 32] = okm[32..64].try_into().unwrap();
+        let challenge: [u8; 32] = okm[64..96].try_into().unwrap();
+
+        let (send_key, recv_key) = if we_are_low {
+            (key_low_to_high, key_high_to_low)
+        } else {
+            (key_high_to_low, key_low_to_high)
+        };
+
+        Ok(HandshakeSecrets {
+            send_key,
+            recv_key,
+            challenge,
+            transcript_hash,
+        })
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The directional keys and challenge derived by [`Handshake::agree`], ready
+/// to be mutually authenticated and turned into a [`SecretConnection`].
+pub struct HandshakeSecrets {
+    send_key: [u8; 32],
+    recv_key: [u8; 32],
+    challenge: [u8; 32],
+    transcript_hash: [u8; 32],
+}
+
+impl HandshakeSecrets {
+    /// The message each side signs to authenticate this handshake: the
+    /// challenge, bound to both parties' ephemeral keys via the transcript
+    /// hash so a signature can't be replayed against a different handshake.
+    fn signed_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(64);
+        payload.extend_from_slice(&self.transcript_hash);
+        payload.extend_from_slice(&self.challenge);
+        payload
+    }
+
+    /// Sign the challenge with our identity key. Send the result alongside
+    /// `identity.public_key_bytes()` to the peer.
+    pub fn sign(&self, identity: &IdentityKeyPair) -> Vec<u8> {
+        identity.sign(&self.signed_payload())
+    }
+
+    /// Verify the peer's signature over the same challenge, authenticating
+    /// them as the holder of `their_identity_public`. Only construct a
+    /// [`SecretConnection`] via [`Self::into_connection`] after this
+    /// succeeds.
+    pub fn verify(
+        &self,
+        their_identity_public: &Ed25519PublicKey,
+        their_signature: &[u8],
+    ) -> CryptoResult<()> {
+        let (_, signature) = system::untag(their_signature)?;
+        let signature = vodozemac::Ed25519Signature::from_slice(signature).map_err(|e| {
+            CryptoError::SignatureError(format!("Invalid signature format: {:?}", e))
+        })?;
+        their_identity_public
+            .verify(&self.signed_payload(), &signature)
+            .map_err(|e| {
+                CryptoError::SignatureError(format!("Handshake signature verification failed: {}", e))
+            })
+    }
+
+    /// Finish the handshake, producing a connection ready to seal and open
+    /// frames. Only call this once [`Self::verify`] has succeeded.
+    pub fn into_connection(self) -> SecretConnection {
+        SecretConnection {
+            send_key: self.send_key,
+            recv_key: self.recv_key,
+            send_nonce: FrameNonceCounter::new(),
+            recv_nonce: FrameNonceCounter::new(),
+        }
+    }
+}
+
+/// A per-direction 96-bit nonce that increments once per frame.
+///
+/// Errors rather than reusing a nonce once the 96-bit space is exhausted;
+/// at that point the connection must be re-keyed via a fresh handshake.
+struct FrameNonceCounter {
+    counter: [u8; 12],
+    exhausted: bool,
+}
+
+impl FrameNonceCounter {
+    fn new() -> Self {
+        Self {
+            counter: [0u8; 12],
+            exhausted: false,
+        }
+    }
+
+    fn next(&mut self) -> CryptoResult<Nonce> {
+        if self.exhausted {
+            return Err(CryptoError::EncryptionError(
+                "transport nonce space exhausted; connection must be re-keyed".to_string(),
+            ));
+        }
+        let nonce = *Nonce::from_slice(&self.counter);
+
+        let mut carry = true;
+        for byte in self.counter.iter_mut().rev() {
+            if !carry {
+                break;
+            }
+            let (res, overflow) = byte.overflowing_add(1);
+            *byte = res;
+            carry = overflow;
+        }
+        if carry {
+            self.exhausted = true;
+        }
+
+        Ok(nonce)
+    }
+}
+
+/// A live authenticated-encryption channel established by [`Handshake`].
+pub struct SecretConnection {
+    send_key: [u8; 32],
+    recv_key: [u8; 32],
+    send_nonce: FrameNonceCounter,
+    recv_nonce: FrameNonceCounter,
+}
+
+impl SecretConnection {
+    /// Seal one frame of plaintext (at most [`MAX_FRAME_LEN`] bytes) for
+    /// sending to the peer, returning the 4-byte little-endian length prefix
+    /// followed by the sealed payload.
+    pub fn seal_frame(&mut self, plaintext: &[u8]) -> CryptoResult<Vec<u8>> {
+        if plaintext.len() > MAX_FRAME_LEN {
+            return Err(CryptoError::EncryptionError(format!(
+                "frame exceeds {} byte limit",
+                MAX_FRAME_LEN
+            )));
+        }
+
+        let nonce = self.send_nonce.next()?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.send_key));
+        let sealed = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| CryptoError::EncryptionError("frame seal failed".to_string()))?;
+
+        let mut framed = Vec::with_capacity(4 + sealed.len());
+        framed.extend_from_slice(&(sealed.len() as u32).to_le_bytes());
+        framed.extend_from_slice(&sealed);
+        Ok(framed)
+    }
+
+    /// Open one sealed frame's payload (the part after the 4-byte length
+    /// prefix has already been read off the wire).
+    pub fn open_frame(&mut self, sealed: &[u8]) -> CryptoResult<Vec<u8>> {
+        let nonce = self.recv_nonce.next()?;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.recv_key));
+        cipher
+            .decrypt(&nonce, sealed)
+            .map_err(|_| CryptoError::DecryptionError("frame open failed".to_string()))
+    }
+}
+
+/// Decode a frame's 4-byte little-endian length prefix into the number of
+/// sealed bytes that follow it on the wire.
+pub fn read_frame_len(header: &[u8; 4]) -> usize {
+    u32::from_le_bytes(*header) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completed_connections() -> (SecretConnection, SecretConnection) {
+        let alice_identity = IdentityKeyPair::generate();
+        let bob_identity = IdentityKeyPair::generate();
+
+        let alice_handshake = Handshake::new();
+        let bob_handshake = Handshake::new();
+
+        let alice_secrets = alice_handshake
+            .agree(&bob_handshake.ephemeral_public_bytes())
+            .unwrap();
+        let bob_secrets = bob_handshake
+            .agree(&alice_handshake.ephemeral_public_bytes())
+            .unwrap();
+
+        let alice_sig = alice_secrets.sign(&alice_identity);
+        let bob_sig = bob_secrets.sign(&bob_identity);
+
+        bob_secrets.verify(&alice_identity.public, &alice_sig).unwrap();
+        alice_secrets.verify(&bob_identity.public, &bob_sig).unwrap();
+
+        (alice_secrets.into_connection(), bob_secrets.into_connection())
+    }
+
+    #[test]
+    fn test_handshake_derives_matching_directional_keys() {
+        let (mut alice, mut bob) = completed_connections();
+
+        let framed = alice.seal_frame(b"hello bob").unwrap();
+        let (len_bytes, sealed) = framed.split_at(4);
+        assert_eq!(read_frame_len(len_bytes.try_into().unwrap()), sealed.len());
+
+        let opened = bob.open_frame(sealed).unwrap();
+        assert_eq!(opened, b"hello bob");
+    }
+
+    #[test]
+    fn test_handshake_rejects_wrong_identity() {
+        let alice_identity = IdentityKeyPair::generate();
+        let impostor_identity = IdentityKeyPair::generate();
+        let bob_identity = IdentityKeyPair::generate();
+
+        let alice_handshake = Handshake::new();
+        let bob_handshake = Handshake::new();
+
+        let alice_secrets = alice_handshake
+            .agree(&bob_handshake.ephemeral_public_bytes())
+            .unwrap();
+        let bob_secrets = bob_handshake
+            .agree(&alice_handshake.ephemeral_public_bytes())
+            .unwrap();
+
+        let alice_sig = alice_secrets.sign(&alice_identity);
+
+        // Bob expects Alice's declared identity key; the impostor's won't verify.
+        assert!(bob_secrets.verify(&impostor_identity.public, &alice_sig).is_err());
+        assert!(bob_secrets.verify(&alice_identity.public, &alice_sig).is_ok());
+        let _ = bob_identity; // bob's own identity is unused on this path
+    }
+
+    #[test]
+    fn test_frames_round_trip_both_directions() {
+        let (mut alice, mut bob) = completed_connections();
+
+        let a_to_b = alice.seal_frame(b"ping").unwrap();
+        assert_eq!(bob.open_frame(&a_to_b[4..]).unwrap(), b"ping");
+
+        let b_to_a = bob.seal_frame(b"pong").unwrap();
+        assert_eq!(alice.open_frame(&b_to_a[4..]).unwrap(), b"pong");
+    }
+
+    #[test]
+    fn test_oversized_frame_is_rejected() {
+        let (mut alice, _bob) = completed_connections();
+        let too_big = vec![0u8; MAX_FRAME_LEN + 1];
+        assert!(alice.seal_frame(&too_big).is_err());
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_fails_to_open() {
+        let (mut alice, mut bob) = completed_connections();
+        let mut framed = alice.seal_frame(b"hello").unwrap();
+        let last = framed.len() - 1;
+        framed[last] ^= 0xFF;
+        assert!(bob.open_frame(&framed[4..]).is_err());
+    }
+
+    #[test]
+    fn test_nonce_counter_advances_and_detects_wraparound() {
+        let mut counter = FrameNonceCounter {
+            counter: [0xFF; 12],
+            exhausted: false,
+        };
+        // This call uses the last valid nonce and wraps the counter to zero.
+        assert!(counter.next().is_ok());
+        assert!(counter.exhausted);
+        // Any further use would reuse nonce zero, so it must be rejected.
+        assert!(counter.next().is_err());
+    }
+}
@@ -21,19 +21,53 @@ use hkdf::Hkdf;
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use vodozemac::{Curve25519PublicKey, Ed25519PublicKey};
+use zeroize::Zeroizing;
 
 use crate::crypto::errors::{CryptoError, CryptoResult};
-use crate::crypto::keys::{Curve25519KeyPair, IdentityKeyPair, OneTimePreKey, SignedPreKey};
+use crate::crypto::keys::{Curve25519KeyPair, FallbackPreKey, IdentityKeyPair, OneTimePreKey, SignedPreKey};
+use crate::crypto::system::{self, CryptoSystemId};
+
+/// Which kind of reusable/single-use prekey (if any) contributed DH4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PreKeyKind {
+    /// A single-use prekey, consumed after this handshake.
+    OneTime,
+    /// The reusable "last-resort" fallback prekey.
+    Fallback,
+}
+
+/// How to encode the ephemeral public key emitted by [`x3dh_initiate`].
+///
+/// A raw Curve25519 point lives in a recognizable subset of 32-byte strings,
+/// so a passive observer on a hostile network can fingerprint X3DH traffic
+/// just from that distribution. [`HandshakeEncoding::Obfuscated`] instead
+/// emits an Elligator2 representative of the point - indistinguishable from
+/// uniform random bytes - at the cost of retrying ephemeral key generation
+/// until a representable point comes up (roughly a coin flip per attempt).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeEncoding {
+    /// Emit the ephemeral public key as-is. Cheapest, but fingerprintable.
+    Raw,
+    /// Emit an Elligator2 representative instead of the raw point.
+    Obfuscated,
+}
 
 /// Result of X3DH key agreement (initiator side)
 #[derive(Debug)]
 pub struct X3dhResult {
-    /// The derived shared secret (32 bytes)
-    pub shared_secret: [u8; 32],
-    /// The ephemeral public key to send to the responder
+    /// The derived shared secret (32 bytes), scrubbed on drop
+    pub shared_secret: Zeroizing<[u8; 32]>,
+    /// The ephemeral public key to send to the responder - a raw point or
+    /// an Elligator2 representative, depending on `obfuscated`
     pub ephemeral_public: Vec<u8>,
-    /// The ID of the one-time prekey that was used (if any)
-    pub used_one_time_prekey: Option<u32>,
+    /// Whether `ephemeral_public` is an Elligator2 representative rather
+    /// than a raw Curve25519 point - propagate this into
+    /// [`X3dhHeader::obfuscated_ephemeral_key`] for the responder
+    pub obfuscated: bool,
+    /// The id of the one-time or fallback prekey that contributed DH4, if any
+    pub used_prekey_id: Option<u32>,
+    /// Which kind `used_prekey_id` refers to - present only when it is
+    pub used_prekey_kind: Option<PreKeyKind>,
 }
 
 /// Prekey bundle fetched from the server
@@ -45,22 +79,32 @@ pub struct PreKeyBundle {
     pub signed_prekey: SignedPreKey,
     /// Optional one-time prekey (consumed after use)
     pub one_time_prekey: Option<OneTimePreKey>,
+    /// The responder's reusable "last-resort" prekey, carried alongside
+    /// `one_time_prekey` so a handshake still gets a DH4 contribution once
+    /// the responder's one-time pool is exhausted.
+    pub fallback_key: Option<FallbackPreKey>,
 }
 
 impl PreKeyBundle {
     /// Get the identity key as Ed25519PublicKey
     pub fn get_identity_key(&self) -> CryptoResult<Ed25519PublicKey> {
-        let arr: [u8; 32] = self.identity_key.as_slice().try_into()
+        let (_, identity_key) = system::untag(&self.identity_key)?;
+        let arr: [u8; 32] = identity_key.try_into()
             .map_err(|_| CryptoError::InvalidKey("Identity key must be 32 bytes".to_string()))?;
         Ed25519PublicKey::from_slice(&arr).map_err(|e| {
             CryptoError::InvalidKey(format!("Invalid identity key in bundle: {:?}", e))
         })
     }
 
-    /// Verify that the signed prekey has a valid signature from the identity key
+    /// Verify that the signed prekey - and the fallback prekey, if present -
+    /// have a valid signature from the identity key
     pub fn verify(&self) -> CryptoResult<()> {
         let identity_key = self.get_identity_key()?;
-        self.signed_prekey.verify(&identity_key)
+        self.signed_prekey.verify(&identity_key)?;
+        if let Some(fallback_key) = &self.fallback_key {
+            fallback_key.verify(&identity_key)?;
+        }
+        Ok(())
     }
 }
 
@@ -74,6 +118,7 @@ impl PreKeyBundle {
 ///
 /// * `our_identity` - Our long-term identity key pair
 /// * `their_bundle` - The responder's prekey bundle (fetched from server)
+/// * `encoding` - Whether to emit the ephemeral key as-is or Elligator2-obfuscated
 ///
 /// # Returns
 ///
@@ -81,12 +126,23 @@ impl PreKeyBundle {
 pub fn x3dh_initiate(
     our_identity: &IdentityKeyPair,
     their_bundle: &PreKeyBundle,
+    encoding: HandshakeEncoding,
 ) -> CryptoResult<X3dhResult> {
     // Verify the bundle first
     their_bundle.verify()?;
 
-    // Generate ephemeral key pair for this session
-    let ephemeral = Curve25519KeyPair::generate();
+    // Generate an ephemeral key pair for this session. In obfuscated mode,
+    // not every point is Elligator2-representable, so regenerate until one
+    // is - each attempt succeeds independently with probability ~1/2.
+    let (ephemeral, ephemeral_representative) = match encoding {
+        HandshakeEncoding::Raw => (Curve25519KeyPair::generate(), None),
+        HandshakeEncoding::Obfuscated => loop {
+            let candidate = Curve25519KeyPair::generate();
+            if let Some(representative) = elligator::encode(&candidate.public) {
+                break (candidate, Some(representative));
+            }
+        },
+    };
 
     // Parse their keys
     let their_identity = their_bundle.get_identity_key()?;
@@ -100,32 +156,52 @@ pub fn x3dh_initiate(
     // DH1 = DH(IK_A_curve, SPK_B)
     // We need to convert our Ed25519 identity to Curve25519
     // This is typically done by the Account, but we can approximate it
-    let our_identity_curve = convert_ed25519_to_curve25519_secret(&our_identity.secret_key_bytes())?;
-    let dh1 = our_identity_curve.diffie_hellman(&their_signed_prekey);
+    let our_identity_secret = our_identity.secret_key_bytes();
+    let (_, our_identity_secret) = system::untag(&our_identity_secret)?;
+    let our_identity_curve = convert_ed25519_to_curve25519_secret(our_identity_secret)?;
+    let dh1 = Zeroizing::new(our_identity_curve.diffie_hellman(&their_signed_prekey));
 
     // DH2 = DH(EK_A, IK_B_curve)
     // Convert their Ed25519 identity to Curve25519
     let their_identity_curve = convert_ed25519_to_curve25519_public(&their_identity)?;
-    let dh2 = ephemeral.diffie_hellman(&their_identity_curve);
+    let dh2 = Zeroizing::new(ephemeral.diffie_hellman(&their_identity_curve));
 
     // DH3 = DH(EK_A, SPK_B)
-    let dh3 = ephemeral.diffie_hellman(&their_signed_prekey);
+    let dh3 = Zeroizing::new(ephemeral.diffie_hellman(&their_signed_prekey));
 
-    // DH4 = DH(EK_A, OPK_B) if one-time prekey exists
-    let (dh4, used_otk_id) = if let Some(ref otk) = their_bundle.one_time_prekey {
+    // DH4 = DH(EK_A, OPK_B) or DH(EK_A, FPK_B), preferring a one-time prekey
+    // (so it's consumed for forward secrecy) and only falling back to the
+    // reusable fallback key once the one-time pool is exhausted.
+    let (dh4, used_prekey) = if let Some(ref otk) = their_bundle.one_time_prekey {
         let their_otk = otk.get_public_key()?;
-        (Some(ephemeral.diffie_hellman(&their_otk)), Some(otk.key_id))
+        (
+            Some(Zeroizing::new(ephemeral.diffie_hellman(&their_otk))),
+            Some((PreKeyKind::OneTime, otk.key_id)),
+        )
+    } else if let Some(ref fallback) = their_bundle.fallback_key {
+        let their_fallback = fallback.get_public_key()?;
+        (
+            Some(Zeroizing::new(ephemeral.diffie_hellman(&their_fallback))),
+            Some((PreKeyKind::Fallback, fallback.key_id)),
+        )
     } else {
         (None, None)
     };
 
     // Combine secrets with KDF
-    let shared_secret = kdf_x3dh(&dh1, &dh2, &dh3, dh4.as_ref())?;
+    let shared_secret = kdf_x3dh(&dh1, &dh2, &dh3, dh4.as_deref())?;
+
+    let ephemeral_public = match ephemeral_representative {
+        Some(representative) => system::tag(CryptoSystemId::V0, &representative),
+        None => ephemeral.public_key_bytes(),
+    };
 
     Ok(X3dhResult {
         shared_secret,
-        ephemeral_public: ephemeral.public_key_bytes(),
-        used_one_time_prekey: used_otk_id,
+        ephemeral_public,
+        obfuscated: ephemeral_representative.is_some(),
+        used_prekey_id: used_prekey.map(|(_, id)| id),
+        used_prekey_kind: used_prekey.map(|(kind, _)| kind),
     })
 }
 
@@ -138,7 +214,10 @@ pub fn x3dh_initiate(
 ///
 /// * `our_identity` - Our long-term identity key pair
 /// * `our_signed_prekey` - Our signed prekey that was used
-/// * `our_one_time_prekey` - Our one-time prekey (if one was used)
+/// * `our_prekey` - The private key behind whichever id the initiator's
+///   [`X3dhHeader`] named for DH4 - a one-time prekey or the fallback key,
+///   resolved by the caller (e.g. `PreKeyManager::consume_or_fallback_prekey`)
+///   since the DH computation itself doesn't care which kind it is
 /// * `their_identity` - The initiator's identity public key
 /// * `their_ephemeral` - The initiator's ephemeral public key
 ///
@@ -148,28 +227,62 @@ pub fn x3dh_initiate(
 pub fn x3dh_respond(
     our_identity: &IdentityKeyPair,
     our_signed_prekey: &Curve25519KeyPair,
-    our_one_time_prekey: Option<&Curve25519KeyPair>,
+    our_prekey: Option<&Curve25519KeyPair>,
     their_identity: &Ed25519PublicKey,
     their_ephemeral: &Curve25519PublicKey,
-) -> CryptoResult<[u8; 32]> {
+) -> CryptoResult<Zeroizing<[u8; 32]>> {
     // Convert keys as needed
     let their_identity_curve = convert_ed25519_to_curve25519_public(their_identity)?;
 
     // DH1 = DH(SPK_B, IK_A_curve)
-    let dh1 = our_signed_prekey.diffie_hellman(&their_identity_curve);
+    let dh1 = Zeroizing::new(our_signed_prekey.diffie_hellman(&their_identity_curve));
 
     // DH2 = DH(IK_B_curve, EK_A)
-    let our_identity_curve = convert_ed25519_to_curve25519_secret(&our_identity.secret_key_bytes())?;
-    let dh2 = our_identity_curve.diffie_hellman(their_ephemeral);
+    let our_identity_secret = our_identity.secret_key_bytes();
+    let (_, our_identity_secret) = system::untag(&our_identity_secret)?;
+    let our_identity_curve = convert_ed25519_to_curve25519_secret(our_identity_secret)?;
+    let dh2 = Zeroizing::new(our_identity_curve.diffie_hellman(their_ephemeral));
 
     // DH3 = DH(SPK_B, EK_A)
-    let dh3 = our_signed_prekey.diffie_hellman(their_ephemeral);
+    let dh3 = Zeroizing::new(our_signed_prekey.diffie_hellman(their_ephemeral));
 
-    // DH4 = DH(OPK_B, EK_A) if one-time prekey was used
-    let dh4 = our_one_time_prekey.map(|otk| otk.diffie_hellman(their_ephemeral));
+    // DH4 = DH(OPK_B, EK_A) or DH(FPK_B, EK_A) if the initiator used one
+    let dh4 = our_prekey.map(|key| Zeroizing::new(key.diffie_hellman(their_ephemeral)));
 
     // Combine secrets with KDF
-    kdf_x3dh(&dh1, &dh2, &dh3, dh4.as_ref())
+    kdf_x3dh(&dh1, &dh2, &dh3, dh4.as_deref())
+}
+
+/// Combines X3DH's four (the last optional) DH outputs into a shared
+/// secret. Pulled out as a trait - rather than the hardcoded HKDF-SHA256
+/// call this module used to make directly - so [`Protocol`] can be tested
+/// against a different KDF without touching the DH math around it.
+pub trait Kdf {
+    fn derive(
+        &self,
+        dh1: &[u8; 32],
+        dh2: &[u8; 32],
+        dh3: &[u8; 32],
+        dh4: Option<&[u8; 32]>,
+    ) -> CryptoResult<Zeroizing<[u8; 32]>>;
+}
+
+/// The default [`Kdf`]: HKDF-SHA256 over the concatenated DH outputs, with a
+/// fixed info string and the 32 bytes of 0xFF padding the X3DH spec
+/// prescribes ahead of DH1.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HkdfSha256Kdf;
+
+impl Kdf for HkdfSha256Kdf {
+    fn derive(
+        &self,
+        dh1: &[u8; 32],
+        dh2: &[u8; 32],
+        dh3: &[u8; 32],
+        dh4: Option<&[u8; 32]>,
+    ) -> CryptoResult<Zeroizing<[u8; 32]>> {
+        kdf_x3dh(dh1, dh2, dh3, dh4)
+    }
 }
 
 /// KDF for combining X3DH DH outputs into a shared secret
@@ -180,10 +293,12 @@ fn kdf_x3dh(
     dh2: &[u8; 32],
     dh3: &[u8; 32],
     dh4: Option<&[u8; 32]>,
-) -> CryptoResult<[u8; 32]> {
-    // Concatenate DH outputs with 32 bytes of 0xFF padding
-    // This is per the X3DH specification
-    let mut input = Vec::with_capacity(if dh4.is_some() { 160 } else { 128 });
+) -> CryptoResult<Zeroizing<[u8; 32]>> {
+    // Concatenate DH outputs with 32 bytes of 0xFF padding (per the X3DH
+    // spec). Wrapped in `Zeroizing` so this concatenation of raw DH secrets
+    // is scrubbed the moment it goes out of scope, rather than lingering in
+    // the heap after the KDF has consumed it.
+    let mut input = Zeroizing::new(Vec::with_capacity(if dh4.is_some() { 160 } else { 128 }));
 
     // 32 bytes of 0xFF (per Signal spec)
     input.extend_from_slice(&[0xFF; 32]);
@@ -197,18 +312,256 @@ fn kdf_x3dh(
 
     // Use HKDF to derive the shared secret
     let hkdf = Hkdf::<Sha256>::new(None, &input);
-    let mut output = [0u8; 32];
-    hkdf.expand(b"NoChat X3DH v1", &mut output)
+    let mut output = Zeroizing::new([0u8; 32]);
+    hkdf.expand(b"NoChat X3DH v1", &mut *output)
         .map_err(|e| CryptoError::KeyExchangeFailed(format!("HKDF expansion failed: {}", e)))?;
 
     Ok(output)
 }
 
+/// Resolves the long-term identity key pair used for DH1/DH2.
+///
+/// Split out so [`Protocol`] can be built against an in-memory identity (as
+/// in the tests below, where any bare [`IdentityKeyPair`] already implements
+/// this) or a SQLite-backed one without the DH math caring which.
+pub trait IdentityKeyStore {
+    fn identity_key_pair(&self) -> &IdentityKeyPair;
+}
+
+impl IdentityKeyStore for IdentityKeyPair {
+    fn identity_key_pair(&self) -> &IdentityKeyPair {
+        self
+    }
+}
+
+/// Resolves the signed prekey an [`X3dhHeader`] named for DH1/DH3, by id.
+pub trait SignedPreKeyStore {
+    fn signed_prekey(&self, key_id: u32) -> CryptoResult<Curve25519KeyPair>;
+}
+
+/// Resolves - and, for one-time prekeys, consumes - the prekey an
+/// [`X3dhHeader`] named for DH4, by id and [`PreKeyKind`].
+///
+/// Implementations must enforce "consume on use" themselves for
+/// `PreKeyKind::OneTime` (the key must not be resolvable again after this
+/// call returns it) while leaving a `PreKeyKind::Fallback` key resolvable
+/// indefinitely, since it's reusable by design - this is the "storage layer
+/// enforces consume-on-use" half of [`Protocol::respond`].
+pub trait OneTimePreKeyStore {
+    fn take_prekey(&mut self, kind: PreKeyKind, key_id: u32) -> CryptoResult<Curve25519KeyPair>;
+}
+
+/// A trivial in-memory [`SignedPreKeyStore`]/[`OneTimePreKeyStore`], for
+/// tests and other short-lived [`Protocol`]s that don't need a SQLite-backed
+/// store like `PreKeyManager`'s eventual one.
+#[derive(Default)]
+pub struct InMemoryPreKeyStore {
+    signed: std::collections::HashMap<u32, Curve25519KeyPair>,
+    one_time: std::collections::HashMap<u32, Curve25519KeyPair>,
+    fallback: std::collections::HashMap<u32, Curve25519KeyPair>,
+}
+
+impl InMemoryPreKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert_signed_prekey(&mut self, key_id: u32, key_pair: Curve25519KeyPair) {
+        self.signed.insert(key_id, key_pair);
+    }
+
+    pub fn insert_one_time_prekey(&mut self, key_id: u32, key_pair: Curve25519KeyPair) {
+        self.one_time.insert(key_id, key_pair);
+    }
+
+    pub fn insert_fallback_prekey(&mut self, key_id: u32, key_pair: Curve25519KeyPair) {
+        self.fallback.insert(key_id, key_pair);
+    }
+}
+
+impl SignedPreKeyStore for InMemoryPreKeyStore {
+    fn signed_prekey(&self, key_id: u32) -> CryptoResult<Curve25519KeyPair> {
+        let key_pair = self
+            .signed
+            .get(&key_id)
+            .ok_or_else(|| CryptoError::X3dhError(format!("no signed prekey for id {}", key_id)))?;
+        Curve25519KeyPair::from_bytes(&key_pair.public_key_bytes(), &key_pair.secret_key_bytes())
+    }
+}
+
+impl OneTimePreKeyStore for InMemoryPreKeyStore {
+    /// Removes (consumes) a one-time prekey, but only reconstructs a fresh
+    /// handle to a fallback prekey, which stays resolvable for the next call.
+    fn take_prekey(&mut self, kind: PreKeyKind, key_id: u32) -> CryptoResult<Curve25519KeyPair> {
+        match kind {
+            PreKeyKind::OneTime => self.one_time.remove(&key_id).ok_or_else(|| {
+                CryptoError::X3dhError(format!("no one-time prekey for id {}", key_id))
+            }),
+            PreKeyKind::Fallback => {
+                let key_pair = self.fallback.get(&key_id).ok_or_else(|| {
+                    CryptoError::X3dhError(format!("no fallback prekey for id {}", key_id))
+                })?;
+                Curve25519KeyPair::from_bytes(&key_pair.public_key_bytes(), &key_pair.secret_key_bytes())
+            }
+        }
+    }
+}
+
+/// X3DH, generalized over where identity/signed/one-time prekeys come from
+/// and how DH outputs are combined into a shared secret.
+///
+/// The free functions [`x3dh_initiate`]/[`x3dh_respond`] remain the
+/// lower-level entry points (the caller resolves keys itself, which suits a
+/// one-off handshake); `Protocol` is the entry point meant for long-lived
+/// owners of key state - e.g. a `PreKeyManager`/`SessionStore` pair - that
+/// want to hand X3DH an id from a header and let the storage layer resolve
+/// and, where applicable, consume the matching key, rather than duplicating
+/// that lookup/consumption logic at each call site.
+pub struct Protocol<I, S, O, K = HkdfSha256Kdf>
+where
+    I: IdentityKeyStore,
+    S: SignedPreKeyStore,
+    O: OneTimePreKeyStore,
+    K: Kdf,
+{
+    identity: I,
+    signed_prekeys: S,
+    prekeys: O,
+    kdf: K,
+}
+
+impl<I, S, O> Protocol<I, S, O, HkdfSha256Kdf>
+where
+    I: IdentityKeyStore,
+    S: SignedPreKeyStore,
+    O: OneTimePreKeyStore,
+{
+    /// Build a `Protocol` using the default [`HkdfSha256Kdf`].
+    pub fn new(identity: I, signed_prekeys: S, prekeys: O) -> Self {
+        Self::with_kdf(identity, signed_prekeys, prekeys, HkdfSha256Kdf)
+    }
+}
+
+impl<I, S, O, K> Protocol<I, S, O, K>
+where
+    I: IdentityKeyStore,
+    S: SignedPreKeyStore,
+    O: OneTimePreKeyStore,
+    K: Kdf,
+{
+    /// Build a `Protocol` with a non-default [`Kdf`].
+    pub fn with_kdf(identity: I, signed_prekeys: S, prekeys: O, kdf: K) -> Self {
+        Self { identity, signed_prekeys, prekeys, kdf }
+    }
+
+    /// Initiator side - identical DH math to [`x3dh_initiate`], but reading
+    /// our identity key pair from `self.identity` and combining DH outputs
+    /// through `self.kdf` instead of the hardcoded free-function versions.
+    pub fn initiate(
+        &self,
+        their_bundle: &PreKeyBundle,
+        encoding: HandshakeEncoding,
+    ) -> CryptoResult<X3dhResult> {
+        their_bundle.verify()?;
+
+        let (ephemeral, ephemeral_representative) = match encoding {
+            HandshakeEncoding::Raw => (Curve25519KeyPair::generate(), None),
+            HandshakeEncoding::Obfuscated => loop {
+                let candidate = Curve25519KeyPair::generate();
+                if let Some(representative) = elligator::encode(&candidate.public) {
+                    break (candidate, Some(representative));
+                }
+            },
+        };
+
+        let our_identity = self.identity.identity_key_pair();
+        let their_identity = their_bundle.get_identity_key()?;
+        let their_signed_prekey = their_bundle.signed_prekey.get_public_key()?;
+
+        let our_identity_secret = our_identity.secret_key_bytes();
+        let (_, our_identity_secret) = system::untag(&our_identity_secret)?;
+        let our_identity_curve = convert_ed25519_to_curve25519_secret(our_identity_secret)?;
+        let dh1 = Zeroizing::new(our_identity_curve.diffie_hellman(&their_signed_prekey));
+
+        let their_identity_curve = convert_ed25519_to_curve25519_public(&their_identity)?;
+        let dh2 = Zeroizing::new(ephemeral.diffie_hellman(&their_identity_curve));
+
+        let dh3 = Zeroizing::new(ephemeral.diffie_hellman(&their_signed_prekey));
+
+        let (dh4, used_prekey) = if let Some(ref otk) = their_bundle.one_time_prekey {
+            let their_otk = otk.get_public_key()?;
+            (
+                Some(Zeroizing::new(ephemeral.diffie_hellman(&their_otk))),
+                Some((PreKeyKind::OneTime, otk.key_id)),
+            )
+        } else if let Some(ref fallback) = their_bundle.fallback_key {
+            let their_fallback = fallback.get_public_key()?;
+            (
+                Some(Zeroizing::new(ephemeral.diffie_hellman(&their_fallback))),
+                Some((PreKeyKind::Fallback, fallback.key_id)),
+            )
+        } else {
+            (None, None)
+        };
+
+        let shared_secret = self.kdf.derive(&dh1, &dh2, &dh3, dh4.as_deref())?;
+
+        let ephemeral_public = match ephemeral_representative {
+            Some(representative) => system::tag(CryptoSystemId::V0, &representative),
+            None => ephemeral.public_key_bytes(),
+        };
+
+        Ok(X3dhResult {
+            shared_secret,
+            ephemeral_public,
+            obfuscated: ephemeral_representative.is_some(),
+            used_prekey_id: used_prekey.map(|(_, id)| id),
+            used_prekey_kind: used_prekey.map(|(kind, _)| kind),
+        })
+    }
+
+    /// Responder side - resolves `header`'s signed prekey id and (if
+    /// present) one-time/fallback prekey id/kind through `self`'s stores,
+    /// consuming a one-time prekey on the way, then runs the same DH math
+    /// as [`x3dh_respond`].
+    pub fn respond(&mut self, header: &X3dhHeader) -> CryptoResult<Zeroizing<[u8; 32]>> {
+        let our_identity = self.identity.identity_key_pair();
+        let our_signed_prekey = self.signed_prekeys.signed_prekey(header.signed_prekey_id)?;
+        let our_prekey = match (header.prekey_id, header.prekey_kind) {
+            (Some(key_id), Some(kind)) => Some(self.prekeys.take_prekey(kind, key_id)?),
+            _ => None,
+        };
+
+        let (_, their_identity_bytes) = system::untag(&header.identity_key)?;
+        let their_identity_arr: [u8; 32] = their_identity_bytes
+            .try_into()
+            .map_err(|_| CryptoError::InvalidKey("Identity key must be 32 bytes".to_string()))?;
+        let their_identity = Ed25519PublicKey::from_slice(&their_identity_arr)?;
+        let their_ephemeral = header.resolve_ephemeral_key()?;
+
+        let their_identity_curve = convert_ed25519_to_curve25519_public(&their_identity)?;
+        let dh1 = Zeroizing::new(our_signed_prekey.diffie_hellman(&their_identity_curve));
+
+        let our_identity_secret = our_identity.secret_key_bytes();
+        let (_, our_identity_secret) = system::untag(&our_identity_secret)?;
+        let our_identity_curve = convert_ed25519_to_curve25519_secret(our_identity_secret)?;
+        let dh2 = Zeroizing::new(our_identity_curve.diffie_hellman(&their_ephemeral));
+
+        let dh3 = Zeroizing::new(our_signed_prekey.diffie_hellman(&their_ephemeral));
+
+        let dh4 = our_prekey
+            .as_ref()
+            .map(|key| Zeroizing::new(key.diffie_hellman(&their_ephemeral)));
+
+        self.kdf.derive(&dh1, &dh2, &dh3, dh4.as_deref())
+    }
+}
+
 /// Convert an Ed25519 secret key to its Curve25519 equivalent
 ///
 /// This is needed because identity keys are Ed25519 (for signing) but
 /// X3DH requires Curve25519 keys (for DH).
-fn convert_ed25519_to_curve25519_secret(ed_secret: &[u8]) -> CryptoResult<Curve25519KeyPair> {
+pub(crate) fn convert_ed25519_to_curve25519_secret(ed_secret: &[u8]) -> CryptoResult<Curve25519KeyPair> {
     use sha2::{Digest, Sha512};
 
     // Ed25519 secret key is 64 bytes: 32-byte seed + 32-byte public
@@ -220,10 +573,10 @@ fn convert_ed25519_to_curve25519_secret(ed_secret: &[u8]) -> CryptoResult<Curve2
 
     // Hash the seed portion (first 32 bytes) with SHA-512
     let seed = &ed_secret[..32];
-    let hash = Sha512::digest(seed);
+    let mut hash = Sha512::digest(seed);
 
     // The first 32 bytes of the hash, with clamping, is the Curve25519 secret
-    let mut curve_secret = [0u8; 32];
+    let mut curve_secret = Zeroizing::new([0u8; 32]);
     curve_secret.copy_from_slice(&hash[..32]);
 
     // Apply Curve25519 clamping
@@ -233,10 +586,15 @@ fn convert_ed25519_to_curve25519_secret(ed_secret: &[u8]) -> CryptoResult<Curve2
 
     // Derive public key from secret
     // Use x25519-dalek for this computation
-    let secret = x25519_dalek::StaticSecret::from(curve_secret);
+    let secret = x25519_dalek::StaticSecret::from(*curve_secret);
     let public = x25519_dalek::PublicKey::from(&secret);
 
-    Curve25519KeyPair::from_bytes(public.as_bytes(), curve_secret.as_ref())
+    // Scrub the SHA-512 expansion buffer now that the clamped secret has
+    // been copied out of it - it's no longer needed and otherwise lingers
+    // with the other 32 bytes of (unused but still sensitive) hash output.
+    hash.iter_mut().for_each(|byte| *byte = 0);
+
+    Curve25519KeyPair::from_bytes(public.as_bytes(), &curve_secret[..])
 }
 
 /// Convert an Ed25519 public key to its Curve25519 equivalent
@@ -267,12 +625,70 @@ fn convert_ed25519_to_curve25519_public(
 pub struct X3dhHeader {
     /// Initiator's identity public key
     pub identity_key: Vec<u8>,
-    /// Initiator's ephemeral public key
+    /// Initiator's ephemeral public key - a raw point or an Elligator2
+    /// representative, per `obfuscated_ephemeral_key`
     pub ephemeral_key: Vec<u8>,
     /// ID of the signed prekey that was used
     pub signed_prekey_id: u32,
-    /// ID of the one-time prekey that was used (if any)
-    pub one_time_prekey_id: Option<u32>,
+    /// ID of the one-time or fallback prekey that was used for DH4, if any
+    pub prekey_id: Option<u32>,
+    /// Which kind `prekey_id` refers to, so the responder knows whether to
+    /// resolve it from the one-time pool or the reusable fallback key -
+    /// present only when `prekey_id` is
+    pub prekey_kind: Option<PreKeyKind>,
+    /// Whether `ephemeral_key` is an Elligator2 representative that needs
+    /// decoding before it can be used in `x3dh_respond`, rather than a raw
+    /// Curve25519 point - set this from [`X3dhResult::obfuscated`]
+    pub obfuscated_ephemeral_key: bool,
+}
+
+impl X3dhHeader {
+    /// Recover the initiator's ephemeral [`Curve25519PublicKey`] from
+    /// `ephemeral_key`, decoding it as an Elligator2 representative first if
+    /// `obfuscated_ephemeral_key` says it was sent that way.
+    pub fn resolve_ephemeral_key(&self) -> CryptoResult<Curve25519PublicKey> {
+        let (_, ephemeral_bytes) = system::untag(&self.ephemeral_key)?;
+
+        if self.obfuscated_ephemeral_key {
+            let representative: [u8; 32] = ephemeral_bytes.try_into().map_err(|_| {
+                CryptoError::InvalidKey("Elligator2 representative must be 32 bytes".to_string())
+            })?;
+            elligator::decode(&representative)
+        } else {
+            Curve25519PublicKey::from_slice(ephemeral_bytes).map_err(Into::into)
+        }
+    }
+}
+
+/// Elligator2 encoding of Curve25519 points, isolated behind a narrow
+/// interface so the rest of this module only ever deals in
+/// `Option`/`CryptoResult` rather than curve25519-dalek's lower-level
+/// Montgomery point API.
+mod elligator {
+    use curve25519_dalek::montgomery::MontgomeryPoint;
+    use vodozemac::Curve25519PublicKey;
+
+    use crate::crypto::errors::{CryptoError, CryptoResult};
+
+    /// Encode `public` as an Elligator2 representative, indistinguishable
+    /// from uniform random bytes. Not every point is representable - only
+    /// about half are - so callers that need an encodable key must retry
+    /// key generation on `None`, same as [`super::x3dh_initiate`] does.
+    pub(super) fn encode(public: &Curve25519PublicKey) -> Option<[u8; 32]> {
+        let point = MontgomeryPoint(public.to_bytes());
+        // The high bit of the sign byte doesn't affect the decoded point, so
+        // any fixed value works here; we don't need it to carry information.
+        point.to_elligator2_representative(0).into_option().map(|r| r.to_bytes())
+    }
+
+    /// Decode an Elligator2 representative back into the Curve25519 point it
+    /// was derived from. Unlike `encode`, this direction always succeeds -
+    /// every 32-byte representative decodes to *some* valid point.
+    pub(super) fn decode(representative: &[u8; 32]) -> CryptoResult<Curve25519PublicKey> {
+        let point = MontgomeryPoint::from_elligator2_representative(representative);
+        Curve25519PublicKey::from_slice(&point.to_bytes())
+            .map_err(|e| CryptoError::InvalidKey(format!("Invalid Elligator2-decoded point: {:?}", e)))
+    }
 }
 
 #[cfg(test)]
@@ -294,14 +710,15 @@ mod tests {
             identity_key: bob_identity.public_key_bytes(),
             signed_prekey: SignedPreKey::new(1, &bob_signed_prekey, &bob_identity),
             one_time_prekey: Some(OneTimePreKey::new(1, &bob_otk)),
+            fallback_key: None,
         };
 
         // Alice initiates X3DH
-        let alice_result = x3dh_initiate(&alice_identity, &bob_bundle).unwrap();
+        let alice_result = x3dh_initiate(&alice_identity, &bob_bundle, HandshakeEncoding::Raw).unwrap();
 
         // Bob responds with the same calculation
-        let alice_ephemeral =
-            Curve25519PublicKey::from_slice(&alice_result.ephemeral_public).unwrap();
+        let (_, alice_ephemeral_bytes) = system::untag(&alice_result.ephemeral_public).unwrap();
+        let alice_ephemeral = Curve25519PublicKey::from_slice(alice_ephemeral_bytes).unwrap();
 
         let bob_result = x3dh_respond(
             &bob_identity,
@@ -314,7 +731,8 @@ mod tests {
 
         // Both should derive the same shared secret
         assert_eq!(alice_result.shared_secret, bob_result);
-        assert_eq!(alice_result.used_one_time_prekey, Some(1));
+        assert_eq!(alice_result.used_prekey_id, Some(1));
+        assert_eq!(alice_result.used_prekey_kind, Some(PreKeyKind::OneTime));
     }
 
     #[test]
@@ -323,29 +741,90 @@ mod tests {
         let bob_identity = IdentityKeyPair::generate();
         let bob_signed_prekey = Curve25519KeyPair::generate();
 
-        // Bundle without one-time prekey
+        // Bundle without one-time prekey or fallback key
+        let bob_bundle = PreKeyBundle {
+            identity_key: bob_identity.public_key_bytes(),
+            signed_prekey: SignedPreKey::new(1, &bob_signed_prekey, &bob_identity),
+            one_time_prekey: None,
+            fallback_key: None,
+        };
+
+        let alice_result = x3dh_initiate(&alice_identity, &bob_bundle, HandshakeEncoding::Raw).unwrap();
+
+        let (_, alice_ephemeral_bytes) = system::untag(&alice_result.ephemeral_public).unwrap();
+        let alice_ephemeral = Curve25519PublicKey::from_slice(alice_ephemeral_bytes).unwrap();
+
+        let bob_result = x3dh_respond(
+            &bob_identity,
+            &bob_signed_prekey,
+            None, // No one-time prekey or fallback key
+            &alice_identity.public,
+            &alice_ephemeral,
+        )
+        .unwrap();
+
+        assert_eq!(alice_result.shared_secret, bob_result);
+        assert_eq!(alice_result.used_prekey_id, None);
+        assert_eq!(alice_result.used_prekey_kind, None);
+    }
+
+    #[test]
+    fn test_x3dh_falls_back_to_fallback_key_when_one_time_pool_empty() {
+        let alice_identity = IdentityKeyPair::generate();
+        let bob_identity = IdentityKeyPair::generate();
+        let bob_signed_prekey = Curve25519KeyPair::generate();
+        let bob_fallback = Curve25519KeyPair::generate();
+
+        // Bundle carries only a fallback key - the one-time pool is dry
         let bob_bundle = PreKeyBundle {
             identity_key: bob_identity.public_key_bytes(),
             signed_prekey: SignedPreKey::new(1, &bob_signed_prekey, &bob_identity),
             one_time_prekey: None,
+            fallback_key: Some(FallbackPreKey::new(7, &bob_fallback, &bob_identity)),
         };
 
-        let alice_result = x3dh_initiate(&alice_identity, &bob_bundle).unwrap();
+        let alice_result = x3dh_initiate(&alice_identity, &bob_bundle, HandshakeEncoding::Raw).unwrap();
+        assert_eq!(alice_result.used_prekey_id, Some(7));
+        assert_eq!(alice_result.used_prekey_kind, Some(PreKeyKind::Fallback));
 
-        let alice_ephemeral =
-            Curve25519PublicKey::from_slice(&alice_result.ephemeral_public).unwrap();
+        let (_, alice_ephemeral_bytes) = system::untag(&alice_result.ephemeral_public).unwrap();
+        let alice_ephemeral = Curve25519PublicKey::from_slice(alice_ephemeral_bytes).unwrap();
 
+        // The responder resolves the fallback key's private half itself
+        // (e.g. via `PreKeyManager::consume_or_fallback_prekey`) and passes
+        // it the same way it would a one-time prekey - the DH math doesn't
+        // distinguish between the two.
         let bob_result = x3dh_respond(
             &bob_identity,
             &bob_signed_prekey,
-            None, // No one-time prekey
+            Some(&bob_fallback),
             &alice_identity.public,
             &alice_ephemeral,
         )
         .unwrap();
 
         assert_eq!(alice_result.shared_secret, bob_result);
-        assert_eq!(alice_result.used_one_time_prekey, None);
+    }
+
+    #[test]
+    fn test_x3dh_prefers_one_time_prekey_over_fallback_key() {
+        let alice_identity = IdentityKeyPair::generate();
+        let bob_identity = IdentityKeyPair::generate();
+        let bob_signed_prekey = Curve25519KeyPair::generate();
+        let bob_otk = Curve25519KeyPair::generate();
+        let bob_fallback = Curve25519KeyPair::generate();
+
+        // Bundle carries both - the one-time prekey should win
+        let bob_bundle = PreKeyBundle {
+            identity_key: bob_identity.public_key_bytes(),
+            signed_prekey: SignedPreKey::new(1, &bob_signed_prekey, &bob_identity),
+            one_time_prekey: Some(OneTimePreKey::new(2, &bob_otk)),
+            fallback_key: Some(FallbackPreKey::new(7, &bob_fallback, &bob_identity)),
+        };
+
+        let alice_result = x3dh_initiate(&alice_identity, &bob_bundle, HandshakeEncoding::Raw).unwrap();
+        assert_eq!(alice_result.used_prekey_id, Some(2));
+        assert_eq!(alice_result.used_prekey_kind, Some(PreKeyKind::OneTime));
     }
 
     #[test]
@@ -357,6 +836,7 @@ mod tests {
             identity_key: identity.public_key_bytes(),
             signed_prekey: SignedPreKey::new(1, &prekey, &identity),
             one_time_prekey: None,
+            fallback_key: None,
         };
 
         // Valid bundle should verify
@@ -367,4 +847,189 @@ mod tests {
         bad_bundle.signed_prekey.signature[0] ^= 0xFF;
         assert!(bad_bundle.verify().is_err());
     }
+
+    #[test]
+    fn test_bundle_verification_rejects_bad_fallback_signature() {
+        let identity = IdentityKeyPair::generate();
+        let prekey = Curve25519KeyPair::generate();
+        let fallback_pair = Curve25519KeyPair::generate();
+
+        let mut bundle = PreKeyBundle {
+            identity_key: identity.public_key_bytes(),
+            signed_prekey: SignedPreKey::new(1, &prekey, &identity),
+            one_time_prekey: None,
+            fallback_key: Some(FallbackPreKey::new(7, &fallback_pair, &identity)),
+        };
+        assert!(bundle.verify().is_ok());
+
+        bundle.fallback_key.as_mut().unwrap().signature[0] ^= 0xFF;
+        assert!(bundle.verify().is_err());
+    }
+
+    #[test]
+    fn test_elligator_encode_decode_round_trips() {
+        // Not every point is representable, so keep generating until we hit
+        // one that is - this also exercises the "not representable, try
+        // again" path that `x3dh_initiate`'s `Obfuscated` loop relies on.
+        let (key_pair, representative) = loop {
+            let candidate = Curve25519KeyPair::generate();
+            if let Some(representative) = elligator::encode(&candidate.public) {
+                break (candidate, representative);
+            }
+        };
+
+        let decoded = elligator::decode(&representative).unwrap();
+        assert_eq!(decoded.to_bytes(), key_pair.public.to_bytes());
+    }
+
+    #[test]
+    fn test_elligator_encode_rejects_some_points_and_accepts_others() {
+        // Elligator2 only represents roughly half of all Curve25519 points,
+        // so across enough random keys we should see both outcomes - if
+        // `encode` always returned `Some` (or always `None`) the regenerate
+        // loop in `x3dh_initiate` would either be pointless or infinite.
+        let mut saw_some = false;
+        let mut saw_none = false;
+        for _ in 0..64 {
+            match elligator::encode(&Curve25519KeyPair::generate().public) {
+                Some(_) => saw_some = true,
+                None => saw_none = true,
+            }
+            if saw_some && saw_none {
+                break;
+            }
+        }
+        assert!(saw_some, "expected at least one representable point in 64 tries");
+        assert!(saw_none, "expected at least one non-representable point in 64 tries");
+    }
+
+    #[test]
+    fn test_x3dh_obfuscated_handshake_matches_raw_shared_secret() {
+        let alice_identity = IdentityKeyPair::generate();
+        let bob_identity = IdentityKeyPair::generate();
+        let bob_signed_prekey = Curve25519KeyPair::generate();
+        let bob_otk = Curve25519KeyPair::generate();
+
+        let bob_bundle = PreKeyBundle {
+            identity_key: bob_identity.public_key_bytes(),
+            signed_prekey: SignedPreKey::new(1, &bob_signed_prekey, &bob_identity),
+            one_time_prekey: Some(OneTimePreKey::new(1, &bob_otk)),
+            fallback_key: None,
+        };
+
+        let alice_result =
+            x3dh_initiate(&alice_identity, &bob_bundle, HandshakeEncoding::Obfuscated).unwrap();
+        assert!(alice_result.obfuscated);
+
+        let header = X3dhHeader {
+            identity_key: alice_identity.public_key_bytes(),
+            ephemeral_key: alice_result.ephemeral_public.clone(),
+            signed_prekey_id: 1,
+            prekey_id: alice_result.used_prekey_id,
+            prekey_kind: alice_result.used_prekey_kind,
+            obfuscated_ephemeral_key: alice_result.obfuscated,
+        };
+        let alice_ephemeral = header.resolve_ephemeral_key().unwrap();
+
+        let bob_result = x3dh_respond(
+            &bob_identity,
+            &bob_signed_prekey,
+            Some(&bob_otk),
+            &alice_identity.public,
+            &alice_ephemeral,
+        )
+        .unwrap();
+
+        assert_eq!(alice_result.shared_secret, bob_result);
+    }
+
+    #[test]
+    fn test_protocol_matches_free_function_shared_secret() {
+        let alice_identity = IdentityKeyPair::generate();
+        let bob_identity = IdentityKeyPair::generate();
+        let bob_signed_prekey = Curve25519KeyPair::generate();
+        let bob_otk = Curve25519KeyPair::generate();
+
+        let bob_bundle = PreKeyBundle {
+            identity_key: bob_identity.public_key_bytes(),
+            signed_prekey: SignedPreKey::new(1, &bob_signed_prekey, &bob_identity),
+            one_time_prekey: Some(OneTimePreKey::new(5, &bob_otk)),
+            fallback_key: None,
+        };
+
+        // The free functions and `Protocol` must derive the same secret, so
+        // drive the initiator side with the free function and the responder
+        // side with `Protocol` backed by an `InMemoryPreKeyStore`.
+        let alice_result =
+            x3dh_initiate(&alice_identity, &bob_bundle, HandshakeEncoding::Raw).unwrap();
+
+        let header = X3dhHeader {
+            identity_key: alice_identity.public_key_bytes(),
+            ephemeral_key: alice_result.ephemeral_public.clone(),
+            signed_prekey_id: 1,
+            prekey_id: alice_result.used_prekey_id,
+            prekey_kind: alice_result.used_prekey_kind,
+            obfuscated_ephemeral_key: alice_result.obfuscated,
+        };
+
+        let mut bob_store = InMemoryPreKeyStore::new();
+        bob_store.insert_signed_prekey(1, clone_keypair(&bob_signed_prekey));
+        let mut bob_prekey_store = InMemoryPreKeyStore::new();
+        bob_prekey_store.insert_one_time_prekey(5, clone_keypair(&bob_otk));
+        let mut bob_protocol = Protocol::new(bob_identity, bob_store, bob_prekey_store);
+
+        let bob_result = bob_protocol.respond(&header).unwrap();
+        assert_eq!(alice_result.shared_secret, bob_result);
+
+        // The one-time prekey was consumed - resolving it again must fail.
+        assert!(bob_protocol.prekeys.take_prekey(PreKeyKind::OneTime, 5).is_err());
+    }
+
+    #[test]
+    fn test_protocol_falls_back_to_reusable_fallback_key() {
+        let alice_identity = IdentityKeyPair::generate();
+        let bob_identity = IdentityKeyPair::generate();
+        let bob_signed_prekey = Curve25519KeyPair::generate();
+        let bob_fallback = Curve25519KeyPair::generate();
+
+        let bob_bundle = PreKeyBundle {
+            identity_key: bob_identity.public_key_bytes(),
+            signed_prekey: SignedPreKey::new(1, &bob_signed_prekey, &bob_identity),
+            one_time_prekey: None,
+            fallback_key: Some(FallbackPreKey::new(9, &bob_fallback, &bob_identity)),
+        };
+
+        let alice_result =
+            x3dh_initiate(&alice_identity, &bob_bundle, HandshakeEncoding::Raw).unwrap();
+        assert_eq!(alice_result.used_prekey_kind, Some(PreKeyKind::Fallback));
+
+        let header = X3dhHeader {
+            identity_key: alice_identity.public_key_bytes(),
+            ephemeral_key: alice_result.ephemeral_public.clone(),
+            signed_prekey_id: 1,
+            prekey_id: alice_result.used_prekey_id,
+            prekey_kind: alice_result.used_prekey_kind,
+            obfuscated_ephemeral_key: alice_result.obfuscated,
+        };
+
+        let mut bob_store = InMemoryPreKeyStore::new();
+        bob_store.insert_signed_prekey(1, clone_keypair(&bob_signed_prekey));
+        let mut bob_prekey_store = InMemoryPreKeyStore::new();
+        bob_prekey_store.insert_fallback_prekey(9, clone_keypair(&bob_fallback));
+        let mut bob_protocol = Protocol::new(bob_identity, bob_store, bob_prekey_store);
+
+        let bob_result = bob_protocol.respond(&header).unwrap();
+        assert_eq!(alice_result.shared_secret, bob_result);
+
+        // Unlike a one-time prekey, the fallback key is still resolvable -
+        // a second handshake against it should succeed too.
+        assert!(bob_protocol.prekeys.take_prekey(PreKeyKind::Fallback, 9).is_ok());
+    }
+
+    /// Test-only helper: rebuild an equivalent `Curve25519KeyPair` from
+    /// another one's bytes, since the type deliberately isn't `Clone` (see
+    /// its doc comment in `crypto::keys`).
+    fn clone_keypair(key_pair: &Curve25519KeyPair) -> Curve25519KeyPair {
+        Curve25519KeyPair::from_bytes(&key_pair.public_key_bytes(), &key_pair.secret_key_bytes()).unwrap()
+    }
 }
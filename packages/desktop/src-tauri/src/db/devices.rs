@@ -0,0 +1,222 @@
+//! Durable storage for the multi-device identity model
+//!
+//! Backs [`crate::crypto::DeviceManager`]: a `devices` table tracking which
+//! device ids belong to which user, a `device_id` column on `sessions` so
+//! more than one device's access token can be cached per user, and a
+//! device-scoped variant of [`crate::db::pool::get_active_session`].
+
+use sqlx::{Row, SqlitePool};
+
+use crate::error::AppResult;
+
+/// Create the `devices` table and add the `device_id` column to `sessions`
+/// if they don't already exist. Safe to call on every startup.
+pub async fn ensure_schema(pool: &SqlitePool) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS devices (
+            device_id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            revoked_at TEXT
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // SQLite has no `ADD COLUMN IF NOT EXISTS` on the versions this app
+    // supports, so these are best-effort ALTERs that ignore "duplicate
+    // column" - the only way they can fail once the tables themselves exist.
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN device_id TEXT")
+        .execute(pool)
+        .await;
+    // Which device a cached user's most recent activity was attributed to
+    // (e.g. to show "active on phone" in the UI) - not used for routing.
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN device_id TEXT")
+        .execute(pool)
+        .await;
+
+    Ok(())
+}
+
+/// Register a device for `user_id`. If the device is already registered
+/// (e.g. re-running login on the same device), only its revoked mark is
+/// cleared, so a previously removed device is listed again.
+pub async fn register_device(pool: &SqlitePool, user_id: &str, device_id: &str) -> AppResult<()> {
+    ensure_schema(pool).await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO devices (device_id, user_id) VALUES (?, ?)
+        ON CONFLICT(device_id) DO UPDATE SET revoked_at = NULL
+        "#,
+    )
+    .bind(device_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Remove a device: mark it revoked and invalidate every cached session row
+/// addressed to it, so a stale access token for a removed device can't be
+/// served by [`get_active_session_for_device`].
+///
+/// Does not tear down established ratchet sessions with peers - that's a
+/// [`crate::crypto::CryptoService`] concern, since it needs the in-memory
+/// session store, not just the database.
+pub async fn remove_device(pool: &SqlitePool, device_id: &str) -> AppResult<()> {
+    ensure_schema(pool).await?;
+
+    sqlx::query("UPDATE devices SET revoked_at = datetime('now') WHERE device_id = ?")
+        .bind(device_id)
+        .execute(pool)
+        .await?;
+
+    sqlx::query("DELETE FROM sessions WHERE device_id = ?")
+        .bind(device_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// List the (non-revoked) device ids registered for `user_id`.
+pub async fn list_devices_for_user(pool: &SqlitePool, user_id: &str) -> AppResult<Vec<String>> {
+    ensure_schema(pool).await?;
+
+    let rows = sqlx::query(
+        "SELECT device_id FROM devices WHERE user_id = ? AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.get("device_id")).collect())
+}
+
+/// Get the active session for a specific `(user_id, device_id)` pair, rather
+/// than [`crate::db::pool::get_active_session`]'s single most-recent-session
+/// lookup across all of a user's devices.
+pub async fn get_active_session_for_device(
+    pool: &SqlitePool,
+    user_id: &str,
+    device_id: &str,
+) -> AppResult<Option<(String, String, Option<String>)>> {
+    ensure_schema(pool).await?;
+
+    let row = sqlx::query(
+        r#"
+        SELECT s.id, s.token, s.refresh_token
+        FROM sessions s
+        WHERE s.user_id = ?
+            AND s.device_id = ?
+            AND (s.expires_at IS NULL OR s.expires_at > datetime('now'))
+        ORDER BY s.created_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(device_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.get("id"), row.get("token"), row.get("refresh_token"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        sqlx::query(
+            r#"
+            CREATE TABLE sessions (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                token TEXT NOT NULL,
+                refresh_token TEXT,
+                expires_at TEXT,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_register_device_then_list() {
+        let pool = memory_pool().await;
+        register_device(&pool, "alice", "laptop").await.unwrap();
+        register_device(&pool, "alice", "phone").await.unwrap();
+
+        let mut devices = list_devices_for_user(&pool, "alice").await.unwrap();
+        devices.sort();
+        assert_eq!(devices, vec!["laptop".to_string(), "phone".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_device_drops_its_sessions_and_listing() {
+        let pool = memory_pool().await;
+        register_device(&pool, "alice", "laptop").await.unwrap();
+
+        sqlx::query(
+            "INSERT INTO sessions (id, user_id, token, device_id) VALUES ('s1', 'alice', 'tok', 'laptop')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        remove_device(&pool, "laptop").await.unwrap();
+
+        assert!(list_devices_for_user(&pool, "alice").await.unwrap().is_empty());
+        assert!(get_active_session_for_device(&pool, "alice", "laptop")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_active_session_for_device_is_scoped_per_device() {
+        let pool = memory_pool().await;
+        register_device(&pool, "alice", "laptop").await.unwrap();
+        register_device(&pool, "alice", "phone").await.unwrap();
+
+        sqlx::query(
+            "INSERT INTO sessions (id, user_id, token, device_id) VALUES ('s1', 'alice', 'laptop-token', 'laptop')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO sessions (id, user_id, token, device_id) VALUES ('s2', 'alice', 'phone-token', 'phone')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let laptop_session = get_active_session_for_device(&pool, "alice", "laptop")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(laptop_session.1, "laptop-token");
+
+        let phone_session = get_active_session_for_device(&pool, "alice", "phone")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(phone_session.1, "phone-token");
+    }
+}
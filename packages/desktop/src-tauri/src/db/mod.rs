@@ -0,0 +1,24 @@
+//! Local SQLite cache
+//!
+//! ## Components
+//!
+//! - **pool**: Query helpers for the local cache (sessions, users, conversations, messages, settings)
+//! - **store_cipher**: Opt-in at-rest encryption for sensitive cached column values
+//! - **prekeys**: Durable storage for `PreKeyManager` state, keyed per `KeyDomain`
+//! - **devices**: Multi-device identity - device registry and per-device session lookup
+//! - **outbox**: Persistent offline outbox for messages that failed to send
+//! - **search**: Offline-first FTS5 full-text search over cached message content
+//! - **receipts**: Read-receipt/delivery-status tracking and the flush queue backing `crate::receipts`
+
+pub mod devices;
+pub mod outbox;
+pub mod pool;
+pub mod prekeys;
+pub mod receipts;
+pub mod search;
+pub mod store_cipher;
+
+pub use devices::{get_active_session_for_device, list_devices_for_user, register_device, remove_device};
+pub use pool::*;
+pub use prekeys::{delete_consumed_prekey, load_prekeys, save_prekeys, PersistedPreKeys};
+pub use store_cipher::{migrate_legacy_plaintext, StoreCipher};
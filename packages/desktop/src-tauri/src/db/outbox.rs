@@ -0,0 +1,241 @@
+//! Persistent offline outbox for messages the API rejected or couldn't reach
+//!
+//! `commands::messaging::send_message` enqueues here instead of losing the
+//! user's message whenever `ApiClient::send_message` fails with anything but
+//! `AppError::SessionExpired`. `crate::outbox`'s background drainer then
+//! retries queued rows in FIFO order with exponential backoff until the
+//! server acknowledges them, at which point the client-generated id gets
+//! reconciled with the server-assigned one.
+//!
+//! Like `devices` and `prekeys`, this table has no entry in a real
+//! migrations directory - it's created lazily via `ensure_schema`.
+
+use chrono::{DateTime, Utc};
+use sqlx::{Row, SqlitePool};
+
+use crate::error::AppResult;
+use crate::models::{OutboxEntry, OutboxStatus};
+
+/// Unit of the retry backoff: after `n` failed attempts a row waits
+/// `BASE_BACKOFF * 2^n` (so 10s after the first failure), capped at
+/// [`MAX_BACKOFF`].
+const BASE_BACKOFF: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Upper bound on the backoff delay between retries.
+const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// Create the `outbox` table if it doesn't already exist. Safe to call on
+/// every startup.
+pub async fn ensure_schema(pool: &SqlitePool) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS outbox (
+            id TEXT PRIMARY KEY,
+            conversation_id TEXT NOT NULL,
+            content TEXT NOT NULL,
+            sent INTEGER NOT NULL DEFAULT 0,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at TEXT NOT NULL,
+            server_message_id TEXT,
+            last_error TEXT,
+            created_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Queue `content` for `conversation_id` under client-generated `id`, ready
+/// to be picked up by the next drain. A no-op if `id` is already queued.
+pub async fn enqueue(pool: &SqlitePool, id: &str, conversation_id: &str, content: &str) -> AppResult<()> {
+    ensure_schema(pool).await?;
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        INSERT INTO outbox (id, conversation_id, content, next_attempt_at, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO NOTHING
+        "#,
+    )
+    .bind(id)
+    .bind(conversation_id)
+    .bind(content)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Unsent rows due for a retry, oldest first. Pass `ignore_backoff = true`
+/// to force every unsent row (used by `retry_outbox`), regardless of
+/// `next_attempt_at`.
+pub async fn due_for_retry(pool: &SqlitePool, ignore_backoff: bool) -> AppResult<Vec<OutboxEntry>> {
+    ensure_schema(pool).await?;
+
+    let rows = sqlx::query(
+        "SELECT id, conversation_id, content, sent, attempts, server_message_id, last_error,
+                next_attempt_at, created_at
+         FROM outbox WHERE sent = 0 ORDER BY created_at ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let now = Utc::now();
+    let mut due = Vec::new();
+    for row in rows {
+        let next_attempt_at = parse_timestamp(row.try_get("next_attempt_at")?);
+        if ignore_backoff || next_attempt_at <= now {
+            due.push(row_to_entry(row)?);
+        }
+    }
+
+    Ok(due)
+}
+
+/// Every outbox row, newest first, for the `get_outbox_status` command.
+pub async fn all(pool: &SqlitePool) -> AppResult<Vec<OutboxEntry>> {
+    ensure_schema(pool).await?;
+
+    let rows = sqlx::query(
+        "SELECT id, conversation_id, content, sent, attempts, server_message_id, last_error, created_at
+         FROM outbox ORDER BY created_at DESC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter().map(row_to_entry).collect()
+}
+
+/// Mark `id` as sent and record the server-assigned message id it was
+/// reconciled to.
+pub async fn mark_sent(pool: &SqlitePool, id: &str, server_message_id: &str) -> AppResult<()> {
+    sqlx::query("UPDATE outbox SET sent = 1, server_message_id = ? WHERE id = ?")
+        .bind(server_message_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record a failed send attempt for `id`, bumping its attempt count and
+/// scheduling the next retry with exponential backoff.
+pub async fn record_failure(pool: &SqlitePool, id: &str, attempts: i32, error: &str) -> AppResult<()> {
+    let delay_secs = backoff_for_attempt(attempts).as_secs() as i64;
+    let next_attempt_at = Utc::now() + chrono::Duration::seconds(delay_secs);
+
+    sqlx::query("UPDATE outbox SET attempts = ?, last_error = ?, next_attempt_at = ? WHERE id = ?")
+        .bind(attempts)
+        .bind(error)
+        .bind(next_attempt_at.to_rfc3339())
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Delay after `attempts` failed attempts: [`BASE_BACKOFF`] doubled
+/// `attempts` times, capped at [`MAX_BACKOFF`].
+fn backoff_for_attempt(attempts: i32) -> std::time::Duration {
+    let shift = attempts.clamp(0, 10) as u32;
+    BASE_BACKOFF.saturating_mul(1u32 << shift).min(MAX_BACKOFF)
+}
+
+fn row_to_entry(row: sqlx::sqlite::SqliteRow) -> AppResult<OutboxEntry> {
+    let sent: i64 = row.try_get("sent")?;
+    let attempts: i32 = row.try_get("attempts")?;
+    let created_at: String = row.try_get("created_at")?;
+
+    Ok(OutboxEntry {
+        id: row.try_get("id")?,
+        conversation_id: row.try_get("conversation_id")?,
+        content: row.try_get("content")?,
+        status: if sent != 0 {
+            OutboxStatus::Sent
+        } else if attempts > 0 {
+            OutboxStatus::Retrying
+        } else {
+            OutboxStatus::Pending
+        },
+        attempts,
+        server_message_id: row.try_get("server_message_id")?,
+        last_error: row.try_get("last_error")?,
+        created_at: parse_timestamp(created_at),
+    })
+}
+
+/// Parse an RFC 3339 timestamp written by this module, falling back to the
+/// current time if it's ever malformed (it never is in practice - every row
+/// is written by [`enqueue`]/[`record_failure`], both of which bind
+/// `to_rfc3339()` output).
+fn parse_timestamp(value: String) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(&value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_then_due_for_retry() {
+        let pool = memory_pool().await;
+        enqueue(&pool, "msg-1", "conv-1", "hello").await.unwrap();
+
+        let due = due_for_retry(&pool, false).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "msg-1");
+        assert_eq!(due[0].status, OutboxStatus::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_mark_sent_excludes_row_from_due_for_retry() {
+        let pool = memory_pool().await;
+        enqueue(&pool, "msg-1", "conv-1", "hello").await.unwrap();
+        mark_sent(&pool, "msg-1", "server-123").await.unwrap();
+
+        assert!(due_for_retry(&pool, false).await.unwrap().is_empty());
+
+        let entries = all(&pool).await.unwrap();
+        assert_eq!(entries[0].status, OutboxStatus::Sent);
+        assert_eq!(entries[0].server_message_id.as_deref(), Some("server-123"));
+    }
+
+    #[tokio::test]
+    async fn test_record_failure_defers_until_backoff_elapses() {
+        let pool = memory_pool().await;
+        enqueue(&pool, "msg-1", "conv-1", "hello").await.unwrap();
+        record_failure(&pool, "msg-1", 1, "connection refused").await.unwrap();
+
+        // The backoff window hasn't elapsed yet, so a normal drain skips it...
+        assert!(due_for_retry(&pool, false).await.unwrap().is_empty());
+        // ...but a forced retry (`retry_outbox`) ignores backoff entirely.
+        let forced = due_for_retry(&pool, true).await.unwrap();
+        assert_eq!(forced.len(), 1);
+        assert_eq!(forced[0].status, OutboxStatus::Retrying);
+        assert_eq!(forced[0].last_error.as_deref(), Some("connection refused"));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        assert_eq!(backoff_for_attempt(0), BASE_BACKOFF);
+        assert_eq!(backoff_for_attempt(1), BASE_BACKOFF * 2);
+        assert_eq!(backoff_for_attempt(20), MAX_BACKOFF);
+    }
+}
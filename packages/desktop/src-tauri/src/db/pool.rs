@@ -3,14 +3,19 @@
 use chrono::{DateTime, Utc};
 use sqlx::SqlitePool;
 
+use crate::db::store_cipher::StoreCipher;
 use crate::error::AppResult;
-use crate::models::{Conversation, ConversationType, Message, Settings, Theme};
+use crate::models::{Conversation, ConversationType, Message, Settings, Theme, UserInfo};
 
 // ============================================================================
 // User Queries
 // ============================================================================
 
-/// Save or update user in local cache
+/// Save or update user in local cache.
+///
+/// `cipher` is optional: when set (the encrypted store is unlocked),
+/// `display_name` is encrypted with [`StoreCipher::encrypt_to_text`] before
+/// it's written, matching how `save_session` protects tokens below.
 pub async fn upsert_user(
     pool: &SqlitePool,
     id: &str,
@@ -19,7 +24,13 @@ pub async fn upsert_user(
     display_name: Option<&str>,
     avatar_url: Option<&str>,
     is_anonymous: bool,
+    cipher: Option<&StoreCipher>,
 ) -> AppResult<()> {
+    let display_name = display_name.map(|name| match cipher {
+        Some(cipher) => cipher.encrypt_to_text(name),
+        None => name.to_string(),
+    });
+
     sqlx::query(
         r#"
         INSERT INTO users (id, email, username, display_name, avatar_url, is_anonymous, updated_at)
@@ -45,20 +56,88 @@ pub async fn upsert_user(
     Ok(())
 }
 
+/// Locally cached users whose username, email, or display name contains
+/// `query` (case-insensitive), so `search_users` can show suggestions
+/// immediately from the offline cache while the API request is in flight.
+///
+/// `cipher` must match whatever `upsert_user` used to write `display_name`:
+/// since it's encrypted at rest when set, it can't be matched with `LIKE`
+/// in SQL, so every row with a display name is pulled back and matched
+/// against `query` in Rust after decrypting it - fine at the scale of a
+/// single user's locally cached contacts.
+pub async fn search_cached_users(
+    pool: &SqlitePool,
+    query: &str,
+    limit: i64,
+    cipher: Option<&StoreCipher>,
+) -> AppResult<Vec<UserInfo>> {
+    let pattern = format!("%{}%", query);
+
+    let rows = sqlx::query_as::<_, (String, Option<String>, Option<String>, Option<String>, Option<String>, bool)>(
+        r#"
+        SELECT id, email, username, display_name, avatar_url, is_anonymous
+        FROM users
+        WHERE username LIKE ? COLLATE NOCASE
+            OR email LIKE ? COLLATE NOCASE
+            OR display_name IS NOT NULL
+        "#,
+    )
+    .bind(&pattern)
+    .bind(&pattern)
+    .fetch_all(pool)
+    .await?;
+
+    let query_lower = query.to_lowercase();
+    let limit = limit.max(0) as usize;
+
+    let matches = rows
+        .into_iter()
+        .filter_map(|(id, email, username, display_name, avatar_url, is_anonymous)| {
+            let display_name = display_name.map(|name| match cipher {
+                Some(cipher) => cipher.decrypt_or_plaintext(&name),
+                None => name,
+            });
+
+            let matched = username.as_deref().is_some_and(|u| u.to_lowercase().contains(&query_lower))
+                || email.as_deref().is_some_and(|e| e.to_lowercase().contains(&query_lower))
+                || display_name.as_deref().is_some_and(|d| d.to_lowercase().contains(&query_lower));
+
+            matched.then_some(UserInfo { id, email, username, display_name, avatar_url, is_anonymous })
+        })
+        .take(limit)
+        .collect();
+
+    Ok(matches)
+}
+
 // ============================================================================
 // Session Queries
 // ============================================================================
 
-/// Save session to database
+/// Save session to database.
+///
+/// `cipher` is optional: when set (the encrypted store is unlocked), `token`
+/// and `refresh_token` are encrypted with [`StoreCipher::encrypt_to_text`]
+/// before they're written, so the raw SQLite file never holds them in the
+/// clear.
 pub async fn save_session(
     pool: &SqlitePool,
     user_id: &str,
     token: &str,
     refresh_token: Option<&str>,
     expires_at: Option<DateTime<Utc>>,
+    cipher: Option<&StoreCipher>,
 ) -> AppResult<String> {
     let id = uuid::Uuid::new_v4().to_string();
 
+    let (token, refresh_token) = match cipher {
+        Some(cipher) => (
+            cipher.encrypt_to_text(token),
+            refresh_token.map(|t| cipher.encrypt_to_text(t)),
+        ),
+        None => (token.to_string(), refresh_token.map(|t| t.to_string())),
+    };
+
     sqlx::query(
         r#"
         INSERT INTO sessions (id, user_id, token, refresh_token, expires_at)
@@ -76,9 +155,14 @@ pub async fn save_session(
     Ok(id)
 }
 
-/// Get active session for user
+/// Get active session for user.
+///
+/// `cipher` is optional: when set, `token` and `refresh_token` are decrypted
+/// (falling back to the raw value for rows written before encryption was
+/// enabled - see [`crate::db::store_cipher::migrate_legacy_plaintext`]).
 pub async fn get_active_session(
     pool: &SqlitePool,
+    cipher: Option<&StoreCipher>,
 ) -> AppResult<Option<(String, String, String, Option<String>)>> {
     let result = sqlx::query_as::<_, (String, String, String, Option<String>)>(
         r#"
@@ -92,6 +176,16 @@ pub async fn get_active_session(
     .fetch_optional(pool)
     .await?;
 
+    let result = result.map(|(id, user_id, token, refresh_token)| match cipher {
+        Some(cipher) => (
+            id,
+            user_id,
+            cipher.decrypt_or_plaintext(&token),
+            refresh_token.map(|t| cipher.decrypt_or_plaintext(&t)),
+        ),
+        None => (id, user_id, token, refresh_token),
+    });
+
     Ok(result)
 }
 
@@ -103,6 +197,78 @@ pub async fn clear_sessions(pool: &SqlitePool) -> AppResult<()> {
     Ok(())
 }
 
+// ============================================================================
+// Pending Verification Queries
+// ============================================================================
+//
+// A half-finished signup (awaiting email verification or invite redemption)
+// has no session yet, so it can't be tracked via the `sessions` table. It's
+// small enough to piggyback on the generic `settings` key/value store rather
+// than adding a dedicated table.
+
+const PENDING_VERIFICATION_KEY: &str = "pending_verification_email";
+
+/// Record that a signup is waiting on email verification (or invite
+/// redemption) for the given email, so `restore_session` can resume it.
+pub async fn save_pending_verification(pool: &SqlitePool, email: &str) -> AppResult<()> {
+    update_setting(pool, PENDING_VERIFICATION_KEY, email).await
+}
+
+/// Get the email address of a half-finished signup, if any.
+pub async fn get_pending_verification(pool: &SqlitePool) -> AppResult<Option<String>> {
+    let row = sqlx::query_as::<_, (String,)>("SELECT value FROM settings WHERE key = ?")
+        .bind(PENDING_VERIFICATION_KEY)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|(value,)| value))
+}
+
+/// Clear the pending-verification marker once a session has been established.
+pub async fn clear_pending_verification(pool: &SqlitePool) -> AppResult<()> {
+    sqlx::query("DELETE FROM settings WHERE key = ?")
+        .bind(PENDING_VERIFICATION_KEY)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// ============================================================================
+// Push Subscription Queries
+// ============================================================================
+//
+// Like the pending-verification marker, a single device only ever has one
+// active push subscription, so this piggybacks on the `settings` key/value
+// store rather than a dedicated table.
+
+const PUSH_SUBSCRIPTION_KEY: &str = "push_subscription_endpoint";
+
+/// Record the endpoint of this device's active push subscription, so
+/// `logout` can unregister it from the server without the frontend needing
+/// to resupply it.
+pub async fn save_push_subscription(pool: &SqlitePool, endpoint: &str) -> AppResult<()> {
+    update_setting(pool, PUSH_SUBSCRIPTION_KEY, endpoint).await
+}
+
+/// Get the endpoint of this device's active push subscription, if any.
+pub async fn get_push_subscription(pool: &SqlitePool) -> AppResult<Option<String>> {
+    let row = sqlx::query_as::<_, (String,)>("SELECT value FROM settings WHERE key = ?")
+        .bind(PUSH_SUBSCRIPTION_KEY)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|(value,)| value))
+}
+
+/// Clear the push subscription marker once it's been unregistered.
+pub async fn clear_push_subscription(pool: &SqlitePool) -> AppResult<()> {
+    sqlx::query("DELETE FROM settings WHERE key = ?")
+        .bind(PUSH_SUBSCRIPTION_KEY)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
 // ============================================================================
 // Conversation Queries
 // ============================================================================
@@ -193,8 +359,23 @@ pub async fn get_conversations(
 // Message Queries
 // ============================================================================
 
-/// Save message to local cache
-pub async fn save_message(pool: &SqlitePool, message: &Message) -> AppResult<()> {
+/// Save message to local cache.
+///
+/// `message.content` already holds Signal Protocol ciphertext, but that only
+/// protects the message in transit - anyone with the SQLite file can still
+/// read it. When `cipher` is set, `encrypted_content` gets a second,
+/// independent layer of encryption at rest, matching matrix-rust-sdk's
+/// crypto_store, which wraps already-encrypted event payloads the same way.
+pub async fn save_message(
+    pool: &SqlitePool,
+    message: &Message,
+    cipher: Option<&StoreCipher>,
+) -> AppResult<()> {
+    let content = match cipher {
+        Some(cipher) => cipher.encrypt_to_text(&message.content),
+        None => message.content.clone(),
+    };
+
     sqlx::query(
         r#"
         INSERT INTO messages (id, conversation_id, sender_id, encrypted_content, message_type, encryption_version, created_at)
@@ -205,7 +386,7 @@ pub async fn save_message(pool: &SqlitePool, message: &Message) -> AppResult<()>
     .bind(&message.id)
     .bind(&message.conversation_id)
     .bind(&message.sender_id)
-    .bind(&message.content)
+    .bind(content)
     .bind(&message.message_type)
     .bind(message.encryption_version)
     .bind(message.created_at.to_rfc3339())
@@ -215,12 +396,17 @@ pub async fn save_message(pool: &SqlitePool, message: &Message) -> AppResult<()>
     Ok(())
 }
 
-/// Get messages for a conversation
+/// Get messages for a conversation.
+///
+/// `cipher` is optional and must match whatever `save_message` used to write
+/// `encrypted_content`; when set, it's decrypted (falling back to the raw
+/// value for rows written before encryption was enabled).
 pub async fn get_messages(
     pool: &SqlitePool,
     conversation_id: &str,
     limit: i64,
     offset: i64,
+    cipher: Option<&StoreCipher>,
 ) -> AppResult<Vec<Message>> {
     let rows = sqlx::query_as::<_, (String, String, String, String, String, i32, String)>(
         r#"
@@ -241,6 +427,11 @@ pub async fn get_messages(
         .into_iter()
         .map(
             |(id, conversation_id, sender_id, content, message_type, encryption_version, created_at)| {
+                let content = match cipher {
+                    Some(cipher) => cipher.decrypt_or_plaintext(&content),
+                    None => content,
+                };
+
                 Message {
                     id,
                     conversation_id,
@@ -260,6 +451,19 @@ pub async fn get_messages(
     Ok(messages)
 }
 
+/// Replace a locally cached message's id, used by the outbox drainer
+/// (`crate::outbox`) to reconcile a client-generated optimistic id with the
+/// server-assigned one once a queued message is finally acknowledged.
+pub async fn reconcile_message_id(pool: &SqlitePool, old_id: &str, new_id: &str) -> AppResult<()> {
+    sqlx::query("UPDATE messages SET id = ? WHERE id = ?")
+        .bind(new_id)
+        .bind(old_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
 // ============================================================================
 // Settings Queries
 // ============================================================================
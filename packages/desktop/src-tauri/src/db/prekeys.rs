@@ -0,0 +1,390 @@
+//! Durable storage for [`PreKeyManager`](crate::crypto::prekeys::PreKeyManager) state
+//!
+//! `PreKeyManager::get_stored_prekeys` and `PreKeyManager::restore` exist,
+//! but until now nothing wired them to the database, so every relaunch
+//! regenerated a fresh batch of prekeys instead of reusing the committed
+//! ones - silently breaking any X3DH session a peer had already established
+//! against the old keys, since the private halves were gone. This module
+//! adds the tables and query functions to round-trip a `PreKeyManager`'s
+//! signed, fallback, and one-time prekeys across restarts.
+//!
+//! Rows are keyed by [`KeyDomain`] as well as kind/key id, so the primary
+//! account identity's prekeys and a secondary phone-number identity's
+//! prekeys are stored side by side without colliding.
+//!
+//! Private key material is encrypted through the at-rest [`StoreCipher`]
+//! (see [`crate::db::store_cipher`]) before it touches disk whenever a
+//! cipher is passed in, the same as session tokens and cached display
+//! names. The callers in `crate::prekey` pass no cipher, so the keys they
+//! save are written unencrypted.
+//!
+//! Note: which keys have already been published to the server
+//! ([`PreKeyManager::published_key_ids`](crate::crypto::prekeys::PreKeyManager::published_key_ids))
+//! isn't persisted here - a restored manager treats every stored key as
+//! unpublished, so the next publish round re-uploads keys the server
+//! already has. That's a harmless, idempotent no-op server-side, not a
+//! correctness issue, so it's left out of scope for now.
+
+use sqlx::{Row, SqlitePool};
+
+use crate::crypto::keys::StoredPreKey;
+use crate::crypto::prekeys::KeyDomain;
+use crate::db::store_cipher::StoreCipher;
+use crate::error::AppResult;
+
+/// Everything [`PreKeyManager::restore_domain`](crate::crypto::prekeys::PreKeyManager::restore_domain)
+/// needs for one domain, short of the identity key pair, published-key
+/// tracking, and config - all supplied by the caller.
+pub struct PersistedPreKeys {
+    pub signed_prekey: StoredPreKey,
+    pub signed_prekey_created: i64,
+    pub fallback_prekey: StoredPreKey,
+    pub fallback_prekey_created: i64,
+    pub one_time_prekeys: Vec<StoredPreKey>,
+    pub next_prekey_id: u32,
+}
+
+fn domain_key(domain: KeyDomain) -> &'static str {
+    match domain {
+        KeyDomain::Account => "account",
+        KeyDomain::PhoneNumber => "phone_number",
+    }
+}
+
+/// Save the current signed prekey, fallback prekey, one-time prekey pool,
+/// and next-id counter for `domain`. Call this after `PreKeyManager::new`,
+/// `replenish`, `rotate_signed_prekey`, or `rotate_fallback_prekey` so the
+/// database always mirrors the manager's in-memory state.
+///
+/// The signed and fallback rows are replaced outright (there's only ever
+/// one current key of each kind per domain); one-time prekeys are inserted
+/// without touching rows already on disk, so calling this after `replenish`
+/// only adds the new batch instead of re-encrypting the whole pool.
+#[allow(clippy::too_many_arguments)]
+pub async fn save_prekeys(
+    pool: &SqlitePool,
+    domain: KeyDomain,
+    signed: &StoredPreKey,
+    signed_created: i64,
+    fallback: &StoredPreKey,
+    fallback_created: i64,
+    otks: &[StoredPreKey],
+    next_id: u32,
+    cipher: Option<&StoreCipher>,
+) -> AppResult<()> {
+    ensure_tables(pool).await?;
+    let domain = domain_key(domain);
+
+    for (kind, key, created_at) in [("signed", signed, signed_created), ("fallback", fallback, fallback_created)] {
+        sqlx::query(
+            r#"
+            INSERT INTO signed_prekeys (domain, kind, key_id, public_key, secret_key, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(domain, kind) DO UPDATE SET
+                key_id = excluded.key_id,
+                public_key = excluded.public_key,
+                secret_key = excluded.secret_key,
+                created_at = excluded.created_at
+            "#,
+        )
+        .bind(domain)
+        .bind(kind)
+        .bind(key.key_id)
+        .bind(&key.public_key)
+        .bind(seal_secret(&key.secret_key, cipher))
+        .bind(created_at)
+        .execute(pool)
+        .await?;
+    }
+
+    for otk in otks {
+        sqlx::query(
+            r#"
+            INSERT INTO prekeys (domain, key_id, public_key, secret_key)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(domain, key_id) DO NOTHING
+            "#,
+        )
+        .bind(domain)
+        .bind(otk.key_id)
+        .bind(&otk.public_key)
+        .bind(seal_secret(&otk.secret_key, cipher))
+        .execute(pool)
+        .await?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO prekey_meta (domain, next_prekey_id) VALUES (?, ?)
+        ON CONFLICT(domain) DO UPDATE SET next_prekey_id = excluded.next_prekey_id
+        "#,
+    )
+    .bind(domain)
+    .bind(next_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Load `domain`'s persisted prekey state, if any has been saved yet.
+pub async fn load_prekeys(pool: &SqlitePool, domain: KeyDomain, cipher: Option<&StoreCipher>) -> AppResult<Option<PersistedPreKeys>> {
+    ensure_tables(pool).await?;
+    let domain = domain_key(domain);
+
+    let signed_row = sqlx::query("SELECT key_id, public_key, secret_key, created_at FROM signed_prekeys WHERE domain = ? AND kind = 'signed'")
+        .bind(domain)
+        .fetch_optional(pool)
+        .await?;
+    let fallback_row = sqlx::query("SELECT key_id, public_key, secret_key, created_at FROM signed_prekeys WHERE domain = ? AND kind = 'fallback'")
+        .bind(domain)
+        .fetch_optional(pool)
+        .await?;
+
+    let (Some(signed_row), Some(fallback_row)) = (signed_row, fallback_row) else {
+        return Ok(None);
+    };
+
+    let signed_prekey = StoredPreKey {
+        key_id: signed_row.get::<i64, _>("key_id") as u32,
+        public_key: signed_row.get("public_key"),
+        secret_key: unseal_secret(signed_row.get("secret_key"), cipher)?,
+        is_signed: true,
+    };
+    let signed_prekey_created: i64 = signed_row.get("created_at");
+
+    let fallback_prekey = StoredPreKey {
+        key_id: fallback_row.get::<i64, _>("key_id") as u32,
+        public_key: fallback_row.get("public_key"),
+        secret_key: unseal_secret(fallback_row.get("secret_key"), cipher)?,
+        is_signed: true,
+    };
+    let fallback_prekey_created: i64 = fallback_row.get("created_at");
+
+    let otk_rows = sqlx::query("SELECT key_id, public_key, secret_key FROM prekeys WHERE domain = ?")
+        .bind(domain)
+        .fetch_all(pool)
+        .await?;
+    let mut one_time_prekeys = Vec::with_capacity(otk_rows.len());
+    for row in otk_rows {
+        one_time_prekeys.push(StoredPreKey {
+            key_id: row.get::<i64, _>("key_id") as u32,
+            public_key: row.get("public_key"),
+            secret_key: unseal_secret(row.get("secret_key"), cipher)?,
+            is_signed: false,
+        });
+    }
+
+    let next_prekey_id = sqlx::query("SELECT next_prekey_id FROM prekey_meta WHERE domain = ?")
+        .bind(domain)
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.get::<i64, _>("next_prekey_id") as u32)
+        .unwrap_or(0);
+
+    Ok(Some(PersistedPreKeys {
+        signed_prekey,
+        signed_prekey_created,
+        fallback_prekey,
+        fallback_prekey_created,
+        one_time_prekeys,
+        next_prekey_id,
+    }))
+}
+
+/// Remove a one-time prekey from `domain`'s durable storage once
+/// `PreKeyManager::consume_prekey` has taken it out of the in-memory pool.
+pub async fn delete_consumed_prekey(pool: &SqlitePool, domain: KeyDomain, key_id: u32) -> AppResult<()> {
+    sqlx::query("DELETE FROM prekeys WHERE domain = ? AND key_id = ?")
+        .bind(domain_key(domain))
+        .bind(key_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Encrypt a prekey's secret key bytes under `cipher` before they're
+/// written to disk, or pass them through unchanged if no cipher is set.
+fn seal_secret(secret_key: &[u8], cipher: Option<&StoreCipher>) -> Vec<u8> {
+    match cipher {
+        Some(cipher) => cipher.encrypt_value(secret_key),
+        None => secret_key.to_vec(),
+    }
+}
+
+/// Inverse of [`seal_secret`]. Falls back to treating `data` as already
+/// plaintext when no cipher is supplied or decryption fails, mirroring the
+/// legacy-plaintext tolerance in [`StoreCipher::decrypt_or_plaintext`].
+fn unseal_secret(data: Vec<u8>, cipher: Option<&StoreCipher>) -> AppResult<zeroize::Zeroizing<Vec<u8>>> {
+    let plaintext = match cipher {
+        Some(cipher) => cipher.decrypt_value(&data).unwrap_or(data),
+        None => data,
+    };
+    Ok(zeroize::Zeroizing::new(plaintext))
+}
+
+async fn ensure_tables(pool: &SqlitePool) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS signed_prekeys (
+            domain TEXT NOT NULL DEFAULT 'account',
+            kind TEXT NOT NULL CHECK (kind IN ('signed', 'fallback')),
+            key_id INTEGER NOT NULL,
+            public_key BLOB NOT NULL,
+            secret_key BLOB NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (domain, kind)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS prekeys (
+            domain TEXT NOT NULL DEFAULT 'account',
+            key_id INTEGER NOT NULL,
+            public_key BLOB NOT NULL,
+            secret_key BLOB NOT NULL,
+            PRIMARY KEY (domain, key_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS prekey_meta (
+            domain TEXT PRIMARY KEY,
+            next_prekey_id INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keys::{Curve25519KeyPair, StoredPreKey};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const ACCOUNT: KeyDomain = KeyDomain::Account;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    fn sample_prekey(id: u32) -> StoredPreKey {
+        StoredPreKey::from_keypair(id, &Curve25519KeyPair::generate(), false)
+    }
+
+    #[tokio::test]
+    async fn test_load_prekeys_before_any_save_returns_none() {
+        let pool = memory_pool().await;
+        assert!(load_prekeys(&pool, ACCOUNT, None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_round_trip() {
+        let pool = memory_pool().await;
+        let signed = sample_prekey(0);
+        let fallback = sample_prekey(1);
+        let otks = vec![sample_prekey(2), sample_prekey(3)];
+
+        save_prekeys(&pool, ACCOUNT, &signed, 1000, &fallback, 2000, &otks, 4, None)
+            .await
+            .unwrap();
+
+        let loaded = load_prekeys(&pool, ACCOUNT, None).await.unwrap().unwrap();
+        assert_eq!(loaded.signed_prekey.key_id, 0);
+        assert_eq!(loaded.signed_prekey.secret_key, signed.secret_key);
+        assert_eq!(loaded.fallback_prekey.key_id, 1);
+        assert_eq!(loaded.one_time_prekeys.len(), 2);
+        assert_eq!(loaded.next_prekey_id, 4);
+    }
+
+    #[tokio::test]
+    async fn test_save_prekeys_is_encrypted_when_cipher_is_set() {
+        let pool = memory_pool().await;
+        let cipher = StoreCipher::unlock(&pool, b"passphrase").await.unwrap();
+        let signed = sample_prekey(0);
+        let fallback = sample_prekey(1);
+
+        save_prekeys(&pool, ACCOUNT, &signed, 1000, &fallback, 2000, &[], 2, Some(&cipher))
+            .await
+            .unwrap();
+
+        let raw: Vec<u8> = sqlx::query("SELECT secret_key FROM signed_prekeys WHERE domain = 'account' AND kind = 'signed'")
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .get("secret_key");
+        assert_ne!(raw, *signed.secret_key);
+
+        let loaded = load_prekeys(&pool, ACCOUNT, Some(&cipher)).await.unwrap().unwrap();
+        assert_eq!(loaded.signed_prekey.secret_key, signed.secret_key);
+    }
+
+    #[tokio::test]
+    async fn test_delete_consumed_prekey_removes_it() {
+        let pool = memory_pool().await;
+        let signed = sample_prekey(0);
+        let fallback = sample_prekey(1);
+        let otks = vec![sample_prekey(2), sample_prekey(3)];
+
+        save_prekeys(&pool, ACCOUNT, &signed, 1000, &fallback, 2000, &otks, 4, None)
+            .await
+            .unwrap();
+        delete_consumed_prekey(&pool, ACCOUNT, 2).await.unwrap();
+
+        let loaded = load_prekeys(&pool, ACCOUNT, None).await.unwrap().unwrap();
+        assert_eq!(loaded.one_time_prekeys.len(), 1);
+        assert_eq!(loaded.one_time_prekeys[0].key_id, 3);
+    }
+
+    #[tokio::test]
+    async fn test_replenishing_does_not_duplicate_or_drop_existing_otks() {
+        let pool = memory_pool().await;
+        let signed = sample_prekey(0);
+        let fallback = sample_prekey(1);
+
+        save_prekeys(&pool, ACCOUNT, &signed, 1000, &fallback, 2000, &[sample_prekey(2)], 3, None)
+            .await
+            .unwrap();
+        save_prekeys(&pool, ACCOUNT, &signed, 1000, &fallback, 2000, &[sample_prekey(2), sample_prekey(3)], 4, None)
+            .await
+            .unwrap();
+
+        let loaded = load_prekeys(&pool, ACCOUNT, None).await.unwrap().unwrap();
+        assert_eq!(loaded.one_time_prekeys.len(), 2);
+        assert_eq!(loaded.next_prekey_id, 4);
+    }
+
+    #[tokio::test]
+    async fn test_account_and_phone_number_domains_do_not_collide() {
+        let pool = memory_pool().await;
+        let account_signed = sample_prekey(0);
+        let pni_signed = sample_prekey(0); // same key_id, different domain
+
+        save_prekeys(&pool, ACCOUNT, &account_signed, 1000, &sample_prekey(1), 2000, &[], 2, None)
+            .await
+            .unwrap();
+        save_prekeys(&pool, KeyDomain::PhoneNumber, &pni_signed, 1500, &sample_prekey(1), 2500, &[], 2, None)
+            .await
+            .unwrap();
+
+        let account = load_prekeys(&pool, ACCOUNT, None).await.unwrap().unwrap();
+        let pni = load_prekeys(&pool, KeyDomain::PhoneNumber, None).await.unwrap().unwrap();
+        assert_eq!(account.signed_prekey_created, 1000);
+        assert_eq!(pni.signed_prekey_created, 1500);
+    }
+}
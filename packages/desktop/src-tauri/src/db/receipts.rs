@@ -0,0 +1,324 @@
+//! Durable storage backing read-receipt and delivery-status tracking
+//!
+//! Adds `delivered_at`/`read_at` columns to the existing `messages` table and
+//! a `pending_receipts` queue that `crate::receipts`'s background flusher
+//! drains into one batched API call per conversation, so marking several
+//! messages read in the same thread doesn't fire a request per message.
+//!
+//! Like `outbox`/`devices`, none of this has an entry in a real migrations
+//! directory - it's created lazily via `ensure_schema`.
+
+use chrono::{DateTime, Utc};
+use sqlx::{Row, SqlitePool};
+
+use crate::error::AppResult;
+use crate::models::UnreadCount;
+
+/// Add the `delivered_at`/`read_at` columns to `messages` and create the
+/// `pending_receipts` queue table if they don't already exist. Safe to call
+/// on every startup.
+pub async fn ensure_schema(pool: &SqlitePool) -> AppResult<()> {
+    // SQLite has no `ADD COLUMN IF NOT EXISTS` on the versions this app
+    // supports, so these are best-effort ALTERs that ignore "duplicate
+    // column" - the only way they can fail once `messages` itself exists.
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN delivered_at TEXT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN read_at TEXT")
+        .execute(pool)
+        .await;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS pending_receipts (
+            conversation_id TEXT NOT NULL,
+            message_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (conversation_id, message_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Record that we've read `message_id` and queue a receipt for the
+/// background flusher to send. A no-op if it was already marked read.
+pub async fn mark_read(pool: &SqlitePool, conversation_id: &str, message_id: &str) -> AppResult<()> {
+    ensure_schema(pool).await?;
+
+    let result = sqlx::query("UPDATE messages SET read_at = ? WHERE id = ? AND read_at IS NULL")
+        .bind(Utc::now().to_rfc3339())
+        .bind(message_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() > 0 {
+        queue_receipt(pool, conversation_id, message_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Mark every currently-unread message in `conversation_id` as read (other
+/// than our own, which have no unread state) and queue a receipt for each -
+/// the common "user opened the thread" case. Returns the message ids that
+/// were newly marked.
+pub async fn mark_conversation_read(
+    pool: &SqlitePool,
+    conversation_id: &str,
+    own_user_id: &str,
+) -> AppResult<Vec<String>> {
+    ensure_schema(pool).await?;
+
+    let rows = sqlx::query(
+        "SELECT id FROM messages WHERE conversation_id = ? AND read_at IS NULL AND sender_id != ?",
+    )
+    .bind(conversation_id)
+    .bind(own_user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let message_ids: Vec<String> = rows.into_iter().map(|row| row.get::<String, _>("id")).collect();
+    if message_ids.is_empty() {
+        return Ok(message_ids);
+    }
+
+    sqlx::query(
+        "UPDATE messages SET read_at = ? WHERE conversation_id = ? AND read_at IS NULL AND sender_id != ?",
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(conversation_id)
+    .bind(own_user_id)
+    .execute(pool)
+    .await?;
+
+    for message_id in &message_ids {
+        queue_receipt(pool, conversation_id, message_id).await?;
+    }
+
+    Ok(message_ids)
+}
+
+async fn queue_receipt(pool: &SqlitePool, conversation_id: &str, message_id: &str) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO pending_receipts (conversation_id, message_id, created_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT(conversation_id, message_id) DO NOTHING
+        "#,
+    )
+    .bind(conversation_id)
+    .bind(message_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Queued receipts grouped by conversation, oldest first, for the background
+/// flusher (`crate::receipts`) to send as one batched API call per
+/// conversation.
+pub async fn due_receipts(pool: &SqlitePool) -> AppResult<Vec<(String, Vec<String>)>> {
+    ensure_schema(pool).await?;
+
+    let rows = sqlx::query(
+        "SELECT conversation_id, message_id FROM pending_receipts ORDER BY created_at ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut grouped: Vec<(String, Vec<String>)> = Vec::new();
+    for row in rows {
+        let conversation_id: String = row.get("conversation_id");
+        let message_id: String = row.get("message_id");
+
+        match grouped.iter_mut().find(|(id, _)| *id == conversation_id) {
+            Some((_, ids)) => ids.push(message_id),
+            None => grouped.push((conversation_id, vec![message_id])),
+        }
+    }
+
+    Ok(grouped)
+}
+
+/// Drop queued receipts once the server has acknowledged them.
+pub async fn clear_receipts(pool: &SqlitePool, conversation_id: &str, message_ids: &[String]) -> AppResult<()> {
+    for message_id in message_ids {
+        sqlx::query("DELETE FROM pending_receipts WHERE conversation_id = ? AND message_id = ?")
+            .bind(conversation_id)
+            .bind(message_id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Per-conversation unread tallies - messages from others with no `read_at`
+/// yet - for the `get_unread_counts` command.
+pub async fn get_unread_counts(pool: &SqlitePool, own_user_id: &str) -> AppResult<Vec<UnreadCount>> {
+    ensure_schema(pool).await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT conversation_id, COUNT(*) AS count
+        FROM messages
+        WHERE read_at IS NULL AND sender_id != ?
+        GROUP BY conversation_id
+        "#,
+    )
+    .bind(own_user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| UnreadCount {
+            conversation_id: row.get("conversation_id"),
+            count: row.get::<i64, _>("count"),
+        })
+        .collect())
+}
+
+/// Apply a delivery/read-receipt event pushed from the server for a message
+/// *we* sent, updating our local copy so the UI can show the right checkmark
+/// state without a full refetch - see `crate::receipts::handle_incoming_receipt`.
+pub async fn apply_incoming_receipt(
+    pool: &SqlitePool,
+    message_id: &str,
+    delivered_at: Option<DateTime<Utc>>,
+    read_at: Option<DateTime<Utc>>,
+) -> AppResult<()> {
+    ensure_schema(pool).await?;
+
+    sqlx::query(
+        r#"
+        UPDATE messages SET
+            delivered_at = COALESCE(?, delivered_at),
+            read_at = COALESCE(?, read_at)
+        WHERE id = ?
+        "#,
+    )
+    .bind(delivered_at.map(|dt| dt.to_rfc3339()))
+    .bind(read_at.map(|dt| dt.to_rfc3339()))
+    .bind(message_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        sqlx::query(
+            r#"
+            CREATE TABLE messages (
+                id TEXT PRIMARY KEY,
+                conversation_id TEXT NOT NULL,
+                sender_id TEXT NOT NULL,
+                encrypted_content TEXT NOT NULL,
+                message_type TEXT NOT NULL,
+                encryption_version INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        pool
+    }
+
+    async fn insert_message(pool: &SqlitePool, id: &str, conversation_id: &str, sender_id: &str) {
+        sqlx::query(
+            "INSERT INTO messages (id, conversation_id, sender_id, encrypted_content, message_type, encryption_version, created_at)
+             VALUES (?, ?, ?, 'ciphertext', 'text', 1, datetime('now'))",
+        )
+        .bind(id)
+        .bind(conversation_id)
+        .bind(sender_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mark_read_queues_a_receipt_once() {
+        let pool = memory_pool().await;
+        insert_message(&pool, "m1", "c1", "peer").await;
+
+        mark_read(&pool, "c1", "m1").await.unwrap();
+        mark_read(&pool, "c1", "m1").await.unwrap();
+
+        let due = due_receipts(&pool).await.unwrap();
+        assert_eq!(due, vec![("c1".to_string(), vec!["m1".to_string()])]);
+    }
+
+    #[tokio::test]
+    async fn test_mark_conversation_read_skips_own_messages() {
+        let pool = memory_pool().await;
+        insert_message(&pool, "m1", "c1", "peer").await;
+        insert_message(&pool, "m2", "c1", "me").await;
+
+        let marked = mark_conversation_read(&pool, "c1", "me").await.unwrap();
+        assert_eq!(marked, vec!["m1".to_string()]);
+
+        let counts = get_unread_counts(&pool, "me").await.unwrap();
+        assert!(counts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_unread_counts_excludes_read_messages() {
+        let pool = memory_pool().await;
+        insert_message(&pool, "m1", "c1", "peer").await;
+        insert_message(&pool, "m2", "c1", "peer").await;
+        mark_read(&pool, "c1", "m1").await.unwrap();
+
+        let counts = get_unread_counts(&pool, "me").await.unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].conversation_id, "c1");
+        assert_eq!(counts[0].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_clear_receipts_removes_from_queue() {
+        let pool = memory_pool().await;
+        insert_message(&pool, "m1", "c1", "peer").await;
+        mark_read(&pool, "c1", "m1").await.unwrap();
+
+        clear_receipts(&pool, "c1", &["m1".to_string()]).await.unwrap();
+        assert!(due_receipts(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_apply_incoming_receipt_updates_timestamps() {
+        let pool = memory_pool().await;
+        insert_message(&pool, "m1", "c1", "me").await;
+
+        let read_at = Utc::now();
+        apply_incoming_receipt(&pool, "m1", None, Some(read_at)).await.unwrap();
+
+        let row = sqlx::query("SELECT delivered_at, read_at FROM messages WHERE id = 'm1'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let delivered_at: Option<String> = row.get("delivered_at");
+        let read_at_stored: Option<String> = row.get("read_at");
+        assert!(delivered_at.is_none());
+        assert!(read_at_stored.is_some());
+    }
+}
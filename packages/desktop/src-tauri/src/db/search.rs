@@ -0,0 +1,180 @@
+//! Offline-first full-text search over locally cached message history
+//!
+//! Messages are cached E2E-encrypted at rest (`messages.encrypted_content`
+//! via `save_message`) - `CryptoService::decrypt` only ever runs on demand,
+//! in the frontend, never when a message is written to the local cache. A
+//! SQL trigger on `messages` would therefore only ever have ciphertext to
+//! index, which isn't searchable for anything meaningful. Instead, the
+//! frontend calls `index_message_content` with the plaintext right after it
+//! decrypts a message for display, the same explicit, frontend-driven
+//! pattern every other crypto operation in this app already follows -
+//! ciphertext is still the only thing ever written to `messages` itself.
+//!
+//! Like `devices`/`outbox`, this table has no entry in a real migrations
+//! directory - it's created lazily via `ensure_schema`.
+
+use sqlx::{Row, SqlitePool};
+
+use crate::error::AppResult;
+use crate::models::MessageSearchHit;
+
+/// Create the `messages_fts` FTS5 virtual table if it doesn't already
+/// exist. Safe to call on every startup.
+pub async fn ensure_schema(pool: &SqlitePool) -> AppResult<()> {
+    sqlx::query(
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+            message_id UNINDEXED,
+            conversation_id UNINDEXED,
+            body,
+            tokenize = 'porter unicode61'
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Index (or re-index) a message's decrypted content for search. Replaces
+/// any prior entry for `message_id` - FTS5 has no upsert, so this is a
+/// delete-then-insert.
+pub async fn index_message(
+    pool: &SqlitePool,
+    message_id: &str,
+    conversation_id: &str,
+    content: &str,
+) -> AppResult<()> {
+    ensure_schema(pool).await?;
+
+    remove_message(pool, message_id).await?;
+
+    sqlx::query("INSERT INTO messages_fts (message_id, conversation_id, body) VALUES (?, ?, ?)")
+        .bind(message_id)
+        .bind(conversation_id)
+        .bind(content)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Drop a message from the search index, e.g. once it's deleted or found to
+/// no longer decrypt cleanly.
+pub async fn remove_message(pool: &SqlitePool, message_id: &str) -> AppResult<()> {
+    ensure_schema(pool).await?;
+
+    sqlx::query("DELETE FROM messages_fts WHERE message_id = ?")
+        .bind(message_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Search indexed message content, best-match-first, with `<mark>`-wrapped
+/// highlights around the matched terms.
+pub async fn search_messages(
+    pool: &SqlitePool,
+    query: &str,
+    limit: i64,
+    offset: i64,
+) -> AppResult<Vec<MessageSearchHit>> {
+    ensure_schema(pool).await?;
+
+    let match_query = build_match_query(query);
+    if match_query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rows = sqlx::query(
+        r#"
+        SELECT message_id, conversation_id,
+               snippet(messages_fts, 2, '<mark>', '</mark>', '…', 10) AS snippet
+        FROM messages_fts
+        WHERE messages_fts MATCH ?
+        ORDER BY rank
+        LIMIT ? OFFSET ?
+        "#,
+    )
+    .bind(&match_query)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| MessageSearchHit {
+            message_id: row.get("message_id"),
+            conversation_id: row.get("conversation_id"),
+            snippet: row.get("snippet"),
+        })
+        .collect())
+}
+
+/// Turn free-form user input into an FTS5 `MATCH` query: each whitespace-
+/// separated token is quoted as a literal string and the tokens are
+/// implicitly AND-ed together (FTS5's default for a multi-token query), so
+/// stray FTS5 syntax in the user's input (a bare `NOT`, a `column:` filter,
+/// an unbalanced `"`) can't change how the query is parsed.
+fn build_match_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|token| format!("\"{}\"", token.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_index_then_search_finds_match() {
+        let pool = memory_pool().await;
+        index_message(&pool, "m1", "c1", "let's grab lunch tomorrow").await.unwrap();
+        index_message(&pool, "m2", "c1", "the weather is nice today").await.unwrap();
+
+        let hits = search_messages(&pool, "lunch", 10, 0).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message_id, "m1");
+        assert!(hits[0].snippet.contains("<mark>lunch</mark>"));
+    }
+
+    #[tokio::test]
+    async fn test_reindexing_replaces_prior_entry() {
+        let pool = memory_pool().await;
+        index_message(&pool, "m1", "c1", "original content").await.unwrap();
+        index_message(&pool, "m1", "c1", "edited content").await.unwrap();
+
+        assert!(search_messages(&pool, "original", 10, 0).await.unwrap().is_empty());
+        assert_eq!(search_messages(&pool, "edited", 10, 0).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_remove_message_drops_it_from_results() {
+        let pool = memory_pool().await;
+        index_message(&pool, "m1", "c1", "searchable text").await.unwrap();
+        remove_message(&pool, "m1").await.unwrap();
+
+        assert!(search_messages(&pool, "searchable", 10, 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_empty_query_returns_no_results() {
+        let pool = memory_pool().await;
+        index_message(&pool, "m1", "c1", "hello world").await.unwrap();
+
+        assert!(search_messages(&pool, "   ", 10, 0).await.unwrap().is_empty());
+    }
+}
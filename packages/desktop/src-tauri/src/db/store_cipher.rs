@@ -0,0 +1,322 @@
+//! StoreCipher: opt-in at-rest encryption for sensitive local-cache values
+//!
+//! The local cache stores session tokens, refresh tokens, and display names
+//! as plaintext SQLite columns - only `messages.encrypted_content` carries
+//! any protection, and that's the Signal Protocol ciphertext, not protection
+//! against someone reading the SQLite file directly. This module adds a
+//! second, independent encryption layer modeled on matrix-rust-sdk's
+//! `crypto_store` `StoreCipher`: a user passphrase derives a wrapping key via
+//! Argon2id, which seals a random data key with XChaCha20-Poly1305. Only the
+//! sealed data key and the salt used to derive the wrapping key are
+//! persisted (in a dedicated `store_meta` row), so rotating the passphrase
+//! means re-sealing one small value rather than re-encrypting every row.
+
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use sqlx::{Row, SqlitePool};
+
+use crate::error::{AppError, AppResult};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const DATA_KEY_LEN: usize = 32;
+
+/// Transparent value-encryption layer for the local SQLite cache. See the
+/// module docs for the key hierarchy.
+pub struct StoreCipher {
+    data_key: [u8; DATA_KEY_LEN],
+}
+
+impl StoreCipher {
+    /// Unlock the store: load (or, on first run, create) the `store_meta`
+    /// row, derive the wrapping key from `passphrase`, and unseal the data
+    /// key used by [`encrypt_value`](Self::encrypt_value) /
+    /// [`decrypt_value`](Self::decrypt_value).
+    pub async fn unlock(pool: &SqlitePool, passphrase: &[u8]) -> AppResult<Self> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS store_meta (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                salt BLOB NOT NULL,
+                sealed_data_key BLOB NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
+
+        let row = sqlx::query("SELECT salt, sealed_data_key FROM store_meta WHERE id = 0")
+            .fetch_optional(pool)
+            .await?;
+
+        match row {
+            Some(row) => {
+                let salt: Vec<u8> = row.get("salt");
+                let sealed: Vec<u8> = row.get("sealed_data_key");
+                let wrapping_key = derive_wrapping_key(passphrase, &salt)?;
+                let data_key = unseal_data_key(&wrapping_key, &sealed)?;
+                Ok(Self { data_key })
+            }
+            None => {
+                let mut salt = [0u8; SALT_LEN];
+                let mut data_key = [0u8; DATA_KEY_LEN];
+                rand::thread_rng().fill_bytes(&mut salt);
+                rand::thread_rng().fill_bytes(&mut data_key);
+
+                let wrapping_key = derive_wrapping_key(passphrase, &salt)?;
+                let sealed = seal_data_key(&wrapping_key, &data_key)?;
+
+                sqlx::query("INSERT INTO store_meta (id, salt, sealed_data_key) VALUES (0, ?, ?)")
+                    .bind(salt.as_slice())
+                    .bind(sealed)
+                    .execute(pool)
+                    .await?;
+
+                Ok(Self { data_key })
+            }
+        }
+    }
+
+    /// Re-seal the data key under a new passphrase. Existing encrypted
+    /// values are untouched - they're encrypted under the data key, not the
+    /// passphrase, so rotation is a single small row update.
+    pub async fn rotate_passphrase(&self, pool: &SqlitePool, new_passphrase: &[u8]) -> AppResult<()> {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+
+        let wrapping_key = derive_wrapping_key(new_passphrase, &salt)?;
+        let sealed = seal_data_key(&wrapping_key, &self.data_key)?;
+
+        sqlx::query("UPDATE store_meta SET salt = ?, sealed_data_key = ? WHERE id = 0")
+            .bind(salt.as_slice())
+            .bind(sealed)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Encrypt `plaintext` under the data key. The nonce is prepended to the
+    /// returned ciphertext so [`decrypt_value`](Self::decrypt_value) doesn't
+    /// need it passed back in separately.
+    pub fn encrypt_value(&self, plaintext: &[u8]) -> Vec<u8> {
+        let cipher = XChaCha20Poly1305::new((&self.data_key).into());
+
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = XNonce::from_slice(&nonce_bytes);
+
+        let ciphertext = cipher
+            .encrypt(nonce, plaintext)
+            .expect("XChaCha20-Poly1305 encryption cannot fail for valid inputs");
+
+        let mut out = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(&nonce_bytes);
+        out.extend_from_slice(&ciphertext);
+        out
+    }
+
+    /// Decrypt a value produced by [`encrypt_value`](Self::encrypt_value).
+    pub fn decrypt_value(&self, data: &[u8]) -> AppResult<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return Err(AppError::Encryption("encrypted value is truncated".to_string()));
+        }
+        let (nonce_bytes, ciphertext) = data.split_at(NONCE_LEN);
+
+        let cipher = XChaCha20Poly1305::new((&self.data_key).into());
+        cipher
+            .decrypt(XNonce::from_slice(nonce_bytes), ciphertext)
+            .map_err(|_| AppError::Encryption("failed to decrypt cached value".to_string()))
+    }
+
+    /// Encrypt `plaintext`, base64-encoding the result so it fits in the same
+    /// `TEXT` columns this codebase already uses for other binary values
+    /// (keys, pickles).
+    pub fn encrypt_to_text(&self, plaintext: &str) -> String {
+        base64::engine::general_purpose::STANDARD.encode(self.encrypt_value(plaintext.as_bytes()))
+    }
+
+    /// Decrypt a value produced by [`encrypt_to_text`](Self::encrypt_to_text).
+    pub fn decrypt_from_text(&self, text: &str) -> AppResult<String> {
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .map_err(|e| AppError::Encryption(format!("invalid base64: {}", e)))?;
+        let plaintext = self.decrypt_value(&data)?;
+        String::from_utf8(plaintext)
+            .map_err(|e| AppError::Encryption(format!("decrypted value wasn't valid UTF-8: {}", e)))
+    }
+
+    /// Decrypt a column value that may still be legacy plaintext (written
+    /// before encryption was enabled), falling back to the raw text as-is if
+    /// it doesn't decrypt. Callers that need to persist the migrated value
+    /// should use [`migrate_legacy_plaintext`] instead, which does so once
+    /// for the whole table.
+    pub fn decrypt_or_plaintext(&self, text: &str) -> String {
+        self.decrypt_from_text(text).unwrap_or_else(|_| text.to_string())
+    }
+}
+
+/// Detect columns still holding legacy plaintext (written before encryption
+/// was enabled) and re-encrypt them under `cipher`. Safe to call on every
+/// unlock: values that already decrypt successfully are left untouched.
+pub async fn migrate_legacy_plaintext(pool: &SqlitePool, cipher: &StoreCipher) -> AppResult<()> {
+    let sessions = sqlx::query("SELECT id, token, refresh_token FROM sessions")
+        .fetch_all(pool)
+        .await?;
+    for row in sessions {
+        let id: String = row.get("id");
+        let token: String = row.get("token");
+        let refresh_token: Option<String> = row.get("refresh_token");
+
+        if cipher.decrypt_from_text(&token).is_ok() {
+            continue;
+        }
+
+        let encrypted_token = cipher.encrypt_to_text(&token);
+        let encrypted_refresh = refresh_token.as_deref().map(|t| cipher.encrypt_to_text(t));
+
+        sqlx::query("UPDATE sessions SET token = ?, refresh_token = ? WHERE id = ?")
+            .bind(encrypted_token)
+            .bind(encrypted_refresh)
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+
+    let users = sqlx::query("SELECT id, display_name FROM users WHERE display_name IS NOT NULL")
+        .fetch_all(pool)
+        .await?;
+    for row in users {
+        let id: String = row.get("id");
+        let display_name: String = row.get("display_name");
+
+        if cipher.decrypt_from_text(&display_name).is_ok() {
+            continue;
+        }
+
+        let encrypted = cipher.encrypt_to_text(&display_name);
+        sqlx::query("UPDATE users SET display_name = ? WHERE id = ?")
+            .bind(encrypted)
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Stretch `passphrase` over `salt` with Argon2id into a 32-byte wrapping key.
+fn derive_wrapping_key(passphrase: &[u8], salt: &[u8]) -> AppResult<[u8; DATA_KEY_LEN]> {
+    let mut key = [0u8; DATA_KEY_LEN];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| AppError::Encryption(format!("Argon2id key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+/// Seal `data_key` under `wrapping_key` with XChaCha20-Poly1305, prepending
+/// the nonce to the sealed output.
+fn seal_data_key(wrapping_key: &[u8; DATA_KEY_LEN], data_key: &[u8; DATA_KEY_LEN]) -> AppResult<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(wrapping_key.into());
+
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce_bytes);
+    let nonce = XNonce::from_slice(&nonce_bytes);
+
+    let sealed = cipher
+        .encrypt(nonce, data_key.as_slice())
+        .map_err(|e| AppError::Encryption(format!("failed to seal data key: {}", e)))?;
+
+    let mut out = Vec::with_capacity(NONCE_LEN + sealed.len());
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Unseal a data key produced by [`seal_data_key`].
+fn unseal_data_key(wrapping_key: &[u8; DATA_KEY_LEN], sealed: &[u8]) -> AppResult<[u8; DATA_KEY_LEN]> {
+    if sealed.len() < NONCE_LEN {
+        return Err(AppError::Encryption("sealed data key is truncated".to_string()));
+    }
+    let (nonce_bytes, ciphertext) = sealed.split_at(NONCE_LEN);
+
+    let cipher = XChaCha20Poly1305::new(wrapping_key.into());
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|_| AppError::Encryption("wrong passphrase for encrypted store".to_string()))?;
+
+    plaintext
+        .try_into()
+        .map_err(|_| AppError::Encryption("unsealed data key has the wrong length".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unlock_creates_store_meta_on_first_run() {
+        let pool = memory_pool().await;
+        let cipher = StoreCipher::unlock(&pool, b"correct horse battery staple")
+            .await
+            .unwrap();
+
+        let encrypted = cipher.encrypt_to_text("super secret token");
+        assert_eq!(cipher.decrypt_from_text(&encrypted).unwrap(), "super secret token");
+    }
+
+    #[tokio::test]
+    async fn test_unlock_is_idempotent_across_reopens() {
+        let pool = memory_pool().await;
+        let first = StoreCipher::unlock(&pool, b"passphrase").await.unwrap();
+        let encrypted = first.encrypt_to_text("value");
+
+        let second = StoreCipher::unlock(&pool, b"passphrase").await.unwrap();
+        assert_eq!(second.decrypt_from_text(&encrypted).unwrap(), "value");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_passphrase_fails_to_unlock() {
+        let pool = memory_pool().await;
+        StoreCipher::unlock(&pool, b"right passphrase").await.unwrap();
+
+        assert!(StoreCipher::unlock(&pool, b"wrong passphrase").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rotate_passphrase_preserves_existing_values() {
+        let pool = memory_pool().await;
+        let cipher = StoreCipher::unlock(&pool, b"old passphrase").await.unwrap();
+        let encrypted = cipher.encrypt_to_text("value");
+
+        cipher.rotate_passphrase(&pool, b"new passphrase").await.unwrap();
+
+        let reopened = StoreCipher::unlock(&pool, b"new passphrase").await.unwrap();
+        assert_eq!(reopened.decrypt_from_text(&encrypted).unwrap(), "value");
+        assert!(StoreCipher::unlock(&pool, b"old passphrase").await.is_err());
+    }
+
+    #[test]
+    fn test_each_encryption_uses_a_fresh_nonce() {
+        let cipher = StoreCipher { data_key: [0x42u8; DATA_KEY_LEN] };
+        let a = cipher.encrypt_value(b"same plaintext");
+        let b = cipher.encrypt_value(b"same plaintext");
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_legacy_plaintext_falls_back_until_migrated() {
+        let cipher = StoreCipher { data_key: [0x42u8; DATA_KEY_LEN] };
+        assert_eq!(cipher.decrypt_or_plaintext("plain-old-token"), "plain-old-token");
+    }
+}
@@ -2,6 +2,87 @@
 
 use thiserror::Error;
 
+/// Structured error codes returned by the NoChat API
+///
+/// Mirrors the `errno` field in the API's `{ "errno": <int>, "message": "..." }`
+/// JSON error body, so callers can branch on the failure reason (e.g. prompt
+/// for email verification vs. show a password error) instead of pattern
+/// matching on free-text messages.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    #[error("an account with this email already exists")]
+    AccountExists,
+
+    #[error("no account found for this email")]
+    UnknownAccount,
+
+    #[error("incorrect password")]
+    IncorrectPassword,
+
+    #[error("account is not yet verified")]
+    UnverifiedAccount,
+
+    #[error("invalid or expired verification code")]
+    InvalidVerificationCode,
+
+    #[error("registration requires an invite")]
+    InviteOnly,
+
+    #[error("request body too large")]
+    RequestTooLarge,
+
+    #[error("no one-time prekeys available for this device")]
+    NoPrekeysAvailable,
+
+    #[error("API error {errno}: {message}")]
+    Unknown { errno: i32, message: String },
+}
+
+impl ApiError {
+    /// Map a server-provided errno to a typed variant, falling back to `Unknown`
+    fn from_errno(errno: i32, message: String) -> Self {
+        match errno {
+            1 => ApiError::AccountExists,
+            2 => ApiError::UnknownAccount,
+            3 => ApiError::IncorrectPassword,
+            4 => ApiError::UnverifiedAccount,
+            5 => ApiError::InvalidVerificationCode,
+            6 => ApiError::InviteOnly,
+            7 => ApiError::RequestTooLarge,
+            8 => ApiError::NoPrekeysAvailable,
+            _ => ApiError::Unknown { errno, message },
+        }
+    }
+
+    /// Stable, machine-readable code for the frontend to branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::AccountExists => "account_exists",
+            ApiError::UnknownAccount => "unknown_account",
+            ApiError::IncorrectPassword => "incorrect_password",
+            ApiError::UnverifiedAccount => "unverified_account",
+            ApiError::InvalidVerificationCode => "invalid_verification_code",
+            ApiError::InviteOnly => "invite_only",
+            ApiError::RequestTooLarge => "request_too_large",
+            ApiError::NoPrekeysAvailable => "no_prekeys_available",
+            ApiError::Unknown { .. } => "unknown",
+        }
+    }
+}
+
+/// JSON shape of an API error body: `{ "errno": <int>, "message": "..." }`
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct ApiErrorBody {
+    pub errno: i32,
+    pub message: String,
+}
+
+impl ApiErrorBody {
+    pub(crate) fn into_error(self) -> ApiError {
+        ApiError::from_errno(self.errno, self.message)
+    }
+}
+
 /// Application-wide error type
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -20,6 +101,9 @@ pub enum AppError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("API error: {0}")]
+    Api(#[from] ApiError),
+
     #[error("Authentication error: {0}")]
     Auth(String),
 
@@ -44,10 +128,23 @@ pub enum AppError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
+impl AppError {
+    /// The structured API error code, if this error originated from the API
+    pub fn api_code(&self) -> Option<&'static str> {
+        match self {
+            AppError::Api(e) => Some(e.code()),
+            _ => None,
+        }
+    }
+}
+
 /// Result type alias for application operations
 pub type AppResult<T> = Result<T, AppError>;
 
@@ -10,6 +10,10 @@ pub mod crypto;
 pub mod db;
 pub mod error;
 pub mod models;
+pub mod outbox;
+pub mod prekey;
+pub mod push;
+pub mod receipts;
 pub mod state;
 pub mod updater;
 
@@ -37,6 +41,25 @@ pub fn take_pending_deep_links() -> Vec<String> {
     }
 }
 
+/// Store every URL in `urls` for later retrieval via [`take_pending_deep_links`]
+/// and emit an `oauth-callback` event in case the frontend is already
+/// listening. Shared by the native re-open path (`on_open_url`), the
+/// at-launch path (`get_current`), and - on Windows/Linux, where an
+/// OAuth-redirect launch spawns a second process instead of re-opening the
+/// running one - the argv forwarded through `tauri_plugin_single_instance`,
+/// so all three funnel into the same pending-link buffer the frontend drains
+/// via `get_pending_oauth_urls`.
+fn handle_incoming_deep_link_urls(app_handle: &tauri::AppHandle, urls: Vec<String>) {
+    for url in urls {
+        tracing::info!("Processing deep link: {}", url);
+        store_pending_deep_link(url.clone());
+
+        if let Err(e) = app_handle.emit("oauth-callback", &url) {
+            tracing::error!("Failed to emit oauth-callback event: {}", e);
+        }
+    }
+}
+
 /// Run the Tauri application
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -51,6 +74,32 @@ pub fn run() {
     tracing::info!("Starting NoChat Desktop");
 
     tauri::Builder::default()
+        // Must be registered first: on Windows/Linux, launching the app a
+        // second time (e.g. via an OAuth-redirect deep link) spawns a new
+        // process rather than re-opening the running one. This plugin
+        // detects that, forwards the second process's argv here, and exits
+        // the second process - so the deep link still reaches the already
+        // running instance instead of being silently lost in a process that
+        // immediately exits.
+        .plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
+            tracing::info!("Second instance launched with argv: {:?}", argv);
+
+            let urls: Vec<String> = argv
+                .into_iter()
+                .skip(1)
+                .filter(|arg| arg.starts_with("nochat://"))
+                .collect();
+
+            if !urls.is_empty() {
+                tracing::info!("Forwarding deep links from second instance: {:?}", urls);
+                handle_incoming_deep_link_urls(app, urls);
+            }
+
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.set_focus();
+                let _ = window.unminimize();
+            }
+        }))
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_notification::init())
@@ -61,6 +110,16 @@ pub fn run() {
             // Setup auto-updater with rollback protection
             #[cfg(not(debug_assertions))]
             updater::setup_updater(app);
+
+            // Periodically replenish/rotate prekeys and notify the frontend
+            prekey::setup_prekey_scheduler(app);
+
+            // Periodically retry queued outbox messages that failed to send
+            outbox::setup_outbox_scheduler(app);
+
+            // Periodically batch-flush queued read receipts to the server
+            receipts::setup_receipt_scheduler(app);
+
             let app_handle = app.handle().clone();
 
             // Initialize state asynchronously
@@ -106,41 +165,20 @@ pub fn run() {
                 use tauri_plugin_deep_link::DeepLinkExt;
                 let app_handle_for_deep_link = app.handle().clone();
 
-                // Handle deep links received while app is running
+                // Handle deep links received while app is running (macOS
+                // native re-open, or Windows/Linux when the OS itself
+                // delivers the URL rather than spawning a second process)
                 app.deep_link().on_open_url(move |event| {
-                    let urls = event.urls();
+                    let urls: Vec<String> = event.urls().into_iter().map(|url| url.to_string()).collect();
                     tracing::info!("Received deep link URLs: {:?}", urls);
-
-                    for url in urls {
-                        let url_str = url.to_string();
-                        tracing::info!("Processing deep link: {}", url_str);
-
-                        // Store in global storage so frontend can retrieve it
-                        store_pending_deep_link(url_str.clone());
-
-                        // Also emit event in case frontend is already listening
-                        if let Err(e) = app_handle_for_deep_link.emit("oauth-callback", &url_str) {
-                            tracing::error!("Failed to emit oauth-callback event: {}", e);
-                        }
-                    }
+                    handle_incoming_deep_link_urls(&app_handle_for_deep_link, urls);
                 });
 
                 // Check for deep links that launched the app
                 if let Ok(Some(urls)) = app.deep_link().get_current() {
+                    let urls: Vec<String> = urls.into_iter().map(|url| url.to_string()).collect();
                     tracing::info!("App launched with deep links: {:?}", urls);
-                    let app_handle_startup = app.handle().clone();
-                    for url in urls {
-                        let url_str: String = url.to_string();
-                        tracing::info!("Processing startup deep link: {}", url_str);
-
-                        // Store in global storage so frontend can retrieve it
-                        store_pending_deep_link(url_str.clone());
-
-                        // Also emit event in case frontend is already listening
-                        if let Err(e) = app_handle_startup.emit("oauth-callback", &url_str) {
-                            tracing::error!("Failed to emit startup oauth-callback event: {}", e);
-                        }
-                    }
+                    handle_incoming_deep_link_urls(&app.handle().clone(), urls);
                 }
             }
 
@@ -150,11 +188,18 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             // Auth commands
             commands::login,
+            commands::signup,
+            commands::verify_email,
+            commands::resend_verification,
+            commands::submit_invite,
             commands::logout,
+            commands::register_device_push,
+            commands::unregister_push,
             commands::get_current_user,
             commands::start_oauth,
             commands::handle_oauth_callback,
             commands::restore_session,
+            commands::get_pending_verification_email,
             commands::get_pending_oauth_urls,
             commands::debug_log,
             // Messaging commands
@@ -164,6 +209,16 @@ pub fn run() {
             commands::mark_as_read,
             commands::create_conversation,
             commands::search_users,
+            // Search commands
+            commands::index_message_content,
+            commands::search_messages,
+            // Receipt commands
+            commands::mark_conversation_read,
+            commands::get_unread_counts,
+            commands::handle_incoming_receipt,
+            // Outbox commands
+            outbox::get_outbox_status,
+            outbox::retry_outbox,
             // Crypto commands (Signal Protocol)
             commands::init_crypto,
             commands::get_identity_key,
@@ -173,14 +228,38 @@ pub fn run() {
             commands::has_session,
             commands::encrypt_message,
             commands::decrypt_message,
+            commands::seal_message,
+            commands::open_sealed_message,
             commands::get_fingerprint,
             commands::get_session_stats,
             commands::needs_more_keys,
             commands::delete_session,
+            commands::handle_push_payload,
+            // Identity verification commands
+            commands::mark_peer_verified,
+            commands::get_verification_status,
+            commands::compute_safety_number,
+            // Key re-request ("gossip") commands
+            commands::request_missing_key,
+            commands::mark_key_request_sent,
+            commands::cancel_key_request,
+            commands::get_outgoing_key_requests,
+            commands::incoming_key_request,
+            commands::import_requested_key,
+            // Prekey lifecycle commands
+            prekey::get_prekey_status,
+            prekey::force_replenish,
+            prekey::force_rotate_signed_prekey,
+            prekey::force_rotate_fallback_prekey,
+            // Device linking commands
+            commands::begin_device_linking,
+            commands::export_linked_device,
+            commands::import_linked_device,
             // Settings commands
             commands::get_settings,
             commands::update_settings,
             commands::reset_settings,
+            commands::unlock_store,
             // Updater commands
             updater::check_update,
             updater::install_update,
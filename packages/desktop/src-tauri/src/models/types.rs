@@ -20,6 +20,16 @@ pub struct AuthResponse {
     pub refresh_token: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Stable, machine-readable error code (e.g. "unverified_account") when
+    /// `error` originated from a structured API error, so the frontend can
+    /// branch on the failure reason instead of parsing the message text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// `true` when the account was created/authenticated but is waiting on
+    /// email verification or an invite redemption - no session has been
+    /// established yet and `token` will be `None`.
+    #[serde(default)]
+    pub pending_verification: bool,
 }
 
 /// User information
@@ -66,6 +76,72 @@ pub struct OAuthUrlResponse {
     pub state: String,
 }
 
+// ============================================================================
+// Device Types
+// ============================================================================
+
+/// Kind of client a device entry represents
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceType {
+    Desktop,
+    Mobile,
+    Web,
+}
+
+/// A device registered for the current user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    pub device_id: String,
+    pub device_type: DeviceType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen_at: Option<DateTime<Utc>>,
+}
+
+/// A single one-time prekey entry, base64-encoded for transport
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyEntry {
+    /// Base64-encoded key id, as produced by vodozemac's `KeyId::to_base64`
+    pub key_id: String,
+    pub public_key: String,
+}
+
+/// Key bundle published for a device so other users can start an encrypted
+/// session with it (identity key plus one-time prekeys to consume)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyPayload {
+    /// Base64-encoded Curve25519 identity public key
+    pub identity_key: String,
+    pub one_time_prekeys: Vec<KeyEntry>,
+}
+
+// ============================================================================
+// Push Notification Types
+// ============================================================================
+
+/// Web Push encryption keys for a push subscription, as returned by the
+/// browser/OS push service alongside the subscription endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+/// A registered push subscription for this device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub keys: PushKeys,
+}
+
 // ============================================================================
 // Conversation Types
 // ============================================================================
@@ -160,6 +236,31 @@ fn default_encryption_version() -> i32 {
     1
 }
 
+/// [`Message::message_type`] value for a sealed-sender envelope: `content`
+/// holds a serialized [`SealedSenderEnvelope`] rather than a Double
+/// Ratchet ciphertext directly, and `sender_id` is a placeholder - the real
+/// sender id only exists inside the envelope once opened.
+pub const SEALED_SENDER_MESSAGE_TYPE: &str = "sealed-sender";
+
+/// Wire format for a sealed-sender message: an HPKE-sealed envelope whose
+/// opened payload carries the true sender id and the inner Double Ratchet
+/// ciphertext, so the transport (and the relay server relaying it) only
+/// ever sees an opaque blob plus the encapsulated key - never who actually
+/// sent the message. There is no separate sender-authentication tag: the
+/// sender id is accepted only because that ciphertext decrypts under an
+/// existing session with it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedSenderEnvelope {
+    /// Our fresh ephemeral public key for this single message (the HPKE
+    /// encapsulated key).
+    pub ephemeral_public: Vec<u8>,
+    /// The HPKE-sealed payload: the same bytes as
+    /// [`crate::crypto::service::SealedSenderPayload::ciphertext`], holding
+    /// the sender id and the inner Double Ratchet ciphertext.
+    pub ciphertext: Vec<u8>,
+}
+
 /// Send message request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -170,6 +271,93 @@ pub struct SendMessageRequest {
     pub recipient_ids: Vec<String>,
 }
 
+// ============================================================================
+// Outbox Types
+// ============================================================================
+
+/// A message queued in the offline outbox ([`crate::db::outbox`]), either
+/// waiting for its next retry or already acknowledged by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxEntry {
+    /// Client-generated id, also used as the optimistic [`Message::id`]
+    /// returned to the caller before the send actually succeeds.
+    pub id: String,
+    pub conversation_id: String,
+    pub content: String,
+    pub status: OutboxStatus,
+    /// Number of send attempts made so far.
+    pub attempts: i32,
+    /// The server-assigned message id, once [`OutboxStatus::Sent`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_message_id: Option<String>,
+    /// The most recent send failure, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outbox entry lifecycle, surfaced to the UI via `get_outbox_status` as a
+/// per-message "sending/failed" indicator.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboxStatus {
+    /// Queued, no attempt made yet.
+    Pending,
+    /// At least one attempt failed; waiting for the next backoff window.
+    Retrying,
+    /// Acknowledged by the server.
+    Sent,
+}
+
+// ============================================================================
+// Receipt Types
+// ============================================================================
+
+/// Per-conversation unread tally computed from the local message cache, for
+/// the `get_unread_counts` command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadCount {
+    pub conversation_id: String,
+    pub count: i64,
+}
+
+// ============================================================================
+// Search Types
+// ============================================================================
+
+/// A single `search_messages` match against the local full-text index
+/// (`crate::db::search`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageSearchHit {
+    pub message_id: String,
+    pub conversation_id: String,
+    /// The matched content with `<mark>`-wrapped highlights around the
+    /// query terms.
+    pub snippet: String,
+}
+
+// ============================================================================
+// Identity Verification Types
+// ============================================================================
+
+/// A peer's trust-on-first-use identity verification state, surfaced by
+/// `get_verification_status` so the UI can show a "verified"/"unverified"
+/// badge (and the short fingerprint to compare by hand, if the user doesn't
+/// want to do a full safety-number check via `compute_safety_number`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationStatus {
+    pub peer_id: String,
+    /// Short (8-byte, hex-encoded) fingerprint of the identity key we have
+    /// on file for this peer, or `None` if we've never seen one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    pub verified: bool,
+}
+
 // ============================================================================
 // Settings Types
 // ============================================================================
@@ -0,0 +1,258 @@
+//! Background prekey lifecycle management
+//!
+//! Nothing previously drove `PreKeyManager::needs_replenishment`/
+//! `needs_signed_prekey_rotation` - an account's one-time prekey pool would
+//! just silently run dry and its signed prekey would silently go stale.
+//! This module spawns a periodic background task (mirroring `updater`'s
+//! `setup_updater`) that polls `PreKeyManager::status()` for every
+//! registered `KeyDomain`, replenishes/rotates as needed, persists the
+//! result via `db::prekeys`, and emits a `prekeys-updated` event so the
+//! frontend can upload the new keys to the server.
+//!
+//! Note: this drives the standalone X3DH `PreKeyManager`, not the
+//! vodozemac-backed `CryptoService` used by the messaging commands - the two
+//! crypto paths are still independent. The `PreKeyManager`'s identity key
+//! pair isn't persisted anywhere yet (only its signed/fallback/one-time
+//! prekeys are, via `db::prekeys`), so today it's regenerated fresh on every
+//! app launch; persisting it is left as follow-up work, same as
+//! `published_key_ids` in `db::prekeys`.
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::crypto::{
+    FallbackPreKey, IdentityKeyPair, KeyDomain, OneTimePreKey, PreKeyManager, PreKeyStatus, SignedPreKey,
+};
+use crate::db;
+use crate::state::SharedState;
+
+/// Interval between prekey health checks.
+const PREKEY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(6 * 60 * 60);
+
+/// Domains checked on every scheduler tick. `PhoneNumber` is only acted on
+/// once a caller has actually registered it via `PreKeyManager::add_domain`;
+/// checking it unconditionally here is harmless since `has_domain` guards it.
+const CHECKED_DOMAINS: [KeyDomain; 2] = [KeyDomain::Account, KeyDomain::PhoneNumber];
+
+/// New prekeys generated for `domain` during a lifecycle check, ready for
+/// the frontend to upload to the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrekeysUpdatedPayload {
+    pub domain: KeyDomain,
+    pub one_time_prekeys: Vec<OneTimePreKey>,
+    pub signed_prekey: Option<SignedPreKey>,
+    pub fallback_prekey: Option<FallbackPreKey>,
+}
+
+/// Spawn the background prekey lifecycle task.
+pub fn setup_prekey_scheduler(app: &tauri::App) {
+    let handle = app.handle().clone();
+
+    tauri::async_runtime::spawn(async move {
+        // Give app state a moment to finish initializing before the first check.
+        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+
+        loop {
+            run_prekey_check(&handle).await;
+            tokio::time::sleep(PREKEY_CHECK_INTERVAL).await;
+        }
+    });
+}
+
+/// Ensure `state` has a `PreKeyManager` for the `Account` domain, generating
+/// one on first use if none exists yet.
+async fn ensure_prekey_manager(state: &SharedState) {
+    let mut app_state = state.write().await;
+    if app_state.prekey_manager.is_none() {
+        tracing::info!("Initializing a fresh PreKeyManager (Account domain)");
+        app_state.prekey_manager = Some(PreKeyManager::new(IdentityKeyPair::generate()));
+    }
+}
+
+/// Run one lifecycle check across every checked domain and emit
+/// `prekeys-updated` for each domain that changed.
+async fn run_prekey_check(app_handle: &AppHandle) {
+    let Some(state) = app_handle.try_state::<SharedState>() else {
+        tracing::debug!("Skipping prekey check: app state not initialized yet");
+        return;
+    };
+    let state = state.inner();
+
+    ensure_prekey_manager(state).await;
+
+    for domain in CHECKED_DOMAINS {
+        if let Some(payload) = check_and_update_domain(state, domain).await {
+            if let Err(e) = app_handle.emit("prekeys-updated", &payload) {
+                tracing::error!("Failed to emit prekeys-updated event: {}", e);
+            }
+        }
+    }
+}
+
+/// Replenish and/or rotate `domain`'s prekeys if needed, persisting the
+/// result. Returns `None` if nothing changed (or `domain` isn't registered).
+async fn check_and_update_domain(state: &SharedState, domain: KeyDomain) -> Option<PrekeysUpdatedPayload> {
+    let mut app_state = state.write().await;
+    let manager = app_state.prekey_manager.as_mut()?;
+    if !manager.has_domain(domain) {
+        return None;
+    }
+
+    let one_time_prekeys = if manager.needs_replenishment(domain) {
+        let new_keys = manager.replenish(domain);
+        tracing::info!("Replenished {} one-time prekeys for {:?}", new_keys.len(), domain);
+        new_keys
+    } else {
+        Vec::new()
+    };
+
+    let signed_prekey = if manager.needs_signed_prekey_rotation(domain) {
+        tracing::info!("Rotating signed prekey for {:?}", domain);
+        manager.rotate_signed_prekey(domain)
+    } else {
+        None
+    };
+
+    // Rotated, not deleted: the old fallback id stays valid server-side
+    // until it ages out naturally, same as `rotate_fallback_prekey` itself
+    // documents - this just drives that rotation on a schedule instead of
+    // leaving the fallback key to go stale forever.
+    let fallback_prekey = if manager.needs_fallback_prekey_rotation(domain) {
+        tracing::info!("Rotating fallback prekey for {:?}", domain);
+        manager.rotate_fallback_prekey(domain)
+    } else {
+        None
+    };
+
+    if one_time_prekeys.is_empty() && signed_prekey.is_none() && fallback_prekey.is_none() {
+        return None;
+    }
+
+    let (signed, otks, fallback) = manager.get_stored_prekeys(domain)?;
+    let signed_created = manager.signed_prekey_created(domain).unwrap_or(0);
+    let fallback_created = manager.fallback_prekey_created(domain).unwrap_or(0);
+    let next_id = manager.next_prekey_id(domain).unwrap_or(0);
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    if let Err(e) = db::save_prekeys(&db, domain, &signed, signed_created, &fallback, fallback_created, &otks, next_id, None).await {
+        tracing::error!("Failed to persist prekeys for {:?}: {}", domain, e);
+    }
+
+    Some(PrekeysUpdatedPayload {
+        domain,
+        one_time_prekeys,
+        signed_prekey,
+        fallback_prekey,
+    })
+}
+
+/// Current prekey health for every registered identity domain.
+#[tauri::command]
+pub async fn get_prekey_status(state: tauri::State<'_, SharedState>) -> Result<Vec<PreKeyStatus>, String> {
+    ensure_prekey_manager(state.inner()).await;
+
+    let app_state = state.read().await;
+    Ok(app_state
+        .prekey_manager
+        .as_ref()
+        .map(|manager| manager.statuses())
+        .unwrap_or_default())
+}
+
+/// Manually replenish `domain`'s one-time prekey pool, regardless of
+/// whether it's currently below the replenishment threshold.
+#[tauri::command]
+pub async fn force_replenish(
+    state: tauri::State<'_, SharedState>,
+    domain: KeyDomain,
+) -> Result<Vec<OneTimePreKey>, String> {
+    ensure_prekey_manager(state.inner()).await;
+
+    let mut app_state = state.write().await;
+    let manager = app_state
+        .prekey_manager
+        .as_mut()
+        .ok_or("Prekey manager not initialized")?;
+
+    let new_keys = manager.replenish(domain);
+    let (signed, otks, fallback) = manager
+        .get_stored_prekeys(domain)
+        .ok_or_else(|| format!("{:?} domain is not registered", domain))?;
+    let signed_created = manager.signed_prekey_created(domain).unwrap_or(0);
+    let fallback_created = manager.fallback_prekey_created(domain).unwrap_or(0);
+    let next_id = manager.next_prekey_id(domain).unwrap_or(0);
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    db::save_prekeys(&db, domain, &signed, signed_created, &fallback, fallback_created, &otks, next_id, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("Manually replenished {} one-time prekeys for {:?}", new_keys.len(), domain);
+    Ok(new_keys)
+}
+
+/// Manually rotate `domain`'s signed prekey, regardless of its age.
+#[tauri::command]
+pub async fn force_rotate_signed_prekey(
+    state: tauri::State<'_, SharedState>,
+    domain: KeyDomain,
+) -> Result<SignedPreKey, String> {
+    ensure_prekey_manager(state.inner()).await;
+
+    let mut app_state = state.write().await;
+    let manager = app_state
+        .prekey_manager
+        .as_mut()
+        .ok_or("Prekey manager not initialized")?;
+
+    let new_signed_prekey = manager
+        .rotate_signed_prekey(domain)
+        .ok_or_else(|| format!("{:?} domain is not registered", domain))?;
+    let (signed, otks, fallback) = manager.get_stored_prekeys(domain).ok_or("Prekey manager not initialized")?;
+    let signed_created = manager.signed_prekey_created(domain).unwrap_or(0);
+    let fallback_created = manager.fallback_prekey_created(domain).unwrap_or(0);
+    let next_id = manager.next_prekey_id(domain).unwrap_or(0);
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    db::save_prekeys(&db, domain, &signed, signed_created, &fallback, fallback_created, &otks, next_id, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("Manually rotated signed prekey for {:?}", domain);
+    Ok(new_signed_prekey)
+}
+
+/// Manually rotate `domain`'s fallback prekey, regardless of its age.
+#[tauri::command]
+pub async fn force_rotate_fallback_prekey(
+    state: tauri::State<'_, SharedState>,
+    domain: KeyDomain,
+) -> Result<FallbackPreKey, String> {
+    ensure_prekey_manager(state.inner()).await;
+
+    let mut app_state = state.write().await;
+    let manager = app_state
+        .prekey_manager
+        .as_mut()
+        .ok_or("Prekey manager not initialized")?;
+
+    let new_fallback_prekey = manager
+        .rotate_fallback_prekey(domain)
+        .ok_or_else(|| format!("{:?} domain is not registered", domain))?;
+    let (signed, otks, fallback) = manager.get_stored_prekeys(domain).ok_or("Prekey manager not initialized")?;
+    let signed_created = manager.signed_prekey_created(domain).unwrap_or(0);
+    let fallback_created = manager.fallback_prekey_created(domain).unwrap_or(0);
+    let next_id = manager.next_prekey_id(domain).unwrap_or(0);
+    let db = app_state.db.clone();
+    drop(app_state);
+
+    db::save_prekeys(&db, domain, &signed, signed_created, &fallback, fallback_created, &otks, next_id, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("Manually rotated fallback prekey for {:?}", domain);
+    Ok(new_fallback_prekey)
+}
@@ -0,0 +1,112 @@
+//! Push notification delivery
+//!
+//! Turns an inbound encrypted push/websocket payload into a native OS
+//! notification. Registration of the OS-level push channel with the backend
+//! lives in `commands::auth` (`register_device_push`/`unregister_push`)
+//! alongside the rest of session lifecycle management.
+//!
+//! The push server only ever sees a ciphertext blob and the sender's peer
+//! id - it never sees message contents. [`notify_incoming_message`] runs
+//! that ciphertext through the Signal session (`CryptoService::decrypt`)
+//! locally to recover the body before showing a notification. There's
+//! deliberately no "is this actually encrypted" flag to check first: that
+//! would just be a boolean in the same payload a compromised server
+//! controls, so it could assert its way past the check. `decrypt` itself -
+//! which a compromised server can't forge without the recipient's session
+//! state - is what stands between an inbound payload and a shown
+//! notification.
+
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+use crate::crypto::{CryptoError, CryptoService};
+
+/// Generic fallback notification shown when a push payload can't be
+/// decrypted yet (no session with the sender, or it arrived out of order).
+/// The message itself is still fetched and decrypted normally the next
+/// time the app polls - this just lets the user know *something* arrived
+/// without ever showing stale or wrong content.
+const FALLBACK_TITLE: &str = "New message";
+
+/// Attempt to decrypt an inbound message payload and show it as a native
+/// notification.
+///
+/// There's no separate "was this encrypted" check - `crypto.decrypt` below
+/// either recovers real plaintext from a real session or it doesn't, and
+/// nothing a malicious payload claims about itself changes that. Plaintext
+/// masquerading as ciphertext just fails to decrypt and falls back to a
+/// contentless alert, the same as any other undecryptable payload.
+///
+/// Decryption failures are otherwise routine for a background notification
+/// handler (a session we haven't established with the sender yet, a
+/// message that arrived out of order), so `DecryptionError`/
+/// `SessionNotFound` fall back to a contentless "New message" alert
+/// instead of silently doing nothing.
+pub async fn notify_incoming_message(
+    app_handle: &AppHandle,
+    crypto: &CryptoService,
+    sender_id: &str,
+    sender_display_name: Option<&str>,
+    conversation_id: &str,
+    ciphertext: &[u8],
+) {
+    let title = sender_display_name.unwrap_or(sender_id).to_string();
+
+    let body = match crypto.decrypt(sender_id, None, ciphertext).await {
+        Ok(plaintext) => String::from_utf8_lossy(&plaintext).to_string(),
+        Err(CryptoError::DecryptionError(_)) | Err(CryptoError::SessionNotFound(_)) => {
+            tracing::debug!(
+                "Could not decrypt push payload for conversation {}; showing a contentless alert",
+                conversation_id
+            );
+            show_notification(app_handle, FALLBACK_TITLE, "");
+            return;
+        }
+        Err(e) => {
+            tracing::warn!("Unexpected error decrypting push payload: {}", e);
+            return;
+        }
+    };
+
+    show_notification(app_handle, &title, &body);
+}
+
+/// Attempt to open a sealed-sender push payload (see
+/// `CryptoService::seal_sender`/`commands::seal_message`) and show it as a
+/// native notification.
+///
+/// Unlike [`notify_incoming_message`], the sender id shown here comes from
+/// inside the opened envelope, not from the payload's own (unauthenticated)
+/// `sender_id` field - that's the whole point of sealed sender, and it's
+/// why there's no display name to show either: the envelope only carries a
+/// user id, never a display name.
+pub async fn notify_incoming_sealed_message(
+    app_handle: &AppHandle,
+    crypto: &CryptoService,
+    sealing_key: &crate::crypto::keys::Curve25519KeyPair,
+    conversation_id: &str,
+    envelope: &crate::crypto::SealedSenderPayload,
+) {
+    match crypto.open_sealed_sender(sealing_key, envelope).await {
+        Ok((sender_id, plaintext)) => {
+            let body = String::from_utf8_lossy(&plaintext).to_string();
+            show_notification(app_handle, &sender_id, &body);
+        }
+        Err(CryptoError::DecryptionError(_)) | Err(CryptoError::SessionNotFound(_)) => {
+            tracing::debug!(
+                "Could not open sealed-sender push payload for conversation {}; showing a contentless alert",
+                conversation_id
+            );
+            show_notification(app_handle, FALLBACK_TITLE, "");
+        }
+        Err(e) => {
+            tracing::warn!("Unexpected error opening sealed-sender push payload: {}", e);
+        }
+    }
+}
+
+fn show_notification(app_handle: &AppHandle, title: &str, body: &str) {
+    if let Err(e) = app_handle.notification().builder().title(title).body(body).show() {
+        tracing::warn!("Failed to show push notification: {}", e);
+    }
+}
@@ -0,0 +1,81 @@
+//! Background read-receipt flusher
+//!
+//! `commands::messaging::mark_as_read`/`mark_conversation_read` record reads
+//! locally and queue a receipt via `db::receipts::mark_read`/
+//! `mark_conversation_read` rather than calling the API directly. This
+//! module's background task (mirroring `outbox`'s drainer) then flushes
+//! queued receipts on a short debounce, batching every message id queued for
+//! a conversation into a single API call so reading several messages in a
+//! row doesn't fire a request per message.
+//!
+//! Receipts flowing the other way - a peer reading a message *we* sent -
+//! arrive as an event the frontend forwards to
+//! `commands::messaging::handle_incoming_receipt`, which updates the local
+//! copy and emits `receipt-updated` so the UI can redraw checkmarks without
+//! a full refetch.
+
+use tauri::{AppHandle, Manager};
+
+use crate::api::ApiClient;
+use crate::db;
+use crate::state::SharedState;
+
+/// Interval between receipt flush attempts - short, since the whole point is
+/// to coalesce a burst of reads into the next flush rather than queue them
+/// for long.
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Spawn the background receipt flusher.
+pub fn setup_receipt_scheduler(app: &tauri::App) {
+    let handle = app.handle().clone();
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(FLUSH_INTERVAL).await;
+            flush_receipts(&handle).await;
+        }
+    });
+}
+
+/// Send every queued receipt, batched into one API call per conversation.
+async fn flush_receipts(app_handle: &AppHandle) {
+    let Some(state) = app_handle.try_state::<SharedState>() else {
+        tracing::debug!("Skipping receipt flush: app state not initialized yet");
+        return;
+    };
+    let shared_state = state.inner().clone();
+
+    let (pool, api_url, token) = {
+        let app_state = shared_state.read().await;
+        let Some(session) = app_state.session.as_ref() else {
+            return;
+        };
+        (app_state.db.clone(), app_state.api_url.clone(), session.token.clone())
+    };
+
+    let due = match db::receipts::due_receipts(&pool).await {
+        Ok(due) => due,
+        Err(e) => {
+            tracing::error!("Failed to load pending receipts: {}", e);
+            return;
+        }
+    };
+    if due.is_empty() {
+        return;
+    }
+
+    let api_client = ApiClient::with_session(&api_url, shared_state.clone());
+
+    for (conversation_id, message_ids) in due {
+        match api_client.send_read_receipts(&conversation_id, &message_ids, &token).await {
+            Ok(()) => {
+                if let Err(e) = db::receipts::clear_receipts(&pool, &conversation_id, &message_ids).await {
+                    tracing::error!("Failed to clear flushed receipts for {}: {}", conversation_id, e);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to flush read receipts for {}: {}", conversation_id, e);
+            }
+        }
+    }
+}
@@ -7,6 +7,8 @@ use sqlx::SqlitePool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use crate::crypto::{CryptoService, Curve25519KeyPair, PreKeyManager};
+use crate::db::store_cipher::StoreCipher;
 use crate::error::{AppError, AppResult};
 
 /// User session information
@@ -26,6 +28,10 @@ pub struct OAuthState {
     pub state: String,
     pub provider: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// PKCE code verifier generated for this flow, sent back to the server on
+    /// callback so it can verify `code_challenge = S256(code_verifier)`
+    /// before exchanging the authorization code for a token.
+    pub code_verifier: String,
 }
 
 /// Global application state
@@ -36,6 +42,14 @@ pub struct AppState {
     /// Database connection pool
     pub db: SqlitePool,
 
+    /// Signal Protocol crypto service, initialized once at startup and
+    /// shared by every crypto command. Previously each command
+    /// re-initialized its own `CryptoService` from `db` on every call,
+    /// reloading identity keys and session state from disk each time and
+    /// risking two in-flight decrypts mutating ratchet state through
+    /// separate instances - this is the single authoritative one.
+    pub crypto: CryptoService,
+
     /// Current user session (None if not authenticated)
     pub session: Option<UserSession>,
 
@@ -50,6 +64,22 @@ pub struct AppState {
 
     /// WebSocket URL
     pub ws_url: String,
+
+    /// Standalone X3DH prekey manager, lazily initialized by the
+    /// `prekey` background scheduler (see `crate::prekey`).
+    pub prekey_manager: Option<PreKeyManager>,
+
+    /// Ephemeral key pair for an in-progress device linking attempt (see
+    /// `crate::commands::provisioning`), held between `begin_device_linking`
+    /// and the matching `export_linked_device`/`import_linked_device` call.
+    pub linking_ephemeral: Option<Curve25519KeyPair>,
+
+    /// Opt-in at-rest encryption for the local cache (session tokens,
+    /// display names - see [`crate::db::store_cipher`]). `None` until the
+    /// user unlocks the store with a passphrase via `commands::unlock_store`,
+    /// in which case every call site that persists or reads one of those
+    /// columns keeps working exactly as before, just without encryption.
+    pub store_cipher: Option<StoreCipher>,
 }
 
 impl AppState {
@@ -74,13 +104,21 @@ impl AppState {
 
         tracing::info!("Database initialized with WAL mode at: {}", db_path);
 
+        let crypto = CryptoService::initialize(db.clone())
+            .await
+            .map_err(|e| AppError::Encryption(e.to_string()))?;
+
         Ok(Self {
             db,
+            crypto,
             session: None,
             pending_oauth: Vec::new(),
             pending_deep_links: Vec::new(),
             api_url: "https://nochat-server.fly.dev".to_string(),
             ws_url: "wss://nochat-server.fly.dev".to_string(),
+            prekey_manager: None,
+            linking_ephemeral: None,
+            store_cipher: None,
         })
     }
 
@@ -119,9 +157,16 @@ impl AppState {
     }
 
     /// Validate and consume OAuth state
+    ///
+    /// Returns `None` (discarding the entry either way) if no matching state
+    /// is pending or if it's older than the 10-minute expiry window.
     pub fn validate_oauth_state(&mut self, state: &str) -> Option<OAuthState> {
-        if let Some(pos) = self.pending_oauth.iter().position(|s| s.state == state) {
-            Some(self.pending_oauth.remove(pos))
+        let pos = self.pending_oauth.iter().position(|s| s.state == state)?;
+        let entry = self.pending_oauth.remove(pos);
+
+        let cutoff = chrono::Utc::now() - chrono::Duration::minutes(10);
+        if entry.created_at > cutoff {
+            Some(entry)
         } else {
             None
         }